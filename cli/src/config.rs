@@ -1,18 +1,31 @@
 use serde::Deserialize;
-use std::{collections::HashSet, path::Path, thread, time};
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    thread, time,
+};
 
-use plojo_core::{Command, Controller, Machine, Stroke};
-use plojo_input_geminipr::GeminiprMachine;
-use plojo_input_keyboard::KeyboardMachine;
-use plojo_input_stdin::StdinMachine;
+use plojo_core::{Command, Controller, KeyDelays, Machine, MultiMachine, Reconnect, Stroke};
+use plojo_input_geminipr::{GeminiprMachine, SerialMachine};
+use plojo_input_keyboard::{ChordResolution, KeyboardMachine, LayoutConfig};
+use plojo_input_stdin::{StdinJsonMachine, StdinMachine};
+use plojo_input_txbolt::TxBoltMachine;
 use plojo_output_enigo::EnigoController;
 use plojo_output_macos::MacController;
+#[cfg(windows)]
+use plojo_output_windows::WindowsController;
+#[cfg(target_os = "linux")]
+use plojo_output_x11::X11Controller;
+use plojo_translator::UndoPolicy;
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct Config {
     #[serde(default)]
     input_machine: InputMachineType,
     #[serde(default)]
+    extra_input_machines: Vec<InputMachineType>,
+    #[serde(default)]
     output_dispatcher: OutputDispatchType,
     #[serde(default)]
     dicts: Vec<String>,
@@ -23,48 +36,203 @@ pub struct Config {
     #[serde(default)]
     pub space_after: bool,
     #[serde(default)]
+    pub capitalize_first_word: bool,
+    #[serde(default)]
+    pub capitalize_after_punctuation: bool,
+    #[serde(default)]
+    pub undo_policy: UndoPolicy,
+    #[serde(default)]
+    pub units: Vec<String>,
+    #[serde(default)]
+    pub number_words: Option<Vec<String>>,
+    #[serde(default)]
+    pub max_stroke_buffer: Option<usize>,
+    #[serde(default)]
+    pub separator: Option<String>,
+    #[serde(default)]
+    macros: Vec<MacroConfig>,
+    #[serde(default)]
+    fallback: HashMap<String, String>,
+    #[serde(default)]
+    orthography_rules: Option<String>,
+    #[serde(default)]
+    orthography_exceptions: Option<String>,
+    #[serde(default)]
     pub delay_output: bool,
     #[serde(default)]
+    pub save_stroke_history: bool,
+    #[serde(default)]
     disable_input_strokes: Vec<String>,
     #[serde(default)]
+    enable_input_strokes: Vec<String>,
+    #[serde(default)]
+    correction_stroke: Option<String>,
+    #[serde(default)]
     enable_input_shortcuts: Vec<Vec<String>>,
     #[serde(default)]
+    keyboard_debounce_ms: Option<u64>,
+    #[serde(default)]
+    keyboard_layout: Option<LayoutConfig>,
+    #[serde(default)]
+    keyboard_modifier_passthrough: bool,
+    #[serde(default)]
+    keyboard_non_steno_passthrough: bool,
+    #[serde(default)]
+    chord_resolution: ChordResolution,
+    #[serde(default)]
+    arpeggiate_trigger: Option<String>,
+    #[serde(default)]
+    keyboard_hold_repeat_ms: Option<u64>,
+    #[serde(default)]
+    hold_repeat_interval_ms: Option<u64>,
+    #[serde(default)]
+    keyboard_idle_timeout_ms: Option<u64>,
+    #[serde(default)]
     disable_scan_keymap: bool,
+    #[serde(default)]
+    key_delays: KeyDelays,
+    #[serde(default)]
+    capture_shell_output: bool,
+    #[serde(default)]
+    use_clipboard_paste: bool,
+    #[serde(default)]
+    clipboard_paste_threshold: Option<usize>,
+    // the last stroke the keyboard formed and when, set by its on_stroke callback
+    #[serde(skip)]
+    keyboard_stroke_time: Arc<Mutex<Option<(Stroke, time::Instant)>>>,
 }
 
 impl Config {
     /// Creates an input machine from the config. Can panic if failed to create machine.
     /// Accepts an override to ignore config and use stdin
     pub fn get_input_machine(&self, use_stdin: bool) -> Box<dyn Machine> {
-        let input = if use_stdin {
+        if use_stdin {
             println!("[INFO] Overriding config to use input from stdin");
+        }
+        println!(
+            "[INFO] Input from: {:?}",
+            self.input_machine_type(use_stdin)
+        );
+        retry_connect(self.input_machine_type(use_stdin), || {
+            self.try_input_machine(use_stdin)
+        })
+    }
+
+    /// Try to create the input machine from the config once, without retrying. If there are extra
+    /// input machines (and stdin isn't overriding the config), they are all read at once, and each
+    /// of them is reconnected on its own if it disconnects
+    pub fn try_input_machine(&self, use_stdin: bool) -> Result<Box<dyn Machine>, String> {
+        let machine_type = self.input_machine_type(use_stdin);
+        let machine = self.try_machine(machine_type)?;
+        if use_stdin || self.extra_input_machines.is_empty() {
+            return Ok(machine);
+        }
+
+        let mut machines = vec![(machine, self.reconnect(machine_type))];
+        for machine_type in &self.extra_input_machines {
+            println!("[INFO] Also input from: {:?}", machine_type);
+            machines.push((
+                self.try_machine(machine_type)?,
+                self.reconnect(machine_type),
+            ));
+        }
+        Ok(Box::new(MultiMachine::with_reconnect(machines)))
+    }
+
+    /// Creates the machine of this type again after it disconnects
+    fn reconnect(&self, machine_type: &InputMachineType) -> Reconnect {
+        let config = self.clone();
+        let machine_type = machine_type.clone();
+        Box::new(move || retry_connect(&machine_type, || config.try_machine(&machine_type)))
+    }
+
+    fn try_machine(
+        &self,
+        machine_type: &InputMachineType,
+    ) -> Result<Box<dyn Machine + Send>, String> {
+        match machine_type {
+            InputMachineType::Stdin => Ok(Box::new(StdinMachine::new()) as Box<dyn Machine + Send>),
+            InputMachineType::StdinJson => {
+                Ok(Box::new(StdinJsonMachine::new()) as Box<dyn Machine + Send>)
+            }
+            InputMachineType::Geminipr { port, baud_rate } => {
+                match GeminiprMachine::from_serial(serial_machine(port, *baud_rate)) {
+                    Ok(machine) => Ok(Box::new(machine) as Box<dyn Machine + Send>),
+                    Err(e) => Err(format!("unable to open serial port {:?}: {}", port, e)),
+                }
+            }
+            InputMachineType::TxBolt { port, baud_rate } => {
+                match TxBoltMachine::from_serial(serial_machine(port, *baud_rate)) {
+                    Ok(machine) => Ok(Box::new(machine) as Box<dyn Machine + Send>),
+                    Err(e) => Err(format!("unable to open serial port {:?}: {}", port, e)),
+                }
+            }
+            InputMachineType::Keyboard => {
+                let mut machine = KeyboardMachine::new()
+                    .with_reenable_shortcuts(self.enable_input_shortcuts.clone())
+                    .with_modifier_passthrough(self.keyboard_modifier_passthrough)
+                    .with_non_steno_passthrough(self.keyboard_non_steno_passthrough)
+                    .with_chord_resolution(self.chord_resolution);
+                if let Some(ms) = self.keyboard_debounce_ms {
+                    machine = machine.with_debounce(time::Duration::from_millis(ms));
+                }
+                if let Some(ms) = self.keyboard_hold_repeat_ms {
+                    machine = machine.with_hold_repeat(time::Duration::from_millis(ms));
+                }
+                if let Some(ms) = self.keyboard_idle_timeout_ms {
+                    machine = machine.with_idle_timeout(time::Duration::from_millis(ms));
+                }
+                if let Some(trigger) = &self.arpeggiate_trigger {
+                    machine = machine
+                        .with_arpeggiate_trigger(trigger)
+                        .map_err(|e| format!("invalid arpeggiate_trigger: {}", e))?;
+                }
+                if let Some(layout) = &self.keyboard_layout {
+                    machine = machine
+                        .with_layout(layout.clone())
+                        .map_err(|e| format!("invalid keyboard_layout: {}", e))?;
+                }
+                let stroke_time = Arc::clone(&self.keyboard_stroke_time);
+                machine = machine.with_on_stroke(Box::new(move |stroke, time| {
+                    *stroke_time.lock().unwrap() = Some((stroke.clone(), time));
+                }));
+                Ok(Box::new(machine) as Box<dyn Machine + Send>)
+            }
+        }
+    }
+
+    /// When a stroke that was just read was formed. The keyboard knows when the last key of the
+    /// stroke was released, which is earlier than when it is read if the previous stroke took a
+    /// while to translate and dispatch. For the other machines it is when it was read
+    pub fn stroke_time(&self, stroke: &Stroke) -> time::Instant {
+        match self.keyboard_stroke_time.lock().unwrap().take() {
+            Some((formed, time)) if formed == *stroke => time,
+            _ => time::Instant::now(),
+        }
+    }
+
+    /// A hint for the user on how to fix the input machine if it fails to work
+    pub fn input_machine_hint(&self, use_stdin: bool) -> &'static str {
+        match self.input_machine_type(use_stdin) {
+            InputMachineType::Stdin => "check that stdin is readable",
+            InputMachineType::StdinJson => {
+                "check that stdin is readable and sends one JSON message per line"
+            }
+            InputMachineType::Geminipr { .. } | InputMachineType::TxBolt { .. } => {
+                "check that the machine is plugged in and that the port matches one listed by --ports"
+            }
+            InputMachineType::Keyboard => {
+                "on macOS, grant accessibility access to the terminal in System Preferences > \
+                 Security & Privacy > Privacy > Accessibility"
+            }
+        }
+    }
+
+    fn input_machine_type(&self, use_stdin: bool) -> &InputMachineType {
+        if use_stdin {
             &InputMachineType::Stdin
         } else {
             &self.input_machine
-        };
-        println!("[INFO] Input from: {:?}", input);
-        match input {
-            InputMachineType::Stdin => Box::new(StdinMachine::new()) as Box<dyn Machine>,
-            InputMachineType::Geminipr { ref port } => {
-                let mut issued_warning = false;
-                loop {
-                    if let Ok(machine) = GeminiprMachine::new(port) {
-                        return Box::new(machine) as Box<dyn Machine>;
-                    } else {
-                        if !issued_warning {
-                            println!(
-                                "[WARN] Machine not found on serial port. Will try again every 5 seconds"
-                            );
-                            issued_warning = true;
-                        }
-                        // try to connect to machine again after a delay
-                        thread::sleep(time::Duration::from_secs(5));
-                    }
-                }
-            }
-            InputMachineType::Keyboard => Box::new(
-                KeyboardMachine::new().with_reenable_shortcuts(self.enable_input_shortcuts.clone()),
-            ) as Box<dyn Machine>,
         }
     }
 
@@ -79,12 +247,37 @@ impl Config {
         };
         println!("[INFO] Output to: {:?}", output);
         match output {
-            OutputDispatchType::Enigo => {
-                Box::new(EnigoController::new(self.disable_scan_keymap)) as Box<dyn Controller>
-            }
+            OutputDispatchType::Enigo => Box::new(
+                EnigoController::new(self.disable_scan_keymap)
+                    .with_capture_shell_output(self.capture_shell_output)
+                    .with_key_delays(self.key_delays),
+            ) as Box<dyn Controller>,
             OutputDispatchType::MacNative => {
-                Box::new(MacController::new(self.disable_scan_keymap)) as Box<dyn Controller>
+                let mut controller = MacController::new(self.disable_scan_keymap)
+                    .with_capture_shell_output(self.capture_shell_output)
+                    .with_key_delays(self.key_delays)
+                    .with_clipboard_paste(self.use_clipboard_paste);
+                if let Some(threshold) = self.clipboard_paste_threshold {
+                    controller = controller.with_paste_threshold(threshold);
+                }
+                Box::new(controller) as Box<dyn Controller>
             }
+            #[cfg(target_os = "linux")]
+            OutputDispatchType::X11 => Box::new(
+                X11Controller::new(self.disable_scan_keymap)
+                    .with_capture_shell_output(self.capture_shell_output)
+                    .with_key_delays(self.key_delays),
+            ) as Box<dyn Controller>,
+            #[cfg(not(target_os = "linux"))]
+            OutputDispatchType::X11 => panic!("X11 output is only supported on Linux"),
+            #[cfg(windows)]
+            OutputDispatchType::Windows => Box::new(
+                WindowsController::new(self.disable_scan_keymap)
+                    .with_capture_shell_output(self.capture_shell_output)
+                    .with_key_delays(self.key_delays),
+            ) as Box<dyn Controller>,
+            #[cfg(not(windows))]
+            OutputDispatchType::Windows => panic!("Windows output is only supported on Windows"),
             OutputDispatchType::Stdout => {
                 Box::new(StdoutController::new(self.disable_scan_keymap)) as Box<dyn Controller>
             }
@@ -93,9 +286,8 @@ impl Config {
 
     /// Read dictionary files with the path from the config given the base path to them
     pub fn get_dicts(&self, base_path: &Path) -> Vec<String> {
-        self.dicts
-            .iter()
-            .map(|p| base_path.join(&p))
+        self.get_dict_paths(base_path)
+            .into_iter()
             .map(|p| {
                 println!("[INFO] Loading {:?}", p);
                 match std::fs::read_to_string(&p) {
@@ -106,6 +298,47 @@ impl Config {
             .collect()
     }
 
+    /// Read dictionary files like `get_dicts`, but returns an error instead of panicking
+    pub fn try_get_dicts(&self, base_path: &Path) -> Result<Vec<String>, String> {
+        self.try_get_dict_paths(base_path)?
+            .into_iter()
+            .map(|p| {
+                std::fs::read_to_string(&p)
+                    .map_err(|e| format!("unable to read dictionary file {:?}: {}", p, e))
+            })
+            .collect()
+    }
+
+    /// Resolve the dictionary paths from the config given the base path to them. Entries can be
+    /// glob patterns (ex: `dicts/*.json`), which are expanded in sorted order. Panics if a
+    /// pattern is invalid or matches no files
+    pub fn get_dict_paths(&self, base_path: &Path) -> Vec<PathBuf> {
+        match self.try_get_dict_paths(base_path) {
+            Ok(paths) => paths,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    fn try_get_dict_paths(&self, base_path: &Path) -> Result<Vec<PathBuf>, String> {
+        let mut paths = vec![];
+        for dict in &self.dicts {
+            let path = base_path.join(dict);
+            let pattern = path.to_string_lossy();
+            let mut matches = match glob::glob(&pattern) {
+                Ok(entries) => entries
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|e| format!("unable to read dictionary path: {:?}", e))?,
+                Err(e) => return Err(format!("invalid dictionary path {:?}: {}", dict, e)),
+            };
+            if matches.is_empty() {
+                return Err(format!("no dictionary files match {:?}", path));
+            }
+            matches.sort();
+            paths.extend(matches);
+        }
+        Ok(paths)
+    }
+
     /// Get the strokes for retrospective add space
     pub fn get_retro_add_space(&self) -> Vec<Stroke> {
         self.retrospective_add_space_strokes
@@ -119,6 +352,44 @@ impl Config {
         self.space_stroke.as_ref().map(|s| Stroke::new(s))
     }
 
+    /// Get the command macros as their stroke sequences and commands. Panics if the commands
+    /// are not valid
+    pub fn get_macros(&self) -> Vec<(Vec<Stroke>, Vec<Command>)> {
+        self.macros
+            .iter()
+            .map(|m| {
+                let strokes = m.strokes.iter().map(|s| Stroke::new(s)).collect();
+                let cmds = match serde_json::from_str(&m.cmds) {
+                    Ok(cmds) => cmds,
+                    Err(e) => panic!("invalid commands for macro {:?}: {}", m.strokes, e),
+                };
+                (strokes, cmds)
+            })
+            .collect()
+    }
+
+    /// Get the text that unknown strokes are translated to instead of the raw stroke
+    pub fn get_fallback(&self) -> HashMap<Stroke, String> {
+        self.fallback
+            .iter()
+            .map(|(stroke, text)| (Stroke::new(stroke), text.clone()))
+            .collect()
+    }
+
+    /// Read the custom orthography rules file (relative to the config folder) if there is one.
+    /// Panics if the file can't be read
+    pub fn get_orthography_rules(&self, base_path: &Path) -> Option<String> {
+        let path = base_path.join(self.orthography_rules.as_ref()?);
+        Some(read_orthography_file(&path, "rules"))
+    }
+
+    /// Read the orthography exceptions file (relative to the config folder) if there is one.
+    /// Panics if the file can't be read
+    pub fn get_orthography_exceptions(&self, base_path: &Path) -> Option<String> {
+        let path = base_path.join(self.orthography_exceptions.as_ref()?);
+        Some(read_orthography_file(&path, "exceptions"))
+    }
+
     /// Get the strokes for disabling input (mainly for keyboard input)
     pub fn get_disable_input_strokes(&self) -> HashSet<Stroke> {
         self.disable_input_strokes
@@ -126,17 +397,98 @@ impl Config {
             .map(|s| Stroke::new(s))
             .collect::<HashSet<_>>()
     }
+
+    /// Strokes that enable the input machines again after they were disabled (ex: a stroke from a
+    /// steno machine that enables the keyboard machine read along with it)
+    pub fn get_enable_input_strokes(&self) -> HashSet<Stroke> {
+        self.enable_input_strokes
+            .iter()
+            .map(|s| Stroke::new(s))
+            .collect::<HashSet<_>>()
+    }
+
+    /// How often a navigation key is repeated while its stroke is held down
+    pub fn get_hold_repeat_interval(&self) -> time::Duration {
+        time::Duration::from_millis(
+            self.hold_repeat_interval_ms
+                .unwrap_or(DEFAULT_HOLD_REPEAT_INTERVAL_MS),
+        )
+    }
+
+    /// The stroke that removes only the last stroke (to stroke it again), if there is one
+    pub fn get_correction_stroke(&self) -> Option<Stroke> {
+        self.correction_stroke.as_ref().map(|s| Stroke::new(s))
+    }
 }
 
+// about the rate that the system repeats a key that is held down
+const DEFAULT_HOLD_REPEAT_INTERVAL_MS: u64 = 33;
+
 pub fn load(raw_str: &str) -> Result<Config, toml::de::Error> {
     toml::from_str::<Config>(raw_str)
 }
 
-#[derive(Debug, Deserialize)]
+/// Create a machine of this type, waiting until it can be created if it is on a serial port (which
+/// can show up later). Panics if any other machine can't be created
+fn retry_connect<T>(
+    machine_type: &InputMachineType,
+    mut connect: impl FnMut() -> Result<T, String>,
+) -> T {
+    let can_retry = matches!(
+        machine_type,
+        InputMachineType::Geminipr { .. } | InputMachineType::TxBolt { .. }
+    );
+    let mut issued_warning = false;
+    loop {
+        match connect() {
+            Ok(machine) => return machine,
+            Err(e) if !can_retry => panic!("{}", e),
+            Err(_) => {
+                if !issued_warning {
+                    println!(
+                        "[WARN] Machine not found on serial port. Will try again every 5 seconds"
+                    );
+                    issued_warning = true;
+                }
+                // try to connect to machine again after a delay
+                thread::sleep(time::Duration::from_secs(5));
+            }
+        }
+    }
+}
+
+/// A sequence of strokes that dispatches commands. The commands are in the same JSON format as
+/// the `cmds` of a dictionary entry
+#[derive(Debug, Clone, Deserialize)]
+struct MacroConfig {
+    strokes: Vec<String>,
+    cmds: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
 enum InputMachineType {
     Stdin,
+    StdinJson,
     Keyboard,
-    Geminipr { port: String },
+    Geminipr {
+        port: String,
+        #[serde(default)]
+        baud_rate: Option<u32>,
+    },
+    TxBolt {
+        port: String,
+        #[serde(default)]
+        baud_rate: Option<u32>,
+    },
+}
+
+/// A serial machine on the port, with the baud rate if it is set
+fn serial_machine(port: &str, baud_rate: Option<u32>) -> SerialMachine {
+    let machine = SerialMachine::new(port);
+    match baud_rate {
+        Some(baud_rate) => machine.with_baud_rate(baud_rate),
+        None => machine,
+    }
 }
 
 impl Default for InputMachineType {
@@ -145,10 +497,12 @@ impl Default for InputMachineType {
     }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 enum OutputDispatchType {
     MacNative,
     Enigo,
+    X11,
+    Windows,
     Stdout,
 }
 
@@ -158,6 +512,14 @@ impl Default for OutputDispatchType {
     }
 }
 
+fn read_orthography_file(path: &Path, kind: &str) -> String {
+    println!("[INFO] Loading orthography {} {:?}", kind, path);
+    match std::fs::read_to_string(path) {
+        Ok(s) => s,
+        Err(e) => panic!("unable to read orthography {} {:?}: {:?}", kind, path, e),
+    }
+}
+
 struct StdoutController {}
 impl Controller for StdoutController {
     fn new(_disable_scan_keymap: bool) -> Self {
@@ -167,3 +529,303 @@ impl Controller for StdoutController {
         println!("{:?}", command);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn keyboard_stroke_time() {
+        let config = load("").unwrap();
+        let formed = time::Instant::now();
+        *config.keyboard_stroke_time.lock().unwrap() = Some((Stroke::new("H-L"), formed));
+        thread::sleep(time::Duration::from_millis(10));
+
+        // only the stroke that the keyboard formed was formed earlier
+        assert!(config.stroke_time(&Stroke::new("WORLD")) > formed);
+        *config.keyboard_stroke_time.lock().unwrap() = Some((Stroke::new("H-L"), formed));
+        assert_eq!(config.stroke_time(&Stroke::new("H-L")), formed);
+        // the time is only used once
+        assert!(config.stroke_time(&Stroke::new("H-L")) > formed);
+    }
+
+    #[test]
+    fn dict_glob_expands_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("dicts")).unwrap();
+        for name in &["b.json", "a.json", "c.json", "notes.txt"] {
+            fs::write(dir.path().join("dicts").join(name), "{}").unwrap();
+        }
+        fs::write(dir.path().join("main.json"), "{}").unwrap();
+
+        let config = load(
+            r#"
+            dicts = ["main.json", "dicts/*.json"]
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.get_dict_paths(dir.path()),
+            vec![
+                dir.path().join("main.json"),
+                dir.path().join("dicts").join("a.json"),
+                dir.path().join("dicts").join("b.json"),
+                dir.path().join("dicts").join("c.json"),
+            ]
+        );
+    }
+
+    #[test]
+    fn load_macros() {
+        let config = load(
+            r#"
+            [[macros]]
+            strokes = ["STPH-G", "STPH-P"]
+            cmds = '[{ "Keys": [{ "Layout": "s" }, ["Meta"]] }]'
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.get_macros(),
+            vec![(
+                vec![Stroke::new("STPH-G"), Stroke::new("STPH-P")],
+                vec![Command::Keys(
+                    plojo_core::Key::Layout('s'),
+                    vec![plojo_core::Modifier::Meta]
+                )]
+            )]
+        );
+    }
+
+    #[test]
+    fn load_fallback() {
+        let config = load(
+            r#"
+            [fallback]
+            "TPH" = "n"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.get_fallback(),
+            vec![(Stroke::new("TPH"), "n".to_string())]
+                .into_iter()
+                .collect()
+        );
+    }
+
+    #[test]
+    fn load_orthography_rules() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("orthography.json"), "[]").unwrap();
+
+        fs::write(dir.path().join("exceptions.json"), "{}").unwrap();
+
+        let config = load("").unwrap();
+        assert_eq!(config.get_orthography_rules(dir.path()), None);
+        assert_eq!(config.get_orthography_exceptions(dir.path()), None);
+        let config = load(
+            r#"
+            orthography_rules = "orthography.json"
+            orthography_exceptions = "exceptions.json"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.get_orthography_rules(dir.path()),
+            Some("[]".to_string())
+        );
+        assert_eq!(
+            config.get_orthography_exceptions(dir.path()),
+            Some("{}".to_string())
+        );
+    }
+
+    #[test]
+    fn load_key_delays() {
+        let config = load(
+            r#"
+            [key_delays]
+            type_text = 1
+            backspace = 0
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.key_delays,
+            KeyDelays {
+                type_text: 1,
+                backspace: 0,
+                ..KeyDelays::default()
+            }
+        );
+
+        // the default delays are used if they are not set
+        assert_eq!(load("").unwrap().key_delays, KeyDelays::default());
+    }
+
+    #[test]
+    fn load_serial_baud_rate() {
+        let config = load(
+            r#"
+            [input_machine.Geminipr]
+            port = "/dev/ttyACM0"
+            baud_rate = 115200
+            "#,
+        )
+        .unwrap();
+        assert!(matches!(
+            config.input_machine,
+            InputMachineType::Geminipr {
+                baud_rate: Some(115200),
+                ..
+            }
+        ));
+
+        let config = load(
+            r#"
+            [input_machine.TxBolt]
+            port = "/dev/ttyACM0"
+            "#,
+        )
+        .unwrap();
+        assert!(matches!(
+            config.input_machine,
+            InputMachineType::TxBolt {
+                baud_rate: None,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn load_stdin_json() {
+        let config = load(r#"input_machine = "StdinJson""#).unwrap();
+        assert!(matches!(config.input_machine, InputMachineType::StdinJson));
+    }
+
+    #[test]
+    fn load_extra_input_machines() {
+        let config = load("").unwrap();
+        assert!(config.extra_input_machines.is_empty());
+
+        let config = load(
+            r#"
+            extra_input_machines = [ "Keyboard", { TxBolt = { port = "/dev/ttyACM1" } } ]
+            "#,
+        )
+        .unwrap();
+        assert!(matches!(
+            config.extra_input_machines.as_slice(),
+            [
+                InputMachineType::Keyboard,
+                InputMachineType::TxBolt {
+                    baud_rate: None,
+                    ..
+                }
+            ]
+        ));
+    }
+
+    #[test]
+    fn load_clipboard_paste() {
+        let config = load("use_clipboard_paste = true").unwrap();
+        assert!(config.use_clipboard_paste);
+        assert_eq!(config.clipboard_paste_threshold, None);
+
+        let config = load("clipboard_paste_threshold = 50").unwrap();
+        assert!(!config.use_clipboard_paste);
+        assert_eq!(config.clipboard_paste_threshold, Some(50));
+    }
+
+    #[test]
+    fn load_chord_resolution() {
+        let config = load("").unwrap();
+        assert_eq!(config.chord_resolution, ChordResolution::AllUp);
+
+        let config = load(r#"chord_resolution = "all_up""#).unwrap();
+        assert_eq!(config.chord_resolution, ChordResolution::AllUp);
+        let config = load(r#"chord_resolution = "first_up""#).unwrap();
+        assert_eq!(config.chord_resolution, ChordResolution::FirstUp);
+        let config = load(r#"chord_resolution = "arpeggiate""#).unwrap();
+        assert_eq!(config.chord_resolution, ChordResolution::Arpeggiate);
+
+        assert!(load(r#"chord_resolution = "last_up""#).is_err());
+
+        let config = load(
+            r#"
+            chord_resolution = "arpeggiate"
+            arpeggiate_trigger = "Return"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.arpeggiate_trigger, Some("Return".to_string()));
+    }
+
+    #[test]
+    fn load_hold_repeat() {
+        let config = load("").unwrap();
+        assert_eq!(config.keyboard_hold_repeat_ms, None);
+        assert_eq!(
+            config.get_hold_repeat_interval(),
+            time::Duration::from_millis(33)
+        );
+
+        let config = load(
+            r#"
+            keyboard_hold_repeat_ms = 500
+            hold_repeat_interval_ms = 50
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.keyboard_hold_repeat_ms, Some(500));
+        assert_eq!(
+            config.get_hold_repeat_interval(),
+            time::Duration::from_millis(50)
+        );
+    }
+
+    #[test]
+    fn load_idle_timeout() {
+        let config = load("").unwrap();
+        assert_eq!(config.keyboard_idle_timeout_ms, None);
+
+        let config = load("keyboard_idle_timeout_ms = 3000").unwrap();
+        assert_eq!(config.keyboard_idle_timeout_ms, Some(3000));
+    }
+
+    #[test]
+    fn load_keyboard_layout() {
+        let config = load(
+            r#"
+            [keyboard_layout]
+            left_keys = [["KeyA", "S"], ["KeyS", "T"]]
+            center_left_keys = [["KeyV", "A"]]
+            star_keys = ["KeyG"]
+            center_right_keys = [["KeyN", "E"]]
+            right_keys = [["KeyJ", "F"]]
+            num_keys = []
+            "#,
+        )
+        .unwrap();
+
+        let layout = config.keyboard_layout.unwrap();
+        assert_eq!(
+            layout.left_keys,
+            vec![("KeyA".to_string(), 'S'), ("KeyS".to_string(), 'T')]
+        );
+        assert_eq!(layout.star_keys, vec!["KeyG".to_string()]);
+    }
+
+    #[test]
+    #[should_panic(expected = "no dictionary files match")]
+    fn dict_glob_with_no_matches() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = load(r#"dicts = ["dicts/*.json"]"#).unwrap();
+        config.get_dict_paths(dir.path());
+    }
+}
@@ -0,0 +1,203 @@
+//! Health checks for diagnosing a misconfigured setup (`plojo --doctor`)
+use crate::config::{self, Config};
+use plojo_core::Controller;
+use plojo_translator::StandardTranslator;
+use std::{
+    io,
+    panic::{self, AssertUnwindSafe},
+    path::Path,
+};
+
+/// The outcome of a single health check
+#[derive(Debug, PartialEq)]
+pub enum Check {
+    Pass(String),
+    Fail { reason: String, hint: String },
+}
+
+impl Check {
+    fn fail(reason: impl Into<String>, hint: impl Into<String>) -> Self {
+        Self::Fail {
+            reason: reason.into(),
+            hint: hint.into(),
+        }
+    }
+
+    pub fn passed(&self) -> bool {
+        matches!(self, Self::Pass(_))
+    }
+}
+
+/// Check that the config file was read and parses
+pub fn check_config(raw_config: io::Result<String>) -> (Check, Option<Config>) {
+    let raw_config = match raw_config {
+        Ok(s) => s,
+        Err(e) => return (
+            Check::fail(
+                format!("unable to read config.toml: {}", e),
+                "create config.toml in the config folder (see runtime_files/default_config.toml)",
+            ),
+            None,
+        ),
+    };
+    match config::load(&raw_config) {
+        Ok(config) => (Check::Pass("config.toml parsed".to_string()), Some(config)),
+        Err(e) => (
+            Check::fail(
+                format!("invalid config.toml: {}", e),
+                "compare config.toml against runtime_files/default_config.toml",
+            ),
+            None,
+        ),
+    }
+}
+
+/// Check that the dictionaries were read and can be loaded by the translator
+pub fn check_dicts(raw_dicts: Result<Vec<String>, String>) -> Check {
+    let raw_dicts = match raw_dicts {
+        Ok(d) => d,
+        Err(e) => {
+            return Check::fail(
+                e,
+                "check that the `dicts` entries in config.toml exist in the dicts folder",
+            )
+        }
+    };
+    let count = raw_dicts.len();
+    match StandardTranslator::new(raw_dicts, vec![], vec![], None, false) {
+        Ok(_) => Check::Pass(format!("loaded {} dictionaries", count)),
+        Err(e) => Check::fail(
+            format!("unable to parse dictionaries: {}", e),
+            "plover dictionaries can be converted with the plover_convert tool",
+        ),
+    }
+}
+
+/// Check that the input machine could be initialized
+pub fn check_machine<T>(machine: Result<T, String>, hint: &str) -> Check {
+    match machine {
+        Ok(_) => Check::Pass("input machine initialized".to_string()),
+        Err(e) => Check::fail(e, hint),
+    }
+}
+
+/// Check that the output controller could be created. Creating it connects to the system it
+/// outputs to (ex: the X display) and panics if that fails
+pub fn check_controller(create: impl FnOnce() -> Box<dyn Controller>) -> Check {
+    match panic::catch_unwind(AssertUnwindSafe(create)) {
+        Ok(_) => Check::Pass("output controller initialized".to_string()),
+        Err(_) => Check::fail(
+            "output controller panicked while starting",
+            "check that the output_dispatcher in config.toml is supported on this system (for \
+             X11, that the DISPLAY environment variable is set)",
+        ),
+    }
+}
+
+/// Run all the health checks and print a report. Returns if all of them passed
+pub fn run(config_base: &Path, use_stdin: bool, use_stdout: bool) -> bool {
+    println!("[INFO] Running health checks");
+
+    let (config_check, config) =
+        check_config(std::fs::read_to_string(config_base.join("config.toml")));
+    let mut checks = vec![("config", config_check)];
+    if let Some(config) = config {
+        checks.push((
+            "dictionaries",
+            check_dicts(config.try_get_dicts(&config_base.join("dicts"))),
+        ));
+        checks.push((
+            "input machine",
+            check_machine(
+                config.try_input_machine(use_stdin),
+                config.input_machine_hint(use_stdin),
+            ),
+        ));
+        checks.push((
+            "output controller",
+            check_controller(|| config.get_output_controller(use_stdout)),
+        ));
+    }
+
+    println!();
+    for (name, check) in &checks {
+        match check {
+            Check::Pass(detail) => println!("[PASS] {}: {}", name, detail),
+            Check::Fail { reason, hint } => {
+                println!("[FAIL] {}: {}", name, reason);
+                println!("       hint: {}", hint);
+            }
+        }
+    }
+    println!();
+
+    let all_passed = checks.iter().all(|(_, check)| check.passed());
+    if all_passed {
+        println!("[INFO] All checks passed");
+    } else {
+        println!("[WARN] Some checks failed");
+    }
+    all_passed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use plojo_core::Command;
+
+    #[test]
+    fn config_missing() {
+        let (check, config) = check_config(Err(io::Error::from(io::ErrorKind::NotFound)));
+        assert!(!check.passed());
+        assert!(config.is_none());
+    }
+
+    #[test]
+    fn config_invalid() {
+        let (check, config) = check_config(Ok(r#"input_machine = "Typewriter""#.to_string()));
+        assert!(!check.passed());
+        assert!(config.is_none());
+    }
+
+    #[test]
+    fn config_valid() {
+        let (check, config) = check_config(Ok(r#"dicts = ["dict.json"]"#.to_string()));
+        assert!(check.passed());
+        assert!(config.is_some());
+    }
+
+    #[test]
+    fn dicts_load() {
+        let check = check_dicts(Ok(vec![r#"{"H-L": "hello"}"#.to_string()]));
+        assert_eq!(check, Check::Pass("loaded 1 dictionaries".to_string()));
+    }
+
+    #[test]
+    fn dicts_missing_or_invalid() {
+        assert!(!check_dicts(Err("no dictionary files match".to_string())).passed());
+        assert!(!check_dicts(Ok(vec!["not json".to_string()])).passed());
+    }
+
+    #[test]
+    fn machine_init() {
+        assert!(check_machine(Ok(()), "hint").passed());
+        assert_eq!(
+            check_machine::<()>(Err("unable to open serial port".to_string()), "hint"),
+            Check::fail("unable to open serial port", "hint")
+        );
+    }
+
+    struct MockController;
+    impl Controller for MockController {
+        fn new(_disable_scan_keymap: bool) -> Self {
+            Self
+        }
+        fn dispatch(&mut self, _command: Command) {}
+    }
+
+    #[test]
+    fn controller_init() {
+        assert!(check_controller(|| Box::new(MockController::new(false))).passed());
+        assert!(!check_controller(|| panic!("unable to open X display")).passed());
+    }
+}
@@ -0,0 +1,54 @@
+//! Saving the stroke buffer between sessions so that strokes from before a restart can be undone
+use plojo_core::Stroke;
+use std::{fs, io, path::Path};
+
+/// Read the strokes saved by the last session. Starts with no strokes if there are none or they
+/// can't be read
+pub fn load(path: &Path) -> Vec<Stroke> {
+    let raw = match fs::read_to_string(path) {
+        Ok(raw) => raw,
+        Err(_) => return vec![],
+    };
+    match serde_json::from_str(&raw) {
+        Ok(strokes) => strokes,
+        Err(e) => {
+            println!("[WARN] Ignoring invalid stroke history {:?}: {}", path, e);
+            vec![]
+        }
+    }
+}
+
+/// Save the strokes for the next session as a JSON list. This is done after every stroke so that
+/// the strokes are still saved if plojo is killed instead of exiting on its own
+pub fn save(path: &Path, strokes: &[Stroke]) -> io::Result<()> {
+    let raw = serde_json::to_string(strokes).expect("strokes can always be serialized");
+    fs::write(path, raw)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("stroke_history.json");
+        let strokes = vec![Stroke::new("H-L"), Stroke::new("WORLD"), Stroke::new("-F")];
+        save(&path, &strokes).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            r#"["H-L","WORLD","-F"]"#
+        );
+        assert_eq!(load(&path), strokes);
+    }
+
+    #[test]
+    fn missing_or_invalid_history() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("stroke_history.json");
+        assert_eq!(load(&path), vec![]);
+
+        fs::write(&path, "not json").unwrap();
+        assert_eq!(load(&path), vec![]);
+    }
+}
@@ -1,10 +1,16 @@
 use clap::{App, Arg, ArgMatches};
-use plojo_core::{Command, Translator};
+use metrics::Metrics;
+use plojo_core::{hold_repeat_command, Command, MachineError, Translator};
 use plojo_input_geminipr as geminipr;
 use plojo_translator::StandardTranslator;
-use std::{fs, io, path::Path};
+use status::{Status, StatusLine};
+use std::{fs, path::Path, time::Instant};
 
 mod config;
+mod doctor;
+mod history;
+mod metrics;
+mod status;
 
 pub fn main() {
     let matches = get_arg_matches();
@@ -23,24 +29,92 @@ pub fn main() {
         || Path::new(&dirs::home_dir().unwrap()).join(".plojo"),
         |p: &str| Path::new(p).to_path_buf(),
     );
+
+    if matches.is_present("doctor") {
+        // only run the health checks and exit
+        let passed = doctor::run(
+            &config_base,
+            matches.is_present("stdin"),
+            matches.is_present("stdout"),
+        );
+        println!("[INFO] Exiting.");
+        if !passed {
+            std::process::exit(1);
+        }
+        return;
+    }
+
     let raw_config = fs::read_to_string(config_base.join("config.toml"))
         .expect("unable to read config.toml file");
     let config = config::load(&raw_config).expect("Invalid config format");
 
+    if matches.is_present("lint") {
+        // only check the dictionaries and exit
+        println!("[INFO] Only checking dictionaries for entries that can never be translated");
+        let raw_dicts = config.get_dicts(&config_base.join("dicts"));
+        let unreachable = plojo_translator::find_unreachable_entries(raw_dicts)
+            .expect("unable to load dictionaries");
+        for (stroke, reason) in &unreachable {
+            println!("[WARN] {:?} can never be translated: {:?}", stroke, reason);
+        }
+        println!("[INFO] Found {} unreachable entries", unreachable.len());
+        println!("[INFO] Exiting.");
+        return;
+    }
+
     println!("[INFO] Starting plojo...");
 
     /* Load dictionaries */
     println!("[INFO] Loading dictionaries...");
     let raw_dicts = config.get_dicts(&config_base.join("dicts"));
+    let dict_paths = config.get_dict_paths(&config_base.join("dicts"));
+    let history_path = config_base.join("stroke_history.json");
+    let prev_strokes = if config.save_stroke_history {
+        history::load(&history_path)
+    } else {
+        vec![]
+    };
     let mut translator = StandardTranslator::new(
         raw_dicts,
-        vec![],
+        prev_strokes,
         config.get_retro_add_space(),
         config.get_space_stroke(),
         config.space_after,
     )
-    .expect("unable to create translator");
+    .expect("unable to create translator")
+    .with_capitalize_first_word(config.capitalize_first_word)
+    .with_capitalize_after_punctuation(config.capitalize_after_punctuation)
+    .with_undo_policy(config.undo_policy)
+    .with_units(config.units.clone())
+    .with_macros(config.get_macros())
+    .with_fallback(config.get_fallback())
+    .with_dict_paths(dict_paths.clone());
+    if let Some(number_words) = &config.number_words {
+        translator = translator.with_number_words(number_words.clone());
+    }
+    if let Some(max_stroke_buffer) = config.max_stroke_buffer {
+        translator = translator.with_max_stroke_buffer(max_stroke_buffer);
+    }
+    if let Some(separator) = &config.separator {
+        translator = translator.with_separator(separator.clone());
+    }
+    if let Some(rules) = config.get_orthography_rules(&config_base) {
+        translator = translator
+            .with_orthography_rules(&rules)
+            .expect("invalid orthography rules");
+    }
+    if let Some(exceptions) = config.get_orthography_exceptions(&config_base) {
+        translator = translator
+            .with_orthography_exceptions(&exceptions)
+            .expect("invalid orthography exceptions");
+    }
     println!("[INFO] Loaded dictionaries");
+    for (path, stats) in dict_paths.iter().zip(translator.dict_stats()) {
+        println!(
+            "[INFO]   {:?}: {} entries ({} overriding earlier dictionaries)",
+            path, stats.entries, stats.overridden
+        );
+    }
 
     /* Load machine */
     let mut machine = config.get_input_machine(matches.is_present("stdin"));
@@ -49,53 +123,117 @@ pub fn main() {
     let mut controller = config.get_output_controller(matches.is_present("stdout"));
 
     let disable_input_strokes = config.get_disable_input_strokes();
+    let enable_input_strokes = config.get_enable_input_strokes();
+    let correction_stroke = config.get_correction_stroke();
+    let hold_repeat_interval = config.get_hold_repeat_interval();
 
     println!("[INFO] Ready.");
+    let mut status_line = StatusLine::new(matches.is_present("tui"));
+    let mut metrics = Metrics::new(Instant::now());
 
     loop {
         // wait for the next stroke
         let stroke = match machine.read() {
             Ok(s) => s,
-            Err(e) => {
-                // exit if it is a broken pipe (likely the machine disconnected)
-                if let Some(e) = e.downcast_ref::<io::Error>() {
-                    if e.kind() == io::ErrorKind::BrokenPipe {
-                        println!("[WARN] Machine disconnected");
-                        machine = config.get_input_machine(matches.is_present("stdin"));
-                        println!("[INFO] Machine reconnected");
-                        continue;
-                    }
-                }
-                panic!("unable to read stroke: {}", e);
+            // only a single machine is disconnected here, since several machines read at once
+            // reconnect on their own
+            Err(MachineError::Disconnected) => {
+                status_line.log("[WARN] Machine disconnected");
+                machine = config.get_input_machine(matches.is_present("stdin"));
+                status_line.log("[INFO] Machine reconnected");
+                continue;
+            }
+            Err(MachineError::EndOfInput) => {
+                status_line.clear();
+                println!("[INFO] No more input. Exiting.");
+                return;
+            }
+            Err(MachineError::Decode(e)) => {
+                status_line.log(&format!("[WARN] Unable to decode stroke: {}", e));
+                continue;
+            }
+            Err(e @ MachineError::Io(_)) => {
+                status_line.clear();
+                panic!("unable to read stroke: {}", e)
             }
         };
+        for command in machine.take_translator_commands() {
+            status_line.log(&format!("[INFO] Machine command: {:?}", command));
+            translator.handle_command(command);
+        }
 
         let mut log = String::new();
         log.push_str(&format!("{} ", get_time()));
-        log.push_str(&format!("{:?} => ", stroke));
+        log.push_str(&format!("{} => ", stroke));
+        let stroke_name = stroke.to_string();
+        let stroke_time = config.stroke_time(&stroke);
+        let is_undo = stroke.is_undo();
 
         // translating the stroke
         let commands = if disable_input_strokes.contains(&stroke) {
             machine.disable();
             Vec::new()
-        } else if stroke.is_undo() {
+        } else if enable_input_strokes.contains(&stroke) {
+            machine.enable();
+            Vec::new()
+        } else if is_undo {
             translator.undo()
+        } else if correction_stroke.as_ref() == Some(&stroke) {
+            translator.correct_last_stroke()
         } else {
             translator.translate(stroke)
         };
         // logging the command
         log.push_str(&format!("{:?}", commands));
+        let repeat = hold_repeat_command(&commands).cloned();
+        let translated = commands.clone();
 
         // performing the command
         for command in commands {
             if let Command::TranslatorCommand(cmd) = command {
+                let was_passthrough = translator.is_passthrough();
                 translator.handle_command(cmd);
-            } else {
-                controller.dispatch(command);
+                if translator.is_passthrough() != was_passthrough {
+                    let state = if was_passthrough { "off" } else { "on" };
+                    status_line.log(&format!("[INFO] Turned passthrough {}", state));
+                }
+            } else if let Err(e) = controller.try_dispatch(command) {
+                status_line.error(&format!("[ERR] Could not dispatch command: {}", e));
             }
         }
 
-        println!("{}", log);
+        status_line.log(&log);
+        if status_line.is_enabled() {
+            status_line.update(Status {
+                stroke: stroke_name,
+                commands: translated,
+                undo_depth: translator.undo_depth(),
+                passthrough: translator.is_passthrough(),
+                space_after: translator.is_space_after(),
+            });
+        }
+        if config.save_stroke_history {
+            if let Err(e) = history::save(&history_path, &translator.dump_strokes()) {
+                status_line.error(&format!(
+                    "[WARN] Could not save stroke history {:?}: {}",
+                    history_path, e
+                ));
+            }
+        }
+        // only summarized every so often, so it doesn't clutter the log
+        if let Some(summary) = metrics.record(stroke_time, is_undo) {
+            status_line.log(&summary);
+        }
+
+        // repeat a navigation key for as long as its stroke is held down
+        if let Some(command) = repeat {
+            while machine.wait_held(hold_repeat_interval) {
+                if let Err(e) = controller.try_dispatch(command.clone()) {
+                    status_line.error(&format!("[ERR] Could not dispatch command: {}", e));
+                    break;
+                }
+            }
+        }
     }
 }
 
@@ -116,6 +254,16 @@ fn get_arg_matches() -> ArgMatches<'static> {
                 .long("ports")
                 .help("Only print the serial ports that are available"),
         )
+        .arg(
+            Arg::with_name("lint")
+                .long("lint")
+                .help("Only check the dictionaries for entries that can never be translated"),
+        )
+        .arg(
+            Arg::with_name("doctor")
+                .long("doctor")
+                .help("Only check that the config, dictionaries, machine, and output work"),
+        )
         .arg(
             Arg::with_name("config")
                 .short("c")
@@ -129,6 +277,11 @@ fn get_arg_matches() -> ArgMatches<'static> {
                 .short("i")
                 .help("Overrides the config to use strokes from stdin"),
         )
+        .arg(
+            Arg::with_name("tui")
+                .long("tui")
+                .help("Show the last stroke and the state of the translator in a status line"),
+        )
         .arg(
             Arg::with_name("stdout")
                 .short("o")
@@ -0,0 +1,154 @@
+//! Live typing metrics (how fast strokes are written and how many of them are undone), which are
+//! logged every so often
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+// the rates are computed from at most this many of the latest strokes
+const WINDOW_STROKES: usize = 100;
+// a summary is logged after this many strokes, or after this much time since the last summary
+const SUMMARY_STROKES: usize = 100;
+const SUMMARY_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// The rates of the strokes in the window
+#[derive(Debug, PartialEq)]
+pub struct Rates {
+    pub strokes_per_minute: f64,
+    pub undo_strokes: usize,
+    /// The fraction of the strokes that were undo strokes
+    pub undo_rate: f64,
+}
+
+pub struct Metrics {
+    // when each of the latest strokes was written and whether it was an undo stroke
+    strokes: VecDeque<(Instant, bool)>,
+    since_summary: usize,
+    last_summary: Instant,
+}
+
+impl Metrics {
+    pub fn new(now: Instant) -> Self {
+        Self {
+            strokes: VecDeque::with_capacity(WINDOW_STROKES),
+            since_summary: 0,
+            last_summary: now,
+        }
+    }
+
+    /// Record a stroke. Returns a summary of the rates if it is time to log it
+    pub fn record(&mut self, time: Instant, is_undo: bool) -> Option<String> {
+        if self.strokes.len() == WINDOW_STROKES {
+            self.strokes.pop_front();
+        }
+        self.strokes.push_back((time, is_undo));
+        self.since_summary += 1;
+
+        let is_due = self.since_summary >= SUMMARY_STROKES
+            || time.duration_since(self.last_summary) >= SUMMARY_INTERVAL;
+        if !is_due {
+            return None;
+        }
+        self.since_summary = 0;
+        self.last_summary = time;
+
+        let (times, undos): (Vec<_>, Vec<_>) = self.strokes.iter().cloned().unzip();
+        compute_rates(&times, &undos).map(|rates| {
+            format!(
+                "[INFO] {:.1} strokes per minute, {} undo strokes ({:.1}%) in the last {} strokes",
+                rates.strokes_per_minute,
+                rates.undo_strokes,
+                rates.undo_rate * 100.0,
+                times.len()
+            )
+        })
+    }
+}
+
+/// Compute the rates of strokes written at these times (in order), where `undos` is whether each
+/// of them is an undo stroke. The stroke rate is the number of gaps between the strokes per minute
+/// from the first to the last stroke. Returns None if there aren't enough strokes to tell
+pub fn compute_rates(times: &[Instant], undos: &[bool]) -> Option<Rates> {
+    let (first, last) = (times.first()?, times.last()?);
+    let elapsed = last.duration_since(*first).as_secs_f64();
+    if elapsed <= 0.0 {
+        return None;
+    }
+
+    let undo_strokes = undos.iter().filter(|is_undo| **is_undo).count();
+    Some(Rates {
+        strokes_per_minute: (times.len() - 1) as f64 * 60.0 / elapsed,
+        undo_strokes,
+        undo_rate: undo_strokes as f64 / undos.len() as f64,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rates() {
+        let start = Instant::now();
+        let times: Vec<_> = (0..5)
+            .map(|i| start + Duration::from_millis(i * 500))
+            .collect();
+        assert_eq!(
+            compute_rates(&times, &[false, false, true, false, false]),
+            Some(Rates {
+                strokes_per_minute: 120.0,
+                undo_strokes: 1,
+                undo_rate: 0.2,
+            })
+        );
+        assert_eq!(
+            compute_rates(&times[..2], &[true, true]),
+            Some(Rates {
+                strokes_per_minute: 120.0,
+                undo_strokes: 2,
+                undo_rate: 1.0,
+            })
+        );
+    }
+
+    #[test]
+    fn rates_without_enough_strokes() {
+        let start = Instant::now();
+        assert_eq!(compute_rates(&[], &[]), None);
+        assert_eq!(compute_rates(&[start], &[false]), None);
+        assert_eq!(compute_rates(&[start, start], &[false, true]), None);
+    }
+
+    #[test]
+    fn summary_every_few_strokes() {
+        let start = Instant::now();
+        let mut metrics = Metrics::new(start);
+        for i in 1..SUMMARY_STROKES {
+            let time = start + Duration::from_secs(i as u64);
+            assert_eq!(metrics.record(time, false), None);
+        }
+        let time = start + Duration::from_secs(SUMMARY_STROKES as u64);
+        assert_eq!(
+            metrics.record(time, true),
+            Some(
+                "[INFO] 60.0 strokes per minute, 1 undo strokes (1.0%) in the last 100 strokes"
+                    .to_string()
+            )
+        );
+        assert_eq!(metrics.record(time + Duration::from_secs(1), false), None);
+    }
+
+    #[test]
+    fn summary_after_interval() {
+        let start = Instant::now();
+        let mut metrics = Metrics::new(start);
+        assert_eq!(metrics.record(start, false), None);
+        assert_eq!(
+            metrics.record(start + SUMMARY_INTERVAL, true),
+            Some(
+                "[INFO] 0.2 strokes per minute, 1 undo strokes (50.0%) in the last 2 strokes"
+                    .to_string()
+            )
+        );
+    }
+}
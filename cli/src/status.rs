@@ -0,0 +1,197 @@
+//! A status line at the bottom of the terminal that shows what the last stroke did. The log is
+//! printed above it
+use crossterm::terminal;
+use plojo_core::Command;
+use std::io::{self, IsTerminal, Write};
+
+// moves the cursor to the start of the line and clears it
+const CLEAR_LINE: &str = "\r\x1b[2K";
+// width of the status line if the width of the terminal is unknown
+const DEFAULT_WIDTH: usize = 80;
+
+/// What is shown in the status line
+#[derive(Debug, Default, PartialEq)]
+pub struct Status {
+    pub stroke: String,
+    /// The commands that the stroke was translated to
+    pub commands: Vec<Command>,
+    pub undo_depth: usize,
+    pub passthrough: bool,
+    pub space_after: bool,
+}
+
+pub struct StatusLine {
+    // only drawn if stdout is a terminal
+    enabled: bool,
+    status: Option<Status>,
+}
+
+impl StatusLine {
+    /// Draws the status line if `tui` is set and stdout is a terminal. Otherwise the log is
+    /// printed like usual
+    pub fn new(tui: bool) -> Self {
+        let enabled = tui && io::stdout().is_terminal();
+        if tui && !enabled {
+            println!("[WARN] Not showing the status line because stdout is not a terminal");
+        }
+        Self {
+            enabled,
+            status: None,
+        }
+    }
+
+    /// Whether the status line is drawn. If not, updating it does nothing
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Print a line of the log above the status line
+    pub fn log(&self, line: &str) {
+        if self.enabled {
+            print!("{}", CLEAR_LINE);
+        }
+        println!("{}", line);
+        self.draw();
+    }
+
+    /// Print an error above the status line
+    pub fn error(&self, line: &str) {
+        if self.enabled {
+            print!("{}", CLEAR_LINE);
+            let _ = io::stdout().flush();
+        }
+        eprintln!("{}", line);
+        self.draw();
+    }
+
+    pub fn update(&mut self, status: Status) {
+        self.status = Some(status);
+        self.draw();
+    }
+
+    /// Remove the status line so that anything printed after it starts on an empty line
+    pub fn clear(&mut self) {
+        if self.enabled && self.status.take().is_some() {
+            print!("{}", CLEAR_LINE);
+            let _ = io::stdout().flush();
+        }
+    }
+
+    fn draw(&self) {
+        if let (true, Some(status)) = (self.enabled, &self.status) {
+            print!("{}{}", CLEAR_LINE, format_status(status, terminal_width()));
+            // the status line doesn't end with a new line, so it isn't flushed automatically
+            let _ = io::stdout().flush();
+        }
+    }
+}
+
+/// The current width of the terminal (it can be resized while translating)
+fn terminal_width() -> usize {
+    terminal::size()
+        .map(|(columns, _)| columns as usize)
+        .unwrap_or(DEFAULT_WIDTH)
+}
+
+/// Format the status to fit on one line that is at most `width` characters long
+pub fn format_status(status: &Status, width: usize) -> String {
+    let line = format!(
+        "{} => {} | undo: {} | {} | {}",
+        status.stroke,
+        describe_commands(&status.commands),
+        status.undo_depth,
+        if status.passthrough {
+            "passthrough"
+        } else {
+            "translating"
+        },
+        if status.space_after {
+            "space after"
+        } else {
+            "space before"
+        },
+    );
+
+    if line.chars().count() <= width {
+        line
+    } else {
+        let mut truncated: String = line.chars().take(width.saturating_sub(1)).collect();
+        truncated.push('…');
+        truncated
+    }
+}
+
+/// Describe the commands briefly (ex: the text that was typed instead of the whole command)
+fn describe_commands(commands: &[Command]) -> String {
+    let described: Vec<_> = commands
+        .iter()
+        .filter(|command| **command != Command::NoOp)
+        .map(|command| match command {
+            Command::Replace(0, text) => format!("{:?}", text),
+            Command::Replace(backspaces, text) => format!("-{} {:?}", backspaces, text),
+            command => format!("{:?}", command),
+        })
+        .collect();
+    if described.is_empty() {
+        "nothing".to_string()
+    } else {
+        described.join(", ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use plojo_core::{Key, Modifier};
+
+    #[test]
+    fn status_line() {
+        let status = Status {
+            stroke: "H-L".to_string(),
+            commands: vec![Command::Replace(0, " hello".to_string())],
+            undo_depth: 3,
+            passthrough: false,
+            space_after: false,
+        };
+        assert_eq!(
+            format_status(&status, 80),
+            r#"H-L => " hello" | undo: 3 | translating | space before"#
+        );
+
+        let status = Status {
+            stroke: "WORLD".to_string(),
+            commands: vec![
+                Command::Replace(4, "i\n".to_string()),
+                Command::Keys(Key::Layout('a'), vec![Modifier::Meta]),
+            ],
+            undo_depth: 0,
+            passthrough: true,
+            space_after: true,
+        };
+        assert_eq!(
+            format_status(&status, 100),
+            r#"WORLD => -4 "i\n", Keys(Layout('a'), [Meta]) | undo: 0 | passthrough | space after"#
+        );
+
+        let status = Status {
+            stroke: "*".to_string(),
+            commands: vec![Command::NoOp],
+            ..Status::default()
+        };
+        assert_eq!(
+            format_status(&status, 80),
+            "* => nothing | undo: 0 | translating | space before"
+        );
+    }
+
+    #[test]
+    fn truncated_status_line() {
+        let status = Status {
+            stroke: "H-L".to_string(),
+            commands: vec![Command::Replace(0, " hello".to_string())],
+            ..Status::default()
+        };
+        assert_eq!(format_status(&status, 20), r#"H-L => " hello" | u…"#);
+        assert_eq!(format_status(&status, 20).chars().count(), 20);
+    }
+}
@@ -1,4 +1,4 @@
-use std::{collections::HashMap, env, fs, path::Path};
+use std::{cmp, collections::HashMap, env, fs, path::Path};
 use toml::Value;
 
 mod load;
@@ -7,9 +7,20 @@ type Stroke = String;
 type Translation = String;
 type Dict = HashMap<Translation, Vec<Stroke>>;
 type DictName = String;
+/// A translation that matched the query with its strokes and dictionary. Lower scores are closer
+/// matches
+type PartialMatch<'a> = (usize, &'a Translation, &'a Vec<Stroke>, &'a DictName);
+
+/// How the query is compared to the translations
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum MatchMode {
+    Exact,
+    Contains,
+    Fuzzy,
+}
 
 fn main() {
-    let query = get_query();
+    let (mode, query) = get_query();
     // assume config file with list of dictionaries is at ~/.plojo/config.toml
     let config_base = Path::new(&dirs::home_dir().unwrap()).join(".plojo");
     let raw_config = fs::read_to_string(config_base.join("config.toml"))
@@ -32,6 +43,21 @@ fn main() {
 
     println!("Searching for: {}", query);
 
+    if mode != MatchMode::Exact {
+        let matches = if mode == MatchMode::Contains {
+            lookup_contains(&dicts, &query)
+        } else {
+            lookup_fuzzy(&dicts, &query)
+        };
+        if matches.is_empty() {
+            println!("Not found");
+        } else {
+            println!("{} translations found", matches.len());
+            println!("{}", format_partial_lookup(&matches));
+        }
+        return;
+    }
+
     let matches = lookup(&dicts, query);
     if matches.is_empty() {
         println!("Not found");
@@ -47,12 +73,16 @@ fn main() {
     }
 }
 
-fn get_query() -> String {
+/// The search string and how to match it. `--contains` finds translations with the search string
+/// in them and `--fuzzy` finds translations that are close to it
+fn get_query() -> (MatchMode, String) {
     let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        panic!("You must pass in a search string as the argument");
+    match args.as_slice() {
+        [_, query] => (MatchMode::Exact, query.to_string()),
+        [_, flag, query] if flag == "--contains" => (MatchMode::Contains, query.to_string()),
+        [_, flag, query] if flag == "--fuzzy" => (MatchMode::Fuzzy, query.to_string()),
+        _ => panic!("You must pass in a search string as the argument (optionally after --contains or --fuzzy)"),
     }
-    args[1].to_string()
 }
 
 /// Look up a given translation in the dictionaries.
@@ -69,6 +99,76 @@ fn lookup(dicts: &[(Dict, DictName)], translation: Translation) -> Vec<(&Vec<Str
     strokes
 }
 
+/// Find the translations that contain the query, ignoring case. Shorter translations (which
+/// have less text besides the query) come first
+fn lookup_contains<'a>(dicts: &'a [(Dict, DictName)], query: &str) -> Vec<PartialMatch<'a>> {
+    let query = query.to_lowercase();
+    lookup_scored(dicts, |translation| {
+        let translation = translation.to_lowercase();
+        if translation.contains(&query) {
+            Some(translation.chars().count() - query.chars().count())
+        } else {
+            None
+        }
+    })
+}
+
+/// Find the translations that are within a few edits (Levenshtein distance) of the query, ignoring
+/// case. Longer queries can have more edits. The closest translations come first
+fn lookup_fuzzy<'a>(dicts: &'a [(Dict, DictName)], query: &str) -> Vec<PartialMatch<'a>> {
+    let query = query.to_lowercase();
+    let threshold = fuzzy_threshold(&query);
+    lookup_scored(dicts, |translation| {
+        let distance = levenshtein(&translation.to_lowercase(), &query);
+        if distance <= threshold {
+            Some(distance)
+        } else {
+            None
+        }
+    })
+}
+
+/// Score every translation in the dictionaries, and keep the ones that have a score. Sorted by
+/// score, then by translation, then by the order of the dictionaries
+fn lookup_scored<'a>(
+    dicts: &'a [(Dict, DictName)],
+    score: impl Fn(&str) -> Option<usize>,
+) -> Vec<PartialMatch<'a>> {
+    let mut matches = vec![];
+    for (d, dict_name) in dicts {
+        for (translation, strokes) in d {
+            if let Some(s) = score(translation) {
+                matches.push((s, translation, strokes, dict_name));
+            }
+        }
+    }
+    // stable sort keeps the dictionaries in order
+    matches.sort_by_key(|(s, translation, _, _)| (*s, *translation));
+    matches
+}
+
+/// The most edits a fuzzy match can have: 1 for every 3 chars in the query (rounded up)
+fn fuzzy_threshold(query: &str) -> usize {
+    query.chars().count().div_ceil(3)
+}
+
+/// The number of chars that have to be inserted, deleted, or substituted to turn one string into
+/// the other
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    // distances from the start of `a` so far to each prefix of `b`
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitute = prev[j] + if ca == *cb { 0 } else { 1 };
+            current[j + 1] = cmp::min(substitute, cmp::min(prev[j + 1], current[j]) + 1);
+        }
+        prev = current;
+    }
+    prev[b.len()]
+}
+
 /// Format the matches as a string of the dictionary name and the matched strokes
 fn format_lookup(matches: &[(&Vec<Stroke>, &DictName)]) -> String {
     let mut all_str = String::new();
@@ -85,6 +185,23 @@ fn format_lookup(matches: &[(&Vec<Stroke>, &DictName)]) -> String {
     all_str
 }
 
+/// Format partial matches as a string of the matched translation, its dictionary name, and its
+/// strokes
+fn format_partial_lookup(matches: &[PartialMatch]) -> String {
+    let mut all_str = String::new();
+
+    for (_, translation, strokes, dict_name) in matches {
+        let mut s = format!("\nMatch: {}\nFile: {}\n", translation, dict_name);
+        for stroke in *strokes {
+            s.push_str(stroke);
+            s.push('\n');
+        }
+        all_str.push_str(&s);
+    }
+
+    all_str
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -182,6 +299,97 @@ H*EL
 
 File: secondary.json
 HEL/HRO
+"#
+        )
+    }
+
+    /// The translation and dictionary name of each match, in order
+    fn matched(matches: Vec<PartialMatch<'_>>) -> Vec<(usize, &str, &str)> {
+        matches
+            .into_iter()
+            .map(|(score, translation, _, dict_name)| {
+                (score, translation.as_str(), dict_name.as_str())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn lookup_substring() {
+        let dicts = testing_dict();
+        assert_eq!(
+            matched(lookup_contains(&dicts, "orl")),
+            vec![(2, "world", "default.json"), (2, "world", "secondary.json")]
+        );
+        // sorted by score, then translation, then dictionary
+        assert_eq!(
+            matched(lookup_contains(&dicts, "l")),
+            vec![
+                (4, "hello", "default.json"),
+                (4, "world", "default.json"),
+                (4, "world", "secondary.json")
+            ]
+        );
+        assert_eq!(
+            matched(lookup_contains(&dicts, "hello")),
+            vec![(0, "hello", "default.json")]
+        );
+        // partial matches ignore case
+        assert_eq!(
+            matched(lookup_contains(&dicts, "HEL")),
+            vec![(2, "hello", "default.json")]
+        );
+        assert_eq!(lookup_contains(&dicts, "hello world"), vec![]);
+
+        let matches = lookup_contains(&dicts, "ell");
+        assert_eq!(matches[0].2, &dicts[0].0["hello"]);
+    }
+
+    #[test]
+    fn lookup_fuzzy_match() {
+        let dicts = testing_dict();
+        assert_eq!(
+            matched(lookup_fuzzy(&dicts, "helo")),
+            vec![(1, "hello", "default.json")]
+        );
+        assert_eq!(
+            matched(lookup_fuzzy(&dicts, "Wrold")),
+            vec![(2, "world", "default.json"), (2, "world", "secondary.json")]
+        );
+        assert_eq!(
+            matched(lookup_fuzzy(&dicts, "hello")),
+            vec![(0, "hello", "default.json")]
+        );
+        // too many edits for a short query
+        assert_eq!(lookup_fuzzy(&dicts, "hxyo"), vec![]);
+        assert_eq!(lookup_fuzzy(&dicts, "wo"), vec![]);
+    }
+
+    #[test]
+    fn levenshtein_distance() {
+        assert_eq!(levenshtein("", ""), 0);
+        assert_eq!(levenshtein("hello", ""), 5);
+        assert_eq!(levenshtein("", "hello"), 5);
+        assert_eq!(levenshtein("hello", "hello"), 0);
+        assert_eq!(levenshtein("kitten", "sitting"), 3);
+        assert_eq!(levenshtein("world", "wrold"), 2);
+        assert_eq!(levenshtein("café", "cafe"), 1);
+        assert_eq!(fuzzy_threshold("helo"), 2);
+        assert_eq!(fuzzy_threshold("wo"), 1);
+        assert_eq!(fuzzy_threshold("hello world"), 4);
+    }
+
+    #[test]
+    fn format_partial() {
+        let dicts = testing_dict();
+        assert_eq!(
+            format_partial_lookup(&lookup_fuzzy(&dicts, "helo")),
+            r#"
+Match: hello
+File: default.json
+H-L
+H*EL
+HEL/HRO
+HO*EL
 "#
         )
     }
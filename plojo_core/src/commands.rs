@@ -9,10 +9,41 @@ pub enum Command {
     NoOp,
     /// Press a key with some modifier keys
     Keys(Key, Vec<Modifier>),
+    /// Press a key with some modifier keys a certain number of times
+    RepeatKey {
+        key: Key,
+        modifiers: Vec<Modifier>,
+        count: usize,
+    },
+    /// Hold down the modifier keys while pressing each of the keys in order, then release them.
+    /// Needed for the app switcher (ex: Meta + Tab + Tab), which is closed when the modifier is
+    /// released between the presses
+    KeySequence(Vec<Modifier>, Vec<Key>),
+    /// Press a key with some modifier keys without releasing them (ex: to hold a key in a game).
+    /// The keys stay held down across strokes until a `KeyUp` releases them
+    KeyDown(Key, Vec<Modifier>),
+    /// Release a key and its modifier keys that were held down by a `KeyDown`
+    KeyUp(Key, Vec<Modifier>),
+    /// Move the mouse by this many pixels (x to the right and y down) from where it is now
+    MouseMove(i32, i32),
+    /// Move the mouse to this position on the screen (in pixels from the top left corner)
+    MouseMoveTo(i32, i32),
+    /// Press and release a mouse button where the mouse is
+    MouseClick(MouseButton),
+    /// Scroll the mouse wheel this many lines (x to the right and y down)
+    MouseScroll(i32, i32),
+    /// Move the cursor. Each controller presses the right key combination for its platform
+    Nav(Nav),
     /// Send a raw keystroke with key code
     Raw(u16),
     /// Dispatch a shell command with arguments
     Shell(String, Vec<String>),
+    /// Run a shell command with arguments and type what it prints. The translator runs it and
+    /// types the output like any other text (so it can be undone), so controllers never get it
+    ShellInsert(String, Vec<String>),
+    /// Toggle slow mode in the controller, which lengthens the delays between key presses for
+    /// apps that drop fast keystrokes
+    ToggleSlowMode,
     /// Pass a command to the translator to be handled
     TranslatorCommand(String),
 }
@@ -64,6 +95,39 @@ pub enum Modifier {
     Fn,
 }
 
+/// Where to move the cursor to
+#[derive(Debug, Clone, Copy, PartialEq, Hash, Eq, Deserialize, Serialize)]
+pub enum Nav {
+    WordLeft,
+    WordRight,
+    LineStart,
+    LineEnd,
+    DocumentStart,
+    DocumentEnd,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Hash, Eq, Deserialize, Serialize)]
+pub enum MouseButton {
+    Left,
+    Right,
+    Middle,
+}
+
+/// A single step of dispatching a key sequence
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeyEvent {
+    /// Start holding down a modifier key
+    HoldModifier(Modifier),
+    /// Press and release a key while the held modifiers stay down
+    Tap(Key),
+    /// Release a modifier key that was held down
+    ReleaseModifier(Modifier),
+    /// Press a key without releasing it
+    Press(Key),
+    /// Release a key that was pressed
+    Release(Key),
+}
+
 impl Command {
     pub fn add_text(output: &str) -> Self {
         Self::replace_text(0, output)
@@ -71,4 +135,152 @@ impl Command {
     pub fn replace_text(backspace_num: usize, replace_str: &str) -> Self {
         Self::Replace(backspace_num, replace_str.to_owned())
     }
+
+    /// Whether this only moves the cursor, like pressing an arrow key. A stroke that does nothing
+    /// else can be repeated while it is held down
+    pub fn is_navigation(&self) -> bool {
+        match self {
+            Command::Keys(Key::Special(key), _) => matches!(
+                key,
+                SpecialKey::LeftArrow
+                    | SpecialKey::RightArrow
+                    | SpecialKey::UpArrow
+                    | SpecialKey::DownArrow
+                    | SpecialKey::Home
+                    | SpecialKey::End
+                    | SpecialKey::PageUp
+                    | SpecialKey::PageDown
+            ),
+            Command::Nav(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Convert a key sequence into the events for the controller to dispatch. The modifiers are
+    /// held down for the entire sequence and released in reverse order at the end
+    pub fn key_sequence_events(modifiers: &[Modifier], keys: &[Key]) -> Vec<KeyEvent> {
+        let mut events = Vec::with_capacity(modifiers.len() * 2 + keys.len());
+        events.extend(modifiers.iter().map(|m| KeyEvent::HoldModifier(*m)));
+        events.extend(keys.iter().map(|k| KeyEvent::Tap(k.clone())));
+        events.extend(
+            modifiers
+                .iter()
+                .rev()
+                .map(|m| KeyEvent::ReleaseModifier(*m)),
+        );
+        events
+    }
+
+    /// Convert holding down a key into the events for the controller to dispatch. The modifiers
+    /// are held down before the key and nothing is released
+    pub fn key_down_events(key: &Key, modifiers: &[Modifier]) -> Vec<KeyEvent> {
+        let mut events: Vec<KeyEvent> = modifiers
+            .iter()
+            .map(|m| KeyEvent::HoldModifier(*m))
+            .collect();
+        events.push(KeyEvent::Press(key.clone()));
+        events
+    }
+
+    /// Convert releasing a held key into the events for the controller to dispatch. The key is
+    /// released before the modifiers, which are released in reverse order
+    pub fn key_up_events(key: &Key, modifiers: &[Modifier]) -> Vec<KeyEvent> {
+        let mut events = vec![KeyEvent::Release(key.clone())];
+        events.extend(
+            modifiers
+                .iter()
+                .rev()
+                .map(|m| KeyEvent::ReleaseModifier(*m)),
+        );
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn key_sequence_holds_modifier() {
+        let tab = Key::Special(SpecialKey::Tab);
+        assert_eq!(
+            Command::key_sequence_events(&[Modifier::Meta], &[tab.clone(), tab.clone()]),
+            vec![
+                KeyEvent::HoldModifier(Modifier::Meta),
+                KeyEvent::Tap(tab.clone()),
+                KeyEvent::Tap(tab),
+                KeyEvent::ReleaseModifier(Modifier::Meta),
+            ]
+        );
+    }
+
+    #[test]
+    fn key_sequence_releases_in_reverse() {
+        assert_eq!(
+            Command::key_sequence_events(
+                &[Modifier::Meta, Modifier::Shift],
+                &[Key::Special(SpecialKey::Tab)]
+            ),
+            vec![
+                KeyEvent::HoldModifier(Modifier::Meta),
+                KeyEvent::HoldModifier(Modifier::Shift),
+                KeyEvent::Tap(Key::Special(SpecialKey::Tab)),
+                KeyEvent::ReleaseModifier(Modifier::Shift),
+                KeyEvent::ReleaseModifier(Modifier::Meta),
+            ]
+        );
+    }
+
+    #[test]
+    fn key_down_is_not_released() {
+        let w = Key::Layout('w');
+        assert_eq!(
+            Command::key_down_events(&w, &[Modifier::Shift]),
+            vec![
+                KeyEvent::HoldModifier(Modifier::Shift),
+                KeyEvent::Press(w.clone()),
+            ]
+        );
+        assert_eq!(Command::key_down_events(&w, &[]), vec![KeyEvent::Press(w)]);
+    }
+
+    #[test]
+    fn key_up_releases_in_reverse() {
+        let w = Key::Layout('w');
+        assert_eq!(
+            Command::key_up_events(&w, &[Modifier::Control, Modifier::Shift]),
+            vec![
+                KeyEvent::Release(w.clone()),
+                KeyEvent::ReleaseModifier(Modifier::Shift),
+                KeyEvent::ReleaseModifier(Modifier::Control),
+            ]
+        );
+        assert_eq!(Command::key_up_events(&w, &[]), vec![KeyEvent::Release(w)]);
+    }
+
+    #[test]
+    fn deserialize_mouse_commands() {
+        let parse = |json| serde_json::from_str::<Command>(json).unwrap();
+        assert_eq!(
+            parse(r#"{"MouseClick": "Left"}"#),
+            Command::MouseClick(MouseButton::Left)
+        );
+        assert_eq!(
+            parse(r#"{"MouseClick": "Middle"}"#),
+            Command::MouseClick(MouseButton::Middle)
+        );
+        assert_eq!(
+            parse(r#"{"MouseMove": [10, -5]}"#),
+            Command::MouseMove(10, -5)
+        );
+        assert_eq!(
+            parse(r#"{"MouseMoveTo": [100, 200]}"#),
+            Command::MouseMoveTo(100, 200)
+        );
+        assert_eq!(
+            parse(r#"{"MouseScroll": [0, 3]}"#),
+            Command::MouseScroll(0, 3)
+        );
+        assert!(serde_json::from_str::<Command>(r#"{"MouseClick": "Back"}"#).is_err());
+    }
 }
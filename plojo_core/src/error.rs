@@ -0,0 +1,85 @@
+//! Errors from reading strokes from a machine and dispatching commands
+use crate::Modifier;
+use std::{error::Error, fmt, io};
+
+/// Why a machine failed to read a stroke
+#[derive(Debug)]
+pub enum MachineError {
+    /// The machine was disconnected, but it could be reconnected
+    Disconnected,
+    /// There is no more input to read (ex: stdin was closed)
+    EndOfInput,
+    /// Any other IO error
+    Io(io::Error),
+    /// Data was read from the machine, but it could not be decoded into a stroke
+    Decode(String),
+}
+
+impl fmt::Display for MachineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MachineError::Disconnected => write!(f, "machine disconnected"),
+            MachineError::EndOfInput => write!(f, "end of input"),
+            MachineError::Io(e) => write!(f, "IO error: {}", e),
+            MachineError::Decode(msg) => write!(f, "unable to decode stroke: {}", msg),
+        }
+    }
+}
+
+impl Error for MachineError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            MachineError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for MachineError {
+    /// Broken pipes mean the machine disconnected and an unexpected EOF means there is no more
+    /// input. Every other error is kept as is
+    fn from(e: io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::BrokenPipe => MachineError::Disconnected,
+            io::ErrorKind::UnexpectedEof => MachineError::EndOfInput,
+            _ => MachineError::Io(e),
+        }
+    }
+}
+
+/// Why a controller could not dispatch a command
+#[derive(Debug, PartialEq)]
+pub enum DispatchError {
+    /// The char of a keyboard shortcut is not on the current keyboard layout, so there is no key
+    /// to press for it
+    UnmappableKey(char, Vec<Modifier>),
+}
+
+impl fmt::Display for DispatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DispatchError::UnmappableKey(c, modifiers) => write!(
+                f,
+                "cannot press {:?} and {:?}: it is not on the keyboard layout (is caps lock on?)",
+                c, modifiers
+            ),
+        }
+    }
+}
+
+impl Error for DispatchError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_io_error() {
+        let err: MachineError = io::Error::from(io::ErrorKind::BrokenPipe).into();
+        assert!(matches!(err, MachineError::Disconnected));
+        let err: MachineError = io::Error::from(io::ErrorKind::UnexpectedEof).into();
+        assert!(matches!(err, MachineError::EndOfInput));
+        let err: MachineError = io::Error::from(io::ErrorKind::PermissionDenied).into();
+        assert!(matches!(err, MachineError::Io(_)));
+    }
+}
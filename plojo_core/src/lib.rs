@@ -1,14 +1,30 @@
-use std::{error::Error, marker::Sized};
+use serde::Deserialize;
+use std::{marker::Sized, sync::Arc, thread, time::Duration};
 
 mod commands;
+mod error;
+mod multi;
+mod shell;
 mod stroke;
+mod text;
 
 pub use commands::Command;
 pub use commands::Key;
+pub use commands::KeyEvent;
 pub use commands::Modifier;
+pub use commands::MouseButton;
+pub use commands::Nav;
 pub use commands::SpecialKey;
+pub use error::DispatchError;
+pub use error::MachineError;
+pub use multi::MultiMachine;
+pub use multi::Reconnect;
+pub use shell::dispatch_shell;
 pub use stroke::RawStroke;
 pub use stroke::Stroke;
+pub use stroke::StrokeError;
+pub use text::apply_command;
+pub use text::apply_command_at;
 
 /// Translation from a stroke into a command
 pub trait Translator {
@@ -23,12 +39,368 @@ pub trait Controller {
     where
         Self: Sized;
     fn dispatch(&mut self, command: Command);
+
+    /// Dispatch a command, returning an error instead of panicking or only logging it if the
+    /// command can't be dispatched. By default this dispatches the command and always succeeds
+    fn try_dispatch(&mut self, command: Command) -> Result<(), DispatchError> {
+        self.dispatch(command);
+        Ok(())
+    }
+}
+
+/// Dispatch a key press (with modifiers) `count` times to the controller, waiting for the type
+/// text delay between each press
+pub fn repeat_key(
+    controller: &mut dyn Controller,
+    key: Key,
+    modifiers: Vec<Modifier>,
+    count: usize,
+    waiter: KeyWaiter,
+) {
+    for _ in 0..count {
+        controller.dispatch(Command::Keys(key.clone(), modifiers.clone()));
+        waiter.wait_type_text();
+    }
+}
+
+/// The command to repeat while the stroke that dispatched these commands is held down, like
+/// holding down a key. Only a stroke that does nothing but move the cursor (ex: an arrow key) is
+/// repeated
+pub fn hold_repeat_command(commands: &[Command]) -> Option<&Command> {
+    match commands {
+        [command] if command.is_navigation() => Some(command),
+        _ => None,
+    }
+}
+
+/// How much longer the dispatch delays are in slow mode
+pub const SLOW_MODE_MULTIPLIER: u64 = 5;
+
+/// Scales the delays a controller waits between key presses. Slow mode lengthens them for apps
+/// that drop fast keystrokes (ex: remote desktops)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DelayMultiplier {
+    multiplier: u64,
+}
+
+impl Default for DelayMultiplier {
+    fn default() -> Self {
+        Self { multiplier: 1 }
+    }
+}
+
+impl DelayMultiplier {
+    /// Switch between the normal delays and slow mode. Returns if slow mode is now on
+    pub fn toggle_slow_mode(&mut self) -> bool {
+        self.multiplier = if self.is_slow_mode() {
+            1
+        } else {
+            SLOW_MODE_MULTIPLIER
+        };
+        self.is_slow_mode()
+    }
+
+    pub fn is_slow_mode(&self) -> bool {
+        self.multiplier != 1
+    }
+
+    /// The delay (in milliseconds) to actually wait for
+    pub fn apply(&self, delay: u64) -> u64 {
+        delay * self.multiplier
+    }
+}
+
+/// How long (in milliseconds) a controller waits while pressing keys. Shorter delays type faster,
+/// but some apps (or slow computers) drop keys that are pressed too quickly
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct KeyDelays {
+    /// How long a key is held down
+    pub key_hold: u64,
+    /// Delay between successive backspaces for corrections
+    pub backspace: u64,
+    /// Delay between successive letters for typing normal text
+    pub type_text: u64,
+    /// Delay for holding down each modifier key
+    pub modifier: u64,
+}
+
+impl Default for KeyDelays {
+    fn default() -> Self {
+        Self {
+            key_hold: 2,
+            backspace: 2,
+            type_text: 5,
+            modifier: 2,
+        }
+    }
+}
+
+/// Waits for the key delays while a controller presses keys, lengthening them in slow mode. The
+/// output controllers all wait with this, so the delays are the same for each of them
+#[derive(Debug, Clone, Copy)]
+pub struct KeyWaiter {
+    delays: DelayMultiplier,
+    key_delays: KeyDelays,
+    // waits for a duration (instead of sleeping the thread in tests)
+    sleep: fn(Duration),
+}
+
+impl Default for KeyWaiter {
+    fn default() -> Self {
+        Self {
+            delays: DelayMultiplier::default(),
+            key_delays: KeyDelays::default(),
+            sleep: thread::sleep,
+        }
+    }
+}
+
+impl KeyWaiter {
+    /// Wait for these delays instead of the default ones
+    pub fn with_key_delays(mut self, key_delays: KeyDelays) -> Self {
+        self.key_delays = key_delays;
+        self
+    }
+
+    /// Wait by calling this function instead of sleeping the thread (ex: to record the waits)
+    pub fn with_sleep(mut self, sleep: fn(Duration)) -> Self {
+        self.sleep = sleep;
+        self
+    }
+
+    /// Switch between the normal delays and slow mode. Returns if slow mode is now on
+    pub fn toggle_slow_mode(&mut self) -> bool {
+        self.delays.toggle_slow_mode()
+    }
+
+    /// Wait for a delay in milliseconds (lengthened in slow mode)
+    pub fn wait(&self, delay: u64) {
+        (self.sleep)(Duration::from_millis(self.delays.apply(delay)));
+    }
+
+    /// Wait while a key is held down
+    pub fn wait_key_hold(&self) {
+        self.wait(self.key_delays.key_hold);
+    }
+
+    /// Wait after a backspace
+    pub fn wait_backspace(&self) {
+        self.wait(self.key_delays.backspace);
+    }
+
+    /// Wait after typing a letter
+    pub fn wait_type_text(&self) {
+        self.wait(self.key_delays.type_text);
+    }
+
+    /// Wait after pressing or releasing a modifier
+    pub fn wait_modifier(&self) {
+        self.wait(self.key_delays.modifier);
+    }
 }
 
 /// A stenography machine (or equivalent)
 pub trait Machine {
     /// Waits until a new stroke is read
-    fn read(&mut self) -> Result<Stroke, Box<dyn Error>>;
+    fn read(&mut self) -> Result<Stroke, MachineError>;
     /// Temporarily disable input
     fn disable(&self);
+    /// A handle that disables and enables input from another thread, even while this machine is
+    /// waiting for a stroke (ex: when it is read by a `MultiMachine`). Machines that can't be
+    /// disabled have none
+    fn input_switch(&self) -> Option<Arc<dyn InputSwitch>> {
+        None
+    }
+    /// Enable input again after it was disabled. Machines that are only enabled again by their
+    /// own input (or can't be disabled) ignore this
+    fn enable(&self) {}
+    /// Waits up to the timeout while the keys of the last stroke are still held down. Returns
+    /// whether they are still held. Machines that only send a stroke once its keys are released
+    /// never hold a stroke
+    fn wait_held(&mut self, _timeout: Duration) -> bool {
+        false
+    }
+    /// Takes the translator commands (ex: "reload_dicts") that were read since they were last
+    /// taken. They are handled before the stroke that was just read. Most machines only read
+    /// strokes
+    fn take_translator_commands(&mut self) -> Vec<String> {
+        vec![]
+    }
+}
+
+/// Disables and enables the input of a machine from any thread (see `Machine::input_switch`)
+pub trait InputSwitch: Send + Sync {
+    fn disable(&self);
+    fn enable(&self);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    struct RecordingController {
+        commands: Vec<Command>,
+    }
+
+    impl Controller for RecordingController {
+        fn new(_disable_scan_keymap: bool) -> Self {
+            Self { commands: vec![] }
+        }
+
+        fn dispatch(&mut self, command: Command) {
+            self.commands.push(command);
+        }
+    }
+
+    thread_local! {
+        // how long (in milliseconds) each wait of the current test was
+        static WAITS: RefCell<Vec<u64>> = const { RefCell::new(vec![]) };
+    }
+
+    /// Records the wait instead of sleeping
+    fn record_wait(duration: Duration) {
+        WAITS.with(|waits| waits.borrow_mut().push(duration.as_millis() as u64));
+    }
+
+    /// The waits that were recorded since they were last taken
+    fn take_waits() -> Vec<u64> {
+        WAITS.with(|waits| waits.borrow_mut().split_off(0))
+    }
+
+    #[test]
+    fn repeat_key_dispatches_count_times() {
+        let mut controller = RecordingController::new(false);
+        repeat_key(
+            &mut controller,
+            Key::Special(SpecialKey::LeftArrow),
+            vec![Modifier::Shift],
+            5,
+            KeyWaiter::default().with_sleep(record_wait),
+        );
+
+        assert_eq!(
+            controller.commands,
+            vec![Command::Keys(Key::Special(SpecialKey::LeftArrow), vec![Modifier::Shift]); 5]
+        );
+        assert_eq!(take_waits(), vec![KeyDelays::default().type_text; 5]);
+    }
+
+    #[test]
+    fn slow_mode_multiplies_delays() {
+        let mut delays = DelayMultiplier::default();
+        assert_eq!(delays.apply(5), 5);
+
+        assert!(delays.toggle_slow_mode());
+        assert_eq!(delays.apply(5), 5 * SLOW_MODE_MULTIPLIER);
+        assert_eq!(delays.apply(0), 0);
+
+        assert!(!delays.toggle_slow_mode());
+        assert_eq!(delays.apply(5), 5);
+    }
+
+    #[test]
+    fn slow_mode_applies_to_key_delays() {
+        let key_delays = KeyDelays {
+            type_text: 7,
+            ..KeyDelays::default()
+        };
+        let mut delays = DelayMultiplier::default();
+        delays.toggle_slow_mode();
+        assert_eq!(delays.apply(key_delays.type_text), 7 * SLOW_MODE_MULTIPLIER);
+        assert_eq!(delays.apply(key_delays.key_hold), 2 * SLOW_MODE_MULTIPLIER);
+    }
+
+    #[test]
+    fn slow_mode_lengthens_waits() {
+        let mut waiter = KeyWaiter::default().with_sleep(record_wait);
+        waiter.wait_backspace();
+        waiter.wait_type_text();
+        assert!(waiter.toggle_slow_mode());
+        waiter.wait_key_hold();
+        waiter.wait_type_text();
+        waiter.wait(100);
+        assert!(!waiter.toggle_slow_mode());
+        waiter.wait_key_hold();
+
+        let slow = SLOW_MODE_MULTIPLIER;
+        assert_eq!(take_waits(), vec![2, 5, 2 * slow, 5 * slow, 100 * slow, 2]);
+    }
+
+    #[test]
+    fn slow_mode_lengthens_repeated_keys() {
+        let mut controller = RecordingController::new(false);
+        let mut waiter = KeyWaiter::default().with_sleep(record_wait);
+        waiter.toggle_slow_mode();
+        repeat_key(
+            &mut controller,
+            Key::Special(SpecialKey::LeftArrow),
+            vec![],
+            2,
+            waiter,
+        );
+
+        assert_eq!(take_waits(), vec![5 * SLOW_MODE_MULTIPLIER; 2]);
+    }
+
+    #[test]
+    fn waits_for_configured_key_delays() {
+        let key_delays = KeyDelays {
+            key_hold: 10,
+            backspace: 20,
+            type_text: 30,
+            modifier: 40,
+        };
+        let mut waiter = KeyWaiter::default()
+            .with_key_delays(key_delays)
+            .with_sleep(record_wait);
+        waiter.wait_key_hold();
+        waiter.wait_backspace();
+        waiter.wait_type_text();
+        waiter.wait_modifier();
+        waiter.toggle_slow_mode();
+        waiter.wait_type_text();
+
+        assert_eq!(
+            take_waits(),
+            vec![10, 20, 30, 40, 30 * SLOW_MODE_MULTIPLIER]
+        );
+    }
+
+    #[test]
+    fn hold_repeat_single_nav_stroke() {
+        let repeated = |commands: Vec<Command>| hold_repeat_command(&commands).cloned();
+        let left = Command::Keys(Key::Special(SpecialKey::LeftArrow), vec![]);
+        assert_eq!(repeated(vec![left.clone()]), Some(left.clone()));
+        let select = Command::Keys(Key::Special(SpecialKey::End), vec![Modifier::Shift]);
+        assert_eq!(repeated(vec![select.clone()]), Some(select));
+        let word = Command::Nav(Nav::WordRight);
+        assert_eq!(repeated(vec![word.clone()]), Some(word));
+
+        // only a stroke that does nothing but navigate is repeated
+        assert_eq!(repeated(vec![]), None);
+        assert_eq!(repeated(vec![left.clone(), left.clone()]), None);
+        assert_eq!(repeated(vec![Command::add_text(" "), left]), None);
+        assert_eq!(repeated(vec![Command::add_text("hello")]), None);
+        assert_eq!(
+            repeated(vec![Command::Keys(
+                Key::Special(SpecialKey::Backspace),
+                vec![]
+            )]),
+            None
+        );
+        assert_eq!(
+            repeated(vec![Command::Keys(Key::Layout('a'), vec![])]),
+            None
+        );
+        assert_eq!(
+            repeated(vec![Command::RepeatKey {
+                key: Key::Special(SpecialKey::LeftArrow),
+                modifiers: vec![],
+                count: 3,
+            }]),
+            None
+        );
+    }
 }
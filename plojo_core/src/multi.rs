@@ -0,0 +1,354 @@
+//! Reading strokes from several machines at once
+use crate::{InputSwitch, Machine, MachineError, Stroke};
+use std::{
+    mem,
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+/// Creates a machine again after it disconnected, waiting until it can be created
+pub type Reconnect = Box<dyn FnMut() -> Box<dyn Machine + Send> + Send>;
+
+// the switch of the machine that is currently read on a thread, which changes when it reconnects
+type SwitchSlot = Arc<Mutex<Option<Arc<dyn InputSwitch>>>>;
+// what was read from a machine along with the translator commands read before it
+type Read = (Result<Stroke, MachineError>, Vec<String>);
+
+/// A machine that reads from several machines at the same time (ex: a steno machine and the
+/// keyboard). Each machine is read on its own thread and `read` returns whichever stroke arrives
+/// first. Strokes from different machines can be read in any order, but each stroke is read once.
+///
+/// A machine that disconnects is created again on its own thread if it has a way to reconnect,
+/// while the other machines keep being read. Otherwise, a machine is no longer read after it
+/// returns an error other than a decode error. `read` only returns `EndOfInput` once every machine
+/// has run out of input. The translator commands of each machine are taken along with its
+/// strokes. Strokes are never held down, so the hold repeat is not supported
+pub struct MultiMachine {
+    receiver: Receiver<Read>,
+    switches: Vec<SwitchSlot>,
+    // translator commands read since they were last taken
+    commands: Vec<String>,
+}
+
+impl MultiMachine {
+    pub fn new(machines: Vec<Box<dyn Machine + Send>>) -> Self {
+        Self::spawn(
+            machines
+                .into_iter()
+                .map(|machine| (machine, None))
+                .collect(),
+        )
+    }
+
+    /// Read from the machines like `new`, but a machine that disconnects is replaced by the
+    /// machine created by its reconnect function
+    pub fn with_reconnect(machines: Vec<(Box<dyn Machine + Send>, Reconnect)>) -> Self {
+        Self::spawn(
+            machines
+                .into_iter()
+                .map(|(machine, reconnect)| (machine, Some(reconnect)))
+                .collect(),
+        )
+    }
+
+    fn spawn(machines: Vec<(Box<dyn Machine + Send>, Option<Reconnect>)>) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let switches = machines
+            .into_iter()
+            .map(|(machine, reconnect)| {
+                let switch = Arc::new(Mutex::new(machine.input_switch()));
+                let sender = sender.clone();
+                let thread_switch = Arc::clone(&switch);
+                thread::spawn(move || read_machine(machine, reconnect, sender, thread_switch));
+                switch
+            })
+            .collect();
+
+        Self {
+            receiver,
+            switches,
+            commands: vec![],
+        }
+    }
+}
+
+/// Sends every stroke read from the machine until it stops or the multi machine is dropped
+fn read_machine(
+    mut machine: Box<dyn Machine + Send>,
+    mut reconnect: Option<Reconnect>,
+    sender: Sender<Read>,
+    switch: SwitchSlot,
+) {
+    loop {
+        let result = machine.read();
+        if let (Err(MachineError::Disconnected), Some(reconnect)) = (&result, &mut reconnect) {
+            println!("[WARN] Machine disconnected. Trying to reconnect");
+            machine = reconnect();
+            *switch.lock().unwrap() = machine.input_switch();
+            println!("[INFO] Machine reconnected");
+            continue;
+        }
+
+        // a stroke that couldn't be decoded doesn't stop the machine
+        let is_stopped = !matches!(result, Ok(_) | Err(MachineError::Decode(_)));
+        let commands = machine.take_translator_commands();
+        if sender.send((result, commands)).is_err() || is_stopped {
+            return;
+        }
+    }
+}
+
+impl Machine for MultiMachine {
+    fn read(&mut self) -> Result<Stroke, MachineError> {
+        loop {
+            let (result, commands) = match self.receiver.recv() {
+                Ok(read) => read,
+                // every machine has stopped
+                Err(_) => return Err(MachineError::EndOfInput),
+            };
+            self.commands.extend(commands);
+            match result {
+                // the other machines can still have input
+                Err(MachineError::EndOfInput) => continue,
+                result => return result,
+            }
+        }
+    }
+
+    fn take_translator_commands(&mut self) -> Vec<String> {
+        mem::take(&mut self.commands)
+    }
+
+    /// Disables each machine right away through its switch, since the machine itself is waiting
+    /// for a stroke on its own thread. Machines without a switch can't be disabled
+    fn disable(&self) {
+        for switch in &self.switches {
+            if let Some(switch) = &*switch.lock().unwrap() {
+                switch.disable();
+            }
+        }
+    }
+
+    fn enable(&self) {
+        for switch in &self.switches {
+            if let Some(switch) = &*switch.lock().unwrap() {
+                switch.enable();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        collections::HashMap,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    /// Reads its strokes with a delay between them, then runs out of input
+    struct FakeMachine {
+        strokes: Vec<Stroke>,
+        delay: Duration,
+    }
+
+    impl FakeMachine {
+        fn boxed(name: &str, count: usize, delay_ms: u64) -> Box<dyn Machine + Send> {
+            Box::new(Self {
+                strokes: (0..count)
+                    .rev()
+                    .map(|i| Stroke::new(&format!("{}{}", name, i)))
+                    .collect(),
+                delay: Duration::from_millis(delay_ms),
+            })
+        }
+    }
+
+    impl Machine for FakeMachine {
+        fn read(&mut self) -> Result<Stroke, MachineError> {
+            thread::sleep(self.delay);
+            self.strokes.pop().ok_or(MachineError::EndOfInput)
+        }
+
+        fn disable(&self) {}
+    }
+
+    #[test]
+    fn read_from_all_machines() {
+        let mut machine = MultiMachine::new(vec![
+            FakeMachine::boxed("S", 50, 1),
+            FakeMachine::boxed("T", 30, 2),
+        ]);
+
+        let mut strokes = vec![];
+        loop {
+            match machine.read() {
+                Ok(stroke) => strokes.push(stroke.to_raw()),
+                Err(MachineError::EndOfInput) => break,
+                Err(e) => panic!("unexpected error: {}", e),
+            }
+        }
+
+        // every stroke is read exactly once, and each machine's strokes are in order
+        assert_eq!(strokes.len(), 80);
+        let mut by_machine: HashMap<char, Vec<usize>> = HashMap::new();
+        for stroke in &strokes {
+            let (name, i) = stroke.split_at(1);
+            by_machine
+                .entry(name.chars().next().unwrap())
+                .or_default()
+                .push(i.parse().unwrap());
+        }
+        assert_eq!(by_machine[&'S'], (0..50).collect::<Vec<_>>());
+        assert_eq!(by_machine[&'T'], (0..30).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn stops_reading_after_error() {
+        struct DisconnectedMachine;
+        impl Machine for DisconnectedMachine {
+            fn read(&mut self) -> Result<Stroke, MachineError> {
+                Err(MachineError::Disconnected)
+            }
+            fn disable(&self) {}
+        }
+
+        let mut machine = MultiMachine::new(vec![
+            Box::new(DisconnectedMachine),
+            FakeMachine::boxed("S", 2, 20),
+        ]);
+        assert!(matches!(machine.read(), Err(MachineError::Disconnected)));
+        assert_eq!(machine.read().unwrap(), Stroke::new("S0"));
+        assert_eq!(machine.read().unwrap(), Stroke::new("S1"));
+        assert!(matches!(machine.read(), Err(MachineError::EndOfInput)));
+    }
+
+    #[test]
+    fn reconnect_only_disconnected_machine() {
+        /// Reads one stroke and then disconnects
+        struct FlakyMachine {
+            stroke: Option<Stroke>,
+        }
+        impl Machine for FlakyMachine {
+            fn read(&mut self) -> Result<Stroke, MachineError> {
+                self.stroke.take().ok_or(MachineError::Disconnected)
+            }
+            fn disable(&self) {}
+        }
+
+        let reconnects = Arc::new(Mutex::new(0));
+        let counter = Arc::clone(&reconnects);
+        let mut machine = MultiMachine::with_reconnect(vec![
+            (
+                Box::new(FlakyMachine {
+                    stroke: Some(Stroke::new("S")),
+                }),
+                Box::new(move || {
+                    *counter.lock().unwrap() += 1;
+                    FakeMachine::boxed("T", 2, 0)
+                }),
+            ),
+            (
+                FakeMachine::boxed("K", 1, 20),
+                Box::new(|| panic!("the other machine doesn't disconnect")),
+            ),
+        ]);
+
+        let mut strokes = vec![];
+        loop {
+            match machine.read() {
+                Ok(stroke) => strokes.push(stroke.to_raw()),
+                Err(MachineError::EndOfInput) => break,
+                Err(e) => panic!("unexpected error: {}", e),
+            }
+        }
+        strokes.sort();
+        assert_eq!(strokes, vec!["K0", "S", "T0", "T1"]);
+        assert_eq!(*reconnects.lock().unwrap(), 1);
+    }
+
+    #[test]
+    fn take_commands_of_each_machine() {
+        /// Reads a command before each of its strokes
+        struct CommandMachine {
+            strokes: Vec<Stroke>,
+            commands: Vec<String>,
+        }
+        impl Machine for CommandMachine {
+            fn read(&mut self) -> Result<Stroke, MachineError> {
+                let stroke = self.strokes.pop().ok_or(MachineError::EndOfInput)?;
+                self.commands.push(format!("before {}", stroke));
+                Ok(stroke)
+            }
+            fn disable(&self) {}
+            fn take_translator_commands(&mut self) -> Vec<String> {
+                mem::take(&mut self.commands)
+            }
+        }
+
+        let mut machine = MultiMachine::new(vec![Box::new(CommandMachine {
+            strokes: vec![Stroke::new("T"), Stroke::new("S")],
+            commands: vec![],
+        })]);
+        assert_eq!(machine.read().unwrap(), Stroke::new("S"));
+        assert_eq!(machine.take_translator_commands(), vec!["before S"]);
+        assert_eq!(machine.take_translator_commands(), Vec::<String>::new());
+        assert_eq!(machine.read().unwrap(), Stroke::new("T"));
+        assert_eq!(machine.take_translator_commands(), vec!["before T"]);
+        assert!(matches!(machine.read(), Err(MachineError::EndOfInput)));
+    }
+
+    #[test]
+    fn disable_and_enable_while_waiting_for_stroke() {
+        /// Reads the strokes that are sent to it
+        struct ChannelMachine {
+            strokes: Receiver<Stroke>,
+            disabled: Arc<AtomicBool>,
+        }
+        struct ChannelSwitch(Arc<AtomicBool>);
+        impl InputSwitch for ChannelSwitch {
+            fn disable(&self) {
+                self.0.store(true, Ordering::SeqCst);
+            }
+            fn enable(&self) {
+                self.0.store(false, Ordering::SeqCst);
+            }
+        }
+        impl Machine for ChannelMachine {
+            fn read(&mut self) -> Result<Stroke, MachineError> {
+                self.strokes.recv().map_err(|_| MachineError::EndOfInput)
+            }
+            fn disable(&self) {
+                self.disabled.store(true, Ordering::SeqCst);
+            }
+            fn input_switch(&self) -> Option<Arc<dyn InputSwitch>> {
+                Some(Arc::new(ChannelSwitch(Arc::clone(&self.disabled))))
+            }
+        }
+
+        let (sender, strokes) = mpsc::channel();
+        let disabled = Arc::new(AtomicBool::new(false));
+        let mut machine = MultiMachine::new(vec![Box::new(ChannelMachine {
+            strokes,
+            disabled: Arc::clone(&disabled),
+        })]);
+        sender.send(Stroke::new("S")).unwrap();
+        assert_eq!(machine.read().unwrap(), Stroke::new("S"));
+
+        // the machine is waiting for the next stroke, but is disabled and enabled right away
+        machine.disable();
+        assert!(disabled.load(Ordering::SeqCst));
+        machine.enable();
+        assert!(!disabled.load(Ordering::SeqCst));
+
+        drop(sender);
+        assert!(matches!(machine.read(), Err(MachineError::EndOfInput)));
+    }
+}
@@ -0,0 +1,55 @@
+//! Shell commands dispatched by the controllers
+use std::{io, process, thread};
+
+/// Run a shell command without waiting for it. If `capture_output` is set, its output is logged
+/// once it exits (waiting for it in another thread so that it doesn't block typing)
+pub fn dispatch_shell(cmd: String, args: Vec<String>, capture_output: bool) {
+    if capture_output {
+        thread::spawn(move || match run_shell_captured(&cmd, &args) {
+            Ok(log) => println!("{}", log),
+            Err(e) => eprintln!("[WARN] Could not execute shell command: {}", e),
+        });
+        return;
+    }
+
+    if let Err(e) = process::Command::new(cmd).args(args).spawn() {
+        eprintln!("[WARN] Could not execute shell command: {}", e);
+    }
+}
+
+/// Runs a shell command until it exits and formats its output as a debug log message
+fn run_shell_captured(cmd: &str, args: &[String]) -> io::Result<String> {
+    let output = process::Command::new(cmd).args(args).output()?;
+    Ok(format!(
+        "[DEBUG] Shell command {:?} with args {:?} exited with {}\n[DEBUG] stdout: {:?}\n[DEBUG] stderr: {:?}",
+        cmd,
+        args,
+        output.status,
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(not(target_os = "windows"))]
+    fn shell_output_is_logged() {
+        let log = run_shell_captured("echo", &["hello from shell".to_string()]).unwrap();
+        assert!(log.starts_with("[DEBUG]"));
+        assert!(log.contains("stdout: \"hello from shell\\n\""));
+        assert!(log.contains("stderr: \"\""));
+    }
+
+    #[test]
+    #[cfg(target_os = "windows")]
+    fn shell_output_is_logged() {
+        let args = ["/C".to_string(), "echo hello from shell".to_string()];
+        let log = run_shell_captured("cmd", &args).unwrap();
+        assert!(log.starts_with("[DEBUG]"));
+        assert!(log.contains("stdout: \"hello from shell\\r\\n\""));
+        assert!(log.contains("stderr: \"\""));
+    }
+}
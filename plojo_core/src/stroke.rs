@@ -1,7 +1,8 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use std::{error::Error, fmt};
 
 /// A steno stroke. Can be a single stroke (ex: "H-L") or several strokes (ex: "H-L/WORLD")
-#[derive(Debug, PartialEq, Eq, Hash, Clone, Deserialize)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Deserialize, Serialize)]
 pub struct Stroke(String);
 
 impl Stroke {
@@ -18,10 +19,186 @@ impl Stroke {
     }
 
     pub fn is_valid(&self) -> bool {
-        !self.0.is_empty()
+        self.validation_error().is_none()
+    }
+
+    /// Explains why the stroke is not valid steno, or None if it is valid. Each stroke (separated
+    /// by "/") must have its keys in steno order, with an optional "-" between the left and right
+    /// hand keys and the number key ("#") only at the start. Numbers can replace the keys they are
+    /// on (ex: "1-9" for "#S-T")
+    pub fn validation_error(&self) -> Option<StrokeError> {
+        self.0.split('/').find_map(single_stroke_error)
+    }
+
+    /// The canonical way of writing the stroke: keys in steno order, a "-" only before right hand
+    /// keys when there are no center keys, and numbers instead of the keys they are on (ex: "TS"
+    /// becomes "T-S" and "#S-T" becomes "1-9"). Invalid strokes are left as is
+    pub fn normalized(&self) -> Stroke {
+        if !self.is_valid() {
+            return self.clone();
+        }
+        let strokes: Vec<_> = self
+            .0
+            .split('/')
+            .map(|s| Stroke::from(parse_single_stroke(s)).to_raw())
+            .collect();
+        Stroke::new(&strokes.join("/"))
     }
 }
 
+/// Shows the normalized stroke
+impl fmt::Display for Stroke {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.normalized().0)
+    }
+}
+
+/// Why a stroke is not valid steno
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum StrokeError {
+    /// The stroke (or one of the strokes separated by "/") has no keys
+    Empty,
+    /// A char that is not a steno key or a number
+    UnknownKey(char),
+    /// A key that comes before one of the keys in front of it in steno order
+    OutOfOrder(char),
+    /// The number key ("#") is somewhere other than the start of the stroke
+    MisplacedNumberKey,
+    /// More than one "-" or a "-" between right hand keys
+    MisplacedHyphen,
+}
+
+impl fmt::Display for StrokeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StrokeError::Empty => write!(f, "empty stroke"),
+            StrokeError::UnknownKey(c) => write!(f, "unknown key {:?}", c),
+            StrokeError::OutOfOrder(c) => write!(f, "key {:?} is out of steno order", c),
+            StrokeError::MisplacedNumberKey => write!(f, "number key is not at the start"),
+            StrokeError::MisplacedHyphen => write!(f, "misplaced hyphen"),
+        }
+    }
+}
+
+impl Error for StrokeError {}
+
+/// Every key in steno order with the number it becomes with the number key
+const STENO_ORDER: [(char, Option<char>); 22] = [
+    ('S', Some('1')),
+    ('T', Some('2')),
+    ('K', None),
+    ('P', Some('3')),
+    ('W', None),
+    ('H', Some('4')),
+    ('R', None),
+    ('A', Some('5')),
+    ('O', Some('0')),
+    ('*', None),
+    ('E', None),
+    ('U', None),
+    ('F', Some('6')),
+    ('R', None),
+    ('P', Some('7')),
+    ('B', None),
+    ('L', Some('8')),
+    ('G', None),
+    ('T', Some('9')),
+    ('S', None),
+    ('D', None),
+    ('Z', None),
+];
+// where the center keys before the star, the star, and the right hand keys are in the steno order
+const CENTER_LEFT_START: usize = 7;
+const STAR_INDEX: usize = 9;
+const RIGHT_HAND_START: usize = 12;
+
+/// Checks a single stroke (without any "/") by matching each key to the earliest place it can be in
+/// the steno order after the key before it
+fn single_stroke_error(stroke: &str) -> Option<StrokeError> {
+    if stroke.is_empty() {
+        return Some(StrokeError::Empty);
+    }
+
+    let keys = stroke.strip_prefix('#').unwrap_or(stroke);
+    // the next place in the steno order that a key can be
+    let mut next = 0;
+    let mut seen_hyphen = false;
+    let mut chars = keys.chars().peekable();
+    while let Some(key) = chars.next() {
+        match key {
+            '#' => return Some(StrokeError::MisplacedNumberKey),
+            // number strokes can end with a hyphen even if they are on the right hand (ex: "6-")
+            '-' if chars.peek().is_none() && !seen_hyphen => {}
+            '-' => {
+                if seen_hyphen || next > RIGHT_HAND_START {
+                    return Some(StrokeError::MisplacedHyphen);
+                }
+                seen_hyphen = true;
+                next = RIGHT_HAND_START;
+            }
+            _ => {
+                let is_key =
+                    |&(letter, number): &(char, Option<char>)| letter == key || number == Some(key);
+                match STENO_ORDER[next..].iter().position(is_key) {
+                    Some(i) => next += i + 1,
+                    None if STENO_ORDER.iter().any(is_key) => {
+                        return Some(StrokeError::OutOfOrder(key))
+                    }
+                    None => return Some(StrokeError::UnknownKey(key)),
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Splits a valid single stroke (without any "/") into its keys, matching each key to the same
+/// place in the steno order as `single_stroke_error`
+fn parse_single_stroke(stroke: &str) -> RawStroke {
+    let mut raw = RawStroke::default();
+    let keys = match stroke.strip_prefix('#') {
+        Some(keys) => {
+            raw.num_key = true;
+            keys
+        }
+        None => stroke,
+    };
+
+    let mut next = 0;
+    let mut chars = keys.chars().peekable();
+    while let Some(key) = chars.next() {
+        if key == '-' {
+            // a hyphen at the end of a number stroke doesn't move to the right hand
+            if chars.peek().is_some() {
+                next = RIGHT_HAND_START;
+            }
+            continue;
+        }
+
+        let is_key =
+            |&(letter, number): &(char, Option<char>)| letter == key || number == Some(key);
+        let index = match STENO_ORDER[next..].iter().position(is_key) {
+            Some(i) => next + i,
+            // the stroke was already checked, so every key is in order
+            None => continue,
+        };
+        next = index + 1;
+        if key.is_ascii_digit() {
+            raw.num_key = true;
+        }
+
+        let letter = STENO_ORDER[index].0;
+        match index {
+            i if i < CENTER_LEFT_START => raw.left_hand.push(letter),
+            i if i < STAR_INDEX => raw.center_left.push(letter),
+            i if i == STAR_INDEX => raw.star_key = true,
+            i if i < RIGHT_HAND_START => raw.center_right.push(letter),
+            _ => raw.right_hand.push(letter),
+        }
+    }
+    raw
+}
+
 impl From<RawStroke> for Stroke {
     fn from(raw: RawStroke) -> Self {
         let mut stroke = String::from("");
@@ -113,6 +290,102 @@ pub struct RawStroke {
 mod tests {
     use super::*;
 
+    #[test]
+    fn valid_strokes() {
+        for stroke in &[
+            "S",
+            "-S",
+            "STKPWHRAO*EUFRPBLGTSDZ",
+            "KPA*",
+            "H-L",
+            "H-L/WORLD",
+            "TPH-FPL",
+            "*",
+            "#",
+            "#-G",
+            "1-9",
+            "1234",
+            "-9",
+            "3-67",
+            "PWHO",
+            "150",
+            "TS",
+            "KWR-F",
+            "1-",
+            "6-",
+        ] {
+            assert_eq!(Stroke::new(stroke).validation_error(), None, "{}", stroke);
+            assert!(Stroke::new(stroke).is_valid());
+        }
+    }
+
+    #[test]
+    fn invalid_strokes() {
+        let error = |s| Stroke::new(s).validation_error();
+        assert_eq!(error(""), Some(StrokeError::Empty));
+        assert_eq!(error("H-L/"), Some(StrokeError::Empty));
+        assert_eq!(error("H-L//WORLD"), Some(StrokeError::Empty));
+        assert_eq!(error("HXL"), Some(StrokeError::UnknownKey('X')));
+        assert_eq!(error("h-l"), Some(StrokeError::UnknownKey('h')));
+        assert_eq!(error("AK"), Some(StrokeError::OutOfOrder('K')));
+        assert_eq!(error("-FS/WO"), None);
+        assert_eq!(error("H-L/OW"), Some(StrokeError::OutOfOrder('W')));
+        assert_eq!(error("ZD"), Some(StrokeError::OutOfOrder('D')));
+        assert_eq!(error("21"), Some(StrokeError::OutOfOrder('1')));
+        assert_eq!(error("S#"), Some(StrokeError::MisplacedNumberKey));
+        assert_eq!(error("##S"), Some(StrokeError::MisplacedNumberKey));
+        assert_eq!(error("H--L"), Some(StrokeError::MisplacedHyphen));
+        assert_eq!(error("-F-L"), Some(StrokeError::MisplacedHyphen));
+        assert_eq!(error("-F-"), Some(StrokeError::MisplacedHyphen));
+        assert!(!Stroke::new("AK").is_valid());
+    }
+
+    #[test]
+    fn normalized_strokes() {
+        let normalized = |s| Stroke::new(s).normalized().to_raw();
+        // already normalized
+        for stroke in &[
+            "H-L",
+            "KPA*",
+            "STKPWHRAO*EUFRPBLGTSDZ",
+            "-G",
+            "S",
+            "1-9",
+            "#-G",
+        ] {
+            assert_eq!(normalized(stroke), *stroke);
+        }
+        // right hand only
+        assert_eq!(normalized("-S"), "-S");
+        assert_eq!(normalized("TS"), "T-S");
+        assert_eq!(normalized("-FS/WO"), "-FS/WO");
+        // center only
+        assert_eq!(normalized("AO"), "AO");
+        assert_eq!(normalized("*"), "*");
+        assert_eq!(normalized("*E"), "*E");
+        assert_eq!(normalized("AOEU"), "AOEU");
+        assert_eq!(normalized("EU"), "EU");
+        // number strokes
+        assert_eq!(normalized("#S"), "1");
+        assert_eq!(normalized("#S-T"), "1-9");
+        assert_eq!(normalized("1-T"), "1-9");
+        assert_eq!(normalized("6-"), "-6");
+        assert_eq!(normalized("-9"), "-9");
+        assert_eq!(normalized("#"), "#");
+        assert_eq!(normalized("#*"), "#*");
+        assert_eq!(normalized("150"), "150");
+        assert_eq!(normalized("#PWHO"), "3W40");
+        // invalid strokes are left alone
+        assert_eq!(normalized("AK"), "AK");
+        assert_eq!(normalized("H-L/"), "H-L/");
+    }
+
+    #[test]
+    fn display_stroke() {
+        assert_eq!(Stroke::new("TS/HEL").to_string(), "T-S/HEL");
+        assert_eq!(format!("{}", Stroke::new("#-T")), "-9");
+    }
+
     #[test]
     fn test_to_number_stroke() {
         assert_eq!(to_number_stroke("STPH"), String::from("1234"));
@@ -0,0 +1,249 @@
+//! Applying commands to a text buffer, for tests and previews of what the commands would type
+use crate::{Command, Key, SpecialKey};
+use std::cmp;
+
+/// Apply a command to a text buffer as if it was dispatched to a text field with the cursor at
+/// the end of the text. Returns if the command was applied; commands that don't affect text (ex:
+/// shell commands) are ignored.
+///
+/// Each backspace removes a single char (not a grapheme cluster), which is how the translator
+/// counts backspaces. For example, an "é" written as "e" and a combining accent takes 2 backspaces
+pub fn apply_command(buffer: &mut String, command: &Command) -> bool {
+    let mut cursor = buffer.chars().count();
+    apply_command_at(buffer, &mut cursor, command)
+}
+
+/// Same as `apply_command`, but text is typed and deleted at the cursor instead of at the end.
+/// The cursor is the number of chars before it, and it is moved by typing and by the arrow, home,
+/// and end keys.
+///
+/// Keys pressed with modifiers are ignored because their effect depends on the app
+pub fn apply_command_at(buffer: &mut String, cursor: &mut usize, command: &Command) -> bool {
+    *cursor = cmp::min(*cursor, buffer.chars().count());
+
+    match command {
+        Command::Replace(backspace_num, text) => {
+            backspace(buffer, cursor, *backspace_num);
+            insert(buffer, cursor, text);
+            true
+        }
+        Command::Keys(key, modifiers) if modifiers.is_empty() => press_key(buffer, cursor, key),
+        Command::RepeatKey {
+            key,
+            modifiers,
+            count,
+        } if modifiers.is_empty() => {
+            let mut applied = false;
+            for _ in 0..*count {
+                applied = press_key(buffer, cursor, key);
+            }
+            applied
+        }
+        _ => false,
+    }
+}
+
+/// Press a key without modifiers. Returns if the key types, deletes, or moves the cursor
+fn press_key(buffer: &mut String, cursor: &mut usize, key: &Key) -> bool {
+    match key {
+        Key::Layout(c) => insert(buffer, cursor, &c.to_string()),
+        Key::Special(SpecialKey::Space) => insert(buffer, cursor, " "),
+        Key::Special(SpecialKey::Tab) => insert(buffer, cursor, "\t"),
+        Key::Special(SpecialKey::Return) => insert(buffer, cursor, "\n"),
+        Key::Special(SpecialKey::Backspace) => backspace(buffer, cursor, 1),
+        Key::Special(SpecialKey::Delete) => {
+            if *cursor < buffer.chars().count() {
+                buffer.remove(byte_index(buffer, *cursor));
+            }
+        }
+        Key::Special(SpecialKey::LeftArrow) => *cursor = cursor.saturating_sub(1),
+        Key::Special(SpecialKey::RightArrow) => {
+            *cursor = cmp::min(*cursor + 1, buffer.chars().count())
+        }
+        Key::Special(SpecialKey::Home) => {
+            // start of the line
+            let before = &buffer[..byte_index(buffer, *cursor)];
+            *cursor = before
+                .rfind('\n')
+                .map_or(0, |i| before[..=i].chars().count());
+        }
+        Key::Special(SpecialKey::End) => {
+            // end of the line
+            let start = byte_index(buffer, *cursor);
+            let after = &buffer[start..];
+            *cursor += after
+                .find('\n')
+                .map_or(after, |i| &after[..i])
+                .chars()
+                .count();
+        }
+        _ => return false,
+    }
+    true
+}
+
+/// Delete chars before the cursor
+fn backspace(buffer: &mut String, cursor: &mut usize, count: usize) {
+    let count = cmp::min(count, *cursor);
+    let start = byte_index(buffer, *cursor - count);
+    let end = byte_index(buffer, *cursor);
+    buffer.replace_range(start..end, "");
+    *cursor -= count;
+}
+
+/// Type text at the cursor
+fn insert(buffer: &mut String, cursor: &mut usize, text: &str) {
+    buffer.insert_str(byte_index(buffer, *cursor), text);
+    *cursor += text.chars().count();
+}
+
+/// The byte index of the char at a char index (or the end of the text)
+fn byte_index(text: &str, chars: usize) -> usize {
+    text.char_indices()
+        .nth(chars)
+        .map_or(text.len(), |(i, _)| i)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Modifier;
+
+    fn special(key: SpecialKey) -> Command {
+        Command::Keys(Key::Special(key), vec![])
+    }
+
+    #[test]
+    fn replace_text() {
+        let mut buffer = String::new();
+        assert!(apply_command(&mut buffer, &Command::add_text(" hello")));
+        assert_eq!(buffer, " hello");
+        assert!(apply_command(
+            &mut buffer,
+            &Command::replace_text(5, "Hi there")
+        ));
+        assert_eq!(buffer, " Hi there");
+        // deleting more than there is stops at the start
+        assert!(apply_command(&mut buffer, &Command::replace_text(20, "a")));
+        assert_eq!(buffer, "a");
+        assert!(!apply_command(&mut buffer, &Command::NoOp));
+        assert_eq!(buffer, "a");
+    }
+
+    #[test]
+    fn replace_multibyte_text() {
+        let mut buffer = " “hello”".to_string();
+        apply_command(&mut buffer, &Command::replace_text(1, "’"));
+        assert_eq!(buffer, " “hello’");
+        apply_command(&mut buffer, &Command::replace_text(7, "é😀"));
+        assert_eq!(buffer, " é😀");
+        apply_command(&mut buffer, &Command::replace_text(1, "!"));
+        assert_eq!(buffer, " é!");
+
+        // each backspace removes a char, even in the middle of a grapheme cluster
+        let mut buffer = " cafe\u{301}".to_string();
+        apply_command(&mut buffer, &Command::replace_text(1, ""));
+        assert_eq!(buffer, " cafe");
+        let mut buffer = " cafe\u{301}".to_string();
+        apply_command(&mut buffer, &Command::replace_text(2, "é"));
+        assert_eq!(buffer, " café");
+    }
+
+    #[test]
+    fn keys_type_and_delete() {
+        let mut buffer = "ab".to_string();
+        assert!(apply_command(&mut buffer, &special(SpecialKey::Backspace)));
+        assert!(apply_command(
+            &mut buffer,
+            &Command::Keys(Key::Layout('ü'), vec![])
+        ));
+        assert!(apply_command(&mut buffer, &special(SpecialKey::Space)));
+        assert!(apply_command(&mut buffer, &special(SpecialKey::Return)));
+        assert!(apply_command(&mut buffer, &special(SpecialKey::Tab)));
+        assert_eq!(buffer, "aü \n\t");
+        assert!(apply_command(
+            &mut buffer,
+            &Command::RepeatKey {
+                key: Key::Special(SpecialKey::Backspace),
+                modifiers: vec![],
+                count: 4,
+            }
+        ));
+        assert_eq!(buffer, "a");
+    }
+
+    #[test]
+    fn other_commands_are_ignored() {
+        let mut buffer = "hello".to_string();
+        let ignored = vec![
+            Command::Keys(Key::Layout('a'), vec![Modifier::Meta]),
+            Command::Keys(Key::Special(SpecialKey::Escape), vec![]),
+            Command::RepeatKey {
+                key: Key::Special(SpecialKey::Backspace),
+                modifiers: vec![],
+                count: 0,
+            },
+            Command::KeySequence(vec![Modifier::Meta], vec![Key::Special(SpecialKey::Tab)]),
+            Command::KeyDown(Key::Layout('w'), vec![]),
+            Command::Shell("ls".to_string(), vec![]),
+            Command::ShellInsert("date".to_string(), vec![]),
+            Command::TranslatorCommand("toggle_title_case".to_string()),
+            Command::PrintHello,
+        ];
+        for command in ignored {
+            assert!(!apply_command(&mut buffer, &command), "{:?}", command);
+        }
+        assert_eq!(buffer, "hello");
+    }
+
+    #[test]
+    fn cursor_moves_and_edits() {
+        let mut buffer = " héllo".to_string();
+        let mut cursor = 100;
+        apply_command_at(&mut buffer, &mut cursor, &special(SpecialKey::LeftArrow));
+        // the cursor is clamped to the end of the text first
+        assert_eq!(cursor, 5);
+        apply_command_at(
+            &mut buffer,
+            &mut cursor,
+            &Command::RepeatKey {
+                key: Key::Special(SpecialKey::LeftArrow),
+                modifiers: vec![],
+                count: 2,
+            },
+        );
+        assert_eq!(cursor, 3);
+        apply_command_at(&mut buffer, &mut cursor, &Command::replace_text(1, "e"));
+        assert_eq!((buffer.as_str(), cursor), (" hello", 3));
+        apply_command_at(&mut buffer, &mut cursor, &special(SpecialKey::Delete));
+        assert_eq!((buffer.as_str(), cursor), (" helo", 3));
+
+        apply_command_at(&mut buffer, &mut cursor, &special(SpecialKey::End));
+        apply_command_at(&mut buffer, &mut cursor, &special(SpecialKey::Delete));
+        apply_command_at(&mut buffer, &mut cursor, &special(SpecialKey::RightArrow));
+        assert_eq!((buffer.as_str(), cursor), (" helo", 5));
+        apply_command_at(&mut buffer, &mut cursor, &special(SpecialKey::Home));
+        apply_command_at(&mut buffer, &mut cursor, &special(SpecialKey::Backspace));
+        apply_command_at(&mut buffer, &mut cursor, &special(SpecialKey::LeftArrow));
+        assert_eq!((buffer.as_str(), cursor), (" helo", 0));
+    }
+
+    #[test]
+    fn home_and_end_stay_on_the_line() {
+        let mut buffer = "one\ntwö\nthree".to_string();
+        let mut cursor = 6;
+        apply_command_at(&mut buffer, &mut cursor, &special(SpecialKey::Home));
+        assert_eq!(cursor, 4);
+        apply_command_at(&mut buffer, &mut cursor, &special(SpecialKey::End));
+        assert_eq!(cursor, 7);
+        apply_command_at(&mut buffer, &mut cursor, &Command::add_text("!"));
+        assert_eq!((buffer.as_str(), cursor), ("one\ntwö!\nthree", 8));
+
+        let mut cursor = 1;
+        apply_command_at(&mut buffer, &mut cursor, &special(SpecialKey::Home));
+        assert_eq!(cursor, 0);
+        let mut cursor = 11;
+        apply_command_at(&mut buffer, &mut cursor, &special(SpecialKey::End));
+        assert_eq!(cursor, 14);
+    }
+}
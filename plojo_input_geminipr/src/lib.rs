@@ -1,26 +1,90 @@
-use plojo_core::{Machine, Stroke};
+use plojo_core::{Machine, MachineError, Stroke};
+use reconnect::Reconnect;
 use serialport::{available_ports, SerialPortType};
-use std::error::Error;
+use std::{error::Error, thread};
 
 mod machine;
 mod raw_stroke;
+mod reconnect;
 
-use machine::SerialMachine;
+pub use machine::SerialMachine;
+pub use raw_stroke::KeyLayout;
 
 pub struct GeminiprMachine {
     machine: SerialMachine,
+    reconnect: Reconnect,
+    layout: KeyLayout,
+    // bytes read from the machine that aren't part of a full packet yet
+    pending: Vec<u8>,
 }
 
 impl GeminiprMachine {
+    /// Open a machine on the port with the default serial settings
     pub fn new(config_port: &str) -> Result<Self, Box<dyn Error>> {
-        let machine = SerialMachine::new(config_port)?;
-        Ok(Self { machine })
+        Self::from_serial(SerialMachine::new(config_port))
+    }
+
+    /// Open a machine with custom serial settings (ex: a different baud rate)
+    pub fn from_serial(mut machine: SerialMachine) -> Result<Self, Box<dyn Error>> {
+        machine.open()?;
+        Ok(Self {
+            machine,
+            reconnect: Reconnect::default(),
+            layout: KeyLayout::default(),
+            pending: Vec::new(),
+        })
+    }
+
+    /// Read the keys with this layout instead of the standard Gemini PR layout (ex: for a machine
+    /// that sends the number bar differently)
+    pub fn with_key_layout(mut self, layout: KeyLayout) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    /// Try to reopen the machine after it was disconnected (ex: from a loose cable). Returns if it
+    /// was reconnected before the timeout
+    fn reconnect(&mut self) -> bool {
+        let port_name = self.machine.port_name().to_string();
+        let machine = &mut self.machine;
+        let reopen = || {
+            let port = find_port(&port_name)
+                .ok_or_else(|| format!("port {:?} is not available", port_name))?;
+            machine.reopen(&port).map_err(|e| e.to_string())
+        };
+
+        self.reconnect.run(reopen, thread::sleep).is_some()
     }
 }
 
 impl Machine for GeminiprMachine {
-    fn read(&mut self) -> Result<Stroke, Box<dyn Error>> {
-        self.machine.read().map(|raw| raw_stroke::parse_raw(&raw))
+    fn read(&mut self) -> Result<Stroke, MachineError> {
+        loop {
+            let len = self.pending.len();
+            let packet = raw_stroke::take_packet(&mut self.pending);
+            let skipped = len - self.pending.len() - packet.as_ref().map_or(0, Vec::len);
+            if skipped > 0 {
+                println!(
+                    "[WARN] Skipped {} bytes to get back in sync with the machine",
+                    skipped
+                );
+            }
+            if let Some(packet) = packet {
+                return Ok(raw_stroke::parse_raw(&packet, &self.layout));
+            }
+
+            match self.machine.read() {
+                Ok(raw) => self.pending.extend(raw),
+                // only give up if the machine can't be reconnected
+                Err(e @ MachineError::Disconnected) | Err(e @ MachineError::Io(_)) => {
+                    println!("[WARN] Machine disconnected ({}). Trying to reconnect", e);
+                    if !self.reconnect() {
+                        return Err(e);
+                    }
+                }
+                Err(e) => return Err(e),
+            }
+        }
     }
 
     fn disable(&self) {
@@ -73,6 +137,17 @@ pub fn print_available_ports() {
     }
 }
 
+/// The port to reconnect to: the original port if it is available again, or else a Georgi on any
+/// port (it may come back on a different port)
+fn find_port(port_name: &str) -> Option<String> {
+    let ports = available_ports().ok()?;
+    if ports.iter().any(|p| p.port_name == port_name) {
+        Some(port_name.to_string())
+    } else {
+        get_georgi_port()
+    }
+}
+
 pub fn get_georgi_port() -> Option<String> {
     match available_ports() {
         Ok(ports) => {
@@ -1,47 +1,162 @@
+use plojo_core::MachineError;
 use serialport::{SerialPort, SerialPortSettings};
-use std::{error::Error, io::ErrorKind, thread, time::Duration};
+use std::{io::ErrorKind, thread, time::Duration};
 
+const DEFAULT_BAUD_RATE: u32 = 9600;
 const DEFAULT_READ_RATE: u64 = 10;
+const DEFAULT_BUF_SIZE: usize = 6;
 
 pub struct SerialMachine {
+    port_name: String,
+    baud_rate: u32,
     /// How long to wait before trying to read from serial machine again
     read_rate: u64,
     /// Size of buffer to read each time
     buf_size: usize,
-    port: Box<dyn SerialPort>,
+    /// The port is opened with the settings above by `open` or on the first read
+    port: Option<Box<dyn SerialPort>>,
 }
 
 impl SerialMachine {
-    pub fn new(port_name: &str) -> Result<Self, Box<dyn Error>> {
-        let port = serialport::open_with_settings(port_name, &SerialPortSettings::default())?;
-
-        Ok(Self {
+    /// A machine on the port with the default settings. The port is not opened yet
+    pub fn new(port_name: &str) -> Self {
+        Self {
+            port_name: port_name.to_string(),
+            baud_rate: DEFAULT_BAUD_RATE,
             read_rate: DEFAULT_READ_RATE,
-            buf_size: 6,
-            port,
-        })
+            buf_size: DEFAULT_BUF_SIZE,
+            port: None,
+        }
     }
 
-    pub fn read(&mut self) -> Result<Vec<u8>, Box<dyn Error>> {
-        let sleep_time = Duration::from_millis(self.read_rate);
-        let mut serial_buf: Vec<u8> = vec![0; self.buf_size];
+    /// Open the port at this baud rate instead of 9600
+    pub fn with_baud_rate(mut self, baud_rate: u32) -> Self {
+        self.baud_rate = baud_rate;
+        self
+    }
+
+    /// Wait this many milliseconds between reads when there is no data instead of 10
+    pub fn with_read_rate(mut self, read_rate: u64) -> Self {
+        self.read_rate = read_rate;
+        self
+    }
+
+    /// Read this many bytes at a time instead of the 6 bytes of a Gemini PR packet
+    pub fn with_buf_size(mut self, buf_size: usize) -> Self {
+        self.buf_size = buf_size;
+        self
+    }
+
+    /// Open the port with the configured settings (if it isn't open already)
+    pub fn open(&mut self) -> Result<&mut Box<dyn SerialPort>, serialport::Error> {
+        if self.port.is_none() {
+            self.port = Some(serialport::open_with_settings(
+                &self.port_name,
+                &self.settings(),
+            )?);
+        }
+        Ok(self.port.as_mut().unwrap())
+    }
+
+    /// Close the port and open the machine on a (possibly different) port with the same settings
+    pub fn reopen(&mut self, port_name: &str) -> Result<(), serialport::Error> {
+        self.port = None;
+        self.port_name = port_name.to_string();
+        self.open()?;
+        Ok(())
+    }
+
+    /// The name of the port the machine is on
+    pub fn port_name(&self) -> &str {
+        &self.port_name
+    }
+
+    fn settings(&self) -> SerialPortSettings {
+        SerialPortSettings {
+            baud_rate: self.baud_rate,
+            ..SerialPortSettings::default()
+        }
+    }
 
+    /// Block until a full buffer of data is read
+    pub fn read(&mut self) -> Result<Vec<u8>, MachineError> {
         loop {
-            match self.port.read_exact(serial_buf.as_mut_slice()) {
-                Ok(()) => {
-                    // successfully read data
-                    return Ok(serial_buf);
-                }
-                Err(e) => match e.kind() {
-                    ErrorKind::TimedOut => {
-                        // no data to read, wait before trying again
-                        thread::sleep(sleep_time);
-                    }
-                    _ => {
-                        return Err(Box::new(e));
-                    }
-                },
+            match self.try_read()? {
+                Some(serial_buf) => return Ok(serial_buf),
+                None => self.wait(),
             }
         }
     }
+
+    /// Try to read a full buffer of data once. Returns None if there is no data to read yet
+    pub fn try_read(&mut self) -> Result<Option<Vec<u8>>, MachineError> {
+        let mut serial_buf: Vec<u8> = vec![0; self.buf_size];
+        // a port that can't be opened is treated like an unplugged machine
+        let port = self.open().map_err(|_| MachineError::Disconnected)?;
+
+        match port.read_exact(serial_buf.as_mut_slice()) {
+            // successfully read data
+            Ok(()) => Ok(Some(serial_buf)),
+            Err(e) => match e.kind() {
+                ErrorKind::TimedOut => Ok(None),
+                _ => Err(e.into()),
+            },
+        }
+    }
+
+    /// Wait before trying to read from the serial machine again
+    pub fn wait(&self) {
+        thread::sleep(Duration::from_millis(self.read_rate));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_settings() {
+        let machine = SerialMachine::new("/dev/ttyACM0");
+        assert_eq!(machine.port_name, "/dev/ttyACM0");
+        assert_eq!(machine.settings().baud_rate, 9600);
+        assert_eq!(machine.read_rate, 10);
+        assert_eq!(machine.buf_size, 6);
+        assert!(machine.port.is_none());
+    }
+
+    #[test]
+    fn configured_settings() {
+        let machine = SerialMachine::new("COM3")
+            .with_baud_rate(115200)
+            .with_read_rate(2)
+            .with_buf_size(1);
+        assert_eq!(machine.port_name, "COM3");
+        assert_eq!(machine.settings().baud_rate, 115200);
+        assert_eq!(machine.read_rate, 2);
+        assert_eq!(machine.buf_size, 1);
+        // the other settings are the defaults
+        assert_eq!(
+            machine.settings().timeout,
+            SerialPortSettings::default().timeout
+        );
+    }
+
+    #[test]
+    fn missing_port_is_disconnected() {
+        let mut machine = SerialMachine::new("/dev/plojo-missing-port");
+        assert!(machine.open().is_err());
+        assert!(matches!(
+            machine.try_read(),
+            Err(MachineError::Disconnected)
+        ));
+    }
+
+    #[test]
+    fn reopen_on_another_port() {
+        let mut machine = SerialMachine::new("/dev/plojo-missing-port").with_baud_rate(115200);
+        assert!(machine.reopen("/dev/plojo-other-missing-port").is_err());
+        assert_eq!(machine.port_name(), "/dev/plojo-other-missing-port");
+        assert_eq!(machine.settings().baud_rate, 115200);
+        assert!(machine.port.is_none());
+    }
 }
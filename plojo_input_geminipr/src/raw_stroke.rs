@@ -1,143 +1,144 @@
 use plojo_core::{RawStroke, Stroke};
 
-// for reference
-/*
-const STENO_KEY_CHART: [[&str; 7]; 6] = [
-    ["Fn", "#1", "#2", "#3", "#4", "#5", "#6"],
-    ["S1-", "S2-", "T-", "K-", "P-", "W-", "H-"],
-    ["R-", "A-", "O-", "*1", "*2", "res1", "res2"],
-    ["pwr", "*3", "*4", "-E", "-U", "-F", "-R"],
+/// The standard Gemini PR layout. Each row is a byte of the packet (without its first bit, which
+/// only marks the start of the packet). The keys that aren't used are empty
+const STANDARD_LAYOUT: [[&str; 7]; 6] = [
+    // Fn, #1 to #6
+    ["", "#", "#", "#", "#", "#", "#"],
+    ["S-", "S-", "T-", "K-", "P-", "W-", "H-"],
+    // R, A, O, *1, *2, and 2 reserved keys
+    ["R-", "A-", "O-", "*", "*", "", ""],
+    // power, *3, *4, E, U, F, R
+    ["", "*", "*", "-E", "-U", "-F", "-R"],
     ["-P", "-B", "-L", "-G", "-T", "-S", "-D"],
-    ["#7", "#8", "#9", "#A", "#B", "#C", "-Z"],
+    // #7 to #9, #A to #C, Z
+    ["#", "#", "#", "", "", "", "-Z"],
 ];
-*/
 
-/// Parse a raw byte vector into a stroke
-///
-/// # Panics
-///
-/// Panics if the vector passed in does not have a length of 6
-#[rustfmt::skip]
-pub fn parse_raw(raw: &Vec<u8>) -> Stroke {
-    assert_eq!(raw.len(), 6);
-    // checks if the most significant bit is positive
-    fn msb_pos(byte: u8) -> bool {
-        byte > 127
-    }
-
-    let mut raw_stroke: RawStroke = Default::default();
-    let mut bytes = raw.iter();
-
-    // first row: number keys 1-6
-    if let Some(row) = bytes.next() {
-        // always discard the first bit
-        let mut row = row << 1;
-
-        // discard the next bit, which is the "Fn" key
-        row = row << 1;
+/// A steno key that a bit of the packet can be mapped to
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum StenoKey {
+    Num,
+    Star,
+    Left(char),
+    CenterLeft(char),
+    CenterRight(char),
+    Right(char),
+}
 
-        // the next 6 bits are number keys
-        for _ in 0..6 {
-            // any of them can trigger a number stroke
-            if msb_pos(row) {
-                raw_stroke.num_key = true;
-                break;
-            }
-            row = row << 1;
+const LEFT_KEYS: &str = "STKPWHR";
+const CENTER_LEFT_KEYS: &str = "AO";
+const CENTER_RIGHT_KEYS: &str = "EU";
+const RIGHT_KEYS: &str = "FRPBLGTSDZ";
+
+impl StenoKey {
+    /// Parse a key written like in plover (ex: "S-", "-E", "#", or "*")
+    fn parse(key: &str) -> Option<Self> {
+        let mut chars = key.chars();
+        match (chars.next()?, chars.next(), chars.next()) {
+            ('#', None, None) => Some(Self::Num),
+            ('*', None, None) => Some(Self::Star),
+            ('-', Some(c), None) if CENTER_RIGHT_KEYS.contains(c) => Some(Self::CenterRight(c)),
+            ('-', Some(c), None) if RIGHT_KEYS.contains(c) => Some(Self::Right(c)),
+            (c, Some('-'), None) if LEFT_KEYS.contains(c) => Some(Self::Left(c)),
+            (c, Some('-'), None) if CENTER_LEFT_KEYS.contains(c) => Some(Self::CenterLeft(c)),
+            _ => None,
         }
     }
+}
 
-    // second row: left hand S- to H-
-    if let Some(row) = bytes.next() {
-        // always discard the first bit
-        let mut row = row << 1;
-
-        // first and second S- key
-        let mut s_key = false;
-        if msb_pos(row) { s_key = true; } row = row << 1;
-        if msb_pos(row) { s_key = true; } row = row << 1;
-        if s_key {
-            raw_stroke.left_hand.push('S');
-        }
+/// Which steno key each bit of a Gemini PR packet is for, so that machines that send the number
+/// bar or star key in a different place can be read. Defaults to the standard Gemini PR layout
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyLayout {
+    keys: [[Option<StenoKey>; 7]; 6],
+}
 
-        // the next are T, K, P, W, and H
-        if msb_pos(row) { raw_stroke.left_hand.push('T'); } row = row << 1;
-        if msb_pos(row) { raw_stroke.left_hand.push('K'); } row = row << 1;
-        if msb_pos(row) { raw_stroke.left_hand.push('P'); } row = row << 1;
-        if msb_pos(row) { raw_stroke.left_hand.push('W'); } row = row << 1;
-        if msb_pos(row) { raw_stroke.left_hand.push('H'); }
+impl KeyLayout {
+    /// Create a layout from the key of each bit, by byte (without the first bit of each byte).
+    /// Keys are written like in plover (ex: "S-", "-E", "#", or "*"), and an empty string is a
+    /// key that is ignored. Returns the first key that isn't a steno key if there is one
+    pub fn new(layout: &[[&str; 7]; 6]) -> Result<Self, String> {
+        let mut keys = [[None; 7]; 6];
+        for (row, layout_row) in keys.iter_mut().zip(layout.iter()) {
+            for (key, name) in row.iter_mut().zip(layout_row.iter()) {
+                if !name.is_empty() {
+                    *key = Some(StenoKey::parse(name).ok_or_else(|| name.to_string())?);
+                }
+            }
+        }
+        Ok(Self { keys })
     }
+}
 
-    // third row: R, A, O, 1*, 2*, and 2 useless keys
-    if let Some(row) = bytes.next() {
-        // always discard the first bit
-        let mut row = row << 1;
-
-        // R, A, and O
-        if msb_pos(row) { raw_stroke.left_hand.push('R'); } row = row << 1;
-        if msb_pos(row) { raw_stroke.center_left.push('A'); } row = row << 1;
-        if msb_pos(row) { raw_stroke.center_left.push('O'); } row = row << 1;
-
-        // first and second star key
-        if msb_pos(row) { raw_stroke.star_key = true; } row = row << 1;
-        if msb_pos(row) { raw_stroke.star_key = true; }
+impl Default for KeyLayout {
+    fn default() -> Self {
+        Self::new(&STANDARD_LAYOUT).unwrap()
     }
+}
 
-    // fourth row: useless key, 3rd and 4th star, and E, U, F, R
-    if let Some(row) = bytes.next() {
-        // always discard the first bit
-        let mut row = row << 1;
-
-        // discard useless power key
-        row = row << 1;
-
-        // 3rd and 4th star
-        if msb_pos(row) { raw_stroke.star_key = true; } row = row << 1;
-        if msb_pos(row) { raw_stroke.star_key = true; } row = row << 1;
+// how many bytes are in a Gemini PR packet
+const PACKET_LEN: usize = 6;
+
+/// Take the next packet out of the bytes read from the machine. A packet is 6 bytes where only the
+/// first byte has the most significant bit set. Bytes before the start of a packet (or a packet that
+/// is cut off by the start of another one) are out of sync with the machine, so they are skipped.
+/// Returns None if there isn't a full packet yet
+pub fn take_packet(bytes: &mut Vec<u8>) -> Option<Vec<u8>> {
+    loop {
+        // skip to the start of the next packet
+        let start = bytes.iter().position(|b| *b > 127).unwrap_or(bytes.len());
+        bytes.drain(..start);
+        if bytes.len() < PACKET_LEN {
+            return None;
+        }
 
-        // E, U, F, R
-        if msb_pos(row) { raw_stroke.center_right.push('E'); } row = row << 1;
-        if msb_pos(row) { raw_stroke.center_right.push('U'); } row = row << 1;
-        if msb_pos(row) { raw_stroke.right_hand.push('F'); } row = row << 1;
-        if msb_pos(row) { raw_stroke.right_hand.push('R'); }
+        match bytes[1..PACKET_LEN].iter().position(|b| *b > 127) {
+            // another packet starts before this one is finished
+            Some(next) => {
+                bytes.drain(..=next);
+            }
+            None => return Some(bytes.drain(..PACKET_LEN).collect()),
+        }
     }
+}
 
-    // fifth row: P, B, L, G, T, S, D
-    if let Some(row) = bytes.next() {
-        // always discard the first bit
-        let mut row = row << 1;
-
-        if msb_pos(row) { raw_stroke.right_hand.push('P'); } row = row << 1;
-        if msb_pos(row) { raw_stroke.right_hand.push('B'); } row = row << 1;
-        if msb_pos(row) { raw_stroke.right_hand.push('L'); } row = row << 1;
-        if msb_pos(row) { raw_stroke.right_hand.push('G'); } row = row << 1;
-        if msb_pos(row) { raw_stroke.right_hand.push('T'); } row = row << 1;
-        if msb_pos(row) { raw_stroke.right_hand.push('S'); } row = row << 1;
-        if msb_pos(row) { raw_stroke.right_hand.push('D'); }
-    }
+/// Parse the raw bytes of a packet into a stroke, using the layout to find the key of each bit
+///
+/// # Panics
+///
+/// Panics if the bytes passed in do not have a length of 6
+pub fn parse_raw(raw: &[u8], layout: &KeyLayout) -> Stroke {
+    assert_eq!(raw.len(), 6);
 
-    // sixth row: number keys 7-9, A-C (?), and -Z key
-    if let Some(row) = bytes.next() {
+    let mut pressed = Vec::new();
+    for (byte, row) in raw.iter().zip(layout.keys.iter()) {
         // always discard the first bit
-        let mut row = row << 1;
-
-        // number keys 7-9
-        for _ in 0..3 {
-            if msb_pos(row) {
-                raw_stroke.num_key = true;
+        let mut byte = byte << 1;
+        for key in row {
+            // checks if the most significant bit is set
+            if byte > 127 {
+                pressed.extend(key);
             }
-            row = row << 1;
-        }
-
-        // ignore A-C number keys
-        for _ in 0..3 {
-            row = row << 1;
+            byte <<= 1;
         }
-
-        // Z key
-        if msb_pos(row) { raw_stroke.right_hand.push('Z'); }
     }
 
+    // the keys can be in any order in the layout, so add them in steno order
+    let keys = |keys: &str, to_key: fn(char) -> StenoKey| -> String {
+        keys.chars()
+            .filter(|c| pressed.contains(&to_key(*c)))
+            .collect()
+    };
+    let raw_stroke = RawStroke {
+        num_key: pressed.contains(&StenoKey::Num),
+        left_hand: keys(LEFT_KEYS, StenoKey::Left),
+        center_left: keys(CENTER_LEFT_KEYS, StenoKey::CenterLeft),
+        star_key: pressed.contains(&StenoKey::Star),
+        center_right: keys(CENTER_RIGHT_KEYS, StenoKey::CenterRight),
+        right_hand: keys(RIGHT_KEYS, StenoKey::Right),
+    };
+
     // convert raw stroke to stroke
     raw_stroke.into()
 }
@@ -148,16 +149,86 @@ mod tests {
 
     #[test]
     fn test_basic_stroke_parsing() {
-        assert_eq!(parse_raw(&vec![128, 48, 36, 0, 2, 0]), Stroke::new("STA*S"));
-        assert_eq!(parse_raw(&vec![160, 2, 0, 0, 32, 64]), Stroke::new("#W-B"));
+        let parse = |raw: [u8; 6]| parse_raw(&raw, &KeyLayout::default());
+        assert_eq!(parse([128, 48, 36, 0, 2, 0]), Stroke::new("STA*S"));
+        assert_eq!(parse([160, 2, 0, 0, 32, 64]), Stroke::new("#W-B"));
         assert_eq!(
-            parse_raw(&vec![160, 127, 124, 63, 127, 65]),
+            parse([160, 127, 124, 63, 127, 65]),
             Stroke::new("12K3W4R50*EU6R7B8G9SDZ")
         );
-        assert_eq!(parse_raw(&vec![128, 21, 0, 0, 0, 0]), Stroke::new("TPH"));
-        assert_eq!(parse_raw(&vec![128, 0, 64, 0, 64, 0]), Stroke::new("R-P"));
-        assert_eq!(parse_raw(&vec![128, 1, 0, 2, 0, 64]), Stroke::new("4-6"));
-        assert_eq!(parse_raw(&vec![128, 1, 32, 2, 0, 64]), Stroke::new("456"));
-        assert_eq!(parse_raw(&vec![128, 68, 0, 0, 4, 64]), Stroke::new("13-9"));
+        assert_eq!(parse([128, 21, 0, 0, 0, 0]), Stroke::new("TPH"));
+        assert_eq!(parse([128, 0, 64, 0, 64, 0]), Stroke::new("R-P"));
+        assert_eq!(parse([128, 1, 0, 2, 0, 64]), Stroke::new("4-6"));
+        assert_eq!(parse([128, 1, 32, 2, 0, 64]), Stroke::new("456"));
+        assert_eq!(parse([128, 68, 0, 0, 4, 64]), Stroke::new("13-9"));
+        // Fn, power, reserved and #A to #C keys are ignored
+        assert_eq!(parse([192, 0, 3, 64, 0, 14]), Stroke::new(""));
+    }
+
+    #[test]
+    fn test_remapped_layout() {
+        // the number bar is only the #A to #C keys and the reserved keys are star keys
+        let layout = KeyLayout::new(&[
+            ["", "", "", "", "", "", ""],
+            ["S-", "S-", "T-", "K-", "P-", "W-", "H-"],
+            ["R-", "A-", "O-", "", "", "*", "*"],
+            ["", "", "", "-E", "-U", "-F", "-R"],
+            ["-P", "-B", "-L", "-G", "-T", "-S", "-D"],
+            ["", "", "", "#", "#", "#", "-Z"],
+        ])
+        .unwrap();
+        let parse = |raw: [u8; 6]| parse_raw(&raw, &layout);
+        assert_eq!(parse([128, 48, 36, 0, 2, 0]), Stroke::new("STAS"));
+        assert_eq!(parse([160, 2, 0, 0, 32, 64]), Stroke::new("W-B"));
+        assert_eq!(parse([128, 0, 3, 0, 2, 0]), Stroke::new("*S"));
+        assert_eq!(parse([128, 21, 0, 0, 0, 8]), Stroke::new("234"));
+        assert_eq!(parse([128, 0, 64, 0, 64, 4]), Stroke::new("R-7"));
+
+        // the keys are put in steno order
+        let mut reversed = STANDARD_LAYOUT;
+        reversed[1].reverse();
+        let layout = KeyLayout::new(&reversed).unwrap();
+        assert_eq!(
+            parse_raw(&[128, 21, 0, 0, 0, 0], &layout),
+            Stroke::new("STP")
+        );
+    }
+
+    #[test]
+    fn test_invalid_layout() {
+        let mut layout = STANDARD_LAYOUT;
+        layout[2][5] = "Q-";
+        assert_eq!(KeyLayout::new(&layout), Err("Q-".to_string()));
+        layout[2][5] = "-A";
+        assert_eq!(KeyLayout::new(&layout), Err("-A".to_string()));
+        layout[2][5] = "S";
+        assert_eq!(KeyLayout::new(&layout), Err("S".to_string()));
+    }
+
+    #[test]
+    fn test_take_packet() {
+        let mut bytes = vec![128, 48, 36, 0, 2, 0, 128, 0];
+        assert_eq!(take_packet(&mut bytes), Some(vec![128, 48, 36, 0, 2, 0]));
+        // the start of the next packet is kept until the rest of it is read
+        assert_eq!(take_packet(&mut bytes), None);
+        assert_eq!(bytes, vec![128, 0]);
+        bytes.extend(&[1, 2, 3, 4]);
+        assert_eq!(take_packet(&mut bytes), Some(vec![128, 0, 1, 2, 3, 4]));
+        assert!(bytes.is_empty());
+    }
+
+    #[test]
+    fn test_take_packet_resyncs() {
+        // bytes from the middle of a packet are skipped
+        let mut bytes = vec![36, 0, 2, 0, 128, 48, 36, 0, 2, 0];
+        assert_eq!(take_packet(&mut bytes), Some(vec![128, 48, 36, 0, 2, 0]));
+
+        // a packet that is cut off by the start of the next one is skipped
+        let mut bytes = vec![128, 48, 36, 129, 0, 0, 0, 0, 1];
+        assert_eq!(take_packet(&mut bytes), Some(vec![129, 0, 0, 0, 0, 1]));
+
+        let mut bytes = vec![1, 2, 3, 4, 5, 6, 7];
+        assert_eq!(take_packet(&mut bytes), None);
+        assert!(bytes.is_empty());
     }
 }
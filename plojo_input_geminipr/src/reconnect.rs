@@ -0,0 +1,116 @@
+use std::{cmp, fmt::Display, time::Duration};
+
+const INITIAL_DELAY: Duration = Duration::from_millis(100);
+const MAX_DELAY: Duration = Duration::from_secs(5);
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Tries to reopen a disconnected machine with exponential backoff until it opens or the timeout
+/// is reached
+pub(crate) struct Reconnect {
+    /// How long to wait after the first failed attempt
+    initial_delay: Duration,
+    /// The delay doubles after every failed attempt, up to this much
+    max_delay: Duration,
+    /// Give up once the delays between attempts add up to this much
+    timeout: Duration,
+}
+
+impl Default for Reconnect {
+    fn default() -> Self {
+        Self {
+            initial_delay: INITIAL_DELAY,
+            max_delay: MAX_DELAY,
+            timeout: TIMEOUT,
+        }
+    }
+}
+
+impl Reconnect {
+    /// Call `open` until it succeeds, waiting with `sleep` between attempts. Returns None if it
+    /// still fails after the timeout
+    pub fn run<T, E: Display>(
+        &self,
+        mut open: impl FnMut() -> Result<T, E>,
+        mut sleep: impl FnMut(Duration),
+    ) -> Option<T> {
+        let mut delay = self.initial_delay;
+        let mut waited = Duration::from_secs(0);
+        let mut attempt = 1;
+
+        loop {
+            match open() {
+                Ok(opened) => {
+                    println!("[INFO] Machine reconnected after {} attempt(s)", attempt);
+                    return Some(opened);
+                }
+                Err(e) => println!("[INFO] Reconnect attempt {} failed: {}", attempt, e),
+            }
+
+            if waited >= self.timeout {
+                println!("[WARN] Giving up on reconnecting after {:?}", waited);
+                return None;
+            }
+
+            sleep(delay);
+            waited += delay;
+            delay = cmp::min(delay * 2, self.max_delay);
+            attempt += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reconnect() -> Reconnect {
+        Reconnect {
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(500),
+            timeout: Duration::from_secs(2),
+        }
+    }
+
+    /// A port opener that fails a number of times before opening
+    fn fake_opener(failures: usize) -> impl FnMut() -> Result<&'static str, &'static str> {
+        let mut attempts = 0;
+        move || {
+            attempts += 1;
+            if attempts > failures {
+                Ok("port")
+            } else {
+                Err("port not found")
+            }
+        }
+    }
+
+    fn millis(delays: &[u64]) -> Vec<Duration> {
+        delays.iter().map(|&d| Duration::from_millis(d)).collect()
+    }
+
+    #[test]
+    fn opens_immediately() {
+        let mut delays = Vec::new();
+        let opened = reconnect().run(fake_opener(0), |d| delays.push(d));
+        assert_eq!(opened, Some("port"));
+        assert!(delays.is_empty());
+    }
+
+    #[test]
+    fn backs_off_until_opened() {
+        let mut delays = Vec::new();
+        let opened = reconnect().run(fake_opener(5), |d| delays.push(d));
+        assert_eq!(opened, Some("port"));
+        // doubles until the max delay
+        assert_eq!(delays, millis(&[100, 200, 400, 500, 500]));
+    }
+
+    #[test]
+    fn gives_up_after_timeout() {
+        let mut delays = Vec::new();
+        let opened = reconnect().run(fake_opener(usize::MAX), |d| delays.push(d));
+        assert_eq!(opened, None);
+        // tries once more after the delays add up to the timeout
+        assert_eq!(delays, millis(&[100, 200, 400, 500, 500, 500]));
+    }
+}
@@ -1,31 +1,63 @@
 #[macro_use]
 extern crate lazy_static;
 
-use plojo_core::{Machine, RawStroke, Stroke};
+use plojo_core::{InputSwitch, Machine, MachineError, RawStroke, Stroke};
 use rdev::{Event, EventType};
+use serde::{de::IntoDeserializer, Deserialize};
 use std::{
     collections::HashSet,
     error::Error,
+    fmt,
     hash::Hash,
     iter::FromIterator,
+    mem,
     sync::{
         mpsc,
-        mpsc::{Receiver, Sender},
+        mpsc::{Receiver, RecvTimeoutError, Sender},
         Arc, Mutex,
     },
+    time::{Duration, Instant},
 };
 
-#[derive(Debug, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 struct Key(String);
 
 lazy_static! {
     static ref IS_DISABLED: Mutex<bool> = Mutex::new(false);
+    // shared with the event handler, which can only be a fn pointer
+    static ref MODIFIER_PASSTHROUGH: Mutex<ModifierPassthrough> =
+        Mutex::new(ModifierPassthrough::default());
+    static ref NON_STENO_PASSTHROUGH: Mutex<NonStenoPassthrough> =
+        Mutex::new(NonStenoPassthrough::default());
 }
 
 impl Key {
     fn new(key: rdev::Key) -> Self {
         Self(format!("{:?}", key))
     }
+
+    /// Parses a key from the name of an `rdev::Key` variant (such as `KeyQ` or `SemiColon`)
+    fn parse(name: &str) -> Result<Self, LayoutError> {
+        let deserializer: serde::de::value::StrDeserializer<serde::de::value::Error> =
+            name.into_deserializer();
+        rdev::Key::deserialize(deserializer)
+            .map(Self::new)
+            .map_err(|_| LayoutError::UnknownKey(name.to_string()))
+    }
+}
+
+/// When the keys that are pressed and released become a stroke
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChordResolution {
+    /// The stroke is every key that was pressed, once all of them are released
+    #[default]
+    AllUp,
+    /// The stroke is every key that is held down, as soon as one of them is released
+    FirstUp,
+    /// Keys are pressed one at a time (or rolled) and the stroke is sent as soon as the trigger
+    /// key (space by default) is pressed
+    Arpeggiate,
 }
 
 /// Listen to the keyboard as a steno machine
@@ -34,19 +66,52 @@ impl Key {
 pub struct KeyboardMachine {
     down_keys: HashSet<Key>,
     up_keys: HashSet<Key>,
+    // keys that are still held down after the stroke they are in was sent on first up
+    sent_keys: HashSet<Key>,
+    chord_resolution: ChordResolution,
+    // the key that sends the stroke when arpeggiating
+    arpeggiate_trigger: Key,
     stroke: Option<Stroke>,
     reenable_shortcuts: Vec<Shortcut>,
+    // key ups followed by a key down of the same key within this window are ignored
+    debounce: Option<Duration>,
+    // a key up that is held back until the debounce window passes
+    pending_release: Option<(Key, Instant)>,
+    // called as soon as a stroke is formed, before it is read
+    on_stroke: Option<StrokeCallback>,
+    layout: Layout,
+    // keys held down without any changes for this long are sent as a stroke while they are held
+    hold_delay: Option<Duration>,
+    // keys held down without any keys being pressed or released for this long are sent as a
+    // stroke, in case a key is stuck down or a key up was missed
+    idle_timeout: Option<Duration>,
+    // when a key was last pressed or released
+    last_key_at: Option<Instant>,
+    // whether the keys of the last stroke were sent while held and are still held
+    held: bool,
 }
 
 type Shortcut = HashSet<String>;
+type StrokeCallback = Box<dyn FnMut(&Stroke, Instant) + Send>;
 
 impl Default for KeyboardMachine {
     fn default() -> Self {
         Self {
             down_keys: HashSet::new(),
             up_keys: HashSet::new(),
+            sent_keys: HashSet::new(),
+            chord_resolution: ChordResolution::default(),
+            arpeggiate_trigger: Key::new(rdev::Key::Space),
             stroke: None,
             reenable_shortcuts: Vec::new(),
+            debounce: None,
+            pending_release: None,
+            on_stroke: None,
+            layout: Layout::steno_querty(),
+            hold_delay: None,
+            idle_timeout: None,
+            last_key_at: None,
+            held: false,
         }
     }
 }
@@ -64,47 +129,279 @@ impl KeyboardMachine {
         self
     }
 
+    /// Ignore key chatter: a key up that is followed by a key down of the same key within the
+    /// debounce window. Key ups are delayed by up to this much when it is enabled
+    pub fn with_debounce(mut self, debounce: Duration) -> Self {
+        self.debounce = Some(debounce);
+        self
+    }
+
+    /// Call a function whenever a stroke is formed with the time its last key was released. This
+    /// fires before the stroke is read (and translated), so it can be used for live feedback
+    pub fn with_on_stroke(mut self, on_stroke: StrokeCallback) -> Self {
+        self.on_stroke = Some(on_stroke);
+        self
+    }
+
+    /// Use a custom mapping from keyboard keys to steno keys instead of the default qwerty one.
+    /// Fails if any of the key names is not a valid key
+    pub fn with_layout(mut self, layout: LayoutConfig) -> Result<Self, LayoutError> {
+        self.layout = Layout::from_config(&layout)?;
+        self.update_steno_keys();
+        Ok(self)
+    }
+
+    /// Decide when a stroke is finished with this instead of waiting for all the keys to be
+    /// released
+    pub fn with_chord_resolution(mut self, chord_resolution: ChordResolution) -> Self {
+        self.chord_resolution = chord_resolution;
+        self.update_steno_keys();
+        self
+    }
+
+    /// Send the stroke with this key instead of space when arpeggiating. The key is named by its
+    /// `rdev::Key` variant (such as `Return`). Fails if it is not a valid key
+    pub fn with_arpeggiate_trigger(mut self, trigger: &str) -> Result<Self, LayoutError> {
+        self.arpeggiate_trigger = Key::parse(trigger)?;
+        self.update_steno_keys();
+        Ok(self)
+    }
+
+    /// Send the keys that are held down as a stroke once they are held for this long without
+    /// any keys being pressed or released. The machine then reports that the stroke is held until
+    /// one of its keys is released, so that it can be repeated (like holding an arrow key)
+    pub fn with_hold_repeat(mut self, hold_delay: Duration) -> Self {
+        self.hold_delay = Some(hold_delay);
+        self
+    }
+
+    /// Send the keys that are pressed as a stroke if no keys are pressed or released for this long
+    /// while some are still held down (ex: a key is stuck down or its key up was missed), so that
+    /// the machine doesn't wait forever. Releasing them afterwards doesn't send them again
+    pub fn with_idle_timeout(mut self, idle_timeout: Duration) -> Self {
+        self.idle_timeout = Some(idle_timeout);
+        self
+    }
+
+    /// Pass keys through to the system (instead of using them for steno) while a physical
+    /// modifier key (Control, Meta, Alt, or Shift) is held, so that normal keyboard shortcuts can
+    /// be used. Steno resumes once all the modifiers are released
+    pub fn with_modifier_passthrough(self, modifier_passthrough: bool) -> Self {
+        MODIFIER_PASSTHROUGH.lock().unwrap().enabled = modifier_passthrough;
+        self
+    }
+
+    /// Pass keys that are not part of the steno layout (such as Enter or the arrow keys) through
+    /// to the system instead of suppressing them, so that they can still be used for typing
+    pub fn with_non_steno_passthrough(self, non_steno_passthrough: bool) -> Self {
+        NON_STENO_PASSTHROUGH.lock().unwrap().enabled = non_steno_passthrough;
+        self.update_steno_keys();
+        self
+    }
+
+    /// Shares the keys used for steno with the event handler
+    fn update_steno_keys(&self) {
+        let mut steno_keys = self.layout.keys();
+        if self.chord_resolution == ChordResolution::Arpeggiate {
+            steno_keys.insert(self.arpeggiate_trigger.clone());
+        }
+        NON_STENO_PASSTHROUGH.lock().unwrap().steno_keys = steno_keys;
+    }
+
     /// Handles a key pressed down or up
     fn handle_key(&mut self, key: Key, is_down: bool) {
+        self.handle_key_at(key, is_down, Instant::now());
+    }
+
+    /// Handles a key pressed down or up at a certain time
+    fn handle_key_at(&mut self, key: Key, is_down: bool, time: Instant) {
+        if let Some((released, released_at)) = self.pending_release.take() {
+            let is_chatter = time.duration_since(released_at) < self.debounce.unwrap_or_default();
+            if is_down && released == key && is_chatter {
+                // the key was never actually released
+                return;
+            }
+            self.release_key(released, released_at);
+        }
+
+        let is_held = self.down_keys.contains(&key) || self.held && self.sent_keys.contains(&key);
+        if is_down && is_held {
+            // the system repeats the key down of a key that is held
+            return;
+        }
+        self.held = false;
+        self.last_key_at = Some(time);
+
+        if is_down && self.is_arpeggiate_trigger(&key) {
+            // the system repeats the key down while the trigger is held
+            if !self.sent_keys.contains(&key) {
+                self.send_arpeggio(key, time);
+            }
+            return;
+        }
+
         if is_down {
+            // a key that is pressed again is no longer part of the stroke that was sent
+            self.sent_keys.remove(&key);
             self.down_keys.insert(key);
+        } else if self.debounce.is_some() {
+            self.pending_release = Some((key, time));
         } else {
-            if self.down_keys.contains(&key) {
-                self.down_keys.remove(&key);
-            }
-            self.up_keys.insert(key);
+            self.release_key(key, time);
+        }
+    }
+
+    /// Releases the key held back for debouncing if the debounce window has passed. Otherwise
+    /// returns how much longer to wait for a key down that cancels it
+    fn flush_release(&mut self, now: Instant) -> Option<Duration> {
+        let (_, released_at) = self.pending_release.as_ref()?;
+        let debounce = self.debounce.unwrap_or_default();
+        let elapsed = now.duration_since(*released_at);
+        if elapsed < debounce {
+            return Some(debounce - elapsed);
+        }
 
-            // this stroke has ended once all the keys are up
-            if self.down_keys.is_empty() {
-                if self.stroke.is_some() {
-                    panic!("received new stroke but old stroke has not been processed");
+        if let Some((key, released_at)) = self.pending_release.take() {
+            self.release_key(key, released_at);
+        }
+        None
+    }
+
+    /// Sends the keys that are held down as a stroke if they have not changed for the hold delay.
+    /// Otherwise returns how much longer to wait before they are sent
+    fn flush_hold(&mut self, now: Instant) -> Option<Duration> {
+        let hold_delay = self.hold_delay?;
+        match self.send_down_keys_after(hold_delay, now) {
+            Ok(sent) => {
+                if sent {
+                    self.held = self.stroke.is_some();
                 }
+                None
+            }
+            Err(wait) => Some(wait),
+        }
+    }
+
+    /// Sends the keys that are pressed as a stroke if no keys were pressed or released for the idle
+    /// timeout. Otherwise returns how much longer to wait before they are sent
+    fn flush_idle(&mut self, now: Instant) -> Option<Duration> {
+        let idle_timeout = self.idle_timeout?;
+        self.send_down_keys_after(idle_timeout, now).err()
+    }
+
+    /// Sends the keys that are down as a stroke if no keys were pressed or released for the delay
+    /// (unless the keyboard is disabled). Returns whether they were sent, or how much longer to
+    /// wait before they are sent
+    fn send_down_keys_after(&mut self, delay: Duration, now: Instant) -> Result<bool, Duration> {
+        let is_pending = self.pending_release.is_some() || self.stroke.is_some();
+        if self.down_keys.is_empty() || is_pending || *IS_DISABLED.lock().unwrap() {
+            return Ok(false);
+        }
+        let last_key_at = match self.last_key_at {
+            Some(last_key_at) => last_key_at,
+            None => return Ok(false),
+        };
+        let elapsed = now.duration_since(last_key_at);
+        if elapsed < delay {
+            return Err(delay - elapsed);
+        }
+
+        // the keys are sent like on first up, so releasing them doesn't send them again
+        let down = mem::take(&mut self.down_keys);
+        self.up_keys.extend(down.iter().cloned());
+        self.sent_keys.extend(down);
+        self.finish_stroke(now);
+        Ok(true)
+    }
 
-                // check if this stroke reenables shortcuts
-                let mut is_disabled = IS_DISABLED.lock().unwrap();
-                if *is_disabled {
-                    let keys = self
-                        .up_keys
-                        .iter()
-                        .map(|key| key.0.clone())
-                        .collect::<HashSet<_>>();
-                    for shortcut in &self.reenable_shortcuts {
-                        if shortcut == &keys {
-                            *is_disabled = false;
-                            break;
-                        }
-                    }
-                    drop(is_disabled);
-                } else {
-                    drop(is_disabled);
-                    // only send stroke if not currently disabled
-                    let stroke = convert_stroke(&Layout::steno_querty(), &self.up_keys);
-                    self.stroke = stroke;
+    /// Handles a key that was released at a certain time
+    fn release_key(&mut self, key: Key, time: Instant) {
+        // the stroke this key is in was already sent
+        if self.sent_keys.remove(&key) {
+            return;
+        }
+        self.down_keys.remove(&key);
+
+        // shortcuts to reenable the keyboard are always chords
+        let chord_resolution = if *IS_DISABLED.lock().unwrap() {
+            ChordResolution::AllUp
+        } else {
+            self.chord_resolution
+        };
+        match chord_resolution {
+            ChordResolution::AllUp => {
+                self.up_keys.insert(key);
+                // this stroke has ended once all the keys are up
+                if self.down_keys.is_empty() {
+                    self.finish_stroke(time);
                 }
+            }
+            ChordResolution::FirstUp => {
+                self.up_keys.insert(key);
+                // the keys that are still held down are part of this stroke
+                let held = mem::take(&mut self.down_keys);
+                self.up_keys.extend(held.iter().cloned());
+                self.sent_keys = held;
+                self.finish_stroke(time);
+            }
+            ChordResolution::Arpeggiate => {
+                self.up_keys.insert(key);
+            }
+        }
+    }
 
-                self.up_keys.clear();
+    /// Whether pressing this key sends the arpeggiated stroke
+    fn is_arpeggiate_trigger(&self, key: &Key) -> bool {
+        // shortcuts to reenable the keyboard are always chords
+        self.chord_resolution == ChordResolution::Arpeggiate
+            && key == &self.arpeggiate_trigger
+            && !*IS_DISABLED.lock().unwrap()
+    }
+
+    /// Sends every key pressed since the last stroke when the trigger is pressed. Keys that are
+    /// still held down are part of this stroke, so releasing them doesn't add them to the next
+    /// one. Keys pressed after the trigger (even while it is held) start the next stroke
+    fn send_arpeggio(&mut self, trigger: Key, time: Instant) {
+        let held = mem::take(&mut self.down_keys);
+        self.up_keys.extend(held.iter().cloned());
+        self.sent_keys.extend(held);
+        // the trigger is not part of the stroke
+        self.sent_keys.insert(trigger);
+        self.finish_stroke(time);
+    }
+
+    /// Finishes the stroke made of the keys that were released
+    fn finish_stroke(&mut self, time: Instant) {
+        if self.stroke.is_some() {
+            panic!("received new stroke but old stroke has not been processed");
+        }
+
+        // check if this stroke reenables shortcuts
+        let mut is_disabled = IS_DISABLED.lock().unwrap();
+        if *is_disabled {
+            let keys = self
+                .up_keys
+                .iter()
+                .map(|key| key.0.clone())
+                .collect::<HashSet<_>>();
+            for shortcut in &self.reenable_shortcuts {
+                if shortcut == &keys {
+                    *is_disabled = false;
+                    break;
+                }
+            }
+            drop(is_disabled);
+        } else {
+            drop(is_disabled);
+            // only send stroke if not currently disabled
+            let stroke = convert_stroke(&self.layout, &self.up_keys);
+            if let (Some(stroke), Some(on_stroke)) = (&stroke, &mut self.on_stroke) {
+                on_stroke(stroke, time);
             }
+            self.stroke = stroke;
         }
+
+        self.up_keys.clear();
     }
 
     /// Returns the stroke that has been formed or None if the stroke is not ready yet.
@@ -114,6 +411,81 @@ impl KeyboardMachine {
     }
 }
 
+/// Tracks the physically held modifiers to decide which keys pass through to the system
+#[derive(Debug, Default)]
+struct ModifierPassthrough {
+    enabled: bool,
+    held_modifiers: HashSet<Key>,
+    // keys that were passed through when pressed, so their release must be passed through too
+    passed_keys: HashSet<Key>,
+}
+
+impl ModifierPassthrough {
+    /// Updates the held modifiers with a key event. Returns whether the event should be passed
+    /// through to the system instead of being used for steno.
+    ///
+    /// A key that is pressed while a modifier is held passes through until it is released, even
+    /// if the modifier is released first. Keys pressed before the modifier are still used for
+    /// steno when they are released, so that the stroke they are part of is finished
+    fn pass_through(&mut self, key: rdev::Key, is_down: bool) -> bool {
+        if !self.enabled {
+            return false;
+        }
+
+        let is_modifier = is_modifier(key);
+        let key = Key::new(key);
+        if is_modifier {
+            if is_down {
+                self.held_modifiers.insert(key);
+            } else {
+                self.held_modifiers.remove(&key);
+            }
+            // the system must see the modifier for the shortcut to work
+            return true;
+        }
+
+        if is_down {
+            if self.held_modifiers.is_empty() && !self.passed_keys.contains(&key) {
+                return false;
+            }
+            self.passed_keys.insert(key);
+            true
+        } else {
+            self.passed_keys.remove(&key)
+        }
+    }
+}
+
+/// Whether the key is a modifier that is used in keyboard shortcuts
+fn is_modifier(key: rdev::Key) -> bool {
+    matches!(
+        key,
+        rdev::Key::ControlLeft
+            | rdev::Key::ControlRight
+            | rdev::Key::MetaLeft
+            | rdev::Key::MetaRight
+            | rdev::Key::Alt
+            | rdev::Key::AltGr
+            | rdev::Key::ShiftLeft
+            | rdev::Key::ShiftRight
+    )
+}
+
+/// Decides which keys pass through to the system because they are not used for steno
+#[derive(Debug, Default)]
+struct NonStenoPassthrough {
+    enabled: bool,
+    // the keys in the layout (and any other keys the machine needs, like space to arpeggiate)
+    steno_keys: HashSet<Key>,
+}
+
+impl NonStenoPassthrough {
+    /// Whether the key should be passed through to the system instead of being suppressed
+    fn pass_through(&self, key: &Key) -> bool {
+        self.enabled && !self.steno_keys.contains(key)
+    }
+}
+
 /// A mapping from hardware keys to chars to build a stroke
 struct Layout {
     pub left_keys: Vec<(Key, char)>,
@@ -124,7 +496,73 @@ struct Layout {
     pub num_keys: Vec<Key>,
 }
 
+/// A user provided mapping from keyboard keys to steno keys. Keys are named by their
+/// `rdev::Key` variant, such as `KeyQ` or `SemiColon`
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct LayoutConfig {
+    pub left_keys: Vec<(String, char)>,
+    pub center_left_keys: Vec<(String, char)>,
+    pub star_keys: Vec<String>,
+    pub center_right_keys: Vec<(String, char)>,
+    pub right_keys: Vec<(String, char)>,
+    pub num_keys: Vec<String>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum LayoutError {
+    UnknownKey(String),
+}
+
+impl fmt::Display for LayoutError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LayoutError::UnknownKey(name) => {
+                write!(f, "unknown key in keyboard layout: {:?}", name)
+            }
+        }
+    }
+}
+
+impl Error for LayoutError {}
+
 impl Layout {
+    fn from_config(config: &LayoutConfig) -> Result<Self, LayoutError> {
+        fn parse_pairs(pairs: &[(String, char)]) -> Result<Vec<(Key, char)>, LayoutError> {
+            pairs
+                .iter()
+                .map(|(name, c)| Ok((Key::parse(name)?, *c)))
+                .collect()
+        }
+        fn parse_keys(names: &[String]) -> Result<Vec<Key>, LayoutError> {
+            names.iter().map(|name| Key::parse(name)).collect()
+        }
+
+        Ok(Self {
+            left_keys: parse_pairs(&config.left_keys)?,
+            center_left_keys: parse_pairs(&config.center_left_keys)?,
+            star_keys: parse_keys(&config.star_keys)?,
+            center_right_keys: parse_pairs(&config.center_right_keys)?,
+            right_keys: parse_pairs(&config.right_keys)?,
+            num_keys: parse_keys(&config.num_keys)?,
+        })
+    }
+
+    /// All the keys that are part of the layout
+    fn keys(&self) -> HashSet<Key> {
+        let pairs = self
+            .left_keys
+            .iter()
+            .chain(&self.center_left_keys)
+            .chain(&self.center_right_keys)
+            .chain(&self.right_keys)
+            .map(|(k, _)| k);
+        pairs
+            .chain(&self.star_keys)
+            .chain(&self.num_keys)
+            .cloned()
+            .collect()
+    }
+
     fn steno_querty() -> Self {
         Self {
             left_keys: vec![
@@ -244,27 +682,92 @@ lazy_static! {
     };
 }
 
+/// Disables and enables the keyboard machine from any thread. The keyboard is disabled globally, so this works
+/// even while the machine is waiting for a key
+struct KeyboardSwitch;
+
+impl InputSwitch for KeyboardSwitch {
+    fn disable(&self) {
+        *IS_DISABLED.lock().unwrap() = true;
+    }
+
+    fn enable(&self) {
+        *IS_DISABLED.lock().unwrap() = false;
+    }
+}
+
 impl Machine for KeyboardMachine {
-    fn read(&mut self) -> Result<Stroke, Box<dyn Error>> {
+    fn read(&mut self) -> Result<Stroke, MachineError> {
         loop {
-            let receiver = PASSER.1.lock().unwrap();
-            // wait for the next key
-            if let Ok((key, is_down)) = receiver.recv() {
-                self.handle_key(key, is_down);
-            }
-
-            // if this key finished the stroke, return it
+            // a held back key up (or keys that are held down) could have finished the stroke
+            let now = Instant::now();
+            let release_timeout = self.flush_release(now);
+            let hold_timeout = self.flush_hold(now);
+            let idle_timeout = self.flush_idle(now);
             if let Some(stroke) = self.get_stroke() {
                 return Ok(stroke);
             }
+            let timeout = release_timeout
+                .into_iter()
+                .chain(hold_timeout)
+                .chain(idle_timeout)
+                .min();
+
+            let receiver = PASSER.1.lock().unwrap();
+            // wait for the next key, or until the held back key up (or held keys) can be sent
+            match timeout {
+                Some(timeout) => match receiver.recv_timeout(timeout) {
+                    Ok((key, is_down)) => self.handle_key(key, is_down),
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => return Err(MachineError::Disconnected),
+                },
+                None => {
+                    let (key, is_down) = receive_key(&receiver)?;
+                    self.handle_key(key, is_down);
+                }
+            }
         }
     }
 
+    /// Stops suppressing the keys so that the keyboard can be used to type normally. The keys are
+    /// still read to check for a reenable shortcut
     fn disable(&self) {
-        *IS_DISABLED.lock().unwrap() = true;
+        KeyboardSwitch.disable();
+    }
+
+    fn input_switch(&self) -> Option<Arc<dyn InputSwitch>> {
+        Some(Arc::new(KeyboardSwitch))
+    }
+
+    fn enable(&self) {
+        KeyboardSwitch.enable();
+    }
+
+    fn wait_held(&mut self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        while self.held {
+            let now = Instant::now();
+            if now >= deadline {
+                return true;
+            }
+
+            let receiver = PASSER.1.lock().unwrap();
+            match receiver.recv_timeout(deadline - now) {
+                Ok((key, is_down)) => self.handle_key(key, is_down),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => return false,
+            }
+        }
+        false
     }
 }
 
+/// Wait for the next key from the event listener. If the listener has stopped, the keyboard is
+/// treated as disconnected
+fn receive_key(receiver: &Receiver<(Key, bool)>) -> Result<(Key, bool), MachineError> {
+    receiver.recv().map_err(|_| MachineError::Disconnected)
+}
+
 /// Handle a native event
 ///
 /// This is used in rdev::listen, which only takes a fn pointer, which forces me to use Arc<Mutex>
@@ -282,18 +785,50 @@ fn handle_event(event: Event) -> Option<Event> {
         }
     };
 
-    let sender = PASSER.0.lock().unwrap();
-    sender.send((Key::new(key), is_down)).unwrap();
+    let route = route_key(key, is_down);
+    if route.to_machine {
+        let sender = PASSER.0.lock().unwrap();
+        sender.send((Key::new(key), is_down)).unwrap();
+    }
+
+    if route.to_system {
+        Some(event)
+    } else {
+        // suppress the event
+        None
+    }
+}
+
+/// Where a key that was pressed or released goes
+#[derive(Debug, PartialEq)]
+struct KeyRoute {
+    to_machine: bool,
+    // otherwise the key is suppressed
+    to_system: bool,
+}
+
+/// Decides where a key goes based on the passthrough settings and whether the machine is disabled
+fn route_key(key: rdev::Key, is_down: bool) -> KeyRoute {
+    let pass_through = MODIFIER_PASSTHROUGH
+        .lock()
+        .unwrap()
+        .pass_through(key, is_down);
+    // the machine still gets keys that aren't steno keys because they can be part of a reenable
+    // shortcut
+    let non_steno = NON_STENO_PASSTHROUGH
+        .lock()
+        .unwrap()
+        .pass_through(&Key::new(key));
 
-    if *IS_DISABLED.lock().unwrap() {
+    KeyRoute {
+        // keys pressed with a modifier are not part of a stroke, but the modifiers are still sent
+        // because they can be part of a reenable shortcut
+        to_machine: !pass_through || is_modifier(key),
         // Don't suppress the event if keyboard is disabled
         // This allows key press to "pass through" so the keyboard input seems disabled
         // However, we still need to pass keys to sender to detect when to re-enable the keyboard
-        return Some(event);
+        to_system: pass_through || non_steno || *IS_DISABLED.lock().unwrap(),
     }
-
-    // suppress the event
-    None
 }
 
 #[cfg(test)]
@@ -301,6 +836,29 @@ mod tests {
     use super::*;
     use serial_test::serial;
 
+    #[test]
+    fn machine_is_send() {
+        // so that it can be read on its own thread along with other machines
+        fn assert_send<T: Send>() {}
+        assert_send::<KeyboardMachine>();
+    }
+
+    #[test]
+    fn stopped_listener_is_disconnected() {
+        let (sender, receiver) = mpsc::channel();
+        sender.send((Key::new(rdev::Key::KeyQ), true)).unwrap();
+        drop(sender);
+
+        assert_eq!(
+            receive_key(&receiver).unwrap(),
+            (Key::new(rdev::Key::KeyQ), true)
+        );
+        assert!(matches!(
+            receive_key(&receiver),
+            Err(MachineError::Disconnected)
+        ));
+    }
+
     #[test]
     #[serial]
     fn convert_stroke_basic() {
@@ -428,6 +986,531 @@ mod tests {
         assert_eq!(m.get_stroke().unwrap(), Stroke::new("2-R9"));
     }
 
+    #[test]
+    #[serial]
+    fn handle_key_first_up() {
+        let mut m = KeyboardMachine::new().with_chord_resolution(ChordResolution::FirstUp);
+        m.handle_key(Key::new(rdev::Key::KeyQ), true);
+        m.handle_key(Key::new(rdev::Key::KeyW), true);
+        m.handle_key(Key::new(rdev::Key::KeyW), false);
+        assert_eq!(m.get_stroke().unwrap(), Stroke::new("ST"));
+
+        // Q is still held, but it was already sent
+        m.handle_key(Key::new(rdev::Key::KeyU), true);
+        m.handle_key(Key::new(rdev::Key::KeyQ), false);
+        assert!(m.get_stroke().is_none());
+        m.handle_key(Key::new(rdev::Key::KeyU), false);
+        assert_eq!(m.get_stroke().unwrap(), Stroke::new("-F"));
+
+        // a sent key that is pressed again is in the next stroke
+        m.handle_key(Key::new(rdev::Key::KeyQ), true);
+        m.handle_key(Key::new(rdev::Key::KeyW), true);
+        m.handle_key(Key::new(rdev::Key::KeyQ), false);
+        assert_eq!(m.get_stroke().unwrap(), Stroke::new("ST"));
+        m.handle_key(Key::new(rdev::Key::KeyQ), true);
+        m.handle_key(Key::new(rdev::Key::KeyW), false);
+        assert!(m.get_stroke().is_none());
+        m.handle_key(Key::new(rdev::Key::KeyQ), false);
+        assert_eq!(m.get_stroke().unwrap(), Stroke::new("S"));
+    }
+
+    #[test]
+    #[serial]
+    fn handle_key_arpeggiate() {
+        let mut m = KeyboardMachine::new().with_chord_resolution(ChordResolution::Arpeggiate);
+        m.handle_key(Key::new(rdev::Key::KeyQ), true);
+        m.handle_key(Key::new(rdev::Key::KeyQ), false);
+        m.handle_key(Key::new(rdev::Key::KeyW), true);
+        m.handle_key(Key::new(rdev::Key::KeyW), false);
+        assert!(m.get_stroke().is_none());
+        m.handle_key(Key::new(rdev::Key::Space), true);
+        m.handle_key(Key::new(rdev::Key::Space), false);
+        assert_eq!(m.get_stroke().unwrap(), Stroke::new("ST"));
+
+        // space without any keys is not a stroke
+        m.handle_key(Key::new(rdev::Key::Space), true);
+        m.handle_key(Key::new(rdev::Key::Space), false);
+        assert!(m.get_stroke().is_none());
+    }
+
+    #[test]
+    #[serial]
+    fn handle_key_arpeggiate_rolled() {
+        let mut m = KeyboardMachine::new().with_chord_resolution(ChordResolution::Arpeggiate);
+        m.handle_key(Key::new(rdev::Key::KeyQ), true);
+        m.handle_key(Key::new(rdev::Key::KeyW), true);
+        m.handle_key(Key::new(rdev::Key::KeyQ), false);
+        m.handle_key(Key::new(rdev::Key::KeyP), true);
+        // the stroke is sent when space is pressed, with the keys that are still held
+        m.handle_key(Key::new(rdev::Key::Space), true);
+        assert_eq!(m.get_stroke().unwrap(), Stroke::new("ST-T"));
+        // the system repeats space while it is held
+        m.handle_key(Key::new(rdev::Key::Space), true);
+        assert!(m.get_stroke().is_none());
+
+        // keys pressed after space start the next stroke, but keys held through it do not
+        m.handle_key(Key::new(rdev::Key::KeyR), true);
+        m.handle_key(Key::new(rdev::Key::KeyW), false);
+        m.handle_key(Key::new(rdev::Key::Space), false);
+        m.handle_key(Key::new(rdev::Key::KeyP), false);
+        m.handle_key(Key::new(rdev::Key::KeyR), false);
+        assert!(m.get_stroke().is_none());
+        m.handle_key(Key::new(rdev::Key::Space), true);
+        assert_eq!(m.get_stroke().unwrap(), Stroke::new("H"));
+        m.handle_key(Key::new(rdev::Key::Space), false);
+        assert!(m.get_stroke().is_none());
+    }
+
+    #[test]
+    #[serial]
+    fn handle_key_arpeggiate_trigger() {
+        let mut m = KeyboardMachine::new()
+            .with_chord_resolution(ChordResolution::Arpeggiate)
+            .with_arpeggiate_trigger("Return")
+            .unwrap();
+        m.handle_key(Key::new(rdev::Key::KeyQ), true);
+        m.handle_key(Key::new(rdev::Key::Space), true);
+        m.handle_key(Key::new(rdev::Key::Space), false);
+        m.handle_key(Key::new(rdev::Key::KeyQ), false);
+        assert!(m.get_stroke().is_none());
+        m.handle_key(Key::new(rdev::Key::Return), true);
+        assert_eq!(m.get_stroke().unwrap(), Stroke::new("S"));
+
+        assert_eq!(
+            KeyboardMachine::new()
+                .with_arpeggiate_trigger("Enter")
+                .err(),
+            Some(LayoutError::UnknownKey("Enter".to_string()))
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn handle_key_debounce_chatter() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut m = KeyboardMachine::new().with_debounce(Duration::from_millis(10));
+        m.handle_key_at(Key::new(rdev::Key::KeyQ), true, at(0));
+        m.handle_key_at(Key::new(rdev::Key::KeyW), true, at(1));
+        // Q chatters up and down while it is held
+        m.handle_key_at(Key::new(rdev::Key::KeyQ), false, at(20));
+        m.handle_key_at(Key::new(rdev::Key::KeyQ), true, at(22));
+        m.handle_key_at(Key::new(rdev::Key::KeyW), false, at(50));
+        m.handle_key_at(Key::new(rdev::Key::KeyQ), false, at(60));
+        assert!(m.get_stroke().is_none());
+
+        // the last key up is held back until the debounce window passes
+        assert_eq!(m.flush_release(at(65)), Some(Duration::from_millis(5)));
+        assert!(m.get_stroke().is_none());
+        assert_eq!(m.flush_release(at(70)), None);
+        assert_eq!(m.get_stroke().unwrap(), Stroke::new("ST"));
+    }
+
+    #[test]
+    #[serial]
+    fn handle_key_debounce_single_key() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut m = KeyboardMachine::new().with_debounce(Duration::from_millis(10));
+        m.handle_key_at(Key::new(rdev::Key::KeyQ), true, at(0));
+        m.handle_key_at(Key::new(rdev::Key::KeyQ), false, at(30));
+        m.handle_key_at(Key::new(rdev::Key::KeyQ), true, at(31));
+        m.handle_key_at(Key::new(rdev::Key::KeyQ), false, at(60));
+        m.flush_release(at(100));
+        assert_eq!(m.get_stroke().unwrap(), Stroke::new("S"));
+        assert!(m.get_stroke().is_none());
+
+        // a key down after the debounce window is a new stroke
+        m.handle_key_at(Key::new(rdev::Key::KeyW), true, at(200));
+        m.handle_key_at(Key::new(rdev::Key::KeyW), false, at(210));
+        m.handle_key_at(Key::new(rdev::Key::KeyW), true, at(230));
+        assert_eq!(m.get_stroke().unwrap(), Stroke::new("T"));
+        m.handle_key_at(Key::new(rdev::Key::KeyW), false, at(240));
+        m.flush_release(at(300));
+        assert_eq!(m.get_stroke().unwrap(), Stroke::new("T"));
+    }
+
+    #[test]
+    #[serial]
+    fn handle_key_hold_repeat() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut m = KeyboardMachine::new().with_hold_repeat(Duration::from_millis(500));
+        m.handle_key_at(Key::new(rdev::Key::KeyQ), true, at(0));
+        m.handle_key_at(Key::new(rdev::Key::KeyW), true, at(10));
+        assert_eq!(m.flush_hold(at(300)), Some(Duration::from_millis(210)));
+        assert!(m.get_stroke().is_none());
+
+        // the keys are sent once they are held without changes
+        assert_eq!(m.flush_hold(at(510)), None);
+        assert_eq!(m.get_stroke().unwrap(), Stroke::new("ST"));
+        assert!(m.held);
+        // the system repeating the key down doesn't stop the hold or start a new stroke
+        m.handle_key_at(Key::new(rdev::Key::KeyW), true, at(540));
+        assert!(m.held);
+        assert_eq!(m.flush_hold(at(2000)), None);
+
+        // releasing the keys stops the hold without sending them again
+        m.handle_key_at(Key::new(rdev::Key::KeyW), false, at(2010));
+        assert!(!m.held);
+        m.handle_key_at(Key::new(rdev::Key::KeyQ), false, at(2020));
+        assert!(m.get_stroke().is_none());
+
+        // keys that are released before the hold delay are a normal stroke
+        m.handle_key_at(Key::new(rdev::Key::KeyQ), true, at(3000));
+        assert_eq!(m.flush_hold(at(3100)), Some(Duration::from_millis(400)));
+        m.handle_key_at(Key::new(rdev::Key::KeyQ), false, at(3200));
+        assert_eq!(m.get_stroke().unwrap(), Stroke::new("S"));
+        assert!(!m.held);
+        assert_eq!(m.flush_hold(at(5000)), None);
+    }
+
+    #[test]
+    #[serial]
+    fn handle_key_without_hold_repeat() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut m = KeyboardMachine::new();
+        m.handle_key_at(Key::new(rdev::Key::KeyQ), true, at(0));
+        assert_eq!(m.flush_hold(at(5000)), None);
+        assert!(m.get_stroke().is_none());
+        assert!(!m.wait_held(Duration::from_millis(0)));
+        m.handle_key_at(Key::new(rdev::Key::KeyQ), false, at(6000));
+        assert_eq!(m.get_stroke().unwrap(), Stroke::new("S"));
+    }
+
+    #[test]
+    #[serial]
+    fn handle_key_idle_timeout() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut m = KeyboardMachine::new().with_idle_timeout(Duration::from_millis(1000));
+        m.handle_key_at(Key::new(rdev::Key::KeyQ), true, at(0));
+        m.handle_key_at(Key::new(rdev::Key::KeyW), true, at(10));
+        m.handle_key_at(Key::new(rdev::Key::KeyW), false, at(20));
+        assert_eq!(m.flush_idle(at(500)), Some(Duration::from_millis(520)));
+        assert!(m.get_stroke().is_none());
+
+        // the released and stuck keys are sent once no keys change for the timeout
+        assert_eq!(m.flush_idle(at(1020)), None);
+        assert_eq!(m.get_stroke().unwrap(), Stroke::new("ST"));
+        assert_eq!(m.flush_idle(at(5000)), None);
+        assert!(m.get_stroke().is_none());
+
+        // releasing the stuck key later doesn't send it again
+        m.handle_key_at(Key::new(rdev::Key::KeyQ), false, at(6000));
+        assert!(m.get_stroke().is_none());
+        m.handle_key_at(Key::new(rdev::Key::KeyQ), true, at(7000));
+        m.handle_key_at(Key::new(rdev::Key::KeyQ), false, at(7100));
+        assert_eq!(m.get_stroke().unwrap(), Stroke::new("S"));
+    }
+
+    #[test]
+    #[serial]
+    fn handle_key_idle_timeout_while_keys_change() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut m = KeyboardMachine::new().with_idle_timeout(Duration::from_millis(1000));
+        m.handle_key_at(Key::new(rdev::Key::KeyQ), true, at(0));
+        // a long hold isn't sent while other keys are still pressed and released
+        m.handle_key_at(Key::new(rdev::Key::KeyW), true, at(900));
+        assert_eq!(m.flush_idle(at(1500)), Some(Duration::from_millis(400)));
+        m.handle_key_at(Key::new(rdev::Key::KeyE), true, at(1800));
+        m.handle_key_at(Key::new(rdev::Key::KeyE), false, at(2700));
+        assert_eq!(m.flush_idle(at(3500)), Some(Duration::from_millis(200)));
+        assert!(m.get_stroke().is_none());
+
+        // the stroke finishes normally once all the keys are released
+        m.handle_key_at(Key::new(rdev::Key::KeyW), false, at(3600));
+        m.handle_key_at(Key::new(rdev::Key::KeyQ), false, at(3650));
+        assert_eq!(m.get_stroke().unwrap(), Stroke::new("STP"));
+        assert_eq!(m.flush_idle(at(10000)), None);
+        assert!(m.get_stroke().is_none());
+    }
+
+    #[test]
+    #[serial]
+    fn handle_key_idle_timeout_while_disabled() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut m = KeyboardMachine::new().with_idle_timeout(Duration::from_millis(1000));
+        *IS_DISABLED.lock().unwrap() = true;
+        m.handle_key_at(Key::new(rdev::Key::KeyQ), true, at(0));
+        assert_eq!(m.flush_idle(at(5000)), None);
+        assert!(m.get_stroke().is_none());
+        *IS_DISABLED.lock().unwrap() = false;
+    }
+
+    #[test]
+    #[serial]
+    fn handle_key_without_idle_timeout() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut m = KeyboardMachine::new();
+        m.handle_key_at(Key::new(rdev::Key::KeyQ), true, at(0));
+        assert_eq!(m.flush_idle(at(60000)), None);
+        assert!(m.get_stroke().is_none());
+    }
+
+    #[test]
+    #[serial]
+    fn on_stroke_callback() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let fired = Arc::new(Mutex::new(vec![]));
+        let fired_clone = Arc::clone(&fired);
+        let mut m = KeyboardMachine::new().with_on_stroke(Box::new(move |stroke, time| {
+            fired_clone.lock().unwrap().push((stroke.clone(), time))
+        }));
+
+        m.handle_key_at(Key::new(rdev::Key::KeyQ), true, at(0));
+        m.handle_key_at(Key::new(rdev::Key::KeyW), true, at(5));
+        m.handle_key_at(Key::new(rdev::Key::KeyQ), false, at(10));
+        assert!(fired.lock().unwrap().is_empty());
+        m.handle_key_at(Key::new(rdev::Key::KeyW), false, at(20));
+        m.handle_key_at(Key::new(rdev::Key::KeyP), true, at(30));
+        // fires before the stroke is read
+        assert_eq!(*fired.lock().unwrap(), vec![(Stroke::new("ST"), at(20))]);
+        assert_eq!(m.get_stroke().unwrap(), Stroke::new("ST"));
+
+        m.handle_key_at(Key::new(rdev::Key::KeyP), false, at(40));
+        assert_eq!(m.get_stroke().unwrap(), Stroke::new("-T"));
+        assert_eq!(
+            *fired.lock().unwrap(),
+            vec![(Stroke::new("ST"), at(20)), (Stroke::new("-T"), at(40))]
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn custom_layout() {
+        let pairs = |pairs: &[(&str, char)]| {
+            pairs
+                .iter()
+                .map(|(name, c)| (name.to_string(), *c))
+                .collect::<Vec<_>>()
+        };
+        let layout = LayoutConfig {
+            left_keys: pairs(&[("KeyA", 'S'), ("KeyS", 'T')]),
+            center_left_keys: pairs(&[("KeyV", 'A')]),
+            star_keys: vec!["KeyG".to_string()],
+            center_right_keys: pairs(&[("KeyN", 'E')]),
+            right_keys: pairs(&[("KeyJ", 'F'), ("SemiColon", 'T')]),
+            num_keys: vec![],
+        };
+        let mut m = KeyboardMachine::new().with_layout(layout).unwrap();
+
+        m.handle_key(Key::new(rdev::Key::KeyS), true);
+        m.handle_key(Key::new(rdev::Key::KeyS), false);
+        assert_eq!(m.get_stroke().unwrap(), Stroke::new("T"));
+
+        m.handle_key(Key::new(rdev::Key::KeyA), true);
+        m.handle_key(Key::new(rdev::Key::SemiColon), true);
+        m.handle_key(Key::new(rdev::Key::KeyA), false);
+        m.handle_key(Key::new(rdev::Key::SemiColon), false);
+        assert_eq!(m.get_stroke().unwrap(), Stroke::new("S-T"));
+
+        // keys from the default layout are not mapped
+        m.handle_key(Key::new(rdev::Key::KeyQ), true);
+        m.handle_key(Key::new(rdev::Key::KeyQ), false);
+        assert_eq!(m.get_stroke(), None);
+    }
+
+    #[test]
+    fn layout_with_unknown_key() {
+        let layout = LayoutConfig {
+            left_keys: vec![("KeyA".to_string(), 'S'), ("KeyAA".to_string(), 'T')],
+            center_left_keys: vec![],
+            star_keys: vec![],
+            center_right_keys: vec![],
+            right_keys: vec![],
+            num_keys: vec![],
+        };
+        assert_eq!(
+            KeyboardMachine::new().with_layout(layout).err(),
+            Some(LayoutError::UnknownKey("KeyAA".to_string()))
+        );
+    }
+
+    #[test]
+    fn modifier_passthrough_disabled() {
+        let mut p = ModifierPassthrough::default();
+        assert!(!p.pass_through(rdev::Key::ControlLeft, true));
+        assert!(!p.pass_through(rdev::Key::KeyC, true));
+        assert!(!p.pass_through(rdev::Key::KeyC, false));
+        assert!(!p.pass_through(rdev::Key::ControlLeft, false));
+    }
+
+    #[test]
+    fn modifier_passthrough_shortcut() {
+        let mut p = ModifierPassthrough {
+            enabled: true,
+            ..Default::default()
+        };
+        assert!(!p.pass_through(rdev::Key::KeyC, true));
+        assert!(!p.pass_through(rdev::Key::KeyC, false));
+
+        // ctrl-c passes through
+        assert!(p.pass_through(rdev::Key::ControlLeft, true));
+        assert!(p.pass_through(rdev::Key::KeyC, true));
+        assert!(p.pass_through(rdev::Key::KeyC, false));
+        assert!(p.pass_through(rdev::Key::ControlLeft, false));
+
+        // steno resumes after the modifier is released
+        assert!(!p.pass_through(rdev::Key::KeyC, true));
+        assert!(!p.pass_through(rdev::Key::KeyC, false));
+    }
+
+    #[test]
+    fn modifier_passthrough_overlapping_keys() {
+        let mut p = ModifierPassthrough {
+            enabled: true,
+            ..Default::default()
+        };
+        // a key held before the modifier finishes its stroke
+        assert!(!p.pass_through(rdev::Key::KeyQ, true));
+        assert!(p.pass_through(rdev::Key::MetaLeft, true));
+        assert!(!p.pass_through(rdev::Key::KeyQ, false));
+
+        // a key released after the modifier is still passed through
+        assert!(p.pass_through(rdev::Key::KeyV, true));
+        assert!(p.pass_through(rdev::Key::MetaLeft, false));
+        assert!(p.pass_through(rdev::Key::KeyV, true));
+        assert!(p.pass_through(rdev::Key::KeyV, false));
+        assert!(!p.pass_through(rdev::Key::KeyV, true));
+
+        // passes through until every modifier is released
+        assert!(p.pass_through(rdev::Key::ControlLeft, true));
+        assert!(p.pass_through(rdev::Key::ShiftRight, true));
+        assert!(p.pass_through(rdev::Key::ControlLeft, false));
+        assert!(p.pass_through(rdev::Key::KeyT, true));
+        assert!(p.pass_through(rdev::Key::KeyT, false));
+        assert!(p.pass_through(rdev::Key::ShiftRight, false));
+        assert!(!p.pass_through(rdev::Key::KeyT, true));
+    }
+
+    #[test]
+    fn layout_keys() {
+        let keys = Layout::steno_querty().keys();
+        assert_eq!(keys.len(), 8 + 2 + 4 + 2 + 10 + 13);
+        for key in &[
+            rdev::Key::KeyQ,
+            rdev::Key::KeyG,
+            rdev::Key::Quote,
+            rdev::Key::Num1,
+        ] {
+            assert!(keys.contains(&Key::new(*key)));
+        }
+        for key in &[rdev::Key::Return, rdev::Key::Space, rdev::Key::ControlLeft] {
+            assert!(!keys.contains(&Key::new(*key)));
+        }
+    }
+
+    #[test]
+    fn non_steno_passthrough() {
+        let mut p = NonStenoPassthrough {
+            enabled: false,
+            steno_keys: Layout::steno_querty().keys(),
+        };
+        assert!(!p.pass_through(&Key::new(rdev::Key::KeyQ)));
+        assert!(!p.pass_through(&Key::new(rdev::Key::Return)));
+
+        p.enabled = true;
+        assert!(!p.pass_through(&Key::new(rdev::Key::KeyQ)));
+        assert!(!p.pass_through(&Key::new(rdev::Key::SemiColon)));
+        assert!(p.pass_through(&Key::new(rdev::Key::Return)));
+        assert!(p.pass_through(&Key::new(rdev::Key::UpArrow)));
+        assert!(p.pass_through(&Key::new(rdev::Key::ControlLeft)));
+    }
+
+    #[test]
+    #[serial]
+    fn non_steno_passthrough_follows_layout() {
+        let is_passed = |key| {
+            NON_STENO_PASSTHROUGH
+                .lock()
+                .unwrap()
+                .pass_through(&Key::new(key))
+        };
+        let layout = LayoutConfig {
+            left_keys: vec![("KeyA".to_string(), 'S')],
+            center_left_keys: vec![],
+            star_keys: vec![],
+            center_right_keys: vec![],
+            right_keys: vec![],
+            num_keys: vec![],
+        };
+
+        let m = KeyboardMachine::new()
+            .with_non_steno_passthrough(true)
+            .with_layout(layout)
+            .unwrap();
+        assert!(!is_passed(rdev::Key::KeyA));
+        assert!(is_passed(rdev::Key::KeyQ));
+        assert!(is_passed(rdev::Key::Space));
+
+        // space is used for steno when arpeggiating
+        let m = m.with_chord_resolution(ChordResolution::Arpeggiate);
+        assert!(!is_passed(rdev::Key::Space));
+
+        m.with_non_steno_passthrough(false);
+        assert!(!is_passed(rdev::Key::KeyQ));
+    }
+
+    #[test]
+    #[serial]
+    fn stroke_finishes_after_modifier() {
+        // the keys that the event handler sends to the machine form the stroke that was started
+        // before the modifier was pressed
+        let mut p = ModifierPassthrough {
+            enabled: true,
+            ..Default::default()
+        };
+        let mut m = KeyboardMachine::new();
+        let events = vec![
+            (rdev::Key::KeyQ, true),
+            (rdev::Key::ControlLeft, true),
+            (rdev::Key::KeyC, true),
+            (rdev::Key::KeyQ, false),
+            (rdev::Key::KeyC, false),
+            (rdev::Key::ControlLeft, false),
+        ];
+        for (key, is_down) in events {
+            if !p.pass_through(key, is_down) || is_modifier(key) {
+                m.handle_key(Key::new(key), is_down);
+            }
+        }
+        assert_eq!(m.get_stroke().unwrap(), Stroke::new("S"));
+        assert!(m.get_stroke().is_none());
+    }
+
+    #[test]
+    #[serial]
+    fn disabled_keys_pass_through() {
+        let m = KeyboardMachine::new()
+            .with_modifier_passthrough(false)
+            .with_non_steno_passthrough(false);
+        let suppressed = KeyRoute {
+            to_machine: true,
+            to_system: false,
+        };
+        let passed = KeyRoute {
+            to_machine: true,
+            to_system: true,
+        };
+        assert_eq!(route_key(rdev::Key::KeyQ, true), suppressed);
+
+        // the machine still gets the keys to check for the reenable shortcut
+        m.disable();
+        assert_eq!(route_key(rdev::Key::KeyQ, true), passed);
+        assert_eq!(route_key(rdev::Key::KeyQ, false), passed);
+
+        m.enable();
+        assert!(!*IS_DISABLED.lock().unwrap());
+        assert_eq!(route_key(rdev::Key::KeyQ, true), suppressed);
+        assert_eq!(route_key(rdev::Key::KeyQ, false), suppressed);
+    }
+
     #[test]
     #[serial]
     fn reenable_input() {
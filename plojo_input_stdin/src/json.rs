@@ -0,0 +1,163 @@
+//! Reading strokes and commands as JSON messages so that other programs can drive plojo
+use plojo_core::{Machine, MachineError, Stroke};
+use serde::Deserialize;
+use std::{io, io::BufRead};
+
+// translator command that the reload message is handled as
+const RELOAD_DICTS: &str = "reload_dicts";
+
+/// Reads one JSON message per line from stdin, such as `{"stroke": "H-L"}` to press a stroke,
+/// `{"command": "undo"}` to undo, or `{"command": "reload"}` to reload the dictionaries. Unlike the
+/// stdin machine, there is no prompt. Lines that are not valid messages are logged and skipped
+pub struct StdinJsonMachine {
+    // translator commands read since they were last taken
+    commands: Vec<String>,
+}
+
+impl StdinJsonMachine {
+    pub fn new() -> Self {
+        Self { commands: vec![] }
+    }
+}
+
+impl Default for StdinJsonMachine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A line of input
+#[derive(Debug, PartialEq, Deserialize)]
+#[serde(untagged, deny_unknown_fields)]
+enum Message {
+    Stroke { stroke: Stroke },
+    Command { command: MessageCommand },
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum MessageCommand {
+    Undo,
+    Reload,
+}
+
+impl Machine for StdinJsonMachine {
+    fn read(&mut self) -> Result<Stroke, MachineError> {
+        read_stroke(&mut io::stdin().lock(), &mut self.commands)
+    }
+
+    fn disable(&self) {
+        // no point in disabling stdin machine
+    }
+
+    fn take_translator_commands(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.commands)
+    }
+}
+
+/// Parses a line of input into a message. Fails if it isn't JSON, it isn't one of the messages, or
+/// the stroke is invalid
+fn parse_message(line: &str) -> Result<Message, String> {
+    let message = serde_json::from_str(line).map_err(|e| e.to_string())?;
+    if let Message::Stroke { stroke } = &message {
+        if let Some(e) = stroke.validation_error() {
+            return Err(format!("invalid stroke {:?}: {}", stroke, e));
+        }
+    }
+    Ok(message)
+}
+
+/// Read messages until there is a stroke. Undo is read as the undo stroke, and other commands are
+/// added to the translator commands. Returns `EndOfInput` if there is nothing left to read
+fn read_stroke(
+    reader: &mut impl BufRead,
+    commands: &mut Vec<String>,
+) -> Result<Stroke, MachineError> {
+    loop {
+        let mut input = String::new();
+        // blocks until input is read
+        if reader.read_line(&mut input)? == 0 {
+            return Err(MachineError::EndOfInput);
+        }
+        if input.trim().is_empty() {
+            continue;
+        }
+
+        match parse_message(input.trim()) {
+            Ok(Message::Stroke { stroke }) => return Ok(stroke),
+            Ok(Message::Command {
+                command: MessageCommand::Undo,
+            }) => return Ok(Stroke::new("*")),
+            Ok(Message::Command {
+                command: MessageCommand::Reload,
+            }) => commands.push(RELOAD_DICTS.to_string()),
+            Err(e) => eprintln!("[ERR] Invalid message {:?}: {}", input.trim(), e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_each_message() {
+        assert_eq!(
+            parse_message(r#"{"stroke": "H-L"}"#),
+            Ok(Message::Stroke {
+                stroke: Stroke::new("H-L")
+            })
+        );
+        assert_eq!(
+            parse_message(r#"{"command": "undo"}"#),
+            Ok(Message::Command {
+                command: MessageCommand::Undo
+            })
+        );
+        assert_eq!(
+            parse_message(r#"{ "command": "reload" }"#),
+            Ok(Message::Command {
+                command: MessageCommand::Reload
+            })
+        );
+    }
+
+    #[test]
+    fn parse_malformed_messages() {
+        assert!(parse_message("H-L").is_err());
+        assert!(parse_message(r#"{"stroke": "H-L""#).is_err());
+        assert!(parse_message(r#"{"stroke": 5}"#).is_err());
+        assert!(parse_message(r#"{"stroke": "LH"}"#).is_err());
+        assert!(parse_message(r#"{"command": "explode"}"#).is_err());
+        assert!(parse_message(r#"{"stroke": "H-L", "extra": 1}"#).is_err());
+        assert!(parse_message(r#"["H-L"]"#).is_err());
+    }
+
+    #[test]
+    fn read_messages() {
+        let mut input = concat!(
+            "not json\n",
+            "\n",
+            "{\"command\": \"reload\"}\n",
+            "{\"stroke\": \"H-L\"}\n",
+            "{\"command\": \"undo\"}\n",
+        )
+        .as_bytes();
+        let mut commands = vec![];
+
+        // invalid lines are skipped, and commands are read along with the next stroke
+        assert_eq!(
+            read_stroke(&mut input, &mut commands).unwrap(),
+            Stroke::new("H-L")
+        );
+        assert_eq!(commands, vec!["reload_dicts".to_string()]);
+        assert_eq!(
+            read_stroke(&mut input, &mut commands).unwrap(),
+            Stroke::new("*")
+        );
+        assert!(matches!(
+            read_stroke(&mut input, &mut commands),
+            Err(MachineError::EndOfInput)
+        ));
+    }
+}
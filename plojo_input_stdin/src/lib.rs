@@ -1,5 +1,9 @@
-use plojo_core::{Machine, Stroke};
-use std::{error::Error, io, io::Write};
+use plojo_core::{Machine, MachineError, Stroke};
+use std::{io, io::BufRead, io::Write};
+
+mod json;
+
+pub use json::StdinJsonMachine;
 
 pub struct StdinMachine {}
 
@@ -10,26 +14,54 @@ impl StdinMachine {
 }
 
 impl Machine for StdinMachine {
-    fn read(&mut self) -> Result<Stroke, Box<dyn Error>> {
-        let mut stroke = Stroke::new("");
+    fn read(&mut self) -> Result<Stroke, MachineError> {
+        read_stroke(&mut io::stdin().lock())
+    }
+
+    fn disable(&self) {
+        // no point in disabling stdin machine
+    }
+}
 
-        // keep prompting the user until the stroke is valid
-        while !stroke.is_valid() {
-            // prompt the user to provide a stroke
-            print!("Stroke> ");
-            io::stdout().flush()?;
+/// Keep prompting the user for a stroke until it is valid. Returns `EndOfInput` if there is
+/// nothing left to read
+fn read_stroke(reader: &mut impl BufRead) -> Result<Stroke, MachineError> {
+    let mut stroke = Stroke::new("");
 
-            let mut input = String::new();
-            // blocks until input is read
-            io::stdin().read_line(&mut input)?;
+    // keep prompting the user until the stroke is valid
+    while !stroke.is_valid() {
+        // prompt the user to provide a stroke
+        print!("Stroke> ");
+        io::stdout().flush()?;
 
-            stroke = Stroke::new(&input.trim());
+        let mut input = String::new();
+        // blocks until input is read
+        if reader.read_line(&mut input)? == 0 {
+            return Err(MachineError::EndOfInput);
         }
 
-        Ok(stroke)
+        stroke = Stroke::new(&input.trim());
     }
 
-    fn disable(&self) {
-        // no point in disabling stdin machine
+    Ok(stroke)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_valid_stroke() {
+        let mut input = "\nH-L\n".as_bytes();
+        assert_eq!(read_stroke(&mut input).unwrap(), Stroke::new("H-L"));
+    }
+
+    #[test]
+    fn closed_input_is_end_of_input() {
+        let mut input = "".as_bytes();
+        assert!(matches!(
+            read_stroke(&mut input),
+            Err(MachineError::EndOfInput)
+        ));
     }
 }
@@ -0,0 +1,61 @@
+use plojo_core::{Machine, MachineError, Stroke};
+use plojo_input_geminipr::SerialMachine;
+use std::error::Error;
+
+mod raw_stroke;
+
+use raw_stroke::StrokeBuffer;
+
+/// A steno machine speaking the TX Bolt protocol over a serial port
+pub struct TxBoltMachine {
+    machine: SerialMachine,
+    buffer: StrokeBuffer,
+}
+
+impl TxBoltMachine {
+    /// Open a machine on the port with the default serial settings
+    pub fn new(config_port: &str) -> Result<Self, Box<dyn Error>> {
+        Self::from_serial(SerialMachine::new(config_port))
+    }
+
+    /// Open a machine with custom serial settings (ex: a different baud rate)
+    pub fn from_serial(machine: SerialMachine) -> Result<Self, Box<dyn Error>> {
+        // strokes have a variable number of bytes, so read them one at a time
+        let mut machine = machine.with_buf_size(1);
+        machine.open()?;
+        Ok(Self {
+            machine,
+            buffer: StrokeBuffer::default(),
+        })
+    }
+}
+
+impl Machine for TxBoltMachine {
+    fn read(&mut self) -> Result<Stroke, MachineError> {
+        let mut idle = false;
+        loop {
+            match self.machine.try_read()? {
+                Some(byte) => {
+                    idle = false;
+                    if let Some(raw) = self.buffer.push(byte[0]) {
+                        return Ok(raw_stroke::parse_raw(&raw));
+                    }
+                }
+                None => {
+                    // the stroke is finished once the machine stops sending bytes for a while
+                    if idle {
+                        if let Some(raw) = self.buffer.flush() {
+                            return Ok(raw_stroke::parse_raw(&raw));
+                        }
+                    }
+                    idle = true;
+                    self.machine.wait();
+                }
+            }
+        }
+    }
+
+    fn disable(&self) {
+        // no point in disabling serial machine
+    }
+}
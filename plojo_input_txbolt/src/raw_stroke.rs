@@ -0,0 +1,159 @@
+use plojo_core::{RawStroke, Stroke};
+
+// for reference
+/*
+const STENO_KEY_CHART: [[&str; 6]; 4] = [
+    ["S-", "T-", "K-", "P-", "W-", "H-"],
+    ["R-", "A-", "O-", "*", "-E", "-U"],
+    ["-F", "-R", "-P", "-B", "-L", "-G"],
+    ["-T", "-S", "-D", "-Z", "#", "unused"],
+];
+*/
+
+/// The top 2 bits of a byte select which group (row of the chart) its keys are in
+fn key_group(byte: u8) -> u8 {
+    byte >> 6
+}
+
+/// Collects the bytes sent by the machine into strokes. A stroke is sent as up to 4 bytes, one
+/// for each group of keys that has a key pressed, in increasing group order
+#[derive(Debug, Default)]
+pub struct StrokeBuffer {
+    bytes: Vec<u8>,
+}
+
+impl StrokeBuffer {
+    /// Add a byte read from the machine. Returns the bytes of a stroke if it is now finished
+    pub fn push(&mut self, byte: u8) -> Option<Vec<u8>> {
+        // some machines send a zero byte to terminate a stroke
+        if byte == 0 {
+            return self.flush();
+        }
+
+        // a group that does not come after the previous one must start a new stroke
+        if let Some(last) = self.bytes.last() {
+            if key_group(byte) <= key_group(*last) {
+                let stroke = self.flush();
+                self.bytes.push(byte);
+                return stroke;
+            }
+        }
+
+        self.bytes.push(byte);
+        // nothing can come after the last group
+        if key_group(byte) == 3 {
+            return self.flush();
+        }
+        None
+    }
+
+    /// Finish the stroke that is currently being read, if any
+    pub fn flush(&mut self) -> Option<Vec<u8>> {
+        if self.bytes.is_empty() {
+            None
+        } else {
+            Some(self.bytes.drain(..).collect())
+        }
+    }
+}
+
+/// Parse the bytes of a single stroke into a stroke
+#[rustfmt::skip]
+pub fn parse_raw(raw: &[u8]) -> Stroke {
+    // the 6 key bits of each group
+    let mut groups = [0u8; 4];
+    for byte in raw {
+        groups[key_group(*byte) as usize] |= byte & 0b0011_1111;
+    }
+    // checks if the nth key of a group is pressed
+    let pressed = |group: usize, n: u8| groups[group] & (1 << n) != 0;
+
+    let mut raw_stroke: RawStroke = Default::default();
+
+    // first group: left hand S- to H-
+    if pressed(0, 0) { raw_stroke.left_hand.push('S'); }
+    if pressed(0, 1) { raw_stroke.left_hand.push('T'); }
+    if pressed(0, 2) { raw_stroke.left_hand.push('K'); }
+    if pressed(0, 3) { raw_stroke.left_hand.push('P'); }
+    if pressed(0, 4) { raw_stroke.left_hand.push('W'); }
+    if pressed(0, 5) { raw_stroke.left_hand.push('H'); }
+
+    // second group: R, A, O, star, E, and U
+    if pressed(1, 0) { raw_stroke.left_hand.push('R'); }
+    if pressed(1, 1) { raw_stroke.center_left.push('A'); }
+    if pressed(1, 2) { raw_stroke.center_left.push('O'); }
+    if pressed(1, 3) { raw_stroke.star_key = true; }
+    if pressed(1, 4) { raw_stroke.center_right.push('E'); }
+    if pressed(1, 5) { raw_stroke.center_right.push('U'); }
+
+    // third group: right hand F to G
+    if pressed(2, 0) { raw_stroke.right_hand.push('F'); }
+    if pressed(2, 1) { raw_stroke.right_hand.push('R'); }
+    if pressed(2, 2) { raw_stroke.right_hand.push('P'); }
+    if pressed(2, 3) { raw_stroke.right_hand.push('B'); }
+    if pressed(2, 4) { raw_stroke.right_hand.push('L'); }
+    if pressed(2, 5) { raw_stroke.right_hand.push('G'); }
+
+    // fourth group: right hand T to Z and the number key
+    if pressed(3, 0) { raw_stroke.right_hand.push('T'); }
+    if pressed(3, 1) { raw_stroke.right_hand.push('S'); }
+    if pressed(3, 2) { raw_stroke.right_hand.push('D'); }
+    if pressed(3, 3) { raw_stroke.right_hand.push('Z'); }
+    if pressed(3, 4) { raw_stroke.num_key = true; }
+
+    // convert raw stroke to stroke
+    raw_stroke.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_basic_stroke_parsing() {
+        assert_eq!(parse_raw(&[0b00_000001]), Stroke::new("S"));
+        assert_eq!(parse_raw(&[0b11_000001]), Stroke::new("-T"));
+        assert_eq!(
+            parse_raw(&[0b00_111111, 0b01_000001]),
+            Stroke::new("STKPWHR")
+        );
+        assert_eq!(
+            parse_raw(&[0b00_000011, 0b01_001010, 0b11_000010]),
+            Stroke::new("STA*S")
+        );
+        assert_eq!(parse_raw(&[0b10_001000, 0b11_010000]), Stroke::new("#-B"));
+        assert_eq!(
+            parse_raw(&[0b00_111111, 0b01_111111, 0b10_111111, 0b11_001111]),
+            Stroke::new("STKPWHRAO*EUFRPBLGTSDZ")
+        );
+    }
+
+    #[test]
+    fn test_stroke_buffer() {
+        let mut buffer = StrokeBuffer::default();
+        // STKPWHR
+        assert_eq!(buffer.push(0b00_111111), None);
+        assert_eq!(buffer.push(0b01_000001), None);
+        // S starts a new stroke
+        assert_eq!(
+            buffer.push(0b00_000001),
+            Some(vec![0b00_111111, 0b01_000001])
+        );
+        // -T is in the last group, so it finishes the stroke
+        assert_eq!(
+            buffer.push(0b11_000001),
+            Some(vec![0b00_000001, 0b11_000001])
+        );
+        assert_eq!(buffer.flush(), None);
+
+        // zero byte terminator
+        assert_eq!(buffer.push(0b01_000010), None);
+        assert_eq!(buffer.push(0), Some(vec![0b01_000010]));
+        assert_eq!(buffer.push(0), None);
+
+        // a repeated group starts a new stroke
+        assert_eq!(buffer.push(0b10_000001), None);
+        assert_eq!(buffer.push(0b10_000001), Some(vec![0b10_000001]));
+        assert_eq!(buffer.flush(), Some(vec![0b10_000001]));
+    }
+}
@@ -1,46 +1,81 @@
-use enigo::KeyboardControllable;
-use enigo::{Enigo, Key};
-use plojo_core::{Command, Controller, Key as InternalKey, Modifier, SpecialKey};
-use std::{process::Command as ProcessCommand, thread, time::Duration};
+use enigo::{Enigo, Key, MouseButton};
+use enigo::{KeyboardControllable, MouseControllable};
+use plojo_core::{
+    dispatch_shell, repeat_key, Command, Controller, Key as InternalKey, KeyDelays, KeyEvent,
+    KeyWaiter, Modifier, MouseButton as InternalMouseButton, Nav, SpecialKey,
+};
 
 pub struct EnigoController {
     enigo: Enigo,
+    // whether to wait for shell commands and log their output
+    capture_shell_output: bool,
+    // waits while pressing keys (longer in slow mode)
+    // NOTE: the delays are mostly irrelevant because enigo imposes a delay of 20 milliseconds for
+    // every key press
+    delays: KeyWaiter,
 }
 
-// NOTE: these are irrelevant because enigo imposes a delay of 20 milliseconds for every key press
-// Delay between pressing backspace (for corrections)
-const BACKSPACE_DELAY: u64 = 2;
-// Delay between pressing keys for typing normal text
-const KEY_DELAY: u64 = 5;
-// Delay between starting to hold down keys for keyboard shortcuts
-const KEY_HOLD_DELAY: u64 = 2;
-
 impl EnigoController {
-    fn type_with_delay(&mut self, text: &str, delay: u64) {
+    /// Capture the output of shell commands (in a background thread) and log it instead of
+    /// ignoring it
+    pub fn with_capture_shell_output(mut self, capture_shell_output: bool) -> Self {
+        self.capture_shell_output = capture_shell_output;
+        self
+    }
+
+    /// Wait for these delays while pressing keys instead of the default ones
+    pub fn with_key_delays(mut self, key_delays: KeyDelays) -> Self {
+        self.delays = self.delays.with_key_delays(key_delays);
+        self
+    }
+
+    fn type_text(&mut self, text: &str) {
         for c in text.chars() {
             self.enigo.key_sequence(&c.to_string());
-            thread::sleep(Duration::from_millis(delay));
+            self.delays.wait_type_text();
         }
     }
 
-    /// Press the backspace key with specified delay in milliseconds between each press
-    fn backspace(&mut self, num: usize, delay: u64) {
+    /// Press the backspace key `num` times, waiting for the backspace delay between each press
+    fn backspace(&mut self, num: usize) {
         for _ in 0..num {
             self.enigo.key_click(Key::Backspace);
-            thread::sleep(Duration::from_millis(delay));
+            self.delays.wait_backspace();
         }
     }
 
-    fn key_combo(&mut self, keys: Vec<Key>, delay: u64) {
+    fn key_combo(&mut self, keys: Vec<Key>) {
         for k in &keys {
             self.enigo.key_down(*k);
-            thread::sleep(Duration::from_millis(delay));
+            self.delays.wait_key_hold();
         }
 
         for k in &keys {
             self.enigo.key_up(*k);
         }
     }
+
+    /// Dispatch key events in order. Keys that are pressed stay down until they are released
+    fn key_events(&mut self, events: Vec<KeyEvent>) {
+        for event in events {
+            match event {
+                KeyEvent::HoldModifier(m) => {
+                    self.enigo.key_down(from_modifier(m));
+                    self.delays.wait_modifier();
+                }
+                KeyEvent::Tap(k) => {
+                    self.enigo.key_click(from_internal_key(k));
+                    self.delays.wait_type_text();
+                }
+                KeyEvent::ReleaseModifier(m) => self.enigo.key_up(from_modifier(m)),
+                KeyEvent::Press(k) => {
+                    self.enigo.key_down(from_internal_key(k));
+                    self.delays.wait_key_hold();
+                }
+                KeyEvent::Release(k) => self.enigo.key_up(from_internal_key(k)),
+            }
+        }
+    }
 }
 
 impl Controller for EnigoController {
@@ -48,6 +83,8 @@ impl Controller for EnigoController {
         // enigo does not scan keymap, so ignore the option
         Self {
             enigo: Enigo::new(),
+            capture_shell_output: false,
+            delays: KeyWaiter::default(),
         }
     }
 
@@ -55,11 +92,11 @@ impl Controller for EnigoController {
         match command {
             Command::Replace(backspace_num, add_text) => {
                 if backspace_num > 0 {
-                    self.backspace(backspace_num, BACKSPACE_DELAY);
+                    self.backspace(backspace_num);
                 }
 
                 if !add_text.is_empty() {
-                    self.type_with_delay(&add_text, KEY_DELAY);
+                    self.type_text(&add_text);
                 }
             }
             Command::PrintHello => {
@@ -72,17 +109,80 @@ impl Controller for EnigoController {
                     keys.push(from_modifier(m));
                 }
                 keys.push(from_internal_key(key));
-                self.key_combo(keys, KEY_HOLD_DELAY);
+                self.key_combo(keys);
+            }
+            Command::RepeatKey {
+                key,
+                modifiers,
+                count,
+            } => {
+                let delays = self.delays;
+                repeat_key(self, key, modifiers, count, delays)
+            }
+            Command::KeySequence(modifiers, keys) => {
+                self.key_events(Command::key_sequence_events(&modifiers, &keys))
+            }
+            Command::KeyDown(key, modifiers) => {
+                self.key_events(Command::key_down_events(&key, &modifiers))
+            }
+            Command::KeyUp(key, modifiers) => {
+                self.key_events(Command::key_up_events(&key, &modifiers))
+            }
+            Command::MouseMove(x, y) => self.enigo.mouse_move_relative(x, y),
+            Command::MouseMoveTo(x, y) => self.enigo.mouse_move_to(x, y),
+            Command::MouseClick(button) => self.enigo.mouse_click(from_mouse_button(button)),
+            Command::MouseScroll(x, y) => {
+                if x != 0 {
+                    self.enigo.mouse_scroll_x(x);
+                }
+                if y != 0 {
+                    self.enigo.mouse_scroll_y(y);
+                }
+            }
+            Command::Nav(nav) => {
+                let (key, modifiers) = nav_to_keys(nav);
+                self.dispatch(Command::Keys(InternalKey::Special(key), modifiers));
             }
             Command::Raw(code) => {
                 self.enigo.key_click(Key::Raw(code));
             }
-            Command::Shell(cmd, args) => dispatch_shell(cmd, args),
+            Command::Shell(cmd, args) => dispatch_shell(cmd, args, self.capture_shell_output),
+            Command::ToggleSlowMode => {
+                let is_slow = self.delays.toggle_slow_mode();
+                println!("[INFO] Slow mode {}", if is_slow { "on" } else { "off" });
+            }
+            Command::ShellInsert(..) => panic!("cannot handle shell insert command"),
             Command::TranslatorCommand(_) => panic!("cannot handle translator command"),
         }
     }
 }
 
+/// The key combination that moves the cursor on macOS
+#[cfg(target_os = "macos")]
+fn nav_to_keys(nav: Nav) -> (SpecialKey, Vec<Modifier>) {
+    match nav {
+        Nav::WordLeft => (SpecialKey::LeftArrow, vec![Modifier::Option]),
+        Nav::WordRight => (SpecialKey::RightArrow, vec![Modifier::Option]),
+        Nav::LineStart => (SpecialKey::LeftArrow, vec![Modifier::Meta]),
+        Nav::LineEnd => (SpecialKey::RightArrow, vec![Modifier::Meta]),
+        Nav::DocumentStart => (SpecialKey::UpArrow, vec![Modifier::Meta]),
+        Nav::DocumentEnd => (SpecialKey::DownArrow, vec![Modifier::Meta]),
+    }
+}
+
+/// The key combination that moves the cursor on Windows and Linux
+#[cfg(not(target_os = "macos"))]
+fn nav_to_keys(nav: Nav) -> (SpecialKey, Vec<Modifier>) {
+    match nav {
+        Nav::WordLeft => (SpecialKey::LeftArrow, vec![Modifier::Control]),
+        Nav::WordRight => (SpecialKey::RightArrow, vec![Modifier::Control]),
+        Nav::LineStart => (SpecialKey::Home, vec![]),
+        Nav::LineEnd => (SpecialKey::End, vec![]),
+        Nav::DocumentStart => (SpecialKey::Home, vec![Modifier::Control]),
+        Nav::DocumentEnd => (SpecialKey::End, vec![Modifier::Control]),
+    }
+}
+
 fn from_internal_key(key: InternalKey) -> Key {
     match key {
         InternalKey::Special(special_key) => match special_key {
@@ -129,10 +229,67 @@ fn from_modifier(modifier: Modifier) -> Key {
     }
 }
 
-fn dispatch_shell(cmd: String, args: Vec<String>) {
-    let result = ProcessCommand::new(cmd).args(args).spawn();
-    match result {
-        Ok(_) => {}
-        Err(e) => eprintln!("[WARN] Could not execute shell command: {}", e),
+fn from_mouse_button(button: InternalMouseButton) -> MouseButton {
+    match button {
+        InternalMouseButton::Left => MouseButton::Left,
+        InternalMouseButton::Right => MouseButton::Right,
+        InternalMouseButton::Middle => MouseButton::Middle,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(target_os = "macos")]
+    fn nav_keys_macos() {
+        assert_eq!(
+            nav_to_keys(Nav::WordLeft),
+            (SpecialKey::LeftArrow, vec![Modifier::Option])
+        );
+        assert_eq!(
+            nav_to_keys(Nav::WordRight),
+            (SpecialKey::RightArrow, vec![Modifier::Option])
+        );
+        assert_eq!(
+            nav_to_keys(Nav::LineStart),
+            (SpecialKey::LeftArrow, vec![Modifier::Meta])
+        );
+        assert_eq!(
+            nav_to_keys(Nav::LineEnd),
+            (SpecialKey::RightArrow, vec![Modifier::Meta])
+        );
+        assert_eq!(
+            nav_to_keys(Nav::DocumentStart),
+            (SpecialKey::UpArrow, vec![Modifier::Meta])
+        );
+        assert_eq!(
+            nav_to_keys(Nav::DocumentEnd),
+            (SpecialKey::DownArrow, vec![Modifier::Meta])
+        );
+    }
+
+    #[test]
+    #[cfg(not(target_os = "macos"))]
+    fn nav_keys() {
+        assert_eq!(
+            nav_to_keys(Nav::WordLeft),
+            (SpecialKey::LeftArrow, vec![Modifier::Control])
+        );
+        assert_eq!(
+            nav_to_keys(Nav::WordRight),
+            (SpecialKey::RightArrow, vec![Modifier::Control])
+        );
+        assert_eq!(nav_to_keys(Nav::LineStart), (SpecialKey::Home, vec![]));
+        assert_eq!(nav_to_keys(Nav::LineEnd), (SpecialKey::End, vec![]));
+        assert_eq!(
+            nav_to_keys(Nav::DocumentStart),
+            (SpecialKey::Home, vec![Modifier::Control])
+        );
+        assert_eq!(
+            nav_to_keys(Nav::DocumentEnd),
+            (SpecialKey::End, vec![Modifier::Control])
+        );
     }
 }
@@ -1,24 +1,173 @@
 //! Dispatch commands natively using core graphics and core foundations.
 
-use core_graphics::event::{CGEvent, CGEventFlags, CGEventTapLocation, CGKeyCode, KeyCode};
+use core_graphics::event::{
+    CGEvent, CGEventFlags, CGEventTapLocation, CGEventType, CGKeyCode, CGMouseButton, KeyCode,
+    ScrollEventUnit,
+};
 use core_graphics::event_source::{CGEventSource, CGEventSourceStateID};
-use plojo_core::{Command, Controller, Key, Modifier, SpecialKey};
-use std::{collections::HashMap, process, thread, time::Duration};
-
-// How long a key is held down
-const KEY_HOLD_DELAY: u64 = 2;
-// Delay between successive backspaces for corrections
-const BACKSPACE_DELAY: u64 = 2;
-// Delay between successive letters for typing normal text
-const TYPE_DELAY: u64 = 5;
-// Delay for holding down each modifier key
-const MODIFIER_DELAY: u64 = 2;
+use core_graphics::geometry::CGPoint;
+use plojo_core::{
+    dispatch_shell, repeat_key, Command, Controller, DispatchError, Key, KeyDelays, KeyEvent,
+    KeyWaiter, Modifier, MouseButton, Nav, SpecialKey,
+};
+use std::collections::HashMap;
+
+// text longer than this many chars is pasted (if clipboard paste is enabled)
+const DEFAULT_PASTE_THRESHOLD: usize = 20;
+// how long to wait for the app to read the pasted text before restoring the clipboard
+const PASTE_RESTORE_DELAY: u64 = 100;
+
+// the physical key of each char on the keyboard layout and whether shift has to be held for it
+type Keymap = HashMap<char, (CGKeyCode, bool)>;
 
 pub struct MacController {
     // Stores the keymap if keymap scanning is disabled (keymap is only scanned at the beginning)
     // If it's not disabled, then the keymap is scanned for every keyboard shortcut (to see if it
     // changed). This field will be Non
-    char_to_keycode_map: Option<HashMap<char, CGKeyCode>>,
+    char_to_keycode_map: Option<Keymap>,
+    // whether to wait for shell commands and log their output
+    capture_shell_output: bool,
+    // waits while pressing keys (longer in slow mode)
+    delays: KeyWaiter,
+    // whether to paste long text from the clipboard instead of typing it
+    use_clipboard_paste: bool,
+    // text with more chars than this is pasted
+    paste_threshold: usize,
+}
+
+impl MacController {
+    /// Capture the output of shell commands (in a background thread) and log it instead of
+    /// ignoring it
+    pub fn with_capture_shell_output(mut self, capture_shell_output: bool) -> Self {
+        self.capture_shell_output = capture_shell_output;
+        self
+    }
+
+    /// Wait for these delays while pressing keys instead of the default ones
+    pub fn with_key_delays(mut self, key_delays: KeyDelays) -> Self {
+        self.delays = self.delays.with_key_delays(key_delays);
+        self
+    }
+
+    /// Paste text that is longer than the paste threshold by putting it on the clipboard and
+    /// pressing Cmd+V instead of typing it one char at a time. The previous clipboard text is
+    /// restored afterwards (anything on the clipboard that is not text is lost).
+    ///
+    /// This is off by default because the pasted text passes through the clipboard, where other
+    /// apps (like clipboard managers) can read it, and some apps intercept or disable Cmd+V
+    pub fn with_clipboard_paste(mut self, use_clipboard_paste: bool) -> Self {
+        self.use_clipboard_paste = use_clipboard_paste;
+        self
+    }
+
+    /// Paste text with more than this many chars instead of the default 20 (only if clipboard
+    /// paste is enabled)
+    pub fn with_paste_threshold(mut self, paste_threshold: usize) -> Self {
+        self.paste_threshold = paste_threshold;
+        self
+    }
+
+    /// Whether the text should be pasted instead of typed
+    fn should_paste(&self, text: &str) -> bool {
+        self.use_clipboard_paste && text.chars().count() > self.paste_threshold
+    }
+
+    /// Type text by pasting it from the clipboard, then put back what was on the clipboard
+    fn paste_text(&self, text: &str) -> Result<(), DispatchError> {
+        // find the key before changing the clipboard so that it is left alone if there is none
+        let (keycode, modifiers) = self.get_keycode(Key::Layout('v'), &[Modifier::Meta])?;
+        let prev_clipboard = get_clipboard_text();
+        set_clipboard_text(text);
+
+        toggle_key(keycode, true, &modifiers, &self.delays);
+        self.delays.wait_key_hold();
+        toggle_key(keycode, false, &modifiers, &self.delays);
+
+        // the paste happens asynchronously in the app, so wait before replacing the clipboard
+        self.delays.wait(PASTE_RESTORE_DELAY);
+        if let Some(prev) = prev_clipboard {
+            set_clipboard_text(&prev);
+        }
+        Ok(())
+    }
+
+    /// Dispatch key events in order. Keys that are pressed stay down until they are released.
+    ///
+    /// Keys that are not on the keyboard layout are skipped so that the held modifiers are still
+    /// released. The first of those keys is returned as the error. If a key that stays down is
+    /// not on the layout, nothing is dispatched, so its modifiers are not left held down
+    fn key_events(&self, events: Vec<KeyEvent>) -> Result<(), DispatchError> {
+        let mut presses = press_keycodes(&events, |k, m| self.get_keycode(k, m))?.into_iter();
+        let mut held = Vec::new();
+        let mut result = Ok(());
+        for event in events {
+            match event {
+                KeyEvent::HoldModifier(m) => {
+                    held.push(m);
+                    toggle_key(modifier_to_key(m), true, &held, &self.delays);
+                    self.delays.wait_modifier();
+                }
+                KeyEvent::Tap(k) => match self.get_keycode(k, &held) {
+                    Ok((keycode, modifiers)) => {
+                        // only release the key so that the modifiers stay held
+                        toggle_key(keycode, true, &modifiers, &self.delays);
+                        self.delays.wait_key_hold();
+                        toggle_key(keycode, false, &[], &self.delays);
+                        self.delays.wait_type_text();
+                    }
+                    Err(e) => result = result.and(Err(e)),
+                },
+                KeyEvent::ReleaseModifier(m) => {
+                    held.retain(|h| *h != m);
+                    toggle_key(modifier_to_key(m), false, &[], &self.delays);
+                    self.delays.wait_modifier();
+                }
+                KeyEvent::Press(_) => {
+                    if let Some((keycode, modifiers)) = presses.next() {
+                        toggle_key(keycode, true, &modifiers, &self.delays);
+                        self.delays.wait_key_hold();
+                    }
+                }
+                KeyEvent::Release(k) => match self.get_keycode(k, &[]) {
+                    // the modifiers are released by their own events
+                    Ok((keycode, _)) => toggle_key(keycode, false, &[], &self.delays),
+                    Err(e) => result = result.and(Err(e)),
+                },
+            }
+        }
+        result
+    }
+
+    /// Convert a key into its physical key code and the modifiers to press it with. Layout keys
+    /// are looked up in the keymap, and shift is added to the modifiers for chars that are only
+    /// typed with shift (like `A` or `!`). Fails if a layout key does not exist on the current
+    /// keyboard layout
+    fn get_keycode(
+        &self,
+        key: Key,
+        modifiers: &[Modifier],
+    ) -> Result<(CGKeyCode, Vec<Modifier>), DispatchError> {
+        match key {
+            Key::Layout(c) => {
+                // build a new map on each dispatch in case the keyboard layout changed
+                // this map converts chars to keycodes in a keyboard shortcut
+                let local_keymap;
+                let keycode_map = if let Some(ref m) = self.char_to_keycode_map {
+                    m
+                } else {
+                    local_keymap = build_char_to_keycode_map();
+                    &local_keymap
+                };
+
+                // try to convert the char to a physical key
+                keycode_map
+                    .get(&c)
+                    .map(|&(keycode, shift)| (keycode, with_shift(modifiers, shift)))
+                    .ok_or_else(|| DispatchError::UnmappableKey(c, modifiers.to_vec()))
+            }
+            Key::Special(special_key) => Ok((key_to_keycode(special_key), modifiers.to_vec())),
+        }
+    }
 }
 
 impl Controller for MacController {
@@ -30,27 +179,39 @@ impl Controller for MacController {
             } else {
                 None
             },
+            capture_shell_output: false,
+            delays: KeyWaiter::default(),
+            use_clipboard_paste: false,
+            paste_threshold: DEFAULT_PASTE_THRESHOLD,
         }
     }
 
     fn dispatch(&mut self, command: Command) {
+        if let Err(e) = self.try_dispatch(command) {
+            eprintln!("[ERR] {}", e);
+        }
+    }
+
+    fn try_dispatch(&mut self, command: Command) -> Result<(), DispatchError> {
         match command {
             Command::Replace(backspace_num, add_text) => {
                 // tap backspace for corrections
                 for _ in 0..backspace_num {
-                    toggle_key(KeyCode::DELETE, true, &[], MODIFIER_DELAY);
-                    thread::sleep(Duration::from_millis(KEY_HOLD_DELAY));
-                    toggle_key(KeyCode::DELETE, false, &[], MODIFIER_DELAY);
-                    thread::sleep(Duration::from_millis(BACKSPACE_DELAY));
+                    toggle_key(KeyCode::DELETE, true, &[], &self.delays);
+                    self.delays.wait_key_hold();
+                    toggle_key(KeyCode::DELETE, false, &[], &self.delays);
+                    self.delays.wait_backspace();
                 }
 
-                // type text
-                if !add_text.is_empty() {
+                // type text (after the backspaces so that the pasted text isn't deleted)
+                if self.should_paste(&add_text) {
+                    self.paste_text(&add_text)?;
+                } else if !add_text.is_empty() {
                     for c in add_text.chars() {
                         type_char(c, true);
-                        thread::sleep(Duration::from_millis(KEY_HOLD_DELAY));
+                        self.delays.wait_key_hold();
                         type_char(c, false);
-                        thread::sleep(Duration::from_millis(TYPE_DELAY));
+                        self.delays.wait_type_text();
                     }
                 }
             }
@@ -59,52 +220,89 @@ impl Controller for MacController {
             }
             Command::NoOp => {}
             Command::Keys(key, modifiers) => {
-                let keycode = match key {
-                    Key::Layout(c) => {
-                        // build a new map on each dispatch in case the keyboard layout changed
-                        // this map converts chars to keycodes in a keyboard shortcut
-                        let local_keymap;
-                        let keycode_map = if let Some(ref m) = self.char_to_keycode_map {
-                            m
-                        } else {
-                            local_keymap = build_char_to_keycode_map();
-                            &local_keymap
-                        };
-
-                        // try to convert the char to a physical key
-                        if let Some(code) = keycode_map.get(&c) {
-                            *code
-                        } else {
-                            eprintln!("[ERR] Cannot press {:?} and {:?}", c, modifiers);
-                            eprintln!(
-                                "[ERR] Is your caps lock on? Did you change the keyboard layout?"
-                            );
-                            panic!("could not convert {} to a physical key", c);
-                        }
-                    }
-                    Key::Special(special_key) => key_to_keycode(special_key),
-                };
-                toggle_key(keycode, true, &modifiers, MODIFIER_DELAY);
-                thread::sleep(Duration::from_millis(KEY_HOLD_DELAY));
-                toggle_key(keycode, false, &modifiers, MODIFIER_DELAY);
+                let (keycode, modifiers) = self.get_keycode(key, &modifiers)?;
+                toggle_key(keycode, true, &modifiers, &self.delays);
+                self.delays.wait_key_hold();
+                toggle_key(keycode, false, &modifiers, &self.delays);
+            }
+            Command::RepeatKey {
+                key,
+                modifiers,
+                count,
+            } => {
+                let delays = self.delays;
+                repeat_key(self, key, modifiers, count, delays)
+            }
+            Command::KeySequence(modifiers, keys) => {
+                self.key_events(Command::key_sequence_events(&modifiers, &keys))?
+            }
+            Command::KeyDown(key, modifiers) => {
+                self.key_events(Command::key_down_events(&key, &modifiers))?
+            }
+            Command::KeyUp(key, modifiers) => {
+                self.key_events(Command::key_up_events(&key, &modifiers))?
+            }
+            Command::MouseMove(x, y) => {
+                let location = mouse_location();
+                move_mouse(CGPoint::new(location.x + x as f64, location.y + y as f64));
+            }
+            Command::MouseMoveTo(x, y) => move_mouse(CGPoint::new(x as f64, y as f64)),
+            Command::MouseClick(button) => {
+                let (down, up, button) = mouse_button_events(button);
+                let location = mouse_location();
+                post_mouse_event(down, location, button);
+                self.delays.wait_key_hold();
+                post_mouse_event(up, location, button);
+            }
+            Command::MouseScroll(x, y) => {
+                let source = CGEventSource::new(CGEventSourceStateID::HIDSystemState).unwrap();
+                // core graphics scrolls up and left for positive amounts
+                let event =
+                    CGEvent::new_scroll_event(source, ScrollEventUnit::LINE, 2, -y, -x, 0).unwrap();
+                event.post(CGEventTapLocation::Session);
+            }
+            Command::Nav(nav) => {
+                let (key, modifiers) = nav_to_keys(nav);
+                let keycode = key_to_keycode(key);
+                toggle_arrow_key(keycode, true, &modifiers, &self.delays);
+                self.delays.wait_key_hold();
+                toggle_arrow_key(keycode, false, &modifiers, &self.delays);
             }
             Command::Raw(key) => {
-                toggle_key(key, true, &[], MODIFIER_DELAY);
-                thread::sleep(Duration::from_millis(KEY_HOLD_DELAY));
-                toggle_key(key, false, &[], MODIFIER_DELAY);
+                toggle_key(key, true, &[], &self.delays);
+                self.delays.wait_key_hold();
+                toggle_key(key, false, &[], &self.delays);
+            }
+            Command::Shell(cmd, args) => dispatch_shell(cmd, args, self.capture_shell_output),
+            Command::ToggleSlowMode => {
+                let is_slow = self.delays.toggle_slow_mode();
+                println!("[INFO] Slow mode {}", if is_slow { "on" } else { "off" });
             }
-            Command::Shell(cmd, args) => dispatch_shell(cmd, args),
+            Command::ShellInsert(..) => panic!("cannot handle shell insert command"),
             Command::TranslatorCommand(_) => panic!("cannot handle translator command"),
         }
+        Ok(())
     }
 }
 
-fn dispatch_shell(cmd: String, args: Vec<String>) {
-    let result = process::Command::new(cmd).args(args).spawn();
-    match result {
-        Ok(_) => {}
-        Err(e) => eprintln!("[WARN] Could not execute shell command: {}", e),
+/// The key codes (and modifiers) of the keys that the events press and keep down, in order. Each
+/// key is found with the modifiers that are held before it. Fails with the first key that can't
+/// be found
+fn press_keycodes(
+    events: &[KeyEvent],
+    get_keycode: impl Fn(Key, &[Modifier]) -> Result<(CGKeyCode, Vec<Modifier>), DispatchError>,
+) -> Result<Vec<(CGKeyCode, Vec<Modifier>)>, DispatchError> {
+    let mut held = Vec::new();
+    let mut presses = Vec::new();
+    for event in events {
+        match event {
+            KeyEvent::HoldModifier(m) => held.push(*m),
+            KeyEvent::ReleaseModifier(m) => held.retain(|h| h != m),
+            KeyEvent::Press(k) => presses.push(get_keycode(k.clone(), &held)?),
+            KeyEvent::Tap(_) | KeyEvent::Release(_) => {}
+        }
     }
+    Ok(presses)
 }
 
 /// Types a single char. Supports UTF-8
@@ -116,10 +314,82 @@ fn type_char(c: char, down: bool) {
     event.post(CGEventTapLocation::Session);
 }
 
+/// Where the mouse is on the screen
+fn mouse_location() -> CGPoint {
+    let source = CGEventSource::new(CGEventSourceStateID::HIDSystemState).unwrap();
+    CGEvent::new(source).unwrap().location()
+}
+
+fn move_mouse(location: CGPoint) {
+    post_mouse_event(CGEventType::MouseMoved, location, CGMouseButton::Left);
+}
+
+fn post_mouse_event(event_type: CGEventType, location: CGPoint, button: CGMouseButton) {
+    let source = CGEventSource::new(CGEventSourceStateID::HIDSystemState).unwrap();
+    let event = CGEvent::new_mouse_event(source, event_type, location, button).unwrap();
+    event.post(CGEventTapLocation::Session);
+}
+
+/// The event types that press and release a mouse button
+fn mouse_button_events(button: MouseButton) -> (CGEventType, CGEventType, CGMouseButton) {
+    match button {
+        MouseButton::Left => (
+            CGEventType::LeftMouseDown,
+            CGEventType::LeftMouseUp,
+            CGMouseButton::Left,
+        ),
+        MouseButton::Right => (
+            CGEventType::RightMouseDown,
+            CGEventType::RightMouseUp,
+            CGMouseButton::Right,
+        ),
+        MouseButton::Middle => (
+            CGEventType::OtherMouseDown,
+            CGEventType::OtherMouseUp,
+            CGMouseButton::Center,
+        ),
+    }
+}
+
+/// The text on the clipboard, if there is any
+fn get_clipboard_text() -> Option<String> {
+    use cocoa::appkit::{NSPasteboard, NSPasteboardTypeString};
+    use cocoa::base::nil;
+    use cocoa::foundation::NSString;
+    use std::{slice, str};
+
+    unsafe {
+        let pasteboard = NSPasteboard::generalPasteboard(nil);
+        let contents = pasteboard.stringForType(NSPasteboardTypeString);
+        if contents == nil {
+            return None;
+        }
+
+        let str_ptr = slice::from_raw_parts(contents.UTF8String() as *const u8, contents.len());
+        str::from_utf8(str_ptr).ok().map(|s| s.to_string())
+    }
+}
+
+/// Replaces the contents of the clipboard with text
+fn set_clipboard_text(text: &str) {
+    use cocoa::appkit::{NSPasteboard, NSPasteboardTypeString};
+    use cocoa::base::nil;
+    use cocoa::foundation::{NSAutoreleasePool, NSString};
+
+    unsafe {
+        let pool = NSAutoreleasePool::new(nil);
+        let pasteboard = NSPasteboard::generalPasteboard(nil);
+        pasteboard.clearContents();
+        let string = NSString::alloc(nil).init_str(text).autorelease();
+        pasteboard.setString_forType(string, NSPasteboardTypeString);
+        pool.drain();
+    }
+}
+
 /// Toggles a physical key with support for modifiers
 ///
 /// Arrow key + some modifiers don't work. This is a known (and unsolvable) glitch.
-fn toggle_key(key: CGKeyCode, down: bool, modifiers: &[Modifier], modifier_delay: u64) {
+fn toggle_key(key: CGKeyCode, down: bool, modifiers: &[Modifier], delays: &KeyWaiter) {
     // key down must be triggered with modifiers as flags...
     if down {
         let source = CGEventSource::new(CGEventSourceStateID::HIDSystemState).unwrap();
@@ -133,7 +403,7 @@ fn toggle_key(key: CGKeyCode, down: bool, modifiers: &[Modifier], modifier_delay
             let source = CGEventSource::new(CGEventSourceStateID::HIDSystemState).unwrap();
             let event = CGEvent::new_keyboard_event(source, modifier_key, false).unwrap();
             event.post(CGEventTapLocation::Session);
-            thread::sleep(Duration::from_millis(modifier_delay));
+            delays.wait_modifier();
         }
         let source = CGEventSource::new(CGEventSourceStateID::HIDSystemState).unwrap();
         let event = CGEvent::new_keyboard_event(source, key, false).unwrap();
@@ -141,6 +411,36 @@ fn toggle_key(key: CGKeyCode, down: bool, modifiers: &[Modifier], modifier_delay
     }
 }
 
+/// Toggles an arrow key with modifiers. Unlike `toggle_key`, this works around the arrow key +
+/// modifier glitch by flagging the key down like a physical arrow key, which macOS reports as a
+/// numeric pad and fn key
+fn toggle_arrow_key(key: CGKeyCode, down: bool, modifiers: &[Modifier], delays: &KeyWaiter) {
+    if down {
+        let source = CGEventSource::new(CGEventSourceStateID::HIDSystemState).unwrap();
+        let event = CGEvent::new_keyboard_event(source, key, true).unwrap();
+        event.set_flags(
+            modifiers_to_flags(modifiers)
+                | CGEventFlags::CGEventFlagNumericPad
+                | CGEventFlags::CGEventFlagSecondaryFn,
+        );
+        event.post(CGEventTapLocation::Session);
+    } else {
+        toggle_key(key, false, modifiers, delays);
+    }
+}
+
+/// The key combination that moves the cursor
+fn nav_to_keys(nav: Nav) -> (SpecialKey, Vec<Modifier>) {
+    match nav {
+        Nav::WordLeft => (SpecialKey::LeftArrow, vec![Modifier::Option]),
+        Nav::WordRight => (SpecialKey::RightArrow, vec![Modifier::Option]),
+        Nav::LineStart => (SpecialKey::LeftArrow, vec![Modifier::Meta]),
+        Nav::LineEnd => (SpecialKey::RightArrow, vec![Modifier::Meta]),
+        Nav::DocumentStart => (SpecialKey::UpArrow, vec![Modifier::Meta]),
+        Nav::DocumentEnd => (SpecialKey::DownArrow, vec![Modifier::Meta]),
+    }
+}
+
 fn modifiers_to_flags(modifiers: &[Modifier]) -> CGEventFlags {
     let mut flags = CGEventFlags::CGEventFlagNull;
     for m in modifiers {
@@ -199,19 +499,35 @@ fn key_to_keycode(key: SpecialKey) -> CGKeyCode {
     }
 }
 
-/// Build a hashmap between the letter and its physical key (layout dependent)
-fn build_char_to_keycode_map() -> HashMap<char, CGKeyCode> {
+/// Add shift to the modifiers if it is needed and not already there
+fn with_shift(modifiers: &[Modifier], shift: bool) -> Vec<Modifier> {
+    let mut modifiers = modifiers.to_vec();
+    if shift && !modifiers.contains(&Modifier::Shift) {
+        modifiers.push(Modifier::Shift);
+    }
+    modifiers
+}
+
+/// Build a hashmap between the letter and its physical key (layout dependent). Chars that are only
+/// typed with shift held (like `A` or `!`) are marked as needing shift
+fn build_char_to_keycode_map() -> Keymap {
     let mut map = HashMap::new();
     // check each key code to see if it represents a char
     for i in 0..64 {
-        if let Some(c) = keycode_to_char(i) {
-            map.insert(c, i);
+        if let Some(c) = keycode_to_char(i, false) {
+            map.insert(c, (i, false));
+        }
+    }
+    // a char that can be typed without shift is never typed with it
+    for i in 0..64 {
+        if let Some(c) = keycode_to_char(i, true) {
+            map.entry(c).or_insert((i, true));
         }
     }
     map
 }
 
-fn keycode_to_char(code: CGKeyCode) -> Option<char> {
+fn keycode_to_char(code: CGKeyCode, shift: bool) -> Option<char> {
     use cocoa::appkit::{NSEvent, NSEventType};
     use cocoa::base::nil;
     use cocoa::foundation::NSString;
@@ -220,6 +536,9 @@ fn keycode_to_char(code: CGKeyCode) -> Option<char> {
 
     let source = CGEventSource::new(CGEventSourceStateID::HIDSystemState).unwrap();
     let event = CGEvent::new_keyboard_event(source, code, true).unwrap();
+    if shift {
+        event.set_flags(CGEventFlags::CGEventFlagShift);
+    }
 
     unsafe {
         // the eventWithCGEvent_ call causes a memory leak, but I can't fix it
@@ -254,13 +573,80 @@ mod tests {
     fn keycode_conversion() {
         // if you hold down shift while running this test, it will fail
         // these keycodes are for QWERTY layout on US (ANSI) keyboard
-        assert_eq!(keycode_to_char(0), Some('a'));
-        assert_eq!(keycode_to_char(6), Some('z'));
-        assert_eq!(keycode_to_char(50), Some('`'));
-        assert_eq!(keycode_to_char(53), Some('\u{1b}'));
+        assert_eq!(keycode_to_char(0, false), Some('a'));
+        assert_eq!(keycode_to_char(6, false), Some('z'));
+        assert_eq!(keycode_to_char(50, false), Some('`'));
+        assert_eq!(keycode_to_char(53, false), Some('\u{1b}'));
+        assert_eq!(keycode_to_char(0, true), Some('A'));
+        assert_eq!(keycode_to_char(18, true), Some('!'));
 
         // control key
-        assert_eq!(keycode_to_char(59), None);
+        assert_eq!(keycode_to_char(59, false), None);
+    }
+
+    #[test]
+    fn unmappable_key_does_not_panic() {
+        let mut controller = MacController::new(true);
+        let command = Command::Keys(Key::Layout('😀'), vec![Modifier::Meta]);
+        assert_eq!(
+            controller.try_dispatch(command.clone()),
+            Err(DispatchError::UnmappableKey('😀', vec![Modifier::Meta]))
+        );
+        // only logged when dispatched normally
+        controller.dispatch(command);
+    }
+
+    #[test]
+    fn unmappable_held_key_presses_nothing() {
+        let get_keycode = |key: Key, modifiers: &[Modifier]| match key {
+            Key::Layout('a') => Ok((0, modifiers.to_vec())),
+            Key::Layout(c) => Err(DispatchError::UnmappableKey(c, modifiers.to_vec())),
+            Key::Special(k) => Ok((key_to_keycode(k), modifiers.to_vec())),
+        };
+        let shift = [Modifier::Shift];
+        assert_eq!(
+            press_keycodes(
+                &Command::key_down_events(&Key::Layout('a'), &shift),
+                get_keycode
+            ),
+            Ok(vec![(0, vec![Modifier::Shift])])
+        );
+        // the key is found before the modifiers are held down
+        assert_eq!(
+            press_keycodes(
+                &Command::key_down_events(&Key::Layout('😀'), &shift),
+                get_keycode
+            ),
+            Err(DispatchError::UnmappableKey('😀', vec![Modifier::Shift]))
+        );
+        // only held keys have to be found
+        assert_eq!(
+            press_keycodes(
+                &Command::key_sequence_events(&shift, &[Key::Layout('😀')]),
+                get_keycode
+            ),
+            Ok(vec![])
+        );
+
+        let mut controller = MacController::new(true);
+        assert_eq!(
+            controller.try_dispatch(Command::KeyDown(Key::Layout('😀'), vec![Modifier::Shift])),
+            Err(DispatchError::UnmappableKey('😀', vec![Modifier::Shift]))
+        );
+    }
+
+    #[test]
+    fn paste_only_long_text() {
+        let controller = MacController::new(false).with_paste_threshold(5);
+        // clipboard paste is opt in
+        assert!(!controller.should_paste("hello world"));
+
+        let controller = controller.with_clipboard_paste(true);
+        assert!(!controller.should_paste(""));
+        assert!(!controller.should_paste("hello"));
+        assert!(controller.should_paste("hello world"));
+        // the threshold counts chars, not bytes
+        assert!(!controller.should_paste("héllo"));
     }
 
     #[test]
@@ -270,5 +656,54 @@ mod tests {
         assert!(keycode_map.get(&'o').is_some());
         assert!(keycode_map.get(&'4').is_some());
         assert!(keycode_map.get(&';').is_some());
+
+        // these keycodes are for QWERTY layout on US (ANSI) keyboard
+        assert_eq!(keycode_map.get(&'a'), Some(&(0, false)));
+        assert_eq!(keycode_map.get(&'A'), Some(&(0, true)));
+        assert_eq!(keycode_map.get(&'1'), Some(&(18, false)));
+        assert_eq!(keycode_map.get(&'!'), Some(&(18, true)));
+    }
+
+    #[test]
+    fn shift_is_added_once() {
+        assert_eq!(with_shift(&[], false), vec![]);
+        assert_eq!(
+            with_shift(&[Modifier::Meta], true),
+            vec![Modifier::Meta, Modifier::Shift]
+        );
+        assert_eq!(with_shift(&[Modifier::Shift], true), vec![Modifier::Shift]);
+        assert_eq!(with_shift(&[Modifier::Shift], false), vec![Modifier::Shift]);
+    }
+
+    #[test]
+    fn nav_keys() {
+        let keycode = |nav| {
+            let (key, modifiers) = nav_to_keys(nav);
+            (key_to_keycode(key), modifiers)
+        };
+        assert_eq!(
+            keycode(Nav::WordLeft),
+            (KeyCode::LEFT_ARROW, vec![Modifier::Option])
+        );
+        assert_eq!(
+            keycode(Nav::WordRight),
+            (KeyCode::RIGHT_ARROW, vec![Modifier::Option])
+        );
+        assert_eq!(
+            keycode(Nav::LineStart),
+            (KeyCode::LEFT_ARROW, vec![Modifier::Meta])
+        );
+        assert_eq!(
+            keycode(Nav::LineEnd),
+            (KeyCode::RIGHT_ARROW, vec![Modifier::Meta])
+        );
+        assert_eq!(
+            keycode(Nav::DocumentStart),
+            (KeyCode::UP_ARROW, vec![Modifier::Meta])
+        );
+        assert_eq!(
+            keycode(Nav::DocumentEnd),
+            (KeyCode::DOWN_ARROW, vec![Modifier::Meta])
+        );
     }
 }
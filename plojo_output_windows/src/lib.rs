@@ -0,0 +1,562 @@
+//! Dispatch commands natively on Windows using the `SendInput` Win32 API.
+#![cfg(windows)]
+
+use plojo_core::{
+    dispatch_shell, repeat_key, Command, Controller, Key, KeyDelays, KeyEvent, KeyWaiter, Modifier,
+    MouseButton, Nav, SpecialKey,
+};
+use std::{
+    collections::HashMap,
+    mem,
+    os::raw::{c_int, c_short},
+};
+use winapi::{
+    shared::minwindef::{DWORD, UINT},
+    um::winuser::{self, INPUT, KEYBDINPUT, MOUSEINPUT},
+};
+
+pub struct WindowsController {
+    // Stores the virtual key of each char if keymap scanning is disabled (the keyboard layout is
+    // only scanned at the beginning). If it's not disabled, then the keyboard layout is checked
+    // for every keyboard shortcut (to see if it changed). This field will be None
+    char_to_vk_map: Option<HashMap<char, LayoutKey>>,
+    // whether to wait for shell commands and log their output
+    capture_shell_output: bool,
+    // waits while pressing keys (longer in slow mode)
+    delays: KeyWaiter,
+}
+
+/// The virtual key that types a char on the keyboard layout and the modifiers that have to be
+/// held down for it (ex: shift for uppercase letters)
+type LayoutKey = (u16, Vec<Modifier>);
+
+impl WindowsController {
+    /// Capture the output of shell commands (in a background thread) and log it instead of
+    /// ignoring it
+    pub fn with_capture_shell_output(mut self, capture_shell_output: bool) -> Self {
+        self.capture_shell_output = capture_shell_output;
+        self
+    }
+
+    /// Wait for these delays while pressing keys instead of the default ones
+    pub fn with_key_delays(mut self, key_delays: KeyDelays) -> Self {
+        self.delays = self.delays.with_key_delays(key_delays);
+        self
+    }
+
+    /// Press and release a virtual key while holding down the modifiers
+    fn tap_vk(&self, vk: u16, modifiers: &[Modifier]) {
+        toggle_key(vk, true, modifiers, &self.delays);
+        self.delays.wait_key_hold();
+        toggle_key(vk, false, modifiers, &self.delays);
+    }
+
+    /// Type a single char. Newlines and tabs are typed with their keys because apps ignore them
+    /// as unicode input
+    fn tap_char(&self, c: char) {
+        match c {
+            '\n' => self.tap_vk(special_key_to_vk(SpecialKey::Return), &[]),
+            '\t' => self.tap_vk(special_key_to_vk(SpecialKey::Tab), &[]),
+            _ => {
+                type_char(c, true);
+                self.delays.wait_key_hold();
+                type_char(c, false);
+            }
+        }
+    }
+
+    /// Press a key while holding down the modifiers, along with any modifiers that the keyboard
+    /// layout needs to type it
+    fn press_key(&self, key: Key, modifiers: &[Modifier]) {
+        match self.get_vk(&key) {
+            Some((vk, layout_modifiers)) => {
+                let modifiers = with_layout_modifiers(modifiers, &layout_modifiers);
+                self.tap_vk(vk, &modifiers);
+            }
+            None => {
+                eprintln!("[ERR] Cannot press {:?} and {:?}", key, modifiers);
+                eprintln!("[ERR] Is the key on the current keyboard layout?");
+            }
+        }
+    }
+
+    /// Dispatch key events in order. Keys that are pressed stay down until they are released
+    fn key_events(&self, events: Vec<KeyEvent>) {
+        let mut held = Vec::new();
+        for event in events {
+            match event {
+                KeyEvent::HoldModifier(m) => {
+                    held.push(m);
+                    toggle_modifier(m, true);
+                    self.delays.wait_modifier();
+                }
+                KeyEvent::Tap(k) => {
+                    // only press the modifiers that are not already held
+                    match self.get_vk(&k) {
+                        Some((vk, layout_modifiers)) => {
+                            let extra = without_modifiers(&layout_modifiers, &held);
+                            self.tap_vk(vk, &extra);
+                        }
+                        None => eprintln!("[ERR] Cannot press {:?} and {:?}", k, held),
+                    }
+                    self.delays.wait_type_text();
+                }
+                KeyEvent::ReleaseModifier(m) => {
+                    held.retain(|h| *h != m);
+                    toggle_modifier(m, false);
+                    self.delays.wait_modifier();
+                }
+                KeyEvent::Press(k) => {
+                    // the modifiers are pressed by their own events
+                    match self.get_vk(&k) {
+                        Some((vk, _)) => send_key(vk, true),
+                        None => eprintln!("[WARN] Cannot hold {:?}: not on the keyboard", k),
+                    }
+                    self.delays.wait_key_hold();
+                }
+                KeyEvent::Release(k) => match self.get_vk(&k) {
+                    Some((vk, _)) => send_key(vk, false),
+                    None => eprintln!("[WARN] Cannot release {:?}: not on the keyboard", k),
+                },
+            }
+        }
+    }
+
+    /// Convert a key into its virtual key. Layout keys are looked up in the keyboard layout and
+    /// can need modifiers to be typed. Returns None if the char is not on the keyboard layout
+    fn get_vk(&self, key: &Key) -> Option<LayoutKey> {
+        match key {
+            Key::Layout(c) => match self.char_to_vk_map {
+                Some(ref m) => m.get(c).cloned(),
+                // check the layout on each dispatch in case it changed
+                None => scan_char(*c),
+            },
+            Key::Special(special_key) => Some((special_key_to_vk(special_key.clone()), vec![])),
+        }
+    }
+}
+
+impl Controller for WindowsController {
+    fn new(disable_scan_keymap: bool) -> Self {
+        Self {
+            char_to_vk_map: if disable_scan_keymap {
+                // to disable keymap scanning, scan it only once at the beginning
+                Some(build_char_to_vk_map())
+            } else {
+                None
+            },
+            capture_shell_output: false,
+            delays: KeyWaiter::default(),
+        }
+    }
+
+    fn dispatch(&mut self, command: Command) {
+        match command {
+            Command::Replace(backspace_num, add_text) => {
+                // tap backspace for corrections
+                let backspace = special_key_to_vk(SpecialKey::Backspace);
+                for _ in 0..backspace_num {
+                    self.tap_vk(backspace, &[]);
+                    self.delays.wait_backspace();
+                }
+
+                // type text
+                for c in add_text.chars() {
+                    self.tap_char(c);
+                    self.delays.wait_type_text();
+                }
+            }
+            Command::PrintHello => {
+                println!("Hello!");
+            }
+            Command::NoOp => {}
+            Command::Keys(key, modifiers) => self.press_key(key, &modifiers),
+            Command::RepeatKey {
+                key,
+                modifiers,
+                count,
+            } => {
+                let delays = self.delays;
+                repeat_key(self, key, modifiers, count, delays)
+            }
+            Command::KeySequence(modifiers, keys) => {
+                self.key_events(Command::key_sequence_events(&modifiers, &keys))
+            }
+            Command::KeyDown(key, modifiers) => {
+                self.key_events(Command::key_down_events(&key, &modifiers))
+            }
+            Command::KeyUp(key, modifiers) => {
+                self.key_events(Command::key_up_events(&key, &modifiers))
+            }
+            Command::MouseMove(x, y) => send_mouse(x, y, 0, winuser::MOUSEEVENTF_MOVE),
+            Command::MouseMoveTo(x, y) => unsafe {
+                winuser::SetCursorPos(x, y);
+            },
+            Command::MouseClick(button) => {
+                let (down, up) = mouse_button_flags(button);
+                send_mouse(0, 0, 0, down);
+                self.delays.wait_key_hold();
+                send_mouse(0, 0, 0, up);
+            }
+            Command::MouseScroll(x, y) => {
+                // the wheel scrolls up and right for positive amounts
+                let line = winuser::WHEEL_DELTA as i32;
+                if y != 0 {
+                    send_mouse(0, 0, -y * line, winuser::MOUSEEVENTF_WHEEL);
+                }
+                if x != 0 {
+                    send_mouse(0, 0, x * line, winuser::MOUSEEVENTF_HWHEEL);
+                }
+            }
+            Command::Nav(nav) => {
+                let (key, modifiers) = nav_to_keys(nav);
+                self.tap_vk(special_key_to_vk(key), &modifiers);
+            }
+            Command::Raw(vk) => self.tap_vk(vk, &[]),
+            Command::Shell(cmd, args) => dispatch_shell(cmd, args, self.capture_shell_output),
+            Command::ToggleSlowMode => {
+                let is_slow = self.delays.toggle_slow_mode();
+                println!("[INFO] Slow mode {}", if is_slow { "on" } else { "off" });
+            }
+            Command::ShellInsert(..) => panic!("cannot handle shell insert command"),
+            Command::TranslatorCommand(_) => panic!("cannot handle translator command"),
+        }
+    }
+}
+
+/// Send the inputs to the foreground app in order, without other input in between
+fn send_inputs(inputs: &mut [INPUT]) {
+    let sent = unsafe {
+        winuser::SendInput(
+            inputs.len() as UINT,
+            inputs.as_mut_ptr(),
+            mem::size_of::<INPUT>() as c_int,
+        )
+    };
+    if sent as usize != inputs.len() {
+        // input is blocked by another thread or by UIPI (ex: the app is run as administrator)
+        eprintln!("[WARN] Only {} of {} inputs were sent", sent, inputs.len());
+    }
+}
+
+fn keyboard_input(vk: u16, scan: u16, flags: DWORD) -> INPUT {
+    let mut input: INPUT = unsafe { mem::zeroed() };
+    input.type_ = winuser::INPUT_KEYBOARD;
+    unsafe {
+        *input.u.ki_mut() = KEYBDINPUT {
+            wVk: vk,
+            wScan: scan,
+            dwFlags: flags,
+            time: 0,
+            dwExtraInfo: 0,
+        };
+    }
+    input
+}
+
+/// Presses or releases a virtual key
+fn send_key(vk: u16, down: bool) {
+    let mut flags = 0;
+    if is_extended_key(vk) {
+        flags |= winuser::KEYEVENTF_EXTENDEDKEY;
+    }
+    if !down {
+        flags |= winuser::KEYEVENTF_KEYUP;
+    }
+    // some apps read the scan code instead of the virtual key
+    let scan = unsafe { winuser::MapVirtualKeyW(vk.into(), winuser::MAPVK_VK_TO_VSC) };
+    send_inputs(&mut [keyboard_input(vk, scan as u16, flags)]);
+}
+
+/// Toggles a virtual key with support for modifiers
+///
+/// The modifiers are pressed before the key goes down and released after the key goes up
+fn toggle_key(vk: u16, down: bool, modifiers: &[Modifier], delays: &KeyWaiter) {
+    if down {
+        for m in modifiers {
+            toggle_modifier(*m, true);
+            delays.wait_modifier();
+        }
+        send_key(vk, true);
+    } else {
+        send_key(vk, false);
+        for m in modifiers.iter().rev() {
+            delays.wait_modifier();
+            toggle_modifier(*m, false);
+        }
+    }
+}
+
+/// Press or release a modifier key. Modifiers that are not on the keyboard are ignored
+fn toggle_modifier(modifier: Modifier, down: bool) {
+    match modifier_to_vk(modifier) {
+        Some(vk) => send_key(vk, down),
+        None => eprintln!("[WARN] Cannot press modifier {:?}", modifier),
+    }
+}
+
+/// Types a single char as unicode input, regardless of the keyboard layout. Chars outside of the
+/// basic multilingual plane are sent as a surrogate pair
+fn type_char(c: char, down: bool) {
+    let mut flags = winuser::KEYEVENTF_UNICODE;
+    if !down {
+        flags |= winuser::KEYEVENTF_KEYUP;
+    }
+    let mut buf = [0; 2];
+    let mut inputs: Vec<_> = c
+        .encode_utf16(&mut buf)
+        .iter()
+        .map(|unit| keyboard_input(0, *unit, flags))
+        .collect();
+    send_inputs(&mut inputs);
+}
+
+fn send_mouse(dx: i32, dy: i32, data: i32, flags: DWORD) {
+    let mut input: INPUT = unsafe { mem::zeroed() };
+    input.type_ = winuser::INPUT_MOUSE;
+    unsafe {
+        *input.u.mi_mut() = MOUSEINPUT {
+            dx,
+            dy,
+            // negative wheel amounts are stored as their two's complement
+            mouseData: data as DWORD,
+            dwFlags: flags,
+            time: 0,
+            dwExtraInfo: 0,
+        };
+    }
+    send_inputs(&mut [input]);
+}
+
+/// The mouse event flags that press and release a mouse button
+fn mouse_button_flags(button: MouseButton) -> (DWORD, DWORD) {
+    match button {
+        MouseButton::Left => (winuser::MOUSEEVENTF_LEFTDOWN, winuser::MOUSEEVENTF_LEFTUP),
+        MouseButton::Right => (winuser::MOUSEEVENTF_RIGHTDOWN, winuser::MOUSEEVENTF_RIGHTUP),
+        MouseButton::Middle => (
+            winuser::MOUSEEVENTF_MIDDLEDOWN,
+            winuser::MOUSEEVENTF_MIDDLEUP,
+        ),
+    }
+}
+
+/// The key combination that moves the cursor
+fn nav_to_keys(nav: Nav) -> (SpecialKey, Vec<Modifier>) {
+    match nav {
+        Nav::WordLeft => (SpecialKey::LeftArrow, vec![Modifier::Control]),
+        Nav::WordRight => (SpecialKey::RightArrow, vec![Modifier::Control]),
+        Nav::LineStart => (SpecialKey::Home, vec![]),
+        Nav::LineEnd => (SpecialKey::End, vec![]),
+        Nav::DocumentStart => (SpecialKey::Home, vec![Modifier::Control]),
+        Nav::DocumentEnd => (SpecialKey::End, vec![Modifier::Control]),
+    }
+}
+
+/// The modifiers to hold down to type a layout key, followed by the ones the layout needs that
+/// are not already in them
+fn with_layout_modifiers(modifiers: &[Modifier], layout_modifiers: &[Modifier]) -> Vec<Modifier> {
+    let mut all = modifiers.to_vec();
+    all.extend(without_modifiers(layout_modifiers, modifiers));
+    all
+}
+
+fn without_modifiers(modifiers: &[Modifier], held: &[Modifier]) -> Vec<Modifier> {
+    modifiers
+        .iter()
+        .filter(|m| !held.contains(m))
+        .copied()
+        .collect()
+}
+
+/// Build a hashmap between the Latin-1 chars and their virtual keys (layout dependent)
+fn build_char_to_vk_map() -> HashMap<char, LayoutKey> {
+    let mut map = HashMap::new();
+    for c in ' '..='\u{ff}' {
+        if let Some(key) = scan_char(c) {
+            map.insert(c, key);
+        }
+    }
+    map
+}
+
+/// Look up the virtual key that types a char on the current keyboard layout
+fn scan_char(c: char) -> Option<LayoutKey> {
+    let mut buf = [0; 2];
+    match c.encode_utf16(&mut buf) {
+        [unit] => parse_key_scan(unsafe { winuser::VkKeyScanW(*unit) }),
+        // chars outside of the basic multilingual plane are never on the keyboard
+        _ => None,
+    }
+}
+
+/// Convert the result of `VkKeyScanW` into a virtual key and modifiers. The low byte is the
+/// virtual key and the high byte is the shift state, or both are -1 if there is no key
+fn parse_key_scan(scan: c_short) -> Option<LayoutKey> {
+    if scan == -1 {
+        return None;
+    }
+    let vk = scan as u16 & 0xff;
+    let shift_state = scan as u16 >> 8;
+    let modifiers = [
+        (1, Modifier::Shift),
+        (2, Modifier::Control),
+        (4, Modifier::Alt),
+    ]
+    .iter()
+    .filter(|(bit, _)| shift_state & bit != 0)
+    .map(|(_, m)| *m)
+    .collect();
+    Some((vk, modifiers))
+}
+
+/// Keys that are not on the numeric pad but share its scan codes must be sent as extended keys,
+/// otherwise they type numbers when num lock is on
+fn is_extended_key(vk: u16) -> bool {
+    matches!(
+        vk as c_int,
+        winuser::VK_INSERT
+            | winuser::VK_DELETE
+            | winuser::VK_HOME
+            | winuser::VK_END
+            | winuser::VK_PRIOR
+            | winuser::VK_NEXT
+            | winuser::VK_LEFT
+            | winuser::VK_RIGHT
+            | winuser::VK_UP
+            | winuser::VK_DOWN
+            | winuser::VK_RCONTROL
+            | winuser::VK_RMENU
+            | winuser::VK_LWIN
+            | winuser::VK_RWIN
+            | winuser::VK_APPS
+            | winuser::VK_DIVIDE
+            | winuser::VK_NUMLOCK
+            | winuser::VK_SNAPSHOT
+    )
+}
+
+/// The virtual key of the left modifier key. There is no virtual key for the Fn key
+fn modifier_to_vk(modifier: Modifier) -> Option<u16> {
+    let vk = match modifier {
+        Modifier::Alt => winuser::VK_LMENU,
+        Modifier::Control => winuser::VK_LCONTROL,
+        Modifier::Meta => winuser::VK_LWIN,
+        Modifier::Option => winuser::VK_LMENU,
+        Modifier::Shift => winuser::VK_LSHIFT,
+        Modifier::Fn => return None,
+    };
+    Some(vk as u16)
+}
+
+fn special_key_to_vk(key: SpecialKey) -> u16 {
+    let vk = match key {
+        SpecialKey::Backspace => winuser::VK_BACK,
+        SpecialKey::CapsLock => winuser::VK_CAPITAL,
+        SpecialKey::Delete => winuser::VK_DELETE,
+        SpecialKey::DownArrow => winuser::VK_DOWN,
+        SpecialKey::End => winuser::VK_END,
+        SpecialKey::Escape => winuser::VK_ESCAPE,
+        SpecialKey::F1 => winuser::VK_F1,
+        SpecialKey::F10 => winuser::VK_F10,
+        SpecialKey::F11 => winuser::VK_F11,
+        SpecialKey::F12 => winuser::VK_F12,
+        SpecialKey::F2 => winuser::VK_F2,
+        SpecialKey::F3 => winuser::VK_F3,
+        SpecialKey::F4 => winuser::VK_F4,
+        SpecialKey::F5 => winuser::VK_F5,
+        SpecialKey::F6 => winuser::VK_F6,
+        SpecialKey::F7 => winuser::VK_F7,
+        SpecialKey::F8 => winuser::VK_F8,
+        SpecialKey::F9 => winuser::VK_F9,
+        SpecialKey::Home => winuser::VK_HOME,
+        SpecialKey::LeftArrow => winuser::VK_LEFT,
+        SpecialKey::PageDown => winuser::VK_NEXT,
+        SpecialKey::PageUp => winuser::VK_PRIOR,
+        SpecialKey::Return => winuser::VK_RETURN,
+        SpecialKey::RightArrow => winuser::VK_RIGHT,
+        SpecialKey::Space => winuser::VK_SPACE,
+        SpecialKey::Tab => winuser::VK_TAB,
+        SpecialKey::UpArrow => winuser::VK_UP,
+    };
+    vk as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn modifier_vks() {
+        assert_eq!(modifier_to_vk(Modifier::Shift), Some(0xa0));
+        assert_eq!(modifier_to_vk(Modifier::Control), Some(0xa2));
+        assert_eq!(modifier_to_vk(Modifier::Alt), Some(0xa4));
+        assert_eq!(modifier_to_vk(Modifier::Option), Some(0xa4));
+        assert_eq!(modifier_to_vk(Modifier::Meta), Some(0x5b));
+        assert_eq!(modifier_to_vk(Modifier::Fn), None);
+    }
+
+    #[test]
+    fn special_key_vks() {
+        assert_eq!(special_key_to_vk(SpecialKey::Backspace), 0x08);
+        assert_eq!(special_key_to_vk(SpecialKey::Return), 0x0d);
+        assert_eq!(special_key_to_vk(SpecialKey::Space), 0x20);
+        assert_eq!(special_key_to_vk(SpecialKey::LeftArrow), 0x25);
+        assert_eq!(special_key_to_vk(SpecialKey::PageDown), 0x22);
+        assert_eq!(special_key_to_vk(SpecialKey::F1), 0x70);
+        assert_eq!(special_key_to_vk(SpecialKey::F12), 0x7b);
+    }
+
+    #[test]
+    fn extended_keys() {
+        assert!(is_extended_key(special_key_to_vk(SpecialKey::LeftArrow)));
+        assert!(is_extended_key(special_key_to_vk(SpecialKey::Delete)));
+        assert!(is_extended_key(special_key_to_vk(SpecialKey::PageUp)));
+        assert!(is_extended_key(modifier_to_vk(Modifier::Meta).unwrap()));
+        assert!(!is_extended_key(special_key_to_vk(SpecialKey::Return)));
+        assert!(!is_extended_key(modifier_to_vk(Modifier::Shift).unwrap()));
+    }
+
+    #[test]
+    fn key_scan_modifiers() {
+        assert_eq!(parse_key_scan(0x0041), Some((0x41, vec![])));
+        assert_eq!(parse_key_scan(0x0141), Some((0x41, vec![Modifier::Shift])));
+        // AltGr is control and alt
+        assert_eq!(
+            parse_key_scan(0x0645),
+            Some((0x45, vec![Modifier::Control, Modifier::Alt]))
+        );
+        assert_eq!(parse_key_scan(-1), None);
+    }
+
+    #[test]
+    fn layout_modifiers_are_added_once() {
+        assert_eq!(
+            with_layout_modifiers(&[Modifier::Control], &[Modifier::Shift]),
+            vec![Modifier::Control, Modifier::Shift]
+        );
+        assert_eq!(
+            with_layout_modifiers(&[Modifier::Shift, Modifier::Meta], &[Modifier::Shift]),
+            vec![Modifier::Shift, Modifier::Meta]
+        );
+    }
+
+    #[test]
+    fn mouse_buttons() {
+        assert_eq!(mouse_button_flags(MouseButton::Left), (0x02, 0x04));
+        assert_eq!(mouse_button_flags(MouseButton::Right), (0x08, 0x10));
+        assert_eq!(mouse_button_flags(MouseButton::Middle), (0x20, 0x40));
+    }
+
+    #[test]
+    fn nav_keys() {
+        assert_eq!(
+            nav_to_keys(Nav::WordLeft),
+            (SpecialKey::LeftArrow, vec![Modifier::Control])
+        );
+        assert_eq!(nav_to_keys(Nav::LineEnd), (SpecialKey::End, vec![]));
+        assert_eq!(
+            nav_to_keys(Nav::DocumentStart),
+            (SpecialKey::Home, vec![Modifier::Control])
+        );
+    }
+}
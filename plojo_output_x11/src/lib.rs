@@ -0,0 +1,480 @@
+//! Dispatch commands natively on Linux using the X11 XTEST extension.
+
+use plojo_core::{
+    dispatch_shell, repeat_key, Command, Controller, Key, KeyDelays, KeyEvent, KeyWaiter, Modifier,
+    MouseButton, Nav, SpecialKey,
+};
+use std::{
+    collections::HashMap,
+    os::raw::{c_int, c_uint},
+    ptr, slice,
+};
+use x11::{
+    keysym,
+    xlib::{self, Display, KeyCode, KeySym},
+    xtest,
+};
+
+// Delay before changing a remapped key back. The app looks up the keysym of a key when it handles
+// the key press, so changing it too early types the wrong char
+const REMAP_DELAY: u64 = 10;
+
+// X buttons that scroll the mouse wheel by a line when clicked
+const SCROLL_UP: c_uint = 4;
+const SCROLL_DOWN: c_uint = 5;
+const SCROLL_LEFT: c_uint = 6;
+const SCROLL_RIGHT: c_uint = 7;
+
+pub struct X11Controller {
+    display: *mut Display,
+    // Where each keysym is on the keyboard. If keymap scanning is disabled, this is only scanned
+    // at the beginning. Otherwise it is scanned for every command (to see if it changed)
+    keymap: Keymap,
+    scan_keymap: bool,
+    // A key with no keysyms which is remapped to type chars that are not on the keyboard
+    scratch_keycode: Option<KeyCode>,
+    // whether to wait for shell commands and log their output
+    capture_shell_output: bool,
+    // waits while pressing keys (longer in slow mode)
+    delays: KeyWaiter,
+}
+
+/// The keyboard mapping from keycodes to keysyms, reversed
+struct Keymap {
+    // the keycode that types each keysym and whether shift has to be held down for it
+    keysyms: HashMap<KeySym, (KeyCode, bool)>,
+    // the first keycode that does not have any keysyms
+    unused: Option<KeyCode>,
+}
+
+impl Keymap {
+    fn scan(display: *mut Display) -> Self {
+        let mut keysyms = HashMap::new();
+        let mut unused = None;
+
+        let (mut min, mut max) = (0, 0);
+        let mut per_keycode = 0;
+        let mapping = unsafe {
+            xlib::XDisplayKeycodes(display, &mut min, &mut max);
+            xlib::XGetKeyboardMapping(display, min as KeyCode, max - min + 1, &mut per_keycode)
+        };
+        if mapping.is_null() || per_keycode <= 0 {
+            return Self { keysyms, unused };
+        }
+
+        let len = ((max - min + 1) * per_keycode) as usize;
+        let all_syms = unsafe { slice::from_raw_parts(mapping, len) };
+        for (i, syms) in all_syms.chunks(per_keycode as usize).enumerate() {
+            let keycode = (min as usize + i) as KeyCode;
+            if syms.iter().all(|s| *s == xlib::NoSymbol as KeySym) {
+                unused = unused.or(Some(keycode));
+                continue;
+            }
+            // only the first two keysyms are the unshifted and shifted keys of the base layout
+            for (column, sym) in syms.iter().take(2).enumerate() {
+                if *sym != xlib::NoSymbol as KeySym {
+                    keysyms.entry(*sym).or_insert((keycode, column == 1));
+                }
+            }
+        }
+        unsafe { xlib::XFree(mapping as *mut _) };
+
+        Self { keysyms, unused }
+    }
+}
+
+impl X11Controller {
+    /// Capture the output of shell commands (in a background thread) and log it instead of
+    /// ignoring it
+    pub fn with_capture_shell_output(mut self, capture_shell_output: bool) -> Self {
+        self.capture_shell_output = capture_shell_output;
+        self
+    }
+
+    /// Wait for these delays while pressing keys instead of the default ones
+    pub fn with_key_delays(mut self, key_delays: KeyDelays) -> Self {
+        self.delays = self.delays.with_key_delays(key_delays);
+        self
+    }
+
+    /// Press or release a key by its keycode
+    fn toggle_key(&self, keycode: c_uint, down: bool) {
+        unsafe {
+            xtest::XTestFakeKeyEvent(self.display, keycode, down as c_int, xlib::CurrentTime);
+            xlib::XFlush(self.display);
+        }
+    }
+
+    /// Press and release a mouse button by its X button number
+    fn click_button(&self, button: c_uint) {
+        unsafe {
+            xtest::XTestFakeButtonEvent(self.display, button, 1, xlib::CurrentTime);
+            xtest::XTestFakeButtonEvent(self.display, button, 0, xlib::CurrentTime);
+            xlib::XFlush(self.display);
+        }
+    }
+
+    /// Scroll by clicking the scroll wheel buttons once for each line
+    fn scroll(&self, x: i32, y: i32) {
+        for (amount, negative, positive) in
+            [(x, SCROLL_LEFT, SCROLL_RIGHT), (y, SCROLL_UP, SCROLL_DOWN)]
+        {
+            let button = if amount < 0 { negative } else { positive };
+            for _ in 0..amount.unsigned_abs() {
+                self.click_button(button);
+            }
+        }
+    }
+
+    /// Press or release a modifier key. Modifiers that are not on the keyboard are ignored
+    fn toggle_modifier(&self, modifier: Modifier, down: bool) {
+        let keycode = modifier_to_keysym(modifier)
+            .and_then(|keysym| self.keymap.keysyms.get(&keysym))
+            .map(|(keycode, _)| *keycode);
+        match keycode {
+            Some(keycode) => self.toggle_key(keycode.into(), down),
+            None => eprintln!("[WARN] Cannot press modifier {:?}", modifier),
+        }
+    }
+
+    /// Press a keycode while holding down the modifiers
+    fn press_keycode(&self, keycode: KeyCode, modifiers: &[Modifier]) {
+        for m in modifiers {
+            self.toggle_modifier(*m, true);
+            self.delays.wait_modifier();
+        }
+        self.toggle_key(keycode.into(), true);
+        self.delays.wait_key_hold();
+        self.toggle_key(keycode.into(), false);
+        for m in modifiers.iter().rev() {
+            self.toggle_modifier(*m, false);
+        }
+    }
+
+    /// Press the key that types a keysym while holding down the modifiers. If the keysym is not
+    /// on the keyboard, an unused key is temporarily remapped to it
+    fn press_keysym(&self, keysym: KeySym, modifiers: &[Modifier]) {
+        if let Some((keycode, shift)) = self.keymap.keysyms.get(&keysym) {
+            if *shift && !modifiers.contains(&Modifier::Shift) {
+                let mut modifiers = modifiers.to_vec();
+                modifiers.push(Modifier::Shift);
+                self.press_keycode(*keycode, &modifiers);
+            } else {
+                self.press_keycode(*keycode, modifiers);
+            }
+            return;
+        }
+
+        let keycode = match self.scratch_keycode {
+            Some(keycode) => keycode,
+            None => {
+                eprintln!(
+                    "[ERR] Cannot press keysym {:#x}: no unused key to remap",
+                    keysym
+                );
+                return;
+            }
+        };
+        self.remap_keycode(keycode, keysym);
+        self.press_keycode(keycode, modifiers);
+        self.delays.wait(REMAP_DELAY);
+        self.remap_keycode(keycode, xlib::NoSymbol as KeySym);
+    }
+
+    /// Press or release the key that types a keysym. Unlike `press_keysym`, the key is not
+    /// remapped if the keysym is not on the keyboard because it could be held down indefinitely
+    fn toggle_keysym(&self, keysym: KeySym, down: bool) {
+        match self.keymap.keysyms.get(&keysym) {
+            Some((keycode, _)) => self.toggle_key((*keycode).into(), down),
+            None => eprintln!(
+                "[WARN] Cannot hold keysym {:#x}: not on the keyboard",
+                keysym
+            ),
+        }
+    }
+
+    /// Dispatch key events in order. Keys that are pressed stay down until they are released
+    fn key_events(&self, events: Vec<KeyEvent>) {
+        for event in events {
+            match event {
+                KeyEvent::HoldModifier(m) => {
+                    self.toggle_modifier(m, true);
+                    self.delays.wait_modifier();
+                }
+                KeyEvent::Tap(k) => {
+                    // the held modifiers are already down
+                    self.press_keysym(key_to_keysym(k), &[]);
+                    self.delays.wait_type_text();
+                }
+                KeyEvent::ReleaseModifier(m) => {
+                    self.toggle_modifier(m, false);
+                    self.delays.wait_modifier();
+                }
+                KeyEvent::Press(k) => {
+                    self.toggle_keysym(key_to_keysym(k), true);
+                    self.delays.wait_key_hold();
+                }
+                KeyEvent::Release(k) => self.toggle_keysym(key_to_keysym(k), false),
+            }
+        }
+    }
+
+    /// Change the keysym that a key types
+    fn remap_keycode(&self, keycode: KeyCode, keysym: KeySym) {
+        // set the shifted keysym too so that holding shift does not change it
+        let mut keysyms = [keysym, keysym];
+        unsafe {
+            xlib::XChangeKeyboardMapping(
+                self.display,
+                keycode.into(),
+                keysyms.len() as c_int,
+                keysyms.as_mut_ptr(),
+                1,
+            );
+            xlib::XSync(self.display, xlib::False);
+        }
+    }
+}
+
+impl Controller for X11Controller {
+    fn new(disable_scan_keymap: bool) -> Self {
+        let display = unsafe { xlib::XOpenDisplay(ptr::null()) };
+        if display.is_null() {
+            panic!("unable to open X display. Is the DISPLAY environment variable set?");
+        }
+        let keymap = Keymap::scan(display);
+
+        Self {
+            display,
+            scratch_keycode: keymap.unused,
+            keymap,
+            scan_keymap: !disable_scan_keymap,
+            capture_shell_output: false,
+            delays: KeyWaiter::default(),
+        }
+    }
+
+    fn dispatch(&mut self, command: Command) {
+        if self.scan_keymap {
+            // scan the keymap again in case the keyboard layout changed
+            self.keymap = Keymap::scan(self.display);
+        }
+
+        match command {
+            Command::Replace(backspace_num, add_text) => {
+                // tap backspace for corrections
+                let backspace = special_key_to_keysym(SpecialKey::Backspace);
+                for _ in 0..backspace_num {
+                    self.press_keysym(backspace, &[]);
+                    self.delays.wait_backspace();
+                }
+
+                // type text
+                for c in add_text.chars() {
+                    self.press_keysym(char_to_keysym(c), &[]);
+                    self.delays.wait_type_text();
+                }
+            }
+            Command::PrintHello => {
+                println!("Hello!");
+            }
+            Command::NoOp => {}
+            Command::Keys(key, modifiers) => self.press_keysym(key_to_keysym(key), &modifiers),
+            Command::RepeatKey {
+                key,
+                modifiers,
+                count,
+            } => {
+                let delays = self.delays;
+                repeat_key(self, key, modifiers, count, delays)
+            }
+            Command::KeySequence(modifiers, keys) => {
+                self.key_events(Command::key_sequence_events(&modifiers, &keys))
+            }
+            Command::KeyDown(key, modifiers) => {
+                self.key_events(Command::key_down_events(&key, &modifiers))
+            }
+            Command::KeyUp(key, modifiers) => {
+                self.key_events(Command::key_up_events(&key, &modifiers))
+            }
+            // screen -1 is the screen that the mouse is on
+            Command::MouseMove(x, y) => unsafe {
+                xtest::XTestFakeRelativeMotionEvent(self.display, -1, x, y, xlib::CurrentTime);
+                xlib::XFlush(self.display);
+            },
+            Command::MouseMoveTo(x, y) => unsafe {
+                xtest::XTestFakeMotionEvent(self.display, -1, x, y, xlib::CurrentTime);
+                xlib::XFlush(self.display);
+            },
+            Command::MouseClick(button) => self.click_button(mouse_button_number(button)),
+            Command::MouseScroll(x, y) => self.scroll(x, y),
+            Command::Nav(nav) => {
+                let (key, modifiers) = nav_to_keys(nav);
+                self.press_keysym(special_key_to_keysym(key), &modifiers);
+            }
+            Command::Raw(keycode) => {
+                self.toggle_key(keycode.into(), true);
+                self.delays.wait_key_hold();
+                self.toggle_key(keycode.into(), false);
+            }
+            Command::Shell(cmd, args) => dispatch_shell(cmd, args, self.capture_shell_output),
+            Command::ToggleSlowMode => {
+                let is_slow = self.delays.toggle_slow_mode();
+                println!("[INFO] Slow mode {}", if is_slow { "on" } else { "off" });
+            }
+            Command::ShellInsert(..) => panic!("cannot handle shell insert command"),
+            Command::TranslatorCommand(_) => panic!("cannot handle translator command"),
+        }
+    }
+}
+
+impl Drop for X11Controller {
+    fn drop(&mut self) {
+        unsafe { xlib::XCloseDisplay(self.display) };
+    }
+}
+
+/// The key combination that moves the cursor
+fn nav_to_keys(nav: Nav) -> (SpecialKey, Vec<Modifier>) {
+    match nav {
+        Nav::WordLeft => (SpecialKey::LeftArrow, vec![Modifier::Control]),
+        Nav::WordRight => (SpecialKey::RightArrow, vec![Modifier::Control]),
+        Nav::LineStart => (SpecialKey::Home, vec![]),
+        Nav::LineEnd => (SpecialKey::End, vec![]),
+        Nav::DocumentStart => (SpecialKey::Home, vec![Modifier::Control]),
+        Nav::DocumentEnd => (SpecialKey::End, vec![Modifier::Control]),
+    }
+}
+
+/// The X button number of a mouse button
+fn mouse_button_number(button: MouseButton) -> c_uint {
+    match button {
+        MouseButton::Left => 1,
+        MouseButton::Middle => 2,
+        MouseButton::Right => 3,
+    }
+}
+
+fn key_to_keysym(key: Key) -> KeySym {
+    match key {
+        Key::Special(special_key) => special_key_to_keysym(special_key),
+        Key::Layout(c) => char_to_keysym(c),
+    }
+}
+
+/// The keysym that types a char. Latin-1 chars have the same keysym as their code point, and
+/// every other unicode char has its code point offset by 0x01000000
+fn char_to_keysym(c: char) -> KeySym {
+    match c {
+        '\n' => keysym::XK_Return.into(),
+        '\t' => keysym::XK_Tab.into(),
+        ' '..='~' | '\u{a0}'..='\u{ff}' => c as KeySym,
+        _ => 0x0100_0000 + c as KeySym,
+    }
+}
+
+/// The keysym of the left modifier key. There is no keysym for the Fn key
+fn modifier_to_keysym(modifier: Modifier) -> Option<KeySym> {
+    let keysym = match modifier {
+        Modifier::Alt => keysym::XK_Alt_L,
+        Modifier::Control => keysym::XK_Control_L,
+        Modifier::Meta => keysym::XK_Super_L,
+        Modifier::Option => keysym::XK_Alt_L,
+        Modifier::Shift => keysym::XK_Shift_L,
+        Modifier::Fn => return None,
+    };
+    Some(keysym.into())
+}
+
+fn special_key_to_keysym(key: SpecialKey) -> KeySym {
+    let keysym = match key {
+        SpecialKey::Backspace => keysym::XK_BackSpace,
+        SpecialKey::CapsLock => keysym::XK_Caps_Lock,
+        SpecialKey::Delete => keysym::XK_Delete,
+        SpecialKey::DownArrow => keysym::XK_Down,
+        SpecialKey::End => keysym::XK_End,
+        SpecialKey::Escape => keysym::XK_Escape,
+        SpecialKey::F1 => keysym::XK_F1,
+        SpecialKey::F10 => keysym::XK_F10,
+        SpecialKey::F11 => keysym::XK_F11,
+        SpecialKey::F12 => keysym::XK_F12,
+        SpecialKey::F2 => keysym::XK_F2,
+        SpecialKey::F3 => keysym::XK_F3,
+        SpecialKey::F4 => keysym::XK_F4,
+        SpecialKey::F5 => keysym::XK_F5,
+        SpecialKey::F6 => keysym::XK_F6,
+        SpecialKey::F7 => keysym::XK_F7,
+        SpecialKey::F8 => keysym::XK_F8,
+        SpecialKey::F9 => keysym::XK_F9,
+        SpecialKey::Home => keysym::XK_Home,
+        SpecialKey::LeftArrow => keysym::XK_Left,
+        SpecialKey::PageDown => keysym::XK_Page_Down,
+        SpecialKey::PageUp => keysym::XK_Page_Up,
+        SpecialKey::Return => keysym::XK_Return,
+        SpecialKey::RightArrow => keysym::XK_Right,
+        SpecialKey::Space => keysym::XK_space,
+        SpecialKey::Tab => keysym::XK_Tab,
+        SpecialKey::UpArrow => keysym::XK_Up,
+    };
+    keysym.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn special_keysyms() {
+        assert_eq!(special_key_to_keysym(SpecialKey::Backspace), 0xff08);
+        assert_eq!(special_key_to_keysym(SpecialKey::Return), 0xff0d);
+        assert_eq!(special_key_to_keysym(SpecialKey::Space), 0x20);
+        assert_eq!(special_key_to_keysym(SpecialKey::LeftArrow), 0xff51);
+        assert_eq!(special_key_to_keysym(SpecialKey::PageDown), 0xff56);
+        assert_eq!(special_key_to_keysym(SpecialKey::F1), 0xffbe);
+        assert_eq!(special_key_to_keysym(SpecialKey::F12), 0xffc9);
+    }
+
+    #[test]
+    fn char_keysyms() {
+        assert_eq!(char_to_keysym('a'), 0x61);
+        assert_eq!(char_to_keysym('A'), 0x41);
+        assert_eq!(char_to_keysym('~'), 0x7e);
+        assert_eq!(char_to_keysym(' '), 0x20);
+        assert_eq!(char_to_keysym('é'), 0xe9);
+        assert_eq!(char_to_keysym('\n'), 0xff0d);
+        assert_eq!(char_to_keysym('\t'), 0xff09);
+        // outside of Latin-1
+        assert_eq!(char_to_keysym('€'), 0x0100_20ac);
+        assert_eq!(char_to_keysym('→'), 0x0100_2192);
+    }
+
+    #[test]
+    fn modifier_keysyms() {
+        assert_eq!(modifier_to_keysym(Modifier::Shift), Some(0xffe1));
+        assert_eq!(modifier_to_keysym(Modifier::Control), Some(0xffe3));
+        assert_eq!(modifier_to_keysym(Modifier::Alt), Some(0xffe9));
+        assert_eq!(modifier_to_keysym(Modifier::Option), Some(0xffe9));
+        assert_eq!(modifier_to_keysym(Modifier::Meta), Some(0xffeb));
+        assert_eq!(modifier_to_keysym(Modifier::Fn), None);
+    }
+
+    #[test]
+    fn mouse_button_numbers() {
+        assert_eq!(mouse_button_number(MouseButton::Left), 1);
+        assert_eq!(mouse_button_number(MouseButton::Middle), 2);
+        assert_eq!(mouse_button_number(MouseButton::Right), 3);
+    }
+
+    #[test]
+    fn nav_keys() {
+        assert_eq!(
+            nav_to_keys(Nav::WordLeft),
+            (SpecialKey::LeftArrow, vec![Modifier::Control])
+        );
+        assert_eq!(nav_to_keys(Nav::LineEnd), (SpecialKey::End, vec![]));
+        assert_eq!(
+            nav_to_keys(Nav::DocumentStart),
+            (SpecialKey::Home, vec![Modifier::Control])
+        );
+    }
+}
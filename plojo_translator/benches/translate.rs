@@ -0,0 +1,86 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use plojo_core::{Stroke, Translator};
+use plojo_translator::StandardTranslator;
+
+/// Build a dictionary with a few thousand single and multi stroke entries
+fn build_dict() -> String {
+    let left = ["S", "T", "K", "P", "W", "H", "R", "TK", "PW", "HR", "SKWR"];
+    let vowels = ["A", "O", "E", "U", "AO", "AOE", "EU", "AU"];
+    let right = ["", "F", "R", "P", "B", "L", "G", "T", "S", "D"];
+
+    let mut strokes = vec![];
+    for l in left.iter() {
+        for v in vowels.iter() {
+            for r in right.iter() {
+                strokes.push(format!("{}{}{}", l, v, r));
+            }
+        }
+    }
+
+    let mut entries = vec![];
+    for (i, s) in strokes.iter().enumerate() {
+        entries.push(format!(r#""{}": "word{}""#, s, i));
+        // some strokes also start a multi stroke entry
+        if i % 7 == 0 {
+            let next = &strokes[(i * 13) % strokes.len()];
+            entries.push(format!(r#""{}/{}": "phrase{}""#, s, next, i));
+        }
+    }
+    entries.push(r#""-S": "{^s}""#.to_string());
+    entries.push(r#""KPA*": "{^}{-|}""#.to_string());
+
+    format!("{{{}}}", entries.join(",\n"))
+}
+
+/// Translate the strokes over and over again with a full stroke buffer
+fn translate_strokes(c: &mut Criterion, name: &str, raw: &str) {
+    let strokes: Vec<_> = raw.split('/').map(Stroke::new).collect();
+    let mut translator = StandardTranslator::new(vec![build_dict()], vec![], vec![], None, false)
+        .expect("unable to create translator");
+
+    c.bench_function(name, |b| {
+        b.iter(|| {
+            for stroke in &strokes {
+                translator.translate(stroke.clone());
+            }
+        })
+    });
+}
+
+fn single_strokes(c: &mut Criterion) {
+    // every stroke is its own word
+    translate_strokes(
+        c,
+        "single stroke words",
+        "TAT/KAOEG/PWEUL/SOR/HRAUD/TKOT/PEUS/WAF/RUB/HAOEP",
+    );
+}
+
+fn multi_strokes(c: &mut Criterion) {
+    // the second stroke merges with the first, which must translate all the strokes again
+    translate_strokes(c, "multi stroke words", "SAT/TOF/SOB/KER/SAT/TOF/SOB/KER");
+}
+
+fn undo_strokes(c: &mut Criterion) {
+    // undoing translates the whole stroke buffer again for every stroke that is removed
+    let strokes: Vec<_> = "SAT/TOF/SOB/KER/TAT/KAOEG/PWEUL/SOR/HRAUD/TKOT/PEUS/WAF"
+        .split('/')
+        .map(Stroke::new)
+        .collect();
+    let mut translator = StandardTranslator::new(vec![build_dict()], vec![], vec![], None, false)
+        .expect("unable to create translator");
+
+    c.bench_function("undo", |b| {
+        b.iter(|| {
+            for stroke in &strokes {
+                translator.translate(stroke.clone());
+            }
+            for _ in &strokes {
+                translator.undo();
+            }
+        })
+    });
+}
+
+criterion_group!(benches, single_strokes, multi_strokes, undo_strokes);
+criterion_main!(benches);
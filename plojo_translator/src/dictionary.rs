@@ -1,45 +1,234 @@
-use crate::Translation;
-use plojo_core::Stroke;
+use crate::{Text, Translation};
+use plojo_core::{Command, Stroke};
 use std::collections::HashMap;
 use std::error::Error;
 use std::iter::FromIterator;
 
+mod cache;
+mod lint;
 mod load;
 mod translate;
 
+use cache::TranslationCache;
+pub use lint::Unreachable;
+pub(crate) use translate::Span;
+
 type DictEntry = (Stroke, Translation);
 
 #[derive(Debug, PartialEq)]
 pub struct Dictionary {
     strokes: HashMap<Stroke, Translation>,
+    // text for single strokes that are not in the dictionary (instead of the raw stroke)
+    fallback: HashMap<Stroke, String>,
+    // how many entries each of the raw dictionaries had, in the order they were loaded
+    stats: Vec<DictStats>,
+    // entries that replaced an entry from an earlier dictionary
+    overrides: Vec<DictOverride>,
+    // entries added (Some) or removed (None) while translating, which take precedence over the
+    // loaded entries
+    edits: HashMap<Stroke, Option<Translation>>,
+    // translations of recently translated strokes, which are cleared whenever the entries change
+    cache: TranslationCache,
+}
+
+/// The number of entries loaded from a single dictionary
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct DictStats {
+    /// All the entries in the dictionary
+    pub entries: usize,
+    /// Entries that override an entry with the same stroke from an earlier dictionary
+    pub overridden: usize,
+}
+
+/// An entry that replaced the entry with the same stroke from an earlier dictionary. The
+/// dictionaries are given by their index in the order they were loaded
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct DictOverride {
+    pub stroke: Stroke,
+    /// The dictionary that the replaced entry was from
+    pub old_dict: usize,
+    /// The dictionary that the new entry is from
+    pub new_dict: usize,
 }
 
 impl Dictionary {
     /// Create a new dictionary from raw JSON strings. Each string represents a dictionary, with
     /// each dictionaries being able to overwrite any dictionary entry before it
     pub fn new(raw_dicts: Vec<String>) -> Result<Self, Box<dyn Error>> {
-        let mut entries = vec![];
-        for raw_dict in raw_dicts {
-            entries.append(&mut load::load_dicts(&raw_dict)?);
+        let mut strokes: HashMap<Stroke, Translation> = HashMap::new();
+        let mut stats = Vec::with_capacity(raw_dicts.len());
+        let mut overrides = vec![];
+        // which dictionary each entry is from
+        let mut origins: HashMap<Stroke, usize> = HashMap::new();
+        for (index, raw_dict) in raw_dicts.iter().enumerate() {
+            let entries = load::load_dicts(raw_dict)?;
+            let mut dict_overrides: Vec<_> = entries
+                .iter()
+                .filter_map(|(stroke, _)| {
+                    let old_dict = origins.insert(stroke.clone(), index)?;
+                    Some(DictOverride {
+                        stroke: stroke.clone(),
+                        old_dict,
+                        new_dict: index,
+                    })
+                })
+                .collect();
+            dict_overrides.sort_by_key(|o| o.stroke.clone().to_raw());
+            stats.push(DictStats {
+                entries: entries.len(),
+                overridden: dict_overrides.len(),
+            });
+            overrides.append(&mut dict_overrides);
+            strokes.extend(entries);
         }
 
-        Ok(entries.into_iter().collect())
+        Ok(Dictionary {
+            strokes,
+            fallback: HashMap::new(),
+            stats,
+            overrides,
+            edits: HashMap::new(),
+            cache: TranslationCache::default(),
+        })
+    }
+
+    /// How many entries were loaded from each dictionary
+    pub(super) fn stats(&self) -> &[DictStats] {
+        &self.stats
+    }
+
+    /// The entries that replaced an entry from an earlier dictionary, in the order the
+    /// dictionaries were loaded
+    pub(super) fn overrides(&self) -> &[DictOverride] {
+        &self.overrides
     }
 
     fn lookup(&self, strokes: &[Stroke]) -> Option<Translation> {
-        // combine strokes with a `/` between them
-        let combined = strokes
-            .iter()
-            .map(|s| s.clone().to_raw())
-            .collect::<Vec<_>>()
-            .join("/");
+        let stroke = join_strokes(strokes);
+        match self.edits.get(&stroke) {
+            Some(edit) => edit.clone(),
+            None => self.strokes.get(&stroke).cloned(),
+        }
+    }
+
+    /// Add an entry (written the same way as in a dictionary file) for a sequence of strokes,
+    /// replacing any entry that was loaded for them
+    pub(super) fn add_entry(
+        &mut self,
+        strokes: &[Stroke],
+        translation: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        let stroke = load::parse_stroke(&join_strokes(strokes).to_raw())?;
+        let translation = load::parse_string_entry(translation)?;
+        self.edits.insert(stroke, Some(translation));
+        self.cache.clear();
+        Ok(())
+    }
 
-        self.strokes.get(&Stroke::new(&combined)).cloned()
+    /// Remove the entry for a sequence of strokes. An added entry is removed, so the loaded entry
+    /// (if any) is used again. Otherwise the loaded entry is hidden. Returns whether there was an
+    /// entry to remove
+    pub(super) fn remove_entry(&mut self, strokes: &[Stroke]) -> bool {
+        let stroke = join_strokes(strokes);
+        self.cache.clear();
+        match self.edits.get(&stroke) {
+            Some(Some(_)) => {
+                self.edits.remove(&stroke);
+                true
+            }
+            Some(None) => false,
+            None if self.strokes.contains_key(&stroke) => {
+                self.edits.insert(stroke, None);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Keep the entries that were added or removed from another dictionary (when it is reloaded)
+    pub(super) fn with_edits_from(mut self, other: &Dictionary) -> Self {
+        self.edits = other.edits.clone();
+        self.cache.clear();
+        self
+    }
+
+    /// The shell insert commands (and their arguments) of the entry for a sequence of strokes
+    pub(super) fn shell_inserts(&self, strokes: &[Stroke]) -> Vec<(String, Vec<String>)> {
+        match self.lookup(strokes) {
+            Some(Translation::Command { cmds, .. }) => cmds
+                .into_iter()
+                .filter_map(|cmd| match cmd {
+                    Command::ShellInsert(cmd, args) => Some((cmd, args)),
+                    _ => None,
+                })
+                .collect(),
+            _ => vec![],
+        }
+    }
+
+    /// Set the text that an unknown stroke is translated to instead of the raw stroke
+    pub(super) fn with_fallback(mut self, fallback: HashMap<Stroke, String>) -> Self {
+        self.fallback = fallback;
+        self.cache.clear();
+        self
+    }
+
+    pub(super) fn fallback(&self) -> &HashMap<Stroke, String> {
+        &self.fallback
+    }
+
+    /// Look up a stroke that is not in the dictionary in the fallback
+    fn lookup_fallback(&self, stroke: &Stroke) -> Option<Translation> {
+        self.fallback
+            .get(stroke)
+            .map(|text| Translation::Text(vec![Text::Lit(text.clone())]))
+    }
+
+    /// All of the entries in the dictionary (after earlier dictionaries are overridden and the
+    /// entries are added or removed)
+    pub(super) fn entries(&self) -> impl Iterator<Item = (&Stroke, &Translation)> {
+        let loaded = self
+            .strokes
+            .iter()
+            .filter(move |(stroke, _)| !self.edits.contains_key(stroke));
+        let added = self
+            .edits
+            .iter()
+            .filter_map(|(stroke, edit)| Some((stroke, edit.as_ref()?)));
+        loaded.chain(added)
     }
 
     pub(super) fn translate(&self, strokes: &[Stroke]) -> Vec<Translation> {
-        translate::translate_strokes(self, strokes)
+        self.translate_spans(strokes)
+            .into_iter()
+            .flat_map(|(_, translations)| translations)
+            .collect()
     }
+
+    /// Same as `translate`, but keeps track of which strokes each translation came from. The
+    /// translations are cached, so translating the same strokes again is fast
+    pub(super) fn translate_spans(&self, strokes: &[Stroke]) -> Vec<Span> {
+        self.cache
+            .get_or_translate(strokes, || translate::translate_spans(self, strokes))
+    }
+
+    pub(super) fn translate_appended(
+        &self,
+        strokes: &[Stroke],
+        prev_spans: &[Span],
+    ) -> Option<Vec<Span>> {
+        translate::translate_appended(self, strokes, prev_spans)
+    }
+}
+
+/// Combine strokes into a single multi-stroke entry (with a `/` between them)
+fn join_strokes(strokes: &[Stroke]) -> Stroke {
+    let combined = strokes
+        .iter()
+        .map(|s| s.clone().to_raw())
+        .collect::<Vec<_>>()
+        .join("/");
+    Stroke::new(&combined)
 }
 
 impl FromIterator<DictEntry> for Dictionary {
@@ -49,7 +238,14 @@ impl FromIterator<DictEntry> for Dictionary {
             hashmap.insert(stroke, translations);
         }
 
-        Dictionary { strokes: hashmap }
+        Dictionary {
+            strokes: hashmap,
+            fallback: HashMap::new(),
+            stats: vec![],
+            overrides: vec![],
+            edits: HashMap::new(),
+            cache: TranslationCache::default(),
+        }
     }
 }
 
@@ -80,4 +276,130 @@ mod tests {
             Translation::Text(vec![Text::Lit("something else".to_string())])
         );
     }
+
+    #[test]
+    fn dictionary_stats() {
+        let raw_dict1 = r#"
+            {
+                "H-L": "hello",
+                "WORLD": "world",
+                "TEFT": "test"
+            }
+        "#
+        .to_string();
+        let raw_dict2 = r#"
+            {
+                "WORLD": "something else",
+                "H-L": "hi",
+                "TKOG": "dog"
+            }
+        "#
+        .to_string();
+        let raw_dict3 = r#"
+            {
+                "TKOG": "doggo"
+            }
+        "#
+        .to_string();
+
+        let dict = Dictionary::new(vec![raw_dict1, raw_dict2, raw_dict3]).unwrap();
+        assert_eq!(
+            dict.stats(),
+            &[
+                DictStats {
+                    entries: 3,
+                    overridden: 0
+                },
+                DictStats {
+                    entries: 3,
+                    overridden: 2
+                },
+                DictStats {
+                    entries: 1,
+                    overridden: 1
+                },
+            ]
+        );
+        assert_eq!(dict.entries().count(), 4);
+    }
+
+    #[test]
+    fn dictionary_overrides() {
+        let raw_dict1 = r#"{ "H-L": "hello", "WORLD": "world", "TEFT": "test" }"#.to_string();
+        let raw_dict2 = r#"{ "WORLD": "something else", "H-L": "hi", "TKOG": "dog" }"#.to_string();
+        let raw_dict3 = r#"{ "TKOG": "doggo", "TEFT": "tested" }"#.to_string();
+
+        let dict = Dictionary::new(vec![raw_dict1, raw_dict2, raw_dict3]).unwrap();
+        let overrides: Vec<_> = dict
+            .overrides()
+            .iter()
+            .map(|o| (o.stroke.clone().to_raw(), o.old_dict, o.new_dict))
+            .collect();
+        assert_eq!(
+            overrides,
+            vec![
+                ("H-L".to_string(), 0, 1),
+                ("WORLD".to_string(), 0, 1),
+                ("TEFT".to_string(), 0, 2),
+                ("TKOG".to_string(), 1, 2),
+            ]
+        );
+        // the entries are still replaced
+        assert_eq!(
+            dict.lookup(&[Stroke::new("TEFT")]).unwrap(),
+            Translation::Text(vec![Text::Lit("tested".to_string())])
+        );
+    }
+
+    #[test]
+    fn cached_translations() {
+        let raw_dict = r#"{
+            "H-L": "hello",
+            "H-L/WORLD": "hi",
+            "WORLD": "world",
+            "-S": "{^s}",
+            "TP-PL": "{.}"
+        }"#
+        .to_string();
+        let mut dict = Dictionary::new(vec![raw_dict]).unwrap();
+        let strokes: Vec<_> = "H-L/WORLD/H-L/WORLD/-S/TP-PL/WORLD/H-L"
+            .split('/')
+            .map(Stroke::new)
+            .collect();
+
+        // translating again (from the cache) is the same as translating without the cache
+        for _ in 0..2 {
+            for end in 0..=strokes.len() {
+                assert_eq!(
+                    dict.translate_spans(&strokes[..end]),
+                    translate::translate_spans(&dict, &strokes[..end])
+                );
+            }
+        }
+        assert_eq!(dict.cache.len(), strokes.len() + 1);
+
+        // changing the entries clears the cache
+        dict.add_entry(&[Stroke::new("WORLD")], "earth").unwrap();
+        assert_eq!(dict.cache.len(), 0);
+        assert_eq!(
+            dict.translate(&strokes[2..4]),
+            vec![Translation::Text(vec![Text::Lit("hi".to_string())])]
+        );
+        assert_eq!(
+            dict.translate(&strokes[3..4]),
+            vec![Translation::Text(vec![Text::Lit("earth".to_string())])]
+        );
+        assert!(dict.remove_entry(&[Stroke::new("H-L"), Stroke::new("WORLD")]));
+        assert_eq!(
+            dict.translate(&strokes[2..4]),
+            translate::translate_strokes(&dict, &strokes[2..4])
+        );
+        assert_eq!(
+            dict.translate(&strokes[2..4]),
+            vec![
+                Translation::Text(vec![Text::Lit("hello".to_string())]),
+                Translation::Text(vec![Text::Lit("earth".to_string())])
+            ]
+        );
+    }
 }
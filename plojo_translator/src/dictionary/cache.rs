@@ -0,0 +1,58 @@
+//! Remembers the translations of recently translated strokes, because the same strokes are often
+//! translated again (ex: when undoing)
+use super::Span;
+use plojo_core::Stroke;
+use std::{cell::RefCell, collections::HashMap, fmt};
+
+// the cache is cleared when it gets this big so it doesn't grow forever
+const MAX_CACHED: usize = 1000;
+
+#[derive(Default)]
+pub(super) struct TranslationCache {
+    spans: RefCell<HashMap<Vec<Stroke>, Vec<Span>>>,
+}
+
+impl TranslationCache {
+    /// The translations of the strokes if they were translated before. Otherwise translate them
+    /// and remember the translations
+    pub(super) fn get_or_translate(
+        &self,
+        strokes: &[Stroke],
+        translate: impl FnOnce() -> Vec<Span>,
+    ) -> Vec<Span> {
+        if let Some(spans) = self.spans.borrow().get(strokes) {
+            return spans.clone();
+        }
+
+        let spans = translate();
+        let mut cached = self.spans.borrow_mut();
+        if cached.len() >= MAX_CACHED {
+            cached.clear();
+        }
+        cached.insert(strokes.to_vec(), spans.clone());
+        spans
+    }
+
+    /// Forget all the translations. This must be done whenever the dictionary changes
+    pub(super) fn clear(&mut self) {
+        self.spans.get_mut().clear();
+    }
+
+    #[cfg(test)]
+    pub(super) fn len(&self) -> usize {
+        self.spans.borrow().len()
+    }
+}
+
+impl fmt::Debug for TranslationCache {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "TranslationCache({} cached)", self.spans.borrow().len())
+    }
+}
+
+// the cached translations don't change what the dictionary translates to
+impl PartialEq for TranslationCache {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
@@ -0,0 +1,180 @@
+//! Finds dictionary entries that can never be translated because of how strokes are matched
+use super::{
+    translate::{translate_strokes, MAX_TRANSLATION_STROKE_LEN},
+    Dictionary,
+};
+use crate::{Translation, START_PASSTHROUGH, TOGGLE_PASSTHROUGH};
+use plojo_core::Stroke;
+
+/// Reason why a dictionary entry can never be translated
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Unreachable {
+    /// One of the strokes is the undo stroke, which is never translated
+    ContainsUndo,
+    /// There are more strokes than the translator looks up at once
+    TooManyStrokes,
+    /// The first few strokes are a command that turns on passthrough, so the strokes after it are
+    /// typed as raw steno instead of being looked up
+    CommandPrefix(Stroke),
+    /// Translating the strokes on their own gives some other translation
+    Shadowed,
+}
+
+impl Dictionary {
+    /// Finds all the entries that can never be translated by simulating the translator on the
+    /// strokes of each entry. The entries are sorted by stroke.
+    pub(crate) fn find_unreachable(&self) -> Vec<(Stroke, Unreachable)> {
+        let mut entries: Vec<_> = self.entries().collect();
+        entries.sort_by_key(|(stroke, _)| (*stroke).clone().to_raw());
+
+        let mut unreachable = vec![];
+        for (stroke, translation) in entries {
+            if let Some(reason) = self.check_reachable(stroke, translation) {
+                unreachable.push((stroke.clone(), reason));
+            }
+        }
+
+        unreachable
+    }
+
+    /// Returns why an entry cannot be reached, or None if it can be reached
+    fn check_reachable(&self, stroke: &Stroke, translation: &Translation) -> Option<Unreachable> {
+        let strokes: Vec<Stroke> = stroke
+            .clone()
+            .to_raw()
+            .split('/')
+            .map(Stroke::new)
+            .collect();
+
+        if strokes.iter().any(Stroke::is_undo) {
+            return Some(Unreachable::ContainsUndo);
+        }
+        if strokes.len() > MAX_TRANSLATION_STROKE_LEN {
+            return Some(Unreachable::TooManyStrokes);
+        }
+
+        // other commands in the first few strokes are dispatched first, but the strokes are still
+        // translated together once the entry is finished
+        for end in 1..strokes.len() {
+            let starts_passthrough = self.lookup(&strokes[..end]).is_some_and(|t| {
+                t.has_translator_command(TOGGLE_PASSTHROUGH)
+                    || t.has_translator_command(START_PASSTHROUGH)
+            });
+            if starts_passthrough {
+                let prefix = strokes[..end]
+                    .iter()
+                    .map(|s| s.clone().to_raw())
+                    .collect::<Vec<_>>()
+                    .join("/");
+                return Some(Unreachable::CommandPrefix(Stroke::new(&prefix)));
+            }
+        }
+
+        if translate_strokes(self, &strokes) != vec![translation.clone()] {
+            return Some(Unreachable::Shadowed);
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lint(raw_dict: &str) -> Vec<(Stroke, Unreachable)> {
+        Dictionary::new(vec![raw_dict.to_string()])
+            .unwrap()
+            .find_unreachable()
+    }
+
+    #[test]
+    fn test_all_reachable() {
+        let unreachable = lint(
+            r#"
+            {
+                "H-L": "hello",
+                "H-L/WORLD": "hello world",
+                "WORLD": "world",
+                "-S": "{^s}"
+            }
+            "#,
+        );
+
+        assert_eq!(unreachable, vec![]);
+    }
+
+    #[test]
+    fn test_command_prefix() {
+        let unreachable = lint(
+            r#"
+            {
+                "PA*S": { "cmds": [{ "TranslatorCommand": "toggle_passthrough" }] },
+                "PA*S/H-L": "hello",
+                "PA*S/H-L/WORLD": "hello world",
+                "S-PD": "{PLOVER:SUSPEND}",
+                "S-PD/H-L": "hello"
+            }
+            "#,
+        );
+
+        assert_eq!(
+            unreachable,
+            vec![
+                (
+                    Stroke::new("PA*S/H-L"),
+                    Unreachable::CommandPrefix(Stroke::new("PA*S"))
+                ),
+                (
+                    Stroke::new("PA*S/H-L/WORLD"),
+                    Unreachable::CommandPrefix(Stroke::new("PA*S"))
+                ),
+                (
+                    Stroke::new("S-PD/H-L"),
+                    Unreachable::CommandPrefix(Stroke::new("S-PD"))
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_command_prefix_still_reachable() {
+        // the command is dispatched first, but the entry is still translated after it
+        let unreachable = lint(
+            r#"
+            {
+                "R-R": { "cmds": [{ "Keys": [{"Special": "Return"}, []] }] },
+                "R-R/H-L": "hello",
+                "TKPWO": { "cmds": [{ "TranslatorCommand": "clear_prev_strokes" }] },
+                "TKPWO/H-L": "bye"
+            }
+            "#,
+        );
+
+        assert_eq!(unreachable, vec![]);
+    }
+
+    #[test]
+    fn test_undo_and_too_long() {
+        let unreachable = lint(
+            r#"
+            {
+                "H-L/*": "hello",
+                "A/A/A/A/A/A/A/A/A/A/A": "too long",
+                "A/A/A/A/A/A/A/A/A/A": "just right"
+            }
+            "#,
+        );
+
+        assert_eq!(
+            unreachable,
+            vec![
+                (
+                    Stroke::new("A/A/A/A/A/A/A/A/A/A/A"),
+                    Unreachable::TooManyStrokes
+                ),
+                (Stroke::new("H-L/*"), Unreachable::ContainsUndo),
+            ]
+        );
+    }
+}
@@ -1,4 +1,4 @@
-use crate::{AttachedType, StateAction, Text, TextAction, Translation};
+use crate::{plover, AttachedType, NumberFormat, StateAction, Text, TextAction, Translation};
 use plojo_core::{Command, Stroke};
 use regex::Regex;
 use serde_json::{self, Error as JsonError, Value};
@@ -44,6 +44,13 @@ use std::{error::Error, fmt};
 /// - `{*-|}`: capitalize previous word (`{^}{*-|}` also suppresses space)
 ///     - this can be used in conjunction with suffixes: `{*-|}{^ville}` will capitalize the
 ///       previous word and add `ville` to the end. For example: `cat` would become `Catville`.
+/// - `{*-|-}`: capitalize each part of the previous word that is joined by hyphens or underscores.
+///   For example: `jean-luc` would become `Jean-Luc`
+///
+/// ### Toggling space
+/// - `{toggle_suppress_space}`: toggles whether the next word has a space before it. Unlike `{^}`,
+///   this does not attach itself to the previous word; it only changes the state until the next
+///   word is outputted. Toggling it twice will cancel it out.
 ///
 /// ### Carrying capitalizing
 /// - `{~|text}` or `{^~|text^}` where the attach operator is optional and the text can be changed
@@ -53,22 +60,113 @@ use std::{error::Error, fmt};
 /// - `{.}`, `{?}`, `{!}`: inserts a the punctuation joined to the previous word and uppercases anything next
 /// - `{,}`, `{:}`, `{;}`: inserts the punctuation joined to the previous word
 ///
+/// ### Smart quotes
+/// - `{:smart_quote}`: inserts a closing curly quote (”) if the character before it is a letter,
+///   a digit, or closing punctuation (`.,!?)]}”’`), and an opening curly quote (“) otherwise (ex:
+///   after whitespace, an opening parenthesis, or at the start). The word after an opening quote
+///   is attached to it. For example, `{:smart_quote}` after "said" outputs ` “`, and
+///   `{^}{:smart_quote}` after "hi" outputs `”`
+/// - `{:smart_single_quote}`: same as above, but with single quotes (‘ and ’)
+///
 /// ### Retrospective Space
 /// - `{*!}`: retrospectivly remove space before the previous translated word
 ///
+/// ### Number formatting
+/// These reformat the previous word if it is a number (digits and possibly commas). Otherwise they
+/// do nothing
+/// - `{*(,)}`: inserts commas between every 3 digits of the previous number (`1234567` becomes
+///   `1,234,567`)
+/// - `{*($c)}`: formats the previous number as dollars, with the last two digits as cents (`1299`
+///   becomes `$12.99`)
+///
 /// ### Uppercasing
-/// - `{<}`: uppercase (ALL CAPS) next word
+/// - `{<}`: uppercase (ALL CAPS) next word (only the first word of a multi-word translation)
 /// - `{*<}`: uppercase previous word
-/// - `{>}`: lowercase next word
+/// - `{>}`: lowercase the first letter of next word (overrides a previous `{-|}`, and vice versa)
 /// - `{*>}`: lowercase previous word
 ///
 /// ### Literal symbols
 /// - `{bracketleft}`: inserts a literal opening bracket (`{`)
 /// - `{bracketright}`: inserts a literal closing bracket (`}`)
 ///
+/// ### Cursor placement
+/// - `{:cursor}`: after the translation is outputted, moves the cursor back to this position
+///   (with the left arrow key). For example, `Best,\n{:cursor}\nJohn` will put the cursor on the
+///   empty line. Since the cursor is no longer at the end of the text, the strokes up to this
+///   translation can't be undone or corrected afterwards
+///
+/// ### Selecting text
+/// - `{:select_prev}`: after the translation is outputted, moves the cursor back to this position
+///   (like `{:cursor}`) and selects the text right before it up to the previous bracket or the
+///   start of the translation (with shift and the left arrow key). The space before that text is
+///   not selected. This way placeholder text can be overwritten by whatever is written next. For
+///   example, `your name{:select_prev}{^,}` selects "your name"
+/// - `{:select_prev:N}`: same as above, but selects the N chars before this position
+///
+/// ### Suffix folding
+/// - `{:no_fold}`: suffix keys (`-S`, `-D`, `-Z`, `-G`) are never folded onto this entry. For
+///   example, with `"KAR": "car{:no_fold}"`, the stroke `KARS` is an unknown stroke instead of
+///   "cars" (unless `KARS` has its own entry)
+///
+/// ### Plover meta commands
+/// For compatibility with Plover dictionaries, some of Plover's meta commands are translated to
+/// translator commands:
+/// - `{PLOVER:SUSPEND}`: turns passthrough on (strokes are typed as raw steno)
+/// - `{PLOVER:RESUME}`: turns passthrough off
+/// - `{PLOVER:TOGGLE}`: toggles passthrough
+///
+/// Other meta commands (ex: `{PLOVER:ADD_TRANSLATION}`) do nothing, and a warning is printed when
+/// the dictionary is loaded
+///
 /// ### Canceling Formatting of Next Word
 /// - The empty text commmand (`{}`) cancels the state actions (mostly formatting actions)
 ///
+/// ## Commands
+/// Instead of a string, the value can be an object with a list of commands (`cmds`) to dispatch.
+/// The commands are dispatched in order, and do not change the text that the translator keeps
+/// track of (they cannot be undone).
+/// - `text_after`: text (usually only formatting actions) that applies to what comes after the
+///   commands
+/// - `suppress_space_before`: removes the space before the commands when spaces are added after
+///   words
+///
+/// For example, this inserts a pair of parentheses with the cursor between them, and attaches the
+/// next word to the opening parenthesis (in both space before and space after modes):
+/// ```json
+/// {
+///     "cmds": [{ "Replace": [0, " ()"] }, { "Keys": [{ "Special": "LeftArrow" }, []] }],
+///     "text_after": "{^}",
+///     "suppress_space_before": true
+/// }
+/// ```
+///
+/// A paragraph break presses return and capitalizes the next word without a space before it:
+/// ```json
+/// {
+///     "cmds": [{ "Keys": [{ "Special": "Return" }, []] }],
+///     "text_after": "{^}{-|}",
+///     "suppress_space_before": true
+/// }
+/// ```
+///
+/// The mouse can be moved by an amount (`MouseMove`) or to a position on the screen
+/// (`MouseMoveTo`), clicked, and scrolled (positive amounts scroll right and down). This moves the
+/// mouse to the top left corner and clicks there:
+/// ```json
+/// { "cmds": [{ "MouseMoveTo": [0, 0] }, { "MouseClick": "Left" }] }
+/// ```
+///
+/// ### Inline key commands
+/// Plover keyboard shortcuts (`{#...}`) can be written in the middle of text. The text before the
+/// shortcut is typed, then the key is pressed, and then the rest of the text is typed directly
+/// after it. For example, `Hi{#Return}there` types "Hi", presses return, and types "there". Like
+/// other commands, the key press cannot be undone
+///
+/// An entry that is only a keyboard shortcut (`{#Control_L(c)}`), optionally with a `{^}` before it
+/// and a `{^}` or `{^}{-|}` after it, is loaded as a command entry the same way `plover_convert`
+/// would convert it. This means unmodified plover dictionaries can be used directly. Shortcuts with
+/// multiple keys (`{#a b}`) are not supported, so entries with them are skipped with a warning
+///
 /// ## Differences from plover
 ///
 /// - Retrospective remove space works on the previous word, not the previous stroke
@@ -80,13 +178,21 @@ pub(super) fn load_dicts(contents: &str) -> Result<Entries, ParseError> {
 
     let mut result_entries = Vec::with_capacity(object_entries.len());
 
-    for (stroke, translation) in object_entries {
-        let stroke = parse_stroke(stroke)?;
+    for (raw_stroke, translation) in object_entries {
+        let stroke = parse_stroke(raw_stroke)?;
         match translation {
-            Value::String(translation_str) => {
-                let parsed = parse_translation(translation_str)?;
-                result_entries.push((stroke, Translation::Text(parsed)));
-            }
+            Value::String(translation_str) => match parse_string_entry(translation_str) {
+                Ok(translation) => result_entries.push((stroke, translation)),
+                // plover dictionaries can have shortcuts that can't be loaded, which shouldn't
+                // prevent the rest of the dictionary from loading
+                Err(ParseError::UnsupportedShortcut(e)) => {
+                    eprintln!(
+                        "[WARN] Skipping {:?} because its keyboard shortcut is not supported: {}",
+                        raw_stroke, e
+                    );
+                }
+                Err(e) => return Err(e),
+            },
             Value::Object(obj) => {
                 let commands = obj.get("cmds").ok_or_else(|| {
                     ParseError::InvalidTranslation("cmds key not found".to_string())
@@ -121,16 +227,42 @@ pub(super) fn load_dicts(contents: &str) -> Result<Entries, ParseError> {
     Ok(result_entries)
 }
 
+/// Parse the translation of an entry that is written as a string, which is either text or a
+/// plover keyboard shortcut
+pub(super) fn parse_string_entry(translation_str: &str) -> Result<Translation, ParseError> {
+    match plover::parse_keyboard_shortcut(translation_str) {
+        Ok(shortcut) => Ok(Translation::Command {
+            cmds: vec![shortcut.cmd],
+            text_after: shortcut
+                .text_after
+                .map(|t| parse_translation(&t))
+                .transpose()?,
+            suppress_space_before: shortcut.suppress_space_before,
+        }),
+        // not a keyboard shortcut, so it is text
+        Err(plover::KeyComboError::InvalidFormat) => {
+            Ok(Translation::Text(parse_translation(translation_str)?))
+        }
+        Err(e) => Err(ParseError::UnsupportedShortcut(format!(
+            "{}: {:?}",
+            translation_str, e
+        ))),
+    }
+}
+
 #[derive(Debug, PartialEq)]
 pub enum ParseError {
     // if the JSON file does not exclusively contain an object with entries
     NotEntries,
+    // the stroke along with why it is invalid
     InvalidStroke(String),
     UnknownTranslation(String),
     EmptyTranslation,
     InvalidTranslation(String),
     // a special action is one that is wrapped in brackets in the translation
     InvalidSpecialAction(String),
+    // a plover keyboard shortcut that can't be parsed
+    UnsupportedShortcut(String),
     JsonError(String),
 }
 
@@ -150,12 +282,11 @@ impl From<JsonError> for ParseError {
 
 type Entries = Vec<(Stroke, Translation)>;
 
-fn parse_stroke(s: &str) -> Result<Stroke, ParseError> {
+pub(super) fn parse_stroke(s: &str) -> Result<Stroke, ParseError> {
     let stroke = Stroke::new(s);
-    if stroke.is_valid() {
-        Ok(stroke)
-    } else {
-        Err(ParseError::InvalidStroke(s.to_string()))
+    match stroke.validation_error() {
+        None => Ok(stroke),
+        Some(e) => Err(ParseError::InvalidStroke(format!("{:?}: {}", s, e))),
     }
 }
 
@@ -174,7 +305,7 @@ fn parse_translation(t: &str) -> Result<Vec<Text>, ParseError> {
             '{' => {
                 if start < end {
                     // if there's anything before the bracket, that should be a text literal
-                    translations.push(parse_as_text(&t[start..end]));
+                    push_text(&mut translations, &t[start..end]);
                 }
                 // adding 1 here is fine because '{' is one byte long
                 start = end + 1;
@@ -203,7 +334,7 @@ fn parse_translation(t: &str) -> Result<Vec<Text>, ParseError> {
         ));
     } else if start < t.len() {
         // if there's still more text, add that as well as a text literal
-        translations.push(parse_as_text(&t[start..]));
+        push_text(&mut translations, &t[start..]);
     }
 
     Ok(translations)
@@ -245,16 +376,36 @@ fn parse_special(t: &str) -> Result<Vec<Text>, ParseError> {
         "-|" => Ok(vec![Text::StateAction(StateAction::ForceCapitalize)]),
         // capitalize previous word
         "*-|" => Ok(vec![Text::TextAction(TextAction::CapitalizePrev)]),
+        // capitalize each hyphenated part of the previous word
+        "*-|-" => Ok(vec![Text::TextAction(TextAction::TitleCasePrev)]),
         // remove space from prev word
         "*!" => Ok(vec![Text::TextAction(TextAction::SuppressSpacePrev)]),
         // all caps next word
-        "<" => Ok(vec![Text::StateAction(StateAction::SameCase(true))]),
+        "<" => Ok(vec![Text::StateAction(StateAction::UppercaseWord)]),
         // all caps previous word
         "*<" => Ok(vec![Text::TextAction(TextAction::SameCasePrev(true))]),
-        // all lowercase next word
-        ">" => Ok(vec![Text::StateAction(StateAction::SameCase(false))]),
+        // lowercase next word
+        ">" => Ok(vec![Text::StateAction(StateAction::ForceLowercase)]),
         // all lowercase previous word
         "*>" => Ok(vec![Text::TextAction(TextAction::SameCasePrev(false))]),
+        // format the previous number
+        "*(,)" => Ok(vec![Text::TextAction(TextAction::FormatPrevNumber(
+            NumberFormat::Grouped,
+        ))]),
+        "*($c)" => Ok(vec![Text::TextAction(TextAction::FormatPrevNumber(
+            NumberFormat::Currency,
+        ))]),
+        // toggle suppressing the space before the next word
+        "toggle_suppress_space" => Ok(vec![Text::StateAction(StateAction::ToggleSuppressSpace)]),
+        // move the cursor here after the text is outputted
+        ":cursor" => Ok(vec![Text::Cursor]),
+        // move the cursor here and select the previous word
+        ":select_prev" => Ok(vec![Text::SelectPrev(None)]),
+        // don't fold suffixes onto this entry
+        ":no_fold" => Ok(vec![Text::NoFold]),
+        // curly quotes that open or close depending on the text before them
+        ":smart_quote" => Ok(vec![Text::SmartQuote { double: true }]),
+        ":smart_single_quote" => Ok(vec![Text::SmartQuote { double: false }]),
         // insert literal bracket
         "bracketleft" => Ok(vec![Text::Lit("{".to_string())]),
         "bracketright" => Ok(vec![Text::Lit("}".to_string())]),
@@ -332,25 +483,80 @@ fn parse_special(t: &str) -> Result<Vec<Text>, ParseError> {
                 }
             }
 
+            // move the cursor here and select a number of previous chars
+            if let Some(count) = _t.strip_prefix(":select_prev:") {
+                return match count.parse() {
+                    Ok(count) => Ok(vec![Text::SelectPrev(Some(count))]),
+                    Err(_) => Err(ParseError::InvalidSpecialAction(_t.to_string())),
+                };
+            }
+
+            // plover meta command
+            if let Some(meta) = _t.strip_prefix("PLOVER:") {
+                return Ok(vec![Text::InlineCommand(parse_plover_meta(meta))]);
+            }
+
             // allow `{#}` to do nothing for plover compatibility
             if _t == "#" {
                 return Ok(vec![]);
             }
 
+            // inline plover keyboard shortcut
+            if let Some(combo) = _t.strip_prefix('#') {
+                return match plover::parse_key_combo(combo) {
+                    Ok(cmd) => Ok(vec![Text::InlineCommand(cmd)]),
+                    Err(e) => Err(ParseError::UnsupportedShortcut(format!("{}: {:?}", _t, e))),
+                };
+            }
+
             Err(ParseError::InvalidSpecialAction(_t.to_string()))
         }
     }
 }
 
+/// Maps a Plover meta command (without the `PLOVER:` prefix) to a translator command. Meta
+/// commands that aren't supported do nothing
+fn parse_plover_meta(meta: &str) -> Command {
+    let command = match meta {
+        "SUSPEND" => crate::START_PASSTHROUGH,
+        "RESUME" => crate::STOP_PASSTHROUGH,
+        "TOGGLE" => crate::TOGGLE_PASSTHROUGH,
+        _ => {
+            eprintln!("[WARN] Plover meta command {:?} is not supported", meta);
+            return Command::NoOp;
+        }
+    };
+    Command::TranslatorCommand(command.to_string())
+}
+
 // Parses directly as a text literal
 fn parse_as_text(t: &str) -> Text {
     Text::Lit(t.to_string())
 }
 
+/// Adds text to the translations. Text right after a cursor (or selection) marker that isn't at
+/// the start continues the text before the marker, so it is attached without a space
+fn push_text(translations: &mut Vec<Text>, t: &str) {
+    let after_cursor = matches!(
+        translations.last(),
+        Some(Text::Cursor) | Some(Text::SelectPrev(_))
+    );
+    if translations.len() > 1 && after_cursor {
+        translations.push(Text::Attached {
+            text: t.to_string(),
+            joined_next: false,
+            joined_prev: AttachedType::AttachOnly,
+            carry_capitalization: false,
+        });
+    } else {
+        translations.push(parse_as_text(t));
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use plojo_core::{Key, Modifier, SpecialKey};
+    use plojo_core::{Key, Modifier, MouseButton, Nav, SpecialKey};
     use std::collections::HashSet;
     use std::iter::FromIterator;
 
@@ -464,6 +670,21 @@ mod tests {
             parse_translation("{-|}").unwrap(),
             vec![Text::StateAction(StateAction::ForceCapitalize,)],
         );
+        // all caps next word
+        assert_eq!(
+            parse_translation("{<}").unwrap(),
+            vec![Text::StateAction(StateAction::UppercaseWord)],
+        );
+        // lowercase next word
+        assert_eq!(
+            parse_translation("{>}").unwrap(),
+            vec![Text::StateAction(StateAction::ForceLowercase)],
+        );
+        // capitalize each part of the previous word
+        assert_eq!(
+            parse_translation("{*-|-}").unwrap(),
+            vec![Text::TextAction(TextAction::TitleCasePrev)],
+        );
         // uppercase next word and suppress space
         assert_eq!(
             parse_translation("{^}{-|}").unwrap(),
@@ -505,6 +726,16 @@ mod tests {
                 Text::Lit("cause".to_string()),
             ]
         );
+        // quote attached on both sides (with orthography) that carries the capitalization
+        assert_eq!(
+            parse_translation(r#"{^~|'^}"#).unwrap(),
+            vec![Text::Attached {
+                text: "'".to_string(),
+                joined_next: true,
+                joined_prev: AttachedType::ApplyOrthography,
+                carry_capitalization: true,
+            }]
+        );
         // standalone carrying cap
         assert_eq!(
             parse_translation(r#"{~|hello}"#).unwrap(),
@@ -520,6 +751,62 @@ mod tests {
             parse_translation(r#"{}"#).unwrap(),
             vec![Text::StateAction(StateAction::Clear)]
         );
+        // toggle suppress space
+        assert_eq!(
+            parse_translation("{toggle_suppress_space}").unwrap(),
+            vec![Text::StateAction(StateAction::ToggleSuppressSpace)]
+        );
+        // cursor marker
+        assert_eq!(
+            parse_translation("{:cursor}hello").unwrap(),
+            vec![Text::Cursor, Text::Lit("hello".to_string())]
+        );
+        assert_eq!(
+            parse_translation("({:cursor})").unwrap(),
+            vec![
+                Text::Lit("(".to_string()),
+                Text::Cursor,
+                Text::Attached {
+                    text: ")".to_string(),
+                    joined_next: false,
+                    joined_prev: AttachedType::AttachOnly,
+                    carry_capitalization: false,
+                },
+            ]
+        );
+        // selection marker
+        assert_eq!(
+            parse_translation("name{:select_prev}").unwrap(),
+            vec![Text::Lit("name".to_string()), Text::SelectPrev(None)]
+        );
+        assert_eq!(
+            parse_translation("Dear name{:select_prev:4},").unwrap(),
+            vec![
+                Text::Lit("Dear name".to_string()),
+                Text::SelectPrev(Some(4)),
+                Text::Attached {
+                    text: ",".to_string(),
+                    joined_next: false,
+                    joined_prev: AttachedType::AttachOnly,
+                    carry_capitalization: false,
+                },
+            ]
+        );
+        assert!(parse_translation("{:select_prev:x}").is_err());
+        // no fold marker
+        assert_eq!(
+            parse_translation("car{:no_fold}").unwrap(),
+            vec![Text::Lit("car".to_string()), Text::NoFold]
+        );
+        // smart quotes
+        assert_eq!(
+            parse_translation("{:smart_quote}").unwrap(),
+            vec![Text::SmartQuote { double: true }]
+        );
+        assert_eq!(
+            parse_translation("{:smart_single_quote}").unwrap(),
+            vec![Text::SmartQuote { double: false }]
+        );
     }
 
     #[test]
@@ -530,6 +817,68 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_inline_key_command() {
+        assert_eq!(
+            parse_translation("Hi{#Return}there").unwrap(),
+            vec![
+                Text::Lit("Hi".to_string()),
+                Text::InlineCommand(Command::Keys(Key::Special(SpecialKey::Return), vec![])),
+                Text::Lit("there".to_string())
+            ]
+        );
+        assert_eq!(
+            parse_translation("{#shift(tab)}{^}").unwrap(),
+            vec![
+                Text::InlineCommand(Command::Keys(
+                    Key::Special(SpecialKey::Tab),
+                    vec![Modifier::Shift]
+                )),
+                Text::Attached {
+                    text: "".to_string(),
+                    joined_next: true,
+                    joined_prev: AttachedType::ApplyOrthography,
+                    carry_capitalization: false,
+                }
+            ]
+        );
+        assert!(matches!(
+            parse_translation("Hi{#shift(tab}").unwrap_err(),
+            ParseError::UnsupportedShortcut(_)
+        ));
+    }
+
+    #[test]
+    fn test_plover_meta_commands() {
+        let translator_command = |c: &str| {
+            vec![Text::InlineCommand(Command::TranslatorCommand(
+                c.to_string(),
+            ))]
+        };
+        assert_eq!(
+            parse_translation("{PLOVER:SUSPEND}").unwrap(),
+            translator_command("start_passthrough")
+        );
+        assert_eq!(
+            parse_translation("{PLOVER:RESUME}").unwrap(),
+            translator_command("stop_passthrough")
+        );
+        assert_eq!(
+            parse_translation("{PLOVER:TOGGLE}").unwrap(),
+            translator_command("toggle_passthrough")
+        );
+    }
+
+    #[test]
+    fn test_unsupported_plover_meta_command() {
+        assert_eq!(
+            parse_translation("{PLOVER:ADD_TRANSLATION}").unwrap(),
+            vec![Text::InlineCommand(Command::NoOp)]
+        );
+        let entries = load_dicts(r#"{"TKUP": "{PLOVER:ADD_TRANSLATION}", "H-L": "hello"}"#);
+        assert_eq!(entries.unwrap().len(), 2);
+    }
+
     #[test]
     fn test_translation_empty_err() {
         assert_eq!(
@@ -543,7 +892,11 @@ mod tests {
         let contents = r#"
 {
 "UP": {"cmds": [{ "Keys": [{"Special": "UpArrow"}, []] }]},
-"TEGT": {"cmds": [{ "Keys": [{"Layout": "a"}, ["Meta"]] }]}
+"TEGT": {"cmds": [{ "Keys": [{"Layout": "a"}, ["Meta"]] }]},
+"HREFT": {"cmds": [{ "RepeatKey": {"key": {"Special": "LeftArrow"}, "modifiers": [], "count": 5} }]},
+"WORBG": {"cmds": [{ "Nav": "WordLeft" }]},
+"KHREUBG": {"cmds": [{ "MouseMove": [-10, 20] }, { "MouseClick": "Right" }]},
+"TO*P": {"cmds": [{ "MouseMoveTo": [0, 0] }, { "MouseScroll": [0, -3] }]}
 }
         "#;
         let parsed = load_dicts(contents).unwrap();
@@ -566,9 +919,140 @@ mod tests {
                     suppress_space_before: false,
                 },
             ),
+            (
+                Stroke::new("HREFT"),
+                Translation::Command {
+                    cmds: vec![Command::RepeatKey {
+                        key: Key::Special(SpecialKey::LeftArrow),
+                        modifiers: vec![],
+                        count: 5,
+                    }],
+                    text_after: None,
+                    suppress_space_before: false,
+                },
+            ),
+            (
+                Stroke::new("WORBG"),
+                Translation::Command {
+                    cmds: vec![Command::Nav(Nav::WordLeft)],
+                    text_after: None,
+                    suppress_space_before: false,
+                },
+            ),
+            (
+                Stroke::new("KHREUBG"),
+                Translation::Command {
+                    cmds: vec![
+                        Command::MouseMove(-10, 20),
+                        Command::MouseClick(MouseButton::Right),
+                    ],
+                    text_after: None,
+                    suppress_space_before: false,
+                },
+            ),
+            (
+                Stroke::new("TO*P"),
+                Translation::Command {
+                    cmds: vec![Command::MouseMoveTo(0, 0), Command::MouseScroll(0, -3)],
+                    text_after: None,
+                    suppress_space_before: false,
+                },
+            ),
+        ];
+        let expect: HashSet<Entry> = HashSet::from_iter(expect.iter().cloned());
+
+        assert_eq!(parsed, expect);
+    }
+
+    #[test]
+    fn test_keyboard_shortcut_parse_dictionary() {
+        let contents = r#"
+{
+"TA*B": "{#Tab}",
+"SA*": "{#shift_l(alt_l(a))}",
+"TAB": "{^}{#Tab}{^}{-|}",
+"HEU": "Hi{#Return}there"
+}
+        "#;
+        let parsed = load_dicts(contents).unwrap();
+        let parsed: HashSet<Entry> = HashSet::from_iter(parsed.iter().cloned());
+
+        let expect = vec![
+            (
+                Stroke::new("TA*B"),
+                Translation::Command {
+                    cmds: vec![Command::Keys(Key::Special(SpecialKey::Tab), vec![])],
+                    text_after: None,
+                    suppress_space_before: false,
+                },
+            ),
+            (
+                Stroke::new("SA*"),
+                Translation::Command {
+                    cmds: vec![Command::Keys(
+                        Key::Layout('a'),
+                        vec![Modifier::Shift, Modifier::Alt],
+                    )],
+                    text_after: None,
+                    suppress_space_before: false,
+                },
+            ),
+            (
+                Stroke::new("TAB"),
+                Translation::Command {
+                    cmds: vec![Command::Keys(Key::Special(SpecialKey::Tab), vec![])],
+                    text_after: Some(parse_translation("{^}{-|}").unwrap()),
+                    suppress_space_before: true,
+                },
+            ),
+            // text around the shortcut makes it an inline command
+            (
+                Stroke::new("HEU"),
+                Translation::Text(parse_translation("Hi{#Return}there").unwrap()),
+            ),
         ];
         let expect: HashSet<Entry> = HashSet::from_iter(expect.iter().cloned());
 
         assert_eq!(parsed, expect);
+
+        // multiple keys can't be represented, so those entries are skipped
+        assert!(matches!(
+            parse_string_entry("{#Tab Tab}").unwrap_err(),
+            ParseError::UnsupportedShortcut(_)
+        ));
+        let contents = r#"
+{
+"TA*B": "{#Tab Tab}",
+"SA*": "{#shift_l(alt_l(b)}",
+"H-L": "hello"
+}
+        "#;
+        assert_eq!(
+            load_dicts(contents).unwrap(),
+            vec![(
+                Stroke::new("H-L"),
+                Translation::Text(parse_translation("hello").unwrap())
+            )]
+        );
+    }
+
+    #[test]
+    fn test_load_invalid_strokes() {
+        assert_eq!(
+            load_dicts(r#"{"H-L": "hello", "AK": "ack"}"#).unwrap_err(),
+            ParseError::InvalidStroke(r#""AK": key 'K' is out of steno order"#.to_string())
+        );
+        assert_eq!(
+            load_dicts(r#"{"H-L/wORLD": "hello world"}"#).unwrap_err(),
+            ParseError::InvalidStroke(r#""H-L/wORLD": unknown key 'w'"#.to_string())
+        );
+        assert_eq!(
+            load_dicts(r#"{"S#": "is"}"#).unwrap_err(),
+            ParseError::InvalidStroke(r#""S#": number key is not at the start"#.to_string())
+        );
+        assert_eq!(
+            load_dicts(r#"{"": "empty"}"#).unwrap_err(),
+            ParseError::InvalidStroke(r#""": empty stroke"#.to_string())
+        );
     }
 }
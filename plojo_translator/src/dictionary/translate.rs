@@ -1,7 +1,9 @@
 //! Looks up the stroke the dictionary, using a greedy algorithm to convert it into a translation
 use super::Dictionary;
-use crate::{Text, Translation};
-use plojo_core::Stroke;
+use crate::{
+    is_text, shell::has_shell_insert, Text, Translation, REPEAT_LAST, TYPE_LAST_RAW_STROKE,
+};
+use plojo_core::{Command, Stroke};
 use std::slice;
 
 // Limit the max number of strokes per translation for performance reasons
@@ -10,17 +12,38 @@ use std::slice;
 // ```
 // sed 's/[^\/]//g' plover.json | awk '{ print length }' | sort -nr | head -30
 // ```
-const MAX_TRANSLATION_STROKE_LEN: usize = 10;
+pub(super) const MAX_TRANSLATION_STROKE_LEN: usize = 10;
+
+/// A group of translations and the number of strokes that produced them. Usually there is one
+/// translation for the strokes, but suffix folding can produce two translations from one stroke
+pub(crate) type Span = (usize, Vec<Translation>);
 
 /// Looks up the definition of strokes in the dictionary, converting them into a Translation. Since
 /// multiple strokes could map to one dictionary translation, a greedy algorithm is used starting
 /// from the oldest strokes. If a stroke is None, it will forcible break up the translation (used
 /// for retrospective add space)
 pub(super) fn translate_strokes(dict: &Dictionary, strokes: &[Stroke]) -> Vec<Translation> {
-    let mut all_translations: Vec<Translation> = vec![];
+    translate_spans(dict, strokes)
+        .into_iter()
+        .flat_map(|(_, translations)| translations)
+        .collect()
+}
 
-    let mut start = 0;
+/// Same as `translate_strokes`, but keeps track of which strokes each translation came from
+pub(super) fn translate_spans(dict: &Dictionary, strokes: &[Stroke]) -> Vec<Span> {
+    let mut spans = translate_spans_from(dict, strokes, 0);
+    repeat_last(&mut spans);
+    spans
+}
+
+/// Translates the strokes starting from the `from` stroke. The strokes before it are only used for
+/// commands that need the strokes before them
+fn translate_spans_from(dict: &Dictionary, strokes: &[Stroke], from: usize) -> Vec<Span> {
+    let mut all_spans: Vec<Span> = vec![];
+
+    let mut start = from;
     while start < strokes.len() {
+        let prev_stroke = start.checked_sub(1).map(|i| &strokes[i]);
         let mut found_translation = false;
 
         // limit how far to look forward
@@ -30,8 +53,12 @@ pub(super) fn translate_strokes(dict: &Dictionary, strokes: &[Stroke]) -> Vec<Tr
         for end in (start..max_end).rev() {
             // try suffix folding if it's just the single stroke
             if start == end {
-                if let Some(mut translations) = try_suffix_folding(&dict, &strokes[start]) {
-                    all_translations.append(&mut translations);
+                if let Some(translations) = try_suffix_folding(&dict, &strokes[start]) {
+                    let translations = translations
+                        .into_iter()
+                        .map(|t| type_last_raw_stroke(t, prev_stroke))
+                        .collect();
+                    all_spans.push((1, translations));
                     start = end + 1;
                     found_translation = true;
                     break;
@@ -40,7 +67,8 @@ pub(super) fn translate_strokes(dict: &Dictionary, strokes: &[Stroke]) -> Vec<Tr
 
             // if the strokes give a translation, add it and advance start
             if let Some(translation) = dict.lookup(&strokes[start..=end]) {
-                all_translations.push(translation);
+                let translation = type_last_raw_stroke(translation, prev_stroke);
+                all_spans.push((end + 1 - start, vec![translation]));
                 start = end + 1;
                 found_translation = true;
                 break;
@@ -49,15 +77,108 @@ pub(super) fn translate_strokes(dict: &Dictionary, strokes: &[Stroke]) -> Vec<Tr
 
         // if no translation found for any stroke from [start..=start] to [start..=start + max]
         if !found_translation {
-            // translation for this stroke
-            all_translations.push(Translation::Text(vec![Text::UnknownStroke(
-                strokes[start].clone(),
-            )]));
+            // translation for this stroke, using the fallback text if there is any
+            let translation = dict.lookup_fallback(&strokes[start]).unwrap_or_else(|| {
+                Translation::Text(vec![Text::UnknownStroke(strokes[start].clone())])
+            });
+            all_spans.push((1, vec![translation]));
             start += 1;
         }
     }
 
-    all_translations
+    all_spans
+}
+
+/// Translates strokes given the spans of all but the last stroke, which is usually the case when
+/// a new stroke was just pressed. This avoids translating all of the strokes again.
+///
+/// Returns None if the last stroke could be part of a multi stroke translation with the strokes
+/// before it, in which case the earlier translations could change and all of the strokes must be
+/// translated again
+pub(super) fn translate_appended(
+    dict: &Dictionary,
+    strokes: &[Stroke],
+    prev_spans: &[Span],
+) -> Option<Vec<Span>> {
+    let last = strokes.len().checked_sub(1)?;
+    // the spans must be for every stroke but the last
+    if prev_spans.iter().map(|(len, _)| len).sum::<usize>() != last {
+        return None;
+    }
+
+    // the greedy algorithm only starts a translation where a previous one ended, so only those
+    // places (that are close enough to the last stroke) could start a longer translation
+    let mut span_start = last;
+    for (len, _) in prev_spans.iter().rev() {
+        span_start -= len;
+        if strokes.len() - span_start > MAX_TRANSLATION_STROKE_LEN {
+            break;
+        }
+        if dict.lookup(&strokes[span_start..]).is_some() {
+            return None;
+        }
+    }
+
+    let mut spans = prev_spans.to_vec();
+    spans.append(&mut translate_spans_from(dict, strokes, last));
+    repeat_last(&mut spans);
+    Some(spans)
+}
+
+/// A command to type the last raw stroke types the raw steno of the stroke before it as text. This
+/// way it is undone like any other text. Without a stroke before it, nothing is typed
+fn type_last_raw_stroke(translation: Translation, prev_stroke: Option<&Stroke>) -> Translation {
+    match (translation, prev_stroke) {
+        (
+            Translation::Command {
+                cmds,
+                text_after,
+                suppress_space_before,
+            },
+            Some(prev_stroke),
+        ) if cmds.contains(&Command::TranslatorCommand(
+            TYPE_LAST_RAW_STROKE.to_string(),
+        )) =>
+        {
+            let mut texts = vec![Text::Lit(prev_stroke.clone().to_raw())];
+            texts.extend(text_after.unwrap_or_default());
+            Translation::Command {
+                cmds,
+                text_after: Some(texts),
+                suppress_space_before,
+            }
+        }
+        (translation, _) => translation,
+    }
+}
+
+/// A command to repeat the last translation is replaced with the text of the most recent span that
+/// has text (which could be a multi stroke translation). This way the repeat is undone in one step
+/// like any other stroke. Without any text before it, nothing is repeated. A shell insert command
+/// isn't repeated either, because what it printed is only typed by the stroke that ran it
+fn repeat_last(spans: &mut [Span]) {
+    let mut last_text: Option<Vec<Translation>> = None;
+    for (_, translations) in spans.iter_mut() {
+        if translations
+            .iter()
+            .any(|t| t.has_translator_command(REPEAT_LAST))
+        {
+            if let Some(ref text) = last_text {
+                *translations = text.clone();
+            }
+        }
+        if translations.iter().any(has_shell_insert) {
+            last_text = None;
+        } else if translations.iter().cloned().any(is_text) {
+            // only the text is repeated so that commands are not dispatched again
+            last_text = Some(
+                translations
+                    .iter()
+                    .map(|t| Translation::Text(t.as_text()))
+                    .collect(),
+            );
+        }
+    }
 }
 
 // suffixes for suffix folding (currently must all be right hand suffixes)
@@ -71,7 +192,7 @@ const CENTER_KEYS: [char; 6] = ['*', '-', 'A', 'O', 'E', 'U'];
 /// For example, "KARS" will return the look up of "KAR" and "-S" in the dictionary
 /// "WORLD" will return None because there is no suffix to remove
 ///
-/// Suffixes will not be folded on to a stroke that produces a command
+/// Suffixes will not be folded on to a stroke that produces a command or is marked as no fold
 fn try_suffix_folding(dict: &Dictionary, stroke: &Stroke) -> Option<Vec<Translation>> {
     // if the original stroke has a translation, don't extract suffixes
     if let Some(t) = dict.lookup(slice::from_ref(stroke)) {
@@ -105,6 +226,9 @@ fn try_suffix_folding(dict: &Dictionary, stroke: &Stroke) -> Option<Vec<Translat
                     if let Translation::Command { .. } = base {
                         // don't add suffix to commands
                         continue;
+                    } else if base.as_text().contains(&Text::NoFold) {
+                        // the entry opted out of suffix folding
+                        continue;
                     } else {
                         if let Some(suffix_translation) = dict.lookup(&[Stroke::new(s)]) {
                             return Some(vec![base, suffix_translation]);
@@ -147,6 +271,10 @@ mod tests {
             (row("-S", "s")),
             (row("-G", "ing")),
             (row("PH*PB", "mountain")),
+            (
+                Stroke::new("KAR"),
+                Translation::Text(vec![Text::Lit("car".to_string()), Text::NoFold]),
+            ),
             (
                 Stroke::new("KPA"),
                 Translation::Text(vec![Text::StateAction(StateAction::ForceCapitalize)]),
@@ -232,6 +360,23 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_unknown_stroke_fallback() {
+        let fallback = vec![(Stroke::new("SKWR"), "j".to_string())];
+        let dict = testing_dict().with_fallback(fallback.into_iter().collect());
+        let strokes = vec![Stroke::new("SKWR"), Stroke::new("TPHO"), Stroke::new("H-L")];
+        let translations = translate_strokes(&dict, &strokes);
+
+        assert_eq!(
+            translations,
+            vec![
+                Translation::Text(vec![Text::Lit("j".to_string())]),
+                Translation::Text(vec![Text::UnknownStroke(Stroke::new("TPHO"))]),
+                Translation::Text(vec![Text::Lit("Hello".to_string())])
+            ]
+        );
+    }
+
     #[test]
     fn test_all_unknown_stroke() {
         let dict = testing_dict();
@@ -387,5 +532,57 @@ mod tests {
 
         // adding suffix to command stroke does nothing
         assert!(try_suffix_folding(&dict, &Stroke::new("TKAO*ERS")).is_none());
+        // or to a stroke that opted out
+        assert!(try_suffix_folding(&dict, &Stroke::new("KARS")).is_none());
+    }
+
+    #[test]
+    fn test_translate_appended() {
+        let dict = testing_dict();
+        let strokes = vec![Stroke::new("WORLD"), Stroke::new("H-L")];
+        let prev_spans = translate_spans(&dict, &strokes[..1]);
+
+        assert_eq!(
+            translate_appended(&dict, &strokes, &prev_spans),
+            Some(translate_spans(&dict, &strokes))
+        );
+    }
+
+    #[test]
+    fn test_translate_appended_multistroke() {
+        let dict = testing_dict();
+        // "H-L/A" is a correction for "H-L"
+        let strokes = vec![Stroke::new("WORLD"), Stroke::new("H-L"), Stroke::new("A")];
+        let prev_spans = translate_spans(&dict, &strokes[..2]);
+        assert_eq!(translate_appended(&dict, &strokes, &prev_spans), None);
+
+        // "KW/A/TP" merges with more than just the previous translation
+        let strokes = vec![Stroke::new("KW"), Stroke::new("A"), Stroke::new("TP")];
+        let prev_spans = translate_spans(&dict, &strokes[..2]);
+        assert_eq!(translate_appended(&dict, &strokes, &prev_spans), None);
+    }
+
+    #[test]
+    fn test_translate_appended_same_as_full() {
+        let dict = testing_dict();
+        let strokes: Vec<_> =
+            "H-L/A/WORLD/TPHO/WUPB/PWEUG/PWOEU/H-LS/KW/H-L/WORLD/TPAOGD/KW/A/TP/H-L"
+                .split('/')
+                .map(Stroke::new)
+                .collect();
+
+        let mut spans = vec![];
+        for i in 1..=strokes.len() {
+            spans = translate_appended(&dict, &strokes[..i], &spans)
+                .unwrap_or_else(|| translate_spans(&dict, &strokes[..i]));
+            assert_eq!(spans, translate_spans(&dict, &strokes[..i]));
+        }
+    }
+
+    #[test]
+    fn test_translate_appended_wrong_spans() {
+        let dict = testing_dict();
+        let strokes = vec![Stroke::new("WORLD"), Stroke::new("H-L")];
+        assert_eq!(translate_appended(&dict, &strokes, &[]), None);
     }
 }
@@ -1,25 +1,37 @@
 //! Helper functions for finding the difference between 2 translations and turning that into a command.
-use crate::Translation;
-use plojo_core::Command;
+use crate::{is_text, Text, Translation};
+use plojo_core::{Command, Key, Modifier, SpecialKey};
 use std::cmp;
 
 mod parser;
 
-use parser::parse_translation;
+pub(super) use parser::{load_orthography_exceptions, load_orthography_rules, FormatOptions};
+use parser::{parse_translation, parse_translation_with_cursor};
 
 const SPACE: char = ' ';
 
-/// Finds the difference between two translations, converts them to their string representations,
-/// and diffs the strings to create a command. Has an option to insert spaces after words instead
-/// of before
+/// Finds the difference between two translations, converts them to their string representations
+/// (formatted with the `options`), and diffs the strings to create a command
 pub(super) fn translation_diff(
     old: &[Translation],
     new: &[Translation],
-    space_after: bool,
+    options: &FormatOptions,
 ) -> Vec<Command> {
+    translation_diff_with_cursor(old, new, options).0
+}
+
+/// Same as `translation_diff`, but also returns whether the commands move the cursor back into
+/// the text (for a cursor marker in the translation that was just outputted)
+pub(super) fn translation_diff_with_cursor(
+    old: &[Translation],
+    new: &[Translation],
+    options: &FormatOptions,
+) -> (Vec<Command>, bool) {
+    let space_after = options.space_after;
+    let separator = options.separator.as_str();
     // ignore commands and convert old translations to text
     let old_translations: Vec<_> = old.iter().flat_map(|t| Translation::as_text(t)).collect();
-    let old_parsed = parse_translation(old_translations, space_after);
+    let mut old_parsed = parse_translation(old_translations, options);
 
     // if added a command, return that directly
     if old.len() + 1 == new.len() {
@@ -30,27 +42,161 @@ pub(super) fn translation_diff(
         }) = new.last()
         {
             let mut cmds = cmds.clone();
-            // if space after and suppress space, check if there's a space...
-            if space_after && *suppress_space_before && old_parsed.ends_with(SPACE) {
-                // ...and it hasn't been deleted before (to prevent duplicate space deletion)
-                if let Some(t) = old.last() {
-                    if let Translation::Command { .. } = t {
-                        // last translation was a command, which already deleted the space
-                    } else {
-                        cmds.insert(0, Command::Replace(1, "".to_string()));
-                    }
-                }
+            // if space after and suppress space, delete the space before the command (the parsed
+            // text already doesn't have it if an earlier command deleted it)
+            if space_after && *suppress_space_before && old_parsed.ends_with(separator) {
+                cmds.insert(
+                    0,
+                    Command::Replace(separator.chars().count(), "".to_string()),
+                );
+                old_parsed.truncate(old_parsed.len() - separator.len());
             }
-            return cmds;
+
+            // a command that changes the text before it (ex: capitalizing the previous words)
+            // or adds text (ex: typing the last raw stroke) also outputs that change
+            let last = &new[new.len() - 1];
+            let changes_text = is_text(last.clone())
+                || last
+                    .as_text()
+                    .iter()
+                    .any(|t| matches!(t, Text::TextAction(_)));
+            if changes_text {
+                let new_translations: Vec<_> = new.iter().flat_map(Translation::as_text).collect();
+                let new_parsed = parse_translation(new_translations, options);
+                cmds.push(text_diff(old_parsed, new_parsed));
+            }
+            return (coalesce_replaces(cmds), false);
         }
     }
 
+    // dispatch inline commands in between the text around them
+    let parse = |texts| parse_translation(texts, options);
+    if let Some(commands) =
+        inline_command_diff(old, new, &old_parsed, space_after, separator, parse)
+    {
+        return (commands, false);
+    }
+
     // ignore commands and convert old translations to text
     let new_translations: Vec<_> = new.iter().flat_map(|t| Translation::as_text(t)).collect();
-    let new_parsed = parse_translation(new_translations, space_after);
+    let (new_parsed, cursor, select) = parse_translation_with_cursor(new_translations, options);
 
     // compare the two and return the result
-    vec![text_diff(old_parsed, new_parsed)]
+    let mut commands = vec![text_diff(old_parsed, new_parsed)];
+    let mut moved_cursor = false;
+
+    // move the cursor back (and select the text before it) if the translation that was just
+    // outputted has a cursor marker
+    if let (Some(count), Some(last)) = (cursor, new.last()) {
+        let is_new = new.len() > old.len() || old.last() != Some(last);
+        let has_cursor = last
+            .as_text()
+            .iter()
+            .any(|t| matches!(t, Text::Cursor | Text::SelectPrev(_)));
+        if is_new && has_cursor && commands != vec![Command::NoOp] {
+            moved_cursor = count > 0 || select > 0;
+            if count > 0 {
+                commands.push(Command::RepeatKey {
+                    key: Key::Special(SpecialKey::LeftArrow),
+                    modifiers: vec![],
+                    count,
+                });
+            }
+            if select > 0 {
+                commands.push(Command::RepeatKey {
+                    key: Key::Special(SpecialKey::LeftArrow),
+                    modifiers: vec![Modifier::Shift],
+                    count: select,
+                });
+            }
+        }
+    }
+
+    (commands, moved_cursor)
+}
+
+/// If the translations that were added have inline commands, outputs the text up to each inline
+/// command and then dispatches it, so that the commands are in between the text around them.
+/// Otherwise returns None. The `parse` function renders texts into a string
+fn inline_command_diff(
+    old: &[Translation],
+    new: &[Translation],
+    old_parsed: &str,
+    space_after: bool,
+    separator: &str,
+    parse: impl Fn(Vec<Text>) -> String,
+) -> Option<Vec<Command>> {
+    // the translations that haven't changed were already outputted
+    let same = old.iter().zip(new).take_while(|(o, n)| o == n).count();
+    let added: Vec<_> = new[same..].iter().flat_map(Translation::as_text).collect();
+    if !added.iter().any(|t| matches!(t, Text::InlineCommand(_))) {
+        return None;
+    }
+
+    let mut texts: Vec<_> = new[..same].iter().flat_map(Translation::as_text).collect();
+    let mut prev_parsed = old_parsed.to_string();
+    let mut commands = vec![];
+    for text in added {
+        if let Text::InlineCommand(ref cmd) = text {
+            let mut parsed = parse(texts.clone());
+            // the space after the text is only typed after the last command
+            if space_after && parsed.ends_with(separator) {
+                parsed.truncate(parsed.len() - separator.len());
+            }
+            commands.push(text_diff(prev_parsed, parsed.clone()));
+            commands.push(cmd.clone());
+            prev_parsed = parsed;
+        }
+        texts.push(text);
+    }
+    commands.push(text_diff(prev_parsed, parse(texts)));
+
+    commands.retain(|c| *c != Command::NoOp);
+    Some(coalesce_replaces(commands))
+}
+
+/// Renders translations to the text that they type, with the same options as `translation_diff`
+pub(super) fn render_text(translations: &[Translation], options: &FormatOptions) -> String {
+    parse_translation(
+        translations.iter().flat_map(Translation::as_text).collect(),
+        options,
+    )
+}
+
+/// Renders a translation by itself as it would be outputted (without the space before it)
+pub(super) fn render_translation(translation: &Translation) -> String {
+    let rendered = parse_translation(translation.as_text(), &FormatOptions::default());
+    match rendered.strip_prefix(SPACE) {
+        Some(s) => s.to_string(),
+        None => rendered,
+    }
+}
+
+/// Merges adjacent `Replace` commands into one so that the controller can dispatch them at once.
+/// Any other command between two replaces is left in place and stops the merging
+fn coalesce_replaces(cmds: Vec<Command>) -> Vec<Command> {
+    let mut result: Vec<Command> = Vec::with_capacity(cmds.len());
+    for cmd in cmds {
+        if let (
+            Some(Command::Replace(prev_backspace, prev_text)),
+            Command::Replace(backspace, text),
+        ) = (result.last_mut(), &cmd)
+        {
+            let prev_len = prev_text.chars().count();
+            if *backspace <= prev_len {
+                // the backspaces only delete text that was just added
+                *prev_text = prev_text.chars().take(prev_len - backspace).collect();
+            } else {
+                // delete all of the added text and then some more
+                *prev_backspace += backspace - prev_len;
+                prev_text.clear();
+            }
+            prev_text.push_str(text);
+            continue;
+        }
+        result.push(cmd);
+    }
+    result
 }
 
 /// Compute the command necessary to make the old string into the new
@@ -63,7 +209,7 @@ fn text_diff(old: String, new: String) -> Command {
         return Command::add_text(&new);
     }
     if new.is_empty() {
-        return Command::replace_text(old.len(), "");
+        return Command::replace_text(old.chars().count(), "");
     }
 
     let old_chars_len = old.chars().count();
@@ -95,7 +241,7 @@ mod tests {
     use plojo_core::Stroke;
 
     fn translation_diff_space_after(old: &[Translation], new: &[Translation]) -> Vec<Command> {
-        translation_diff(old, new, false)
+        translation_diff(old, new, &FormatOptions::default())
     }
 
     fn basic_command(cmds: Vec<Command>) -> Translation {
@@ -292,4 +438,91 @@ mod tests {
 
         assert_eq!(command, Command::Replace(2, "Ω".to_string()));
     }
+
+    #[test]
+    fn test_diff_coalesce_text_commands() {
+        let command = translation_diff_space_after(
+            &[Translation::Text(vec![Text::Lit("Hello".to_string())])],
+            &[
+                Translation::Text(vec![Text::Lit("Hello".to_string())]),
+                basic_command(vec![
+                    Command::Replace(0, "()".to_string()),
+                    Command::Replace(1, "".to_string()),
+                    Command::Replace(0, "x)".to_string()),
+                ]),
+            ],
+        );
+
+        assert_eq!(command, vec![Command::Replace(0, "(x)".to_string())]);
+    }
+
+    #[test]
+    fn test_render_translation() {
+        assert_eq!(
+            render_translation(&Translation::Text(vec![Text::Lit("hello".to_string())])),
+            "hello"
+        );
+        assert_eq!(
+            render_translation(&Translation::Text(vec![
+                Text::Lit("hello".to_string()),
+                Text::Lit("world".to_string())
+            ])),
+            "hello world"
+        );
+    }
+
+    #[test]
+    fn test_diff_coalesce_suppress_space() {
+        let command = translation_diff(
+            &[Translation::Text(vec![Text::Lit("Hello".to_string())])],
+            &[
+                Translation::Text(vec![Text::Lit("Hello".to_string())]),
+                Translation::Command {
+                    cmds: vec![Command::Replace(0, " ()".to_string())],
+                    text_after: None,
+                    suppress_space_before: true,
+                },
+            ],
+            &FormatOptions {
+                space_after: true,
+                ..FormatOptions::default()
+            },
+        );
+
+        assert_eq!(command, vec![Command::Replace(1, " ()".to_string())]);
+    }
+
+    #[test]
+    fn test_diff_no_coalesce_across_commands() {
+        let command = translation_diff_space_after(
+            &[Translation::Text(vec![Text::Lit("Hello".to_string())])],
+            &[
+                Translation::Text(vec![Text::Lit("Hello".to_string())]),
+                basic_command(vec![
+                    Command::Replace(0, "a".to_string()),
+                    Command::PrintHello,
+                    Command::Replace(0, "b".to_string()),
+                ]),
+            ],
+        );
+
+        assert_eq!(
+            command,
+            vec![
+                Command::Replace(0, "a".to_string()),
+                Command::PrintHello,
+                Command::Replace(0, "b".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_coalesce_extra_backspaces() {
+        let commands = coalesce_replaces(vec![
+            Command::Replace(1, "ab".to_string()),
+            Command::Replace(3, "Ω".to_string()),
+        ]);
+
+        assert_eq!(commands, vec![Command::Replace(2, "Ω".to_string())]);
+    }
 }
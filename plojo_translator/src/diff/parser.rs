@@ -1,7 +1,12 @@
-use crate::{AttachedType, StateAction, Text, TextAction};
+use crate::{AttachedType, NumberFormat, StateAction, Text, TextAction};
 use orthography::apply_orthography;
+pub(crate) use orthography::{
+    load_exceptions as load_orthography_exceptions, load_rules as load_orthography_rules,
+    Orthography,
+};
+use plojo_core::Command;
 use regex::Regex;
-use std::char;
+use std::{char, cmp};
 
 mod orthography;
 
@@ -14,28 +19,121 @@ lazy_static! {
 }
 
 const SPACE: char = ' ';
+// punctuation that ends a sentence, which can capitalize the word after it
+const SENTENCE_END: [char; 3] = ['.', '!', '?'];
+
+/// How translations are turned into text
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) struct FormatOptions {
+    /// Insert spaces after words instead of before them
+    pub space_after: bool,
+    pub capitalize_first_word: bool,
+    /// Capitalize the word after sentence ending punctuation
+    pub capitalize_after_punctuation: bool,
+    /// Glued words that are spaced after a number (ex: "5 km")
+    pub units: Vec<String>,
+    /// The word for each digit, which number strokes are written as when number words are on
+    pub number_words: Vec<String>,
+    /// Custom orthography that is used before the default orthography
+    pub orthography: Orthography,
+    /// Put between words instead of a space
+    pub separator: String,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        Self {
+            space_after: false,
+            capitalize_first_word: false,
+            capitalize_after_punctuation: false,
+            units: vec![],
+            number_words: [
+                "zero", "one", "two", "three", "four", "five", "six", "seven", "eight", "nine",
+            ]
+            .iter()
+            .map(|s| s.to_string())
+            .collect(),
+            orthography: Orthography::default(),
+            separator: SPACE.to_string(),
+        }
+    }
+}
 
 #[derive(Debug, Default)]
 struct State {
     suppress_space: bool,
     force_capitalize: bool,
+    // lowercase the first letter of the next word (the later of this and force_capitalize wins)
+    force_lowercase: bool,
     prev_is_glued: bool,
-    force_same_case: Option<bool>,
+    prev_is_number: bool,
+    // all caps the next word
+    uppercase_word: bool,
+    // unlike the other state, title case persists until it is toggled off
+    title_case: bool,
+    // number strokes are written as words instead of digits until it is toggled off
+    number_words: bool,
 }
 
 /// Converts translations into their string representation by adding spaces in between words and
 /// applying text actions. Has an option to insert spaces after words instead of before.
 ///
 /// A state of the spaces/capitalization is kept as it loops over the Texts to build the string.
-/// StateActions change that state
-pub(super) fn parse_translation(translations: Vec<Text>, space_after: bool) -> String {
+/// StateActions change that state. The first word can be capitalized by seeding that state.
+///
+/// If capitalize_after_punctuation is set, any text that ends with sentence ending punctuation
+/// (even if it is a text literal) will capitalize the next word.
+///
+/// A glued word that is one of the `units` is not glued to a number before it, so that
+/// measurements are spaced (ex: "5 km") even though glued numbers and letters usually join.
+///
+/// After number words are toggled on, single digit number strokes are written as the word for that
+/// digit in `number_words` (indexed by the digit) instead
+///
+/// The custom `orthography` is used before the default orthography when attaching a suffix
+///
+/// Words are separated by the `separator` (usually a space), which is also what suppressing the
+/// space removes
+pub(super) fn parse_translation(translations: Vec<Text>, options: &FormatOptions) -> String {
+    parse_translation_with_cursor(translations, options).0
+}
+
+/// Same as `parse_translation`, but also finds the position of the last cursor (or selection)
+/// marker. The position is the number of chars between the marker and the end of the string. Also
+/// returns the number of chars before the marker to select (0 for a cursor marker)
+pub(super) fn parse_translation_with_cursor(
+    translations: Vec<Text>,
+    options: &FormatOptions,
+) -> (String, Option<usize>, usize) {
+    let FormatOptions {
+        space_after,
+        capitalize_first_word,
+        capitalize_after_punctuation,
+        ..
+    } = *options;
+    let separator = options.separator.as_str();
+    // number of chars before the last cursor marker
+    let mut cursor: Option<usize> = None;
+    // number of chars before the cursor to select
+    let mut select = 0;
+    // number of chars in the last word that was added
+    let mut prev_word_len = 0;
+    // where the last word that was added starts in the string (if it hasn't changed since)
+    let mut prev_word_start: Option<usize> = None;
     // current state
-    let mut state: State = Default::default();
+    let mut state = State {
+        force_capitalize: capitalize_first_word,
+        ..Default::default()
+    };
     let mut str = String::new();
 
     for t in translations {
         let next_word;
-        let mut next_state: State = Default::default();
+        let mut next_state = State {
+            title_case: state.title_case,
+            number_words: state.number_words,
+            ..Default::default()
+        };
 
         match t {
             Text::Lit(text) => {
@@ -53,10 +151,17 @@ pub(super) fn parse_translation(translations: Vec<Text>, space_after: bool) -> S
                 // glue it if it is a number stroke
                 if NUMBER_TRANSLATION_REGEX.is_match(&raw_stroke) {
                     // remove the hyphen
-                    next_word = raw_stroke.replace("-", "");
-                    next_state.prev_is_glued = true;
-                    if state.prev_is_glued {
-                        state.suppress_space = true;
+                    let number = raw_stroke.replace("-", "");
+                    match number_word(&number, &options.number_words) {
+                        // a number word is a normal word, so it isn't glued
+                        Some(word) if state.number_words => next_word = word.clone(),
+                        _ => {
+                            next_word = number;
+                            next_state.prev_is_glued = true;
+                            if state.prev_is_glued {
+                                state.suppress_space = true;
+                            }
+                        }
                     }
                 } else {
                     next_word = raw_stroke;
@@ -75,9 +180,12 @@ pub(super) fn parse_translation(translations: Vec<Text>, space_after: bool) -> S
                 if carry_capitalization {
                     // carry on the capitalization state to the next word
                     next_state.force_capitalize = state.force_capitalize;
-                    next_state.force_same_case = state.force_same_case;
+                    next_state.force_lowercase = state.force_lowercase;
+                    next_state.uppercase_word = state.uppercase_word;
                     // don't capitalize this word
                     state.force_capitalize = false;
+                    state.force_lowercase = false;
+                    state.uppercase_word = false;
                 }
 
                 // don't apply orthography if previous stroke suppressed the next space
@@ -107,15 +215,37 @@ pub(super) fn parse_translation(translations: Vec<Text>, space_after: bool) -> S
                                 assert!(char_size > 0);
                                 i + char_size
                             });
+                            if text.is_empty() {
+                                // only suppresses the space, so uppercase the next word instead
+                                next_state.uppercase_word = state.uppercase_word;
+                            }
+                            // the attached text is the next word, so it can still be uppercased
+                            let text = if state.uppercase_word {
+                                uppercase_first_word(&text)
+                            } else {
+                                text
+                            };
+                            // the suffix can only be split off if the orthography kept it as is
+                            prev_word_start = None;
                             // find the last word and apply orthography rule with the suffix
                             if index < str.len() {
-                                let new_word = apply_orthography(&str[index..], &text);
+                                let old_word = &str[index..];
+                                let new_word =
+                                    apply_orthography(old_word, &text, &options.orthography);
+                                let kept_suffix =
+                                    !text.is_empty() && new_word == old_word.to_string() + &text;
                                 // replace that word with the new (orthography'ed) one
                                 str = str[..index].to_string() + &new_word;
+                                if kept_suffix {
+                                    prev_word_start = Some(str.len() - text.len());
+                                }
                             } else {
                                 // there was no last word, directly add the text
                                 str = str + &text;
                             }
+                            if capitalize_after_punctuation && str.ends_with(&SENTENCE_END[..]) {
+                                next_state.force_capitalize = true;
+                            }
                             state = next_state;
                             continue;
                         }
@@ -125,7 +255,9 @@ pub(super) fn parse_translation(translations: Vec<Text>, space_after: bool) -> S
             Text::Glued(text) => {
                 next_word = text.clone();
                 next_state.prev_is_glued = true;
-                if state.prev_is_glued {
+                // a unit after a number is spaced instead of glued
+                let is_unit_after_number = state.prev_is_number && options.units.contains(&text);
+                if state.prev_is_glued && !is_unit_after_number {
                     state.suppress_space = true;
                 }
             }
@@ -133,57 +265,165 @@ pub(super) fn parse_translation(translations: Vec<Text>, space_after: bool) -> S
                 match action {
                     StateAction::ForceCapitalize => {
                         state.force_capitalize = true;
+                        state.force_lowercase = false;
+                    }
+                    StateAction::ForceLowercase => {
+                        state.force_lowercase = true;
+                        state.force_capitalize = false;
+                    }
+                    StateAction::UppercaseWord => {
+                        state.uppercase_word = true;
+                    }
+                    StateAction::ToggleSuppressSpace => {
+                        state.suppress_space = !state.suppress_space;
+                    }
+                    StateAction::SuppressSpaceBefore => {
+                        if space_after {
+                            state.suppress_space = true;
+                        }
+                    }
+                    StateAction::KeepSpaceBefore => {
+                        if space_after && !str.is_empty() && !state.suppress_space {
+                            // the space is part of the text before the command
+                            str.push_str(separator);
+                            state.suppress_space = true;
+                        }
+                    }
+                    StateAction::ToggleTitleCase => {
+                        state.title_case = !state.title_case;
                     }
-                    StateAction::SameCase(b) => {
-                        state.force_same_case = Some(b);
+                    StateAction::ToggleNumberWords => {
+                        state.number_words = !state.number_words;
                     }
                     StateAction::Clear => {
-                        // reset formatting state
-                        state = Default::default();
+                        // reset formatting state (title case and number words are modes, so they
+                        // are kept)
+                        state = State {
+                            title_case: state.title_case,
+                            number_words: state.number_words,
+                            ..Default::default()
+                        };
+                    }
+                }
+                continue;
+            }
+            Text::TextAction(TextAction::SplitPrev) => {
+                if let Some(start) = prev_word_start {
+                    let attached = start > 0
+                        && !str[..start].ends_with(separator)
+                        && !str[..start].ends_with(char::is_whitespace);
+                    if attached {
+                        str.insert_str(start, separator);
+                        prev_word_start = Some(start + separator.len());
                     }
                 }
                 continue;
             }
             Text::TextAction(action) => {
-                str = perform_text_action(&str, action);
+                str = perform_text_action(&str, action, separator);
+                prev_word_start = None;
+                continue;
+            }
+            Text::Cursor => {
+                cursor = Some(str.chars().count());
+                select = 0;
+                continue;
+            }
+            Text::SelectPrev(count) => {
+                let len = str.chars().count();
+                cursor = Some(len);
+                select = cmp::min(count.unwrap_or(prev_word_len), len);
+                continue;
+            }
+            Text::NoFold => continue,
+            Text::SmartQuote { double } => {
+                // the quote comes after the space unless it is suppressed
+                let prev_char = if state.suppress_space {
+                    str.chars().last()
+                } else {
+                    separator.chars().last().or_else(|| str.chars().last())
+                };
+                let opening = !prev_char.is_some_and(closes_quote);
+                next_word = smart_quote(opening, double).to_string();
+                if opening {
+                    // the quoted text is attached to the quote and keeps its capitalization
+                    next_state.suppress_space = true;
+                    next_state.force_capitalize = state.force_capitalize;
+                    next_state.force_lowercase = state.force_lowercase;
+                    next_state.uppercase_word = state.uppercase_word;
+                }
+            }
+            // these don't type anything, so there is nothing to attach the text after them to
+            Text::InlineCommand(Command::TranslatorCommand(_))
+            | Text::InlineCommand(Command::NoOp) => {
+                continue;
+            }
+            Text::InlineCommand(_) => {
+                // the text after the command is attached to it
+                state.suppress_space = true;
                 continue;
             }
         }
 
         if !state.suppress_space {
-            str.push(SPACE);
+            str.push_str(separator);
         }
 
         let mut word = next_word;
-        if state.force_capitalize {
+        if state.title_case {
+            word = title_case(&word);
+        }
+        // an explicit lowercase also applies in title case mode
+        if state.force_lowercase {
+            word = word_lowercase_first_letter(word);
+        } else if state.force_capitalize {
             word = word_change_first_letter(word);
         }
-        if let Some(b) = state.force_same_case {
-            word = if b {
-                word.to_uppercase()
-            } else {
-                word.to_lowercase()
-            };
+        if state.uppercase_word {
+            word = uppercase_first_word(&word);
         }
+        next_state.prev_is_number = NUMBERS_ONLY_REGEX.is_match(&word);
+        prev_word_len = word.chars().count();
+        prev_word_start = Some(str.len());
         str.push_str(&word);
 
+        if capitalize_after_punctuation && str.ends_with(&SENTENCE_END[..]) {
+            next_state.force_capitalize = true;
+        }
+
         state = next_state;
     }
 
     // put space after if it is configured to do so
     if space_after && !str.is_empty() {
         // remove the leading space if there is any
-        if let Some(maybe_space) = str.chars().next() {
-            if maybe_space == SPACE {
-                str.remove(0);
-            }
+        if str.starts_with(separator) {
+            str.replace_range(..separator.len(), "");
+            // the cursor is after the removed space
+            cursor = cursor.map(|c| c.saturating_sub(separator.chars().count()));
         }
         if !state.suppress_space {
-            str.push(SPACE);
+            str.push_str(separator);
         }
     }
 
-    str
+    let chars_after_cursor = cursor.map(|c| str.chars().count().saturating_sub(c));
+    (str, chars_after_cursor, select)
+}
+
+/// Whether a quote right after this char is a closing quote (after a word or closing punctuation)
+fn closes_quote(c: char) -> bool {
+    c.is_alphanumeric() || ".,!?)]}”’".contains(c)
+}
+
+/// The curly quote to use for an opening or closing double or single quote
+fn smart_quote(opening: bool, double: bool) -> &'static str {
+    match (opening, double) {
+        (true, true) => "“",
+        (false, true) => "”",
+        (true, false) => "‘",
+        (false, false) => "’",
+    }
 }
 
 /// Forces the first letter of a string to be uppercase
@@ -195,19 +435,52 @@ fn word_change_first_letter(text: String) -> String {
     }
 }
 
-/// Find the index in the text after the last space
-/// This index is 0 if there is no whitespace, and text.len() if the last char is a whitespace
-fn find_last_word_space(text: &str) -> usize {
-    if let Some(i) = text.rfind(char::is_whitespace) {
-        // add 1 to remove the space
-        // whitespace takes up 1 byte, so adding 1 is safe here
-        i + 1
-    } else {
-        // no whitespace, so everything must be a word
-        0
+/// Forces the first letter of a string to be lowercase
+fn word_lowercase_first_letter(text: String) -> String {
+    let mut chars = text.chars();
+    match chars.next() {
+        None => String::new(),
+        Some(c) => c.to_lowercase().collect::<String>() + chars.as_str(),
+    }
+}
+
+/// Uppercases the first word (up to the first space) of a string
+fn uppercase_first_word(text: &str) -> String {
+    match text.find(SPACE) {
+        Some(i) => text[..i].to_uppercase() + &text[i..],
+        None => text.to_uppercase(),
+    }
+}
+
+/// The word for a single digit number, if there is one
+fn number_word<'a>(number: &str, number_words: &'a [String]) -> Option<&'a String> {
+    let mut digits = number.chars();
+    match (digits.next().and_then(|c| c.to_digit(10)), digits.next()) {
+        (Some(digit), None) => number_words.get(digit as usize),
+        _ => None,
     }
 }
 
+/// Capitalizes the first letter of every word (separated by spaces)
+fn title_case(text: &str) -> String {
+    text.split(SPACE)
+        .map(|w| word_change_first_letter(w.to_string()))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Find the index in the text after the last space (whitespace or the separator)
+/// This index is 0 if there is no space, and text.len() if the text ends with a space
+fn find_last_word_space(text: &str, separator: &str) -> usize {
+    let whitespace = text
+        .char_indices()
+        .rfind(|(_, c)| c.is_whitespace())
+        .map(|(i, c)| i + c.len_utf8());
+    let separator = text.rfind(separator).map(|i| i + separator.len());
+    // no space, so everything must be a word
+    cmp::max(whitespace, separator).unwrap_or(0)
+}
+
 // chars (besides alphanumeric) that are considered part of a word
 // This is used for deciding what is a word when capitalizing the previous word
 const WORD_CHARS: [char; 2] = ['-', '_'];
@@ -227,15 +500,16 @@ fn find_last_word(text: &str) -> usize {
     }
 }
 
-fn perform_text_action(text: &str, action: TextAction) -> String {
+/// Applies a text action to the text. Words are separated by the `separator`
+fn perform_text_action(text: &str, action: TextAction, separator: &str) -> String {
     match action {
         TextAction::SuppressSpacePrev => {
             let mut new_str = text.to_string();
-            let index = find_last_word_space(&text);
+            let index = find_last_word_space(&text, separator);
             // find the last word and see if there is a space before it
-            if index > 0 && text.get(index - 1..index) == Some(" ") {
-                // remove the space (this is safe because we checked the index above)
-                new_str.remove(index - 1);
+            if text[..index].ends_with(separator) {
+                // remove exactly the space
+                new_str.replace_range(index - separator.len()..index, "");
             }
             new_str
         }
@@ -245,6 +519,26 @@ fn perform_text_action(text: &str, action: TextAction) -> String {
             let capitalized = word_change_first_letter(word);
             text[..index].to_string() + &capitalized
         }
+        TextAction::TitleCasePrev => {
+            let index = find_last_word(text);
+            let capitalized: String = text[index..]
+                .split_inclusive(&WORD_CHARS[..])
+                .map(|part| word_change_first_letter(part.to_string()))
+                .collect();
+            text[..index].to_string() + &capitalized
+        }
+        TextAction::CapitalizePrevWords(count) => {
+            // find the start of the `count`th word from the end
+            let mut index = text.len();
+            for _ in 0..count {
+                index = text[..index]
+                    .trim_end_matches(separator)
+                    .rfind(separator)
+                    .map_or(0, |i| i + separator.len());
+            }
+            let words: Vec<_> = text[index..].split(separator).map(title_case).collect();
+            text[..index].to_string() + &words.join(separator)
+        }
         TextAction::SameCasePrev(b) => {
             let index = find_last_word(&text);
             let word = text[index..].to_string();
@@ -255,9 +549,61 @@ fn perform_text_action(text: &str, action: TextAction) -> String {
             };
             text[..index].to_string() + &changed_case
         }
+        TextAction::FormatPrevNumber(format) => {
+            let index = find_last_word_space(text, separator);
+            match format_number(&text[index..], format) {
+                Some(formatted) => text[..index].to_string() + &formatted,
+                None => text.to_string(),
+            }
+        }
+        TextAction::DeletePrevWord => {
+            // spaces after the word are deleted along with it
+            let end = text.trim_end_matches(separator).len();
+            let index = text[..end].rfind(separator).unwrap_or(0);
+            text[..index].to_string()
+        }
+        // it needs to know where the last word starts, so it is applied when parsing instead
+        TextAction::SplitPrev => text.to_string(),
+    }
+}
+
+/// Reformats a number (which can already have commas). Returns None if it isn't a number
+fn format_number(number: &str, format: NumberFormat) -> Option<String> {
+    if !number.chars().all(|c| c.is_ascii_digit() || c == ',') {
+        return None;
+    }
+    let digits = number.replace(",", "");
+    if digits.is_empty() {
+        return None;
+    }
+
+    match format {
+        NumberFormat::Grouped => Some(group_thousands(&digits)),
+        NumberFormat::Currency => {
+            // there must be at least a dollar digit and two cents digits
+            let digits = format!("{:0>3}", digits);
+            let (dollars, cents) = digits.split_at(digits.len() - 2);
+            let dollars = match dollars.trim_start_matches('0') {
+                "" => "0",
+                d => d,
+            };
+            Some(format!("${}.{}", group_thousands(dollars), cents))
+        }
     }
 }
 
+/// Inserts a comma between every 3 digits, starting from the end
+fn group_thousands(digits: &str) -> String {
+    let mut grouped = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            grouped.push(',');
+        }
+        grouped.push(c);
+    }
+    grouped
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -265,7 +611,7 @@ mod tests {
     use plojo_core::Stroke;
 
     fn translation_diff_space_after(t: Vec<Text>) -> String {
-        parse_translation(t, false)
+        parse_translation(t, &FormatOptions::default())
     }
 
     #[test]
@@ -368,6 +714,117 @@ mod tests {
         assert_eq!(translated, "Hello hi");
     }
 
+    #[test]
+    fn test_parse_capitalize_first_word() {
+        let translated = parse_translation(
+            vec![Text::Lit("hello".to_string()), Text::Lit("hi".to_string())],
+            &FormatOptions {
+                capitalize_first_word: true,
+                ..FormatOptions::default()
+            },
+        );
+
+        assert_eq!(translated, " Hello hi");
+    }
+
+    #[test]
+    fn test_parse_capitalize_after_punctuation() {
+        let translated = parse_translation(
+            vec![
+                Text::Lit("etc.".to_string()),
+                Text::Lit("hello".to_string()),
+                Text::Lit("really?".to_string()),
+                Text::Attached {
+                    text: "!".to_string(),
+                    joined_next: false,
+                    joined_prev: AttachedType::AttachOnly,
+                    carry_capitalization: false,
+                },
+                Text::Lit("hi".to_string()),
+                Text::Lit("e.g".to_string()),
+                Text::Lit("foo".to_string()),
+            ],
+            &FormatOptions {
+                capitalize_after_punctuation: true,
+                ..FormatOptions::default()
+            },
+        );
+
+        assert_eq!(translated, " etc. Hello really?! Hi e.g foo");
+    }
+
+    #[test]
+    fn test_parse_cursor() {
+        let texts = vec![
+            Text::Lit("hello".to_string()),
+            Text::Cursor,
+            Text::Lit("world".to_string()),
+        ];
+
+        assert_eq!(
+            parse_translation_with_cursor(texts.clone(), &FormatOptions::default(),),
+            (" hello world".to_string(), Some(6), 0)
+        );
+        assert_eq!(
+            parse_translation_with_cursor(
+                texts,
+                &FormatOptions {
+                    space_after: true,
+                    ..FormatOptions::default()
+                },
+            ),
+            ("hello world ".to_string(), Some(7), 0)
+        );
+        assert_eq!(
+            parse_translation_with_cursor(
+                vec![Text::Cursor, Text::Lit("hello".to_string())],
+                &FormatOptions {
+                    space_after: true,
+                    ..FormatOptions::default()
+                },
+            ),
+            ("hello ".to_string(), Some(6), 0)
+        );
+    }
+
+    #[test]
+    fn test_parse_select_prev() {
+        let texts = vec![
+            Text::Lit("Dear".to_string()),
+            Text::Lit("your name".to_string()),
+            Text::SelectPrev(None),
+            Text::Attached {
+                text: ",".to_string(),
+                joined_next: false,
+                joined_prev: AttachedType::AttachOnly,
+                carry_capitalization: false,
+            },
+        ];
+
+        assert_eq!(
+            parse_translation_with_cursor(texts.clone(), &FormatOptions::default(),),
+            (" Dear your name,".to_string(), Some(1), 9)
+        );
+        assert_eq!(
+            parse_translation_with_cursor(
+                texts,
+                &FormatOptions {
+                    space_after: true,
+                    ..FormatOptions::default()
+                },
+            ),
+            ("Dear your name, ".to_string(), Some(2), 9)
+        );
+        // can't select more than what is there
+        assert_eq!(
+            parse_translation_with_cursor(
+                vec![Text::Lit("hi".to_string()), Text::SelectPrev(Some(10))],
+                &FormatOptions::default(),
+            ),
+            (" hi".to_string(), Some(0), 3)
+        );
+    }
+
     #[test]
     fn test_parse_glued() {
         let translated = translation_diff_space_after(vec![
@@ -389,6 +846,13 @@ mod tests {
         assert_eq!(word_change_first_letter("Hello".to_owned()), "Hello");
     }
 
+    #[test]
+    fn test_title_case() {
+        assert_eq!(title_case("hello"), "Hello");
+        assert_eq!(title_case("the one  thing"), "The One  Thing");
+        assert_eq!(title_case(""), "");
+    }
+
     #[test]
     fn test_unicode() {
         let translated = translation_diff_space_after(vec![
@@ -427,11 +891,45 @@ mod tests {
 
     #[test]
     fn test_find_last_word_space() {
-        assert_eq!(find_last_word_space("hello world"), 6);
-        assert_eq!(find_last_word_space(" world"), 1);
-        assert_eq!(find_last_word_space("test "), 5);
-        assert_eq!(find_last_word_space("nospace"), 0);
-        assert_eq!(find_last_word_space(" there are many words"), 16);
+        assert_eq!(find_last_word_space("hello world", " "), 6);
+        assert_eq!(find_last_word_space(" world", " "), 1);
+        assert_eq!(find_last_word_space("test ", " "), 5);
+        assert_eq!(find_last_word_space("nospace", " "), 0);
+        assert_eq!(find_last_word_space(" there are many words", " "), 16);
+        assert_eq!(find_last_word_space("hello\u{a0}world", " "), 7);
+        assert_eq!(find_last_word_space("hello__world", "__"), 7);
+    }
+
+    #[test]
+    fn test_text_actions_with_separator() {
+        assert_eq!(
+            perform_text_action("__foo__bar", TextAction::SuppressSpacePrev, "__"),
+            "__foobar"
+        );
+        assert_eq!(
+            perform_text_action(
+                "__new york__times",
+                TextAction::CapitalizePrevWords(2),
+                "__"
+            ),
+            "__New York__Times"
+        );
+        assert_eq!(
+            perform_text_action("__hello__world__", TextAction::DeletePrevWord, "__"),
+            "__hello"
+        );
+        let translated = parse_translation(
+            vec![
+                Text::Lit("hello".to_string()),
+                Text::Lit("world".to_string()),
+            ],
+            &FormatOptions {
+                space_after: true,
+                separator: "\u{a0}".to_string(),
+                ..FormatOptions::default()
+            },
+        );
+        assert_eq!(translated, "hello\u{a0}world\u{a0}");
     }
 
     #[test]
@@ -446,38 +944,164 @@ mod tests {
     #[test]
     fn test_perform_text_action() {
         assert_eq!(
-            perform_text_action("foo bar", TextAction::SuppressSpacePrev),
+            perform_text_action("foo bar", TextAction::SuppressSpacePrev, " "),
             "foobar"
         );
         assert_eq!(
-            perform_text_action(" hello", TextAction::CapitalizePrev),
+            perform_text_action(" hello", TextAction::CapitalizePrev, " "),
             " Hello"
         );
         assert_eq!(
-            perform_text_action(" there are many words", TextAction::CapitalizePrev),
+            perform_text_action(" there are many words", TextAction::CapitalizePrev, " "),
             " there are many Words"
         );
         assert_eq!(
-            perform_text_action(" no previous word ", TextAction::CapitalizePrev),
+            perform_text_action(" no previous word ", TextAction::CapitalizePrev, " "),
             " no previous word "
         );
         assert_eq!(
-            perform_text_action(" ∅∅byteboundary", TextAction::CapitalizePrev),
+            perform_text_action(" ∅∅byteboundary", TextAction::CapitalizePrev, " "),
             " ∅∅Byteboundary"
         );
         assert_eq!(
             // This weird character becomes 2 S's when capitalized
-            perform_text_action(" ßweird_char", TextAction::CapitalizePrev),
+            perform_text_action(" ßweird_char", TextAction::CapitalizePrev, " "),
             " SSweird_char"
         );
         assert_eq!(
-            perform_text_action(" (symbol", TextAction::CapitalizePrev),
+            perform_text_action(" (symbol", TextAction::CapitalizePrev, " "),
             " (Symbol"
         );
         assert_eq!(
-            perform_text_action(" !symbol-hyphen", TextAction::CapitalizePrev),
+            perform_text_action(" !symbol-hyphen", TextAction::CapitalizePrev, " "),
             " !Symbol-hyphen"
         );
+        assert_eq!(
+            perform_text_action(
+                " the new york times",
+                TextAction::CapitalizePrevWords(3),
+                " "
+            ),
+            " the New York Times"
+        );
+        assert_eq!(
+            perform_text_action(
+                " a well-known name",
+                TextAction::CapitalizePrevWords(2),
+                " "
+            ),
+            " a Well-known Name"
+        );
+        assert_eq!(
+            perform_text_action(
+                " more than there are",
+                TextAction::CapitalizePrevWords(9),
+                " "
+            ),
+            " More Than There Are"
+        );
+    }
+
+    #[test]
+    fn test_title_case_prev() {
+        let title_case = |text| perform_text_action(text, TextAction::TitleCasePrev, " ");
+        assert_eq!(title_case(" hello"), " Hello");
+        assert_eq!(title_case(" meet jean-luc"), " meet Jean-Luc");
+        assert_eq!(title_case(" snake_case_word"), " Snake_Case_Word");
+        assert_eq!(title_case(" mixed-up_name"), " Mixed-Up_Name");
+        assert_eq!(title_case(" (jean-luc"), " (Jean-Luc");
+        // empty parts are skipped
+        assert_eq!(title_case(" a--b-"), " A--B-");
+        assert_eq!(title_case(" no previous word "), " no previous word ");
+        assert_eq!(title_case(""), "");
+        // unicode first letters
+        assert_eq!(title_case(" émile-ångström"), " Émile-Ångström");
+        assert_eq!(title_case(" ßtraße-öl"), " SStraße-Öl");
+    }
+
+    #[test]
+    fn test_format_prev_number() {
+        let grouped = TextAction::FormatPrevNumber(NumberFormat::Grouped);
+        let currency = TextAction::FormatPrevNumber(NumberFormat::Currency);
+        assert_eq!(
+            perform_text_action(" it is 1234567", grouped.clone(), " "),
+            " it is 1,234,567"
+        );
+        assert_eq!(perform_text_action(" 123", grouped.clone(), " "), " 123");
+        assert_eq!(perform_text_action(" 1234", grouped.clone(), " "), " 1,234");
+        assert_eq!(
+            perform_text_action(" 1299", currency.clone(), " "),
+            " $12.99"
+        );
+        assert_eq!(
+            perform_text_action(" 12345678", currency.clone(), " "),
+            " $123,456.78"
+        );
+        assert_eq!(perform_text_action(" 5", currency.clone(), " "), " $0.05");
+        assert_eq!(
+            perform_text_action(" 0099", currency.clone(), " "),
+            " $0.99"
+        );
+
+        // already formatted numbers
+        assert_eq!(
+            perform_text_action(" 1,234,567", grouped.clone(), " "),
+            " 1,234,567"
+        );
+        assert_eq!(
+            perform_text_action(" 12,99", currency.clone(), " "),
+            " $12.99"
+        );
+        assert_eq!(
+            perform_text_action(" $12.99", currency.clone(), " "),
+            " $12.99"
+        );
+        assert_eq!(
+            perform_text_action(" $12.99", grouped.clone(), " "),
+            " $12.99"
+        );
+
+        // not a number
+        assert_eq!(
+            perform_text_action(" hello", grouped.clone(), " "),
+            " hello"
+        );
+        assert_eq!(perform_text_action(" 12a", currency.clone(), " "), " 12a");
+        assert_eq!(perform_text_action(" ,", currency.clone(), " "), " ,");
+        assert_eq!(perform_text_action("", grouped, " "), "");
+        assert_eq!(perform_text_action(" 12 ", currency, " "), " 12 ");
+    }
+
+    #[test]
+    fn test_delete_prev_word() {
+        let delete = TextAction::DeletePrevWord;
+        assert_eq!(
+            perform_text_action(" hello world", delete.clone(), " "),
+            " hello"
+        );
+        assert_eq!(perform_text_action(" hello", delete.clone(), " "), "");
+        assert_eq!(perform_text_action("hello", delete.clone(), " "), "");
+        assert_eq!(perform_text_action("", delete.clone(), " "), "");
+        assert_eq!(perform_text_action("  ", delete.clone(), " "), "");
+        // trailing spaces
+        assert_eq!(
+            perform_text_action(" hello world ", delete.clone(), " "),
+            " hello"
+        );
+        // hyphens and punctuation are part of the word
+        assert_eq!(
+            perform_text_action(" a well-known name", delete.clone(), " "),
+            " a well-known"
+        );
+        assert_eq!(
+            perform_text_action(" a well-known", delete.clone(), " "),
+            " a"
+        );
+        assert_eq!(
+            perform_text_action(" hello, world.", delete.clone(), " "),
+            " hello,"
+        );
+        assert_eq!(perform_text_action(" “héllo”", delete, " "), "");
     }
 
     #[test]
@@ -503,6 +1127,53 @@ mod tests {
         assert_eq!(translated, " fairies bHi");
     }
 
+    #[test]
+    fn test_smart_quote() {
+        let suppress_space = Text::Attached {
+            text: "".to_string(),
+            joined_next: true,
+            joined_prev: AttachedType::ApplyOrthography,
+            carry_capitalization: false,
+        };
+        // opens after a space and closes after a letter
+        let translated = translation_diff_space_after(vec![
+            Text::Lit("said".to_string()),
+            Text::SmartQuote { double: true },
+            Text::Lit("hi".to_string()),
+            suppress_space.clone(),
+            Text::SmartQuote { double: true },
+            Text::Lit("to".to_string()),
+        ]);
+        assert_eq!(translated, " said “hi” to");
+
+        // capitalization is carried through an opening quote
+        let translated = translation_diff_space_after(vec![
+            Text::StateAction(StateAction::ForceCapitalize),
+            Text::SmartQuote { double: false },
+            Text::Lit("hi".to_string()),
+            suppress_space,
+            Text::SmartQuote { double: false },
+        ]);
+        assert_eq!(translated, " ‘Hi’");
+
+        // closes after closing punctuation and opens after opening punctuation
+        let attached = |text: &str, joined_prev| Text::Attached {
+            text: text.to_string(),
+            joined_next: true,
+            joined_prev,
+            carry_capitalization: false,
+        };
+        let translated = translation_diff_space_after(vec![
+            Text::Lit("hi".to_string()),
+            attached(".", AttachedType::AttachOnly),
+            Text::SmartQuote { double: true },
+            attached("(", AttachedType::DoNotAttach),
+            Text::SmartQuote { double: true },
+            Text::Lit("yes".to_string()),
+        ]);
+        assert_eq!(translated, " hi.” (“yes");
+    }
+
     #[test]
     fn test_space_after_basic() {
         let translated = parse_translation(
@@ -516,7 +1187,10 @@ mod tests {
                     carry_capitalization: false,
                 },
             ],
-            true,
+            &FormatOptions {
+                space_after: true,
+                ..FormatOptions::default()
+            },
         );
 
         assert_eq!(translated, "helloA ");
@@ -535,7 +1209,10 @@ mod tests {
                     carry_capitalization: false,
                 },
             ],
-            true,
+            &FormatOptions {
+                space_after: true,
+                ..FormatOptions::default()
+            },
         );
 
         assert_eq!(translated, "hello world ");
@@ -549,15 +1226,99 @@ mod tests {
                 Text::Glued("b".to_string()),
                 Text::Glued("c".to_string()),
             ],
-            true,
+            &FormatOptions {
+                space_after: true,
+                ..FormatOptions::default()
+            },
         );
 
         assert_eq!(translated, "abc ");
     }
 
+    #[test]
+    fn test_parse_unit_spacing() {
+        let texts = vec![
+            Text::Lit("5".to_string()),
+            Text::Glued("km".to_string()),
+            Text::Glued("1".to_string()),
+            Text::Glued("2".to_string()),
+            Text::Glued("mg".to_string()),
+            Text::Glued("4".to_string()),
+            Text::Glued("x".to_string()),
+            Text::Attached {
+                text: "km".to_string(),
+                joined_next: false,
+                joined_prev: AttachedType::AttachOnly,
+                carry_capitalization: false,
+            },
+        ];
+        let units = vec!["km".to_string(), "mg".to_string()];
+
+        assert_eq!(
+            parse_translation(
+                texts.clone(),
+                &FormatOptions {
+                    units,
+                    ..FormatOptions::default()
+                },
+            ),
+            " 5 km12 mg4xkm"
+        );
+        assert_eq!(
+            parse_translation(texts, &FormatOptions::default(),),
+            " 5km12mg4xkm"
+        );
+    }
+
+    #[test]
+    fn test_parse_number_words() {
+        let number_words: Vec<String> = ["zero", "one", "two", "three"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let texts = vec![
+            Text::UnknownStroke(Stroke::new("3-")),
+            Text::UnknownStroke(Stroke::new("1-")),
+            Text::StateAction(StateAction::ToggleNumberWords),
+            Text::UnknownStroke(Stroke::new("3-")),
+            Text::UnknownStroke(Stroke::new("1-")),
+            Text::UnknownStroke(Stroke::new("-9")),
+            Text::UnknownStroke(Stroke::new("12-")),
+            Text::StateAction(StateAction::ToggleNumberWords),
+            Text::UnknownStroke(Stroke::new("3-")),
+        ];
+
+        assert_eq!(
+            parse_translation(
+                texts.clone(),
+                &FormatOptions {
+                    number_words,
+                    ..FormatOptions::default()
+                },
+            ),
+            " 31 three one 9123"
+        );
+        assert_eq!(
+            parse_translation(
+                texts,
+                &FormatOptions {
+                    number_words: vec![],
+                    ..FormatOptions::default()
+                },
+            ),
+            " 31319123"
+        );
+    }
+
     #[test]
     fn test_space_after_empty() {
-        let translated = parse_translation(vec![], true);
+        let translated = parse_translation(
+            vec![],
+            &FormatOptions {
+                space_after: true,
+                ..FormatOptions::default()
+            },
+        );
 
         assert_eq!(translated, "");
     }
@@ -580,7 +1341,7 @@ mod tests {
                     carry_capitalization: false,
                 },
             ],
-            false,
+            &FormatOptions::default(),
         );
 
         assert_eq!(translated, " ©modeled");
@@ -590,19 +1351,19 @@ mod tests {
     fn test_force_same_case() {
         let translated = parse_translation(
             vec![
-                Text::StateAction(StateAction::SameCase(true)),
+                Text::StateAction(StateAction::UppercaseWord),
                 Text::StateAction(StateAction::ForceCapitalize),
                 Text::Lit("hello".to_string()),
-                // force same case should override force capitalize
+                // uppercase word should override force capitalize
                 Text::StateAction(StateAction::ForceCapitalize),
-                Text::StateAction(StateAction::SameCase(false)),
+                Text::StateAction(StateAction::UppercaseWord),
                 Text::Attached {
                     text: "(".to_string(),
                     joined_next: true,
                     joined_prev: AttachedType::DoNotAttach,
                     carry_capitalization: true,
                 },
-                Text::Lit("NASA".to_string()),
+                Text::Lit("nasa".to_string()),
                 Text::Lit("hi".to_string()),
                 Text::TextAction(TextAction::CapitalizePrev),
                 Text::TextAction(TextAction::SameCasePrev(true)),
@@ -611,9 +1372,58 @@ mod tests {
                 Text::TextAction(TextAction::CapitalizePrev),
                 Text::TextAction(TextAction::SameCasePrev(false)),
             ],
-            false,
+            &FormatOptions::default(),
+        );
+
+        assert_eq!(translated, " HELLO (NASA HI all_caps");
+    }
+
+    #[test]
+    fn test_uppercase_word() {
+        let translated = parse_translation(
+            vec![
+                Text::StateAction(StateAction::UppercaseWord),
+                Text::Lit("foo bar".to_string()),
+                // only the next word is uppercased
+                Text::Lit("hello".to_string()),
+                Text::StateAction(StateAction::UppercaseWord),
+                Text::Attached {
+                    text: "abc".to_string(),
+                    joined_next: false,
+                    joined_prev: AttachedType::ApplyOrthography,
+                    carry_capitalization: false,
+                },
+                Text::Lit("def".to_string()),
+            ],
+            &FormatOptions::default(),
+        );
+
+        assert_eq!(translated, " FOO bar helloABC def");
+    }
+
+    #[test]
+    fn test_force_lowercase() {
+        let translated = parse_translation(
+            vec![
+                Text::StateAction(StateAction::ForceLowercase),
+                Text::Lit("Hello".to_string()),
+                // the later of force capitalize and force lowercase wins
+                Text::StateAction(StateAction::ForceCapitalize),
+                Text::StateAction(StateAction::ForceLowercase),
+                Text::Lit("NASA".to_string()),
+                Text::StateAction(StateAction::ForceLowercase),
+                Text::StateAction(StateAction::ForceCapitalize),
+                Text::Lit("world".to_string()),
+                // already lowercase
+                Text::StateAction(StateAction::ForceLowercase),
+                Text::Lit("hi".to_string()),
+            ],
+            &FormatOptions {
+                capitalize_first_word: true,
+                ..FormatOptions::default()
+            },
         );
 
-        assert_eq!(translated, " HELLO (nasa HI all_caps");
+        assert_eq!(translated, " hello nASA World hi");
     }
 }
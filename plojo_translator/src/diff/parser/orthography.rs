@@ -1,9 +1,17 @@
-use regex::{Regex, RegexBuilder};
-use std::collections::HashSet;
+use regex::{Error as RegexError, Regex, RegexBuilder};
+use serde::Deserialize;
+use serde_json::Error as JsonError;
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    fmt,
+};
 
 lazy_static! {
     static ref ORTHOGRAPHY_RULES: Rules = default_orthography();
     static ref ORTHOGRAPHY_DICT: HashSet<String> = load_orthography_dict();
+    static ref ORTHOGRAPHY_EXCEPTIONS: Exceptions =
+        load_exceptions(include_str!("orthography_exceptions.json")).unwrap();
 }
 
 fn default_orthography() -> Rules {
@@ -11,7 +19,7 @@ fn default_orthography() -> Rules {
     fn rule_with_lit(b: &str, s: &str, lit: &'static str) -> (Find, Replace) {
         (
             Find::new(b, s),
-            vec![ReplaceItem::BaseGroup(1), ReplaceItem::Lit(lit)],
+            vec![ReplaceItem::BaseGroup(1), ReplaceItem::Lit(lit.to_string())],
         )
     }
 
@@ -44,7 +52,7 @@ fn default_orthography() -> Rules {
             Find::new(r"^(.+[bcdfghjklmnpqrstvwxz])y$", "^([a-hj-xz].*)$"),
             vec![
                 ReplaceItem::BaseGroup(1),
-                ReplaceItem::Lit("i"),
+                ReplaceItem::Lit("i".to_string()),
                 ReplaceItem::SuffixGroup(1),
             ],
         ),
@@ -88,10 +96,23 @@ fn load_orthography_dict() -> HashSet<String> {
 }
 
 /// If a word and its suffix matches Find, it will be replaced with Replace
-type Rules = Vec<(Find, Replace)>;
+pub(crate) type Rule = (Find, Replace);
+type Rules = Vec<Rule>;
 
-#[derive(Debug)]
-struct Find {
+/// The joined word for a word and suffix that the rules get wrong, keyed by "word+suffix" in
+/// lowercase
+type Exceptions = HashMap<String, String>;
+
+/// Custom orthography on top of the default English orthography. The exceptions are looked up
+/// before the default exceptions, and the rules are tried before the default rules
+#[derive(Debug, PartialEq, Default, Clone)]
+pub(crate) struct Orthography {
+    pub rules: Vec<Rule>,
+    pub exceptions: Exceptions,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Find {
     base: Regex,
     suffix: Regex,
 }
@@ -100,16 +121,19 @@ impl Find {
     /// Creates a new find orthography rule with base and suffix regex
     /// Panics if either regex is invalid
     fn new(base_rule: &str, suffix_rule: &str) -> Self {
-        Self {
+        Self::try_new(base_rule, suffix_rule).unwrap()
+    }
+
+    /// Creates a new find orthography rule with base and suffix regex, which can be invalid
+    fn try_new(base_rule: &str, suffix_rule: &str) -> Result<Self, RegexError> {
+        Ok(Self {
             base: RegexBuilder::new(base_rule)
                 .case_insensitive(true)
-                .build()
-                .unwrap(),
+                .build()?,
             suffix: RegexBuilder::new(suffix_rule)
                 .case_insensitive(true)
-                .build()
-                .unwrap(),
-        }
+                .build()?,
+        })
     }
 }
 
@@ -122,17 +146,128 @@ impl PartialEq for Find {
 type Replace = Vec<ReplaceItem>;
 
 /// Replace with a capturing group from base/suffix, or a literal string
-#[derive(Debug, PartialEq)]
-enum ReplaceItem {
+#[derive(Debug, PartialEq, Clone, Deserialize)]
+pub(crate) enum ReplaceItem {
     BaseGroup(usize),
     SuffixGroup(usize),
-    Lit(&'static str),
+    Lit(String),
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum OrthographyError {
+    InvalidRegex(String),
+    // a replacement uses a capturing group that its regex doesn't have
+    InvalidGroup(String),
+    // an exception whose key is not "word+suffix"
+    InvalidException(String),
+    JsonError(String),
+}
+
+impl fmt::Display for OrthographyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl Error for OrthographyError {}
+
+impl From<JsonError> for OrthographyError {
+    fn from(e: JsonError) -> Self {
+        OrthographyError::JsonError(e.to_string())
+    }
+}
+
+/// Loads orthography rules from a JSON list of `[base regex, suffix regex, replace]` triples,
+/// where replace is a list of `{"BaseGroup": n}`, `{"SuffixGroup": n}`, or `{"Lit": "text"}`.
+/// For example, the rule that joins "artistic" and "ly" into "artistically" is:
+///
+/// ```json
+/// ["^(.*[aeiou]c)$", "^ly$", [{"BaseGroup": 1}, {"Lit": "ally"}]]
+/// ```
+///
+/// Like the default rules, the regexes are case insensitive. Fails if a regex is invalid or a
+/// replacement refers to a capturing group that its regex doesn't have
+pub(crate) fn load_rules(raw: &str) -> Result<Vec<Rule>, OrthographyError> {
+    let raw_rules: Vec<(String, String, Replace)> = serde_json::from_str(raw)?;
+    raw_rules
+        .into_iter()
+        .map(|(base, suffix, replace)| {
+            let find = Find::try_new(&base, &suffix)
+                .map_err(|e| OrthographyError::InvalidRegex(e.to_string()))?;
+            for item in &replace {
+                let is_valid = match item {
+                    ReplaceItem::BaseGroup(group) => *group < find.base.captures_len(),
+                    ReplaceItem::SuffixGroup(group) => *group < find.suffix.captures_len(),
+                    ReplaceItem::Lit(_) => true,
+                };
+                if !is_valid {
+                    return Err(OrthographyError::InvalidGroup(format!(
+                        "{:?} in rule for {:?} and {:?}",
+                        item, base, suffix
+                    )));
+                }
+            }
+            Ok((find, replace))
+        })
+        .collect()
+}
+
+/// Loads orthography exceptions from a JSON object that maps "word+suffix" to the joined word,
+/// such as `{"panic+ing": "panicking"}`. The keys are case insensitive. Fails if a key doesn't
+/// have a word and a suffix
+pub(crate) fn load_exceptions(raw: &str) -> Result<Exceptions, OrthographyError> {
+    let raw_exceptions: HashMap<String, String> = serde_json::from_str(raw)?;
+    raw_exceptions
+        .into_iter()
+        .map(|(key, word)| match key.split_once('+') {
+            Some((base, suffix)) if !base.is_empty() && !suffix.is_empty() => {
+                Ok((key.to_lowercase(), word))
+            }
+            _ => Err(OrthographyError::InvalidException(key)),
+        })
+        .collect()
+}
+
+/// Looks up the joined word for a word and suffix in the custom exceptions and then the default
+/// exceptions. The joined word keeps the case of the word at its start and the case of the suffix
+/// at its end
+fn find_exception(base: &str, suffix: &str, exceptions: &Exceptions) -> Option<String> {
+    let key = format!("{}+{}", base, suffix).to_lowercase();
+    let word = exceptions
+        .get(&key)
+        .or_else(|| ORTHOGRAPHY_EXCEPTIONS.get(&key))?;
+
+    let mut chars: Vec<char> = word.chars().collect();
+    let same_letter = |a: char, b: char| a.to_lowercase().eq(b.to_lowercase());
+    for (w, c) in chars.iter_mut().zip(base.chars()) {
+        if !same_letter(*w, c) {
+            break;
+        }
+        *w = c;
+    }
+    for (w, c) in chars.iter_mut().rev().zip(suffix.chars().rev()) {
+        if !same_letter(*w, c) {
+            break;
+        }
+        *w = c;
+    }
+    Some(chars.into_iter().collect())
 }
 
 /// Join a word and suffix together, applying orthographic (spelling) rules
-/// It will first try a simple join of the suffix and look it up in a list of words
-/// Panics for invalid rules
-pub fn apply_orthography(base: &str, suffix: &str) -> String {
+/// It will first look up the word and suffix in the exceptions (for words the rules get wrong).
+/// Then it will try the custom rules, so that they can override the word list and the default
+/// rules. Then it will try a simple join of the suffix and look it up in a list of words. The
+/// custom exceptions are tried before the default ones, so they can override them
+pub fn apply_orthography(base: &str, suffix: &str, custom: &Orthography) -> String {
+    if let Some(word) = find_exception(base, suffix, &custom.exceptions) {
+        return word;
+    }
+
+    if let Some(word) = apply_rules(base, suffix, &custom.rules) {
+        return word;
+    }
+
     // Try matching a simple join first and see if that is an english word
     // This is done mainly for consonant doubling rule, which sometimes doubles a consonant even
     // when it doesn't need to.
@@ -141,27 +276,35 @@ pub fn apply_orthography(base: &str, suffix: &str) -> String {
         return simple_join;
     }
 
-    for (find, replace) in ORTHOGRAPHY_RULES.iter() {
+    // unable to match an orthography rule, just return the simple join of the strokes
+    apply_rules(base, suffix, &ORTHOGRAPHY_RULES).unwrap_or(simple_join)
+}
+
+/// Joins the word and suffix with the first rule that matches them, or None if none do
+fn apply_rules(base: &str, suffix: &str, rules: &[Rule]) -> Option<String> {
+    for (find, replace) in rules {
         if let (Some(base_captures), Some(suffix_captures)) =
             (find.base.captures(base), find.suffix.captures(suffix))
         {
             let mut s = String::new();
             for r in replace {
                 s.push_str(match r {
-                    // using unwrap() is fine here, because we assume the rules are valid
-                    ReplaceItem::BaseGroup(group) => base_captures.get(*group).unwrap().as_str(),
+                    // the groups exist because the rules are checked when they are loaded, but
+                    // an optional group may not have matched anything
+                    ReplaceItem::BaseGroup(group) => {
+                        base_captures.get(*group).map_or("", |m| m.as_str())
+                    }
                     ReplaceItem::SuffixGroup(group) => {
-                        suffix_captures.get(*group).unwrap().as_str()
+                        suffix_captures.get(*group).map_or("", |m| m.as_str())
                     }
-                    ReplaceItem::Lit(str) => *str,
+                    ReplaceItem::Lit(str) => str,
                 });
             }
-            return s;
+            return Some(s);
         }
     }
 
-    // unable to match an orthography rule, just return the simple join of the strokes
-    simple_join
+    None
 }
 
 #[cfg(test)]
@@ -173,7 +316,7 @@ mod tests {
         let mut iter = strs.iter();
         let mut str = iter.next().unwrap().to_string();
         for s in iter {
-            str = apply_orthography(&str, s);
+            str = apply_orthography(&str, s, &Orthography::default());
         }
         str
     }
@@ -215,4 +358,113 @@ mod tests {
         assert_eq!(orthog(vec!["SHiver", "ing"]), "SHivering");
         assert_eq!(orthog(vec!["sHivER", "iNG"]), "sHivERiNG");
     }
+
+    #[test]
+    fn test_orthography_custom_rules() {
+        // a final "l" after a vowel is never doubled (like American "traveling", whereas British
+        // spelling doubles it: "travelling"), and "ise" replaces a final "y"
+        let rules = load_rules(
+            r#"[
+                ["^(.*[aeiou]l)$", "^(ing|ed)$", [{"BaseGroup": 1}, {"SuffixGroup": 1}]],
+                ["^(.+)y$", "^ize$", [{"BaseGroup": 1}, {"Lit": "ise"}]]
+            ]"#,
+        )
+        .unwrap();
+        let rules = Orthography {
+            rules,
+            ..Default::default()
+        };
+        assert_eq!(apply_orthography("travel", "ing", &rules), "traveling");
+        assert_eq!(apply_orthography("cancel", "ed", &rules), "canceled");
+        assert_eq!(apply_orthography("agony", "ize", &rules), "agonise");
+        // the custom rule overrides the default consonant doubling rule
+        assert_eq!(
+            apply_orthography("dispel", "ing", &Orthography::default()),
+            "dispelling"
+        );
+        assert_eq!(apply_orthography("dispel", "ing", &rules), "dispeling");
+        // the default rules are used when no custom rule matches
+        assert_eq!(apply_orthography("narrate", "ing", &rules), "narrating");
+    }
+
+    #[test]
+    fn test_orthography_custom_rules_before_words() {
+        // "traveling" is in the word list, but the custom rule doubles the "l"
+        let rules = load_rules(
+            r#"[["^(.*[aeiou])l$", "^(ing|ed)$", [{"BaseGroup": 1}, {"Lit": "ll"}, {"SuffixGroup": 1}]]]"#,
+        )
+        .unwrap();
+        let rules = Orthography {
+            rules,
+            ..Default::default()
+        };
+        assert_eq!(
+            apply_orthography("travel", "ing", &Orthography::default()),
+            "traveling"
+        );
+        assert_eq!(apply_orthography("travel", "ing", &rules), "travelling");
+        assert_eq!(apply_orthography("cancel", "ed", &rules), "cancelled");
+    }
+
+    #[test]
+    fn test_orthography_invalid_rules() {
+        assert!(matches!(
+            load_rules(r#"[["^(.*$", "^s$", [{"Lit": "es"}]]]"#),
+            Err(OrthographyError::InvalidRegex(_))
+        ));
+        assert!(matches!(
+            load_rules(r#"[["^(.*)$", "^s$", [{"SuffixGroup": 1}]]]"#),
+            Err(OrthographyError::InvalidGroup(_))
+        ));
+        assert!(matches!(
+            load_rules(r#"[["^(.*)$", "^s$"]]"#),
+            Err(OrthographyError::JsonError(_))
+        ));
+        assert_eq!(load_rules("[]"), Ok(vec![]));
+    }
+
+    #[test]
+    fn test_orthography_exceptions() {
+        // begin + ing is joined by the exception instead of the consonant doubling rule
+        assert_eq!(
+            find_exception("begin", "ing", &Exceptions::new()),
+            Some("beginning".to_string())
+        );
+        assert_eq!(orthog(vec!["begin", "ing"]), "beginning");
+        // the rules get these wrong
+        assert_eq!(orthog(vec!["panic", "ing"]), "panicking");
+        assert_eq!(orthog(vec!["dye", "ing"]), "dyeing");
+        assert_eq!(orthog(vec!["argue", "ment"]), "argument");
+        assert_eq!(find_exception("narrate", "ing", &Exceptions::new()), None);
+    }
+
+    #[test]
+    fn test_orthography_exceptions_case() {
+        assert_eq!(orthog(vec!["Panic", "ed"]), "Panicked");
+        assert_eq!(orthog(vec!["PANIC", "ING"]), "PANICkING");
+        assert_eq!(orthog(vec!["True", "ly"]), "Truly");
+    }
+
+    #[test]
+    fn test_orthography_custom_exceptions() {
+        let exceptions =
+            load_exceptions(r#"{"Travel+ing": "travelling", "begin+ing": "begining"}"#).unwrap();
+        let custom = Orthography {
+            exceptions,
+            ..Default::default()
+        };
+        assert_eq!(apply_orthography("travel", "ing", &custom), "travelling");
+        // custom exceptions override the default ones
+        assert_eq!(apply_orthography("begin", "ing", &custom), "begining");
+        assert_eq!(apply_orthography("panic", "ing", &custom), "panicking");
+
+        assert_eq!(
+            load_exceptions(r#"{"travelling": "travelling"}"#),
+            Err(OrthographyError::InvalidException("travelling".to_string()))
+        );
+        assert_eq!(
+            load_exceptions(r#"{"travel+": "travel"}"#),
+            Err(OrthographyError::InvalidException("travel+".to_string()))
+        );
+    }
 }
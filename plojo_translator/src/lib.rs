@@ -1,14 +1,35 @@
 #[macro_use]
 extern crate lazy_static;
 
-use dictionary::Dictionary;
-use diff::translation_diff;
+use dictionary::{Dictionary, Span};
+use diff::{
+    load_orthography_exceptions, load_orthography_rules, render_text, render_translation,
+    translation_diff, translation_diff_with_cursor, FormatOptions,
+};
+use macros::MacroMatcher;
 use plojo_core::{Command, Stroke, Translator};
 use serde::Deserialize;
-use std::{error::Error, hash::Hash};
+use shell::ShellOutputs;
+use std::{
+    borrow::Cow,
+    cmp,
+    collections::HashMap,
+    error::Error,
+    fs,
+    hash::Hash,
+    mem,
+    path::{Path, PathBuf},
+    slice,
+    time::Duration,
+};
 
 mod dictionary;
 mod diff;
+mod macros;
+pub mod plover;
+mod shell;
+
+pub use dictionary::{DictOverride, DictStats, Unreachable};
 
 /// A dictionary entry. It could be a command, in which case it is passed directly to the
 /// dispatcher. Otherwise it is something that pertains to text, which is parsed here in translator
@@ -46,6 +67,20 @@ enum Text {
     StateAction(StateAction),
     // text actions can only affect the text before it
     TextAction(TextAction),
+    // where to move the cursor after the text is outputted
+    Cursor,
+    // moves the cursor here like `Cursor` and then selects this many chars before it (or the text
+    // of the word before it if there is no count)
+    SelectPrev(Option<usize>),
+    // the entry is never used as the base of a folded suffix
+    NoFold,
+    // a curly quote that closes after a word or closing punctuation and opens otherwise (double or
+    // single quote)
+    SmartQuote {
+        double: bool,
+    },
+    // a command dispatched in between the text around it (ex: pressing a key)
+    InlineCommand(Command),
 }
 
 #[derive(Debug, PartialEq, Clone, Hash, Eq, Deserialize)]
@@ -57,10 +92,58 @@ enum AttachedType {
 
 impl Translation {
     /// Convert translation into text, ignoring commands
+    ///
+    /// Translator commands that change the formatting of later text are converted into state
+    /// actions so that they only apply to the text after the command
     fn as_text(&self) -> Vec<Text> {
         match self {
             Translation::Text(ref text) => text.clone(),
-            Translation::Command { text_after, .. } => text_after.clone().unwrap_or_default(),
+            Translation::Command {
+                cmds,
+                text_after,
+                suppress_space_before,
+            } => {
+                let mut texts = Vec::new();
+                if *suppress_space_before {
+                    texts.push(Text::StateAction(StateAction::SuppressSpaceBefore));
+                }
+                // the text after a command is typed after the command is dispatched
+                let dispatches = cmds
+                    .iter()
+                    .any(|c| !matches!(c, Command::TranslatorCommand(_) | Command::NoOp));
+                if dispatches && text_after.is_some() {
+                    texts.push(Text::StateAction(StateAction::KeepSpaceBefore));
+                }
+                for cmd in cmds {
+                    if let Command::TranslatorCommand(c) = cmd {
+                        if c == TOGGLE_TITLE_CASE {
+                            texts.push(Text::StateAction(StateAction::ToggleTitleCase));
+                        } else if c == TOGGLE_NUMBER_WORDS {
+                            texts.push(Text::StateAction(StateAction::ToggleNumberWords));
+                        } else if let Some(count) = parse_cap_prev_word(c) {
+                            texts.push(Text::TextAction(TextAction::CapitalizePrevWords(count)));
+                        } else if c == DELETE_PREV_WORD {
+                            texts.push(Text::TextAction(TextAction::DeletePrevWord));
+                        } else if c == SPLIT_PREV_WORD {
+                            texts.push(Text::TextAction(TextAction::SplitPrev));
+                        }
+                    }
+                }
+                texts.append(&mut text_after.clone().unwrap_or_default());
+                texts
+            }
+        }
+    }
+
+    /// Whether the translation is a command that includes the translator command
+    fn has_translator_command(&self, command: &str) -> bool {
+        match self {
+            Translation::Command { cmds, .. } => cmds
+                .iter()
+                .any(|c| matches!(c, Command::TranslatorCommand(c) if c == command)),
+            Translation::Text(texts) => texts.iter().any(
+                |t| matches!(t, Text::InlineCommand(Command::TranslatorCommand(c)) if c == command),
+            ),
         }
     }
 }
@@ -68,16 +151,58 @@ impl Translation {
 #[derive(Debug, PartialEq, Clone, Hash, Eq, Deserialize)]
 enum StateAction {
     ForceCapitalize,
-    SameCase(bool), // apply all upper (true) or lower (false) case
+    ForceLowercase,
+    UppercaseWord,
+    ToggleSuppressSpace,
+    ToggleTitleCase,
+    ToggleNumberWords,
     Clear,
+    // the space before a command was removed (only when spaces are added after words)
+    SuppressSpaceBefore,
+    // a command was dispatched after the space before it was typed, so the text after the command
+    // (ex: `{^}`) can't remove it (only when spaces are added after words)
+    KeepSpaceBefore,
 }
 
 #[allow(clippy::enum_variant_names)]
 #[derive(Debug, PartialEq, Eq, Hash, Clone, Deserialize)]
 enum TextAction {
     CapitalizePrev,
+    TitleCasePrev, // capitalize each part of the previous word (split by hyphens or underscores)
+    CapitalizePrevWords(usize), // capitalize each of the previous space separated words
     SuppressSpacePrev,
     SameCasePrev(bool), // apply all upper (true) or lower (false) case
+    FormatPrevNumber(NumberFormat),
+    DeletePrevWord, // remove the previous space separated word and the space before it
+    SplitPrev,      // put a space between the last word and the text that it is attached to
+}
+
+/// How to reformat the previous number
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Deserialize)]
+enum NumberFormat {
+    // commas between every 3 digits (ex: 1,234,567)
+    Grouped,
+    // dollars with the last two digits as cents (ex: 1299 as $12.99)
+    Currency,
+}
+
+/// What is removed each time undo is pressed
+#[derive(Debug, PartialEq, Clone, Copy, Default, Deserialize)]
+pub enum UndoPolicy {
+    /// Undo one stroke at a time, which could partially undo a multi-stroke word
+    #[default]
+    Stroke,
+    /// Undo all the strokes that make up the last translation (word) at once
+    Translation,
+}
+
+/// An entry that was added while translating (see `StandardTranslator::add_entry`)
+#[derive(Debug)]
+pub struct AddedEntry {
+    /// Replaces the text that changed because of the new entry
+    pub commands: Vec<Command>,
+    /// Why the entry couldn't be saved to the user dictionary, even though it was added
+    pub save_error: Option<Box<dyn Error>>,
 }
 
 /// The standard translator is very similar in feature to Plover and other CAT software.
@@ -92,13 +217,123 @@ pub struct StandardTranslator {
     dict: Dictionary,
     retrospective_add_space: Vec<Stroke>,
     add_space_insert: Option<Stroke>,
-    space_after: bool,
+    format: FormatOptions,
+    undo_policy: UndoPolicy,
+    macros: MacroMatcher,
+    dict_paths: Vec<PathBuf>,
+    window: TranslatedWindow,
+    max_stroke_buffer: usize,
+    // strokes typed as raw steno since passthrough was turned on, or None if it is off
+    passthrough: Option<Vec<Stroke>>,
+    user_dict: Option<PathBuf>,
+    shell_timeout: Duration,
+    shell_outputs: ShellOutputs,
+}
+
+/// The strokes that were translated by the last call to translate and their translations. This
+/// lets the next call skip translating those strokes again
+#[derive(Debug, PartialEq, Default)]
+struct TranslatedWindow {
+    strokes: Vec<Stroke>,
+    spans: Vec<Span>,
+}
+
+impl TranslatedWindow {
+    /// Reuse the translations if the strokes are at the end of this window and start where one
+    /// of the translations starts (so the translations after that don't change)
+    fn reuse(self, strokes: &[Stroke]) -> Option<Vec<Span>> {
+        let skip = self.strokes.len().checked_sub(strokes.len())?;
+        if self.strokes[skip..] != *strokes {
+            return None;
+        }
+
+        let mut span_start = 0;
+        let mut spans = self.spans.into_iter();
+        while span_start < skip {
+            span_start += spans.next()?.0;
+        }
+        if span_start == skip {
+            Some(spans.collect())
+        } else {
+            None
+        }
+    }
+}
+
+/// Add an entry to a dictionary file, creating it if it doesn't exist
+fn save_entry(path: &Path, strokes: &[Stroke], translation: &str) -> Result<(), Box<dyn Error>> {
+    let mut dict = if path.exists() {
+        serde_json::from_str(&fs::read_to_string(path)?)?
+    } else {
+        serde_json::Map::new()
+    };
+    let stroke = strokes
+        .iter()
+        .map(|s| s.clone().to_raw())
+        .collect::<Vec<_>>()
+        .join("/");
+    dict.insert(stroke, translation.into());
+    fs::write(path, serde_json::to_string_pretty(&dict)?)?;
+    Ok(())
+}
+
+fn flatten_spans(spans: &[Span]) -> Vec<Translation> {
+    spans
+        .iter()
+        .flat_map(|(_, translations)| translations.iter().cloned())
+        .collect()
 }
 
-// most number of strokes to stroke in prev_strokes; limits undo to this many strokes
+/// Checks dictionaries for entries that can never be translated (for example, when the first few
+/// strokes of an entry are a command). Returns the strokes of each of those entries along with the
+/// reason why it can't be translated.
+pub fn find_unreachable_entries(
+    raw_dicts: Vec<String>,
+) -> Result<Vec<(Stroke, Unreachable)>, Box<dyn Error>> {
+    Ok(Dictionary::new(raw_dicts)?.find_unreachable())
+}
+
+// translator command for toggling title case, which is handled when parsing text
+const TOGGLE_TITLE_CASE: &str = "toggle_title_case";
+// translator command for toggling spelling out numbers as words, which is handled when parsing text
+const TOGGLE_NUMBER_WORDS: &str = "toggle_number_words";
+const CAP_PREV_WORD: &str = "cap_prev_word";
+// translator command for deleting the previous word, which is handled when parsing text
+const DELETE_PREV_WORD: &str = "delete_prev_word";
+// translator command for splitting the previous word from the text it is attached to, which is
+// handled when parsing text
+const SPLIT_PREV_WORD: &str = "split_prev_word";
+// translator command for typing the previous stroke as raw steno, which is handled when translating
+const TYPE_LAST_RAW_STROKE: &str = "type_last_raw_stroke";
+// translator command for repeating the last translation, which is handled when translating
+const REPEAT_LAST: &str = "repeat_last";
+// translator command for switching between spaces before and after words. The space after the
+// last word is moved when translating, and the mode is switched when the command is handled
+const TOGGLE_SPACE_MODE: &str = "toggle_space_mode";
+// translator command for typing strokes as raw steno instead of translating them. While it is on,
+// the stroke for this command is the only one that is looked up (to turn it off again)
+const TOGGLE_PASSTHROUGH: &str = "toggle_passthrough";
+// translator commands for turning passthrough on or off (ex: for Plover's `{PLOVER:SUSPEND}` and
+// `{PLOVER:RESUME}`)
+const START_PASSTHROUGH: &str = "start_passthrough";
+const STOP_PASSTHROUGH: &str = "stop_passthrough";
+
+/// Parses the number of words to capitalize from a "cap_prev_word:N" command. The count defaults to
+/// 1 if it is omitted. Returns None if it is not that command or the count is invalid
+fn parse_cap_prev_word(command: &str) -> Option<usize> {
+    let count = command.strip_prefix(CAP_PREV_WORD)?;
+    if count.is_empty() {
+        return Some(1);
+    }
+    count.strip_prefix(':')?.parse().ok()
+}
+
+// default most number of strokes to store in prev_strokes; limits undo to this many strokes
 const MAX_STROKE_BUFFER: usize = 50;
 // only pass a certain number of strokes to be translated
 const MAX_TRANSLATION_STROKE_LEN: usize = 10;
+// default time that a shell insert command can run for before it is killed
+const SHELL_INSERT_TIMEOUT: Duration = Duration::from_secs(2);
 
 /// Check whether the translation is non empty text
 /// Used to determine where to add retrospective space
@@ -115,13 +350,18 @@ fn is_text(translation: Translation) -> bool {
             // check if at least one is non empty text
             for text in texts {
                 match text {
-                    Text::UnknownStroke(_) => return true,
+                    Text::UnknownStroke(_) | Text::SmartQuote { .. } => return true,
                     Text::Attached { text, .. } | Text::Glued(text) | Text::Lit(text) => {
                         if !text.is_empty() {
                             return true;
                         }
                     }
-                    Text::TextAction(_) | Text::StateAction(_) => continue,
+                    Text::TextAction(_)
+                    | Text::StateAction(_)
+                    | Text::Cursor
+                    | Text::SelectPrev(_)
+                    | Text::NoFold
+                    | Text::InlineCommand(_) => continue,
                 }
             }
             false
@@ -157,59 +397,682 @@ impl StandardTranslator {
             dict,
             retrospective_add_space,
             add_space_insert,
-            space_after,
+            format: FormatOptions {
+                space_after,
+                ..FormatOptions::default()
+            },
+            undo_policy: UndoPolicy::default(),
+            macros: MacroMatcher::default(),
+            dict_paths: vec![],
+            window: TranslatedWindow::default(),
+            max_stroke_buffer: MAX_STROKE_BUFFER,
+            passthrough: None,
+            user_dict: None,
+            shell_timeout: SHELL_INSERT_TIMEOUT,
+            shell_outputs: ShellOutputs::default(),
         })
     }
-}
 
-impl Translator for StandardTranslator {
-    fn translate(&mut self, stroke: Stroke) -> Vec<Command> {
-        if self.prev_strokes.len() > MAX_STROKE_BUFFER {
-            self.prev_strokes.remove(0);
+    /// Capitalize the first word that is outputted (at the start of the session or after the
+    /// stroke buffer has been cleared) without needing an explicit `{-|}`
+    pub fn with_capitalize_first_word(mut self, capitalize_first_word: bool) -> Self {
+        self.format.capitalize_first_word = capitalize_first_word;
+        self
+    }
+
+    /// Capitalize the word after any text that ends with sentence ending punctuation (`.`, `!`, or
+    /// `?`), even if the punctuation is part of a text literal (like `etc.`)
+    pub fn with_capitalize_after_punctuation(mut self, capitalize_after_punctuation: bool) -> Self {
+        self.format.capitalize_after_punctuation = capitalize_after_punctuation;
+        self
+    }
+
+    /// Set whether undo removes one stroke or the entire last translation at a time
+    pub fn with_undo_policy(mut self, undo_policy: UndoPolicy) -> Self {
+        self.undo_policy = undo_policy;
+        self
+    }
+
+    /// Words (ex: `km`) that get a space after a number even if they would be glued to it. This
+    /// way `{&km}` glues after letters but is spaced in measurements like "5 km"
+    pub fn with_units(mut self, units: Vec<String>) -> Self {
+        self.format.units = units;
+        self
+    }
+
+    /// Words that number strokes are spelled out as when number words are toggled on. The nth
+    /// word is used for the digit n, and numbers without a word are typed as digits
+    pub fn with_number_words(mut self, number_words: Vec<String>) -> Self {
+        self.format.number_words = number_words;
+        self
+    }
+
+    /// Orthography rules (for joining suffixes to words) to try before the list of English words
+    /// and the default English rules.
+    /// They are a JSON list of `[base regex, suffix regex, replace]` triples, where replace is a
+    /// list of `{"BaseGroup": n}`, `{"SuffixGroup": n}`, or `{"Lit": "text"}`. Fails if the rules
+    /// can't be parsed or any of the regexes are invalid
+    pub fn with_orthography_rules(mut self, raw_rules: &str) -> Result<Self, Box<dyn Error>> {
+        self.format.orthography.rules = load_orthography_rules(raw_rules)?;
+        Ok(self)
+    }
+
+    /// Words that the orthography rules get wrong. They are a JSON object that maps a word and a
+    /// suffix (as "word+suffix") to the joined word, such as `{"panic+ing": "panicking"}`. They are
+    /// looked up before the default exceptions and before any rules are tried. Fails if the
+    /// exceptions can't be parsed
+    pub fn with_orthography_exceptions(
+        mut self,
+        raw_exceptions: &str,
+    ) -> Result<Self, Box<dyn Error>> {
+        self.format.orthography.exceptions = load_orthography_exceptions(raw_exceptions)?;
+        Ok(self)
+    }
+
+    /// Text to put between words instead of a space (ex: a non-breaking space). Suppressing the
+    /// space removes exactly this text.
+    ///
+    /// # Panics
+    /// Panics if the separator is empty
+    pub fn with_separator(mut self, separator: String) -> Self {
+        assert!(!separator.is_empty(), "the word separator can't be empty");
+        self.format.separator = separator;
+        self
+    }
+
+    /// Sequences of strokes that dispatch commands when pressed one after another. The strokes of
+    /// a macro are not added to the stroke buffer, so they don't show up in the output or get
+    /// undone. Strokes that start a macro are held back until the sequence is finished or broken
+    pub fn with_macros(mut self, macros: Vec<(Vec<Stroke>, Vec<Command>)>) -> Self {
+        self.macros = MacroMatcher::new(macros);
+        self
+    }
+
+    /// The dictionary files to read again when reloading the dictionaries (with the
+    /// "reload_dicts" translator command)
+    pub fn with_dict_paths(mut self, dict_paths: Vec<PathBuf>) -> Self {
+        self.dict_paths = dict_paths;
+        self
+    }
+
+    /// Text to output for single strokes that are not in the dictionary instead of the raw
+    /// stroke. This is a last resort, so it is only used when no dictionary entry matches
+    pub fn with_fallback(mut self, fallback: HashMap<Stroke, String>) -> Self {
+        self.dict = self.dict.with_fallback(fallback);
+        self
+    }
+
+    /// Keep at most this many strokes in the stroke buffer, which limits how far undo can go.
+    /// The oldest translations are dropped if there are already more strokes than that
+    pub fn with_max_stroke_buffer(mut self, max_stroke_buffer: usize) -> Self {
+        self.max_stroke_buffer = max_stroke_buffer;
+        let mut prev_strokes = mem::take(&mut self.prev_strokes);
+        let mut shell_outputs = mem::take(&mut self.shell_outputs);
+        self.trim_strokes(&mut prev_strokes, &mut shell_outputs);
+        self.prev_strokes = prev_strokes;
+        self.shell_outputs = shell_outputs;
+        self
+    }
+
+    /// The dictionary file that entries added with `add_entry` are saved to. The file is created
+    /// if it doesn't exist
+    pub fn with_user_dict(mut self, user_dict: PathBuf) -> Self {
+        self.user_dict = Some(user_dict);
+        self
+    }
+
+    /// How long a shell insert command can run for (2 seconds by default). Translating waits for
+    /// the command, so it is killed after this long and nothing is typed
+    pub fn with_shell_timeout(mut self, shell_timeout: Duration) -> Self {
+        self.shell_timeout = shell_timeout;
+        self
+    }
+
+    /// Read the dictionary files again and replace the dictionary with them. The stroke buffer
+    /// is kept. If any of the files can't be read or parsed, the old dictionary is kept
+    pub fn reload_dicts(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.dict_paths.is_empty() {
+            return Err("no dictionary files to reload".into());
+        }
+        let raw_dicts = self
+            .dict_paths
+            .iter()
+            .map(fs::read_to_string)
+            .collect::<Result<Vec<_>, _>>()?;
+        self.dict = Dictionary::new(raw_dicts)?
+            .with_fallback(self.dict.fallback().clone())
+            .with_edits_from(&self.dict);
+        // the old translations may have changed
+        self.window = TranslatedWindow::default();
+        Ok(())
+    }
+
+    /// Define a sequence of strokes while translating, without editing a dictionary file. The
+    /// translation is written the same way as in a dictionary file (ex: `{^ing}`) and takes
+    /// precedence over any loaded entry for the strokes. It is also saved to the user dictionary
+    /// if there is one.
+    ///
+    /// The strokes in the stroke buffer are translated again, so the returned commands replace
+    /// text that changed because of the new entry (like the raw steno of an unknown stroke). If
+    /// the entry can't be parsed, nothing is changed. If it can't be saved, it is still added and
+    /// the commands are still returned along with the error
+    pub fn add_entry(
+        &mut self,
+        stroke_seq: Vec<Stroke>,
+        translation: &str,
+    ) -> Result<AddedEntry, Box<dyn Error>> {
+        let old_translations = self.translate_prev_strokes();
+        self.dict.add_entry(&stroke_seq, translation)?;
+        let commands = self.retranslate(&old_translations);
+
+        let save_error = self
+            .user_dict
+            .as_ref()
+            .and_then(|path| save_entry(path, &stroke_seq, translation).err());
+        Ok(AddedEntry {
+            commands,
+            save_error,
+        })
+    }
+
+    /// Remove the entry for a sequence of strokes while translating. Only entries added with
+    /// `add_entry` are actually removed, so the loaded entry for the strokes (if any) is used
+    /// again. A loaded entry is instead hidden until the translator is restarted (even if the
+    /// dictionaries are reloaded), so the strokes are unknown or part of shorter entries.
+    ///
+    /// Like `add_entry`, the strokes in the stroke buffer are translated again, and the returned
+    /// commands replace the text that changed. Returns None if there was no entry to remove. The
+    /// user dictionary is not changed
+    pub fn remove_entry(&mut self, stroke_seq: &[Stroke]) -> Option<Vec<Command>> {
+        let old_translations = self.translate_prev_strokes();
+        if !self.dict.remove_entry(stroke_seq) {
+            return None;
+        }
+        Some(self.retranslate(&old_translations))
+    }
+
+    /// Translate the stroke buffer again after the dictionary changed, returning the commands that
+    /// replace the old translations of the strokes
+    fn retranslate(&mut self, old_translations: &[Translation]) -> Vec<Command> {
+        self.window = TranslatedWindow::default();
+        if self.passthrough.is_some() {
+            // the text typed in passthrough mode doesn't come from the stroke buffer
+            return vec![Command::NoOp];
+        }
+
+        let new_translations = self.translate_prev_strokes();
+        translation_diff(old_translations, &new_translations, &self.format)
+    }
+
+    /// The strokes in the stroke buffer, oldest first. Passing them as the starting strokes of a
+    /// new translator lets it undo and correct what this translator typed
+    pub fn dump_strokes(&self) -> Vec<Stroke> {
+        self.prev_strokes.clone()
+    }
+
+    /// Whether strokes are typed as raw steno instead of being translated (toggled by the
+    /// "toggle_passthrough" command)
+    pub fn is_passthrough(&self) -> bool {
+        self.passthrough.is_some()
+    }
+
+    /// Whether spaces are added after words instead of before them (toggled by the
+    /// "toggle_space_after" and "toggle_space_mode" commands)
+    pub fn is_space_after(&self) -> bool {
+        self.format.space_after
+    }
+
+    /// How many times undo can be pressed before the stroke buffer is empty. This counts the
+    /// strokes (or translations if the undo policy is `Translation`) that type text, because undo
+    /// skips over the ones that don't
+    pub fn undo_depth(&self) -> usize {
+        self.translate_buffer(&self.prev_strokes, &self.shell_outputs, 0)
+            .into_iter()
+            .filter(|(_, translations)| translations.iter().cloned().any(is_text))
+            .map(|(len, _)| match self.undo_policy {
+                UndoPolicy::Stroke => len,
+                UndoPolicy::Translation => 1,
+            })
+            .sum()
+    }
+
+    /// How many entries were loaded from each dictionary, in the order the dictionaries were
+    /// given
+    pub fn dict_stats(&self) -> &[DictStats] {
+        self.dict.stats()
+    }
+
+    /// The entries that replaced an entry with the same stroke from an earlier dictionary. The
+    /// dictionaries are given by their index in the order they were given. The later entry is
+    /// still the one that is used
+    pub fn dict_overrides(&self) -> &[DictOverride] {
+        self.dict.overrides()
+    }
+
+    /// Find every entry in the dictionary whose text renders to the given text. Each entry is
+    /// returned as its strokes, with the shortest entries first. Entries that were overridden by
+    /// a later dictionary are not included, and commands are never matched
+    pub fn reverse_lookup(&self, text: &str) -> Vec<Vec<Stroke>> {
+        let mut found: Vec<Vec<Stroke>> = self
+            .dict
+            .entries()
+            .filter(|(_, t)| matches!(t, Translation::Text(_)) && render_translation(t) == text)
+            .map(|(stroke, _)| {
+                stroke
+                    .clone()
+                    .to_raw()
+                    .split('/')
+                    .map(Stroke::new)
+                    .collect()
+            })
+            .collect();
+        found.sort_by_key(|strokes: &Vec<Stroke>| {
+            let raw: Vec<String> = strokes.iter().map(|s| s.clone().to_raw()).collect();
+            (strokes.len(), raw)
+        });
+        found
+    }
+
+    /// Render the text that the strokes would type by themselves, without looking at (or changing)
+    /// the stroke buffer. This is the inverse of `reverse_lookup`
+    ///
+    /// Like the text typed by the translator, the text starts with a space (unless spaces are
+    /// added after words). Commands don't add any text
+    pub fn render_strokes(&self, strokes: &[Stroke]) -> String {
+        render_text(&self.dict.translate(strokes), &self.format)
+    }
+
+    /// Remove exactly one stroke (the last one) so that it can be stroked again. Unlike undo, this
+    /// ignores the undo policy, so only the last stroke of a multi-stroke translation is removed
+    /// (ex: `H-L/WORLD` as "hi" goes back to `H-L` as "hello"). It also doesn't keep removing
+    /// strokes until the output changes, so correcting a stroke without any text (like a command)
+    /// outputs nothing
+    pub fn correct_last_stroke(&mut self) -> Vec<Command> {
+        // correcting in the middle of a macro only cancels the macro
+        if self.macros.cancel() {
+            return vec![Command::NoOp];
         }
 
+        let old_translations = self.translate_prev_strokes();
+        if self.prev_strokes.pop().is_none() {
+            return vec![Command::NoOp];
+        }
+        let new_translations = self.translate_prev_strokes();
+        translation_diff(&old_translations, &new_translations, &self.format)
+    }
+
+    /// Remove the strokes that make up the last translation. Because translations are found
+    /// greedily from the oldest stroke, the strokes are removed once the remaining strokes
+    /// translate to the translations before the last one
+    fn remove_last_translation(&mut self) {
+        let translations = self.translate_prev_strokes();
+        while !self.prev_strokes.is_empty() {
+            self.prev_strokes.pop();
+            let new_translations = self.translate_prev_strokes();
+            if new_translations.len() < translations.len()
+                && translations.starts_with(&new_translations)
+            {
+                break;
+            }
+        }
+    }
+
+    /// Add a stroke to the stroke buffer and translate it
+    fn translate_stroke(&mut self, stroke: Stroke) -> Vec<Command> {
+        let mut prev_strokes = mem::take(&mut self.prev_strokes);
+        let mut shell_outputs = mem::take(&mut self.shell_outputs);
+        let window = mem::take(&mut self.window);
+        let is_retro_add_space = self.retrospective_add_space.contains(&stroke);
+        let (mut commands, mut window, mut moved_cursor) =
+            self.add_stroke(&mut prev_strokes, &mut shell_outputs, window, stroke);
+        // translate the stroke again so that it types what the commands printed
+        if !is_retro_add_space && self.run_shell_inserts(&prev_strokes, &mut shell_outputs, &window)
+        {
+            if let Some(stroke) = prev_strokes.pop() {
+                let translated = self.add_stroke(
+                    &mut prev_strokes,
+                    &mut shell_outputs,
+                    Default::default(),
+                    stroke,
+                );
+                commands = translated.0;
+                window = translated.1;
+                moved_cursor = translated.2;
+            }
+        }
+        self.prev_strokes = prev_strokes;
+        self.shell_outputs = shell_outputs;
+
+        let toggles_space_mode = window.spans.last().is_some_and(|(_, translations)| {
+            translations
+                .iter()
+                .any(|t| t.has_translator_command(TOGGLE_SPACE_MODE))
+        });
+        if toggles_space_mode {
+            if let Some(command) = self.move_last_space(&flatten_spans(&window.spans)) {
+                commands.insert(0, command);
+            }
+        }
+        // corrections are typed where the cursor is, which is no longer at the end of the text
+        // once it is moved back (ex: by a cursor marker), so the text before can't be corrected
+        if moved_cursor {
+            self.prev_strokes.clear();
+            self.shell_outputs = ShellOutputs::default();
+            window = TranslatedWindow::default();
+        }
+        self.window = window;
+        commands
+    }
+
+    /// Run the shell insert commands of the last translation (which was just stroked) and save
+    /// their output to be typed by it. A command that fails types nothing. Returns whether any
+    /// commands were run.
+    ///
+    /// Each command is run every time it is stroked. The output is kept with the translation, so
+    /// the earlier translations of the same command still type what they printed when they are
+    /// translated again (ex: when undoing)
+    fn run_shell_inserts(
+        &self,
+        strokes: &[Stroke],
+        shell_outputs: &mut ShellOutputs,
+        window: &TranslatedWindow,
+    ) -> bool {
+        let start = match window.spans.last() {
+            Some((len, _)) => strokes.len() - len,
+            None => return false,
+        };
+        let shell_inserts = self.dict.shell_inserts(&strokes[start..]);
+        if shell_inserts.is_empty() {
+            return false;
+        }
+        let outputs = shell_inserts
+            .iter()
+            .map(|(cmd, args)| {
+                shell::run_for_output(cmd, args, self.shell_timeout).unwrap_or_default()
+            })
+            .collect();
+        shell_outputs.set(start, strokes[start..].to_vec(), outputs);
+        true
+    }
+
+    /// Type the raw steno of a stroke in passthrough mode without looking it up, unless it is the
+    /// stroke that toggles or stops passthrough
+    fn passthrough_stroke(&mut self, stroke: Stroke) -> Vec<Command> {
+        let translations = self.dict.translate(slice::from_ref(&stroke));
+        for command in &[TOGGLE_PASSTHROUGH, STOP_PASSTHROUGH] {
+            if translations
+                .iter()
+                .any(|t| t.has_translator_command(command))
+            {
+                return vec![Command::TranslatorCommand(command.to_string())];
+            }
+        }
+
+        let text = self.raw_text(&stroke);
+        if let Some(strokes) = &mut self.passthrough {
+            strokes.push(stroke);
+        }
+        vec![Command::Replace(0, text)]
+    }
+
+    /// The text a stroke is typed as in passthrough mode (the raw steno and a space)
+    fn raw_text(&self, stroke: &Stroke) -> String {
+        let raw = stroke.clone().to_raw();
+        if self.format.space_after {
+            raw + &self.format.separator
+        } else {
+            self.format.separator.clone() + &raw
+        }
+    }
+
+    /// Moves the space after the last word to the other side of it when the space mode is
+    /// toggled. The stroke buffer is cleared when the mode is switched, so the next word only has
+    /// to be attached correctly. Returns None if the next word is attached to the last word anyway
+    fn move_last_space(&self, translations: &[Translation]) -> Option<Command> {
+        let format = FormatOptions {
+            space_after: true,
+            capitalize_first_word: false,
+            ..self.format.clone()
+        };
+        let has_space_after = render_text(translations, &format).ends_with(&self.format.separator);
+        if !has_space_after {
+            None
+        } else if self.format.space_after {
+            // the space before the next word is added with it
+            Some(Command::Replace(
+                self.format.separator.chars().count(),
+                String::new(),
+            ))
+        } else {
+            Some(Command::Replace(0, self.format.separator.clone()))
+        }
+    }
+
+    /// Remove every stroke before the last, because that stroke triggered the command that cleared
+    /// them and the last stroke could have text_after text that needs to be preserved
+    fn clear_prev_strokes(&mut self) {
+        self.shell_outputs
+            .remove_front(self.prev_strokes.len().saturating_sub(1));
+        let mut v = Vec::with_capacity(self.max_stroke_buffer);
+        if let Some(last) = self.prev_strokes.pop() {
+            v.push(last);
+        }
+        self.prev_strokes = v;
+    }
+
+    /// Remove the oldest translations until there are at most `max_stroke_buffer` strokes left.
+    /// Whole translations are removed so that the strokes left over from a multi-stroke word don't
+    /// change the text before them when they are undone
+    fn trim_strokes(&self, strokes: &mut Vec<Stroke>, shell_outputs: &mut ShellOutputs) {
+        while strokes.len() > self.max_stroke_buffer {
+            let excess = strokes.len() - self.max_stroke_buffer;
+            // the first translation is never longer than the longest translation that is looked up
+            let front = cmp::min(strokes.len(), MAX_TRANSLATION_STROKE_LEN);
+            let mut remove = 0;
+            for (len, _) in self.dict.translate_spans(&strokes[..front]) {
+                remove += len;
+                if remove >= excess {
+                    break;
+                }
+            }
+            let remove = cmp::max(remove, 1);
+            strokes.drain(..remove);
+            shell_outputs.remove_front(remove);
+        }
+    }
+
+    /// Translate the strokes in the stroke buffer (or a copy of it) from `start`. The shell insert
+    /// commands type what they printed when they were stroked
+    fn translate_buffer(
+        &self,
+        strokes: &[Stroke],
+        shell_outputs: &ShellOutputs,
+        start: usize,
+    ) -> Vec<Span> {
+        shell_outputs.insert_into(self.dict.translate_spans(&strokes[start..]), strokes, start)
+    }
+
+    /// The translations of every stroke in the stroke buffer
+    fn translate_prev_strokes(&self) -> Vec<Translation> {
+        flatten_spans(&self.translate_buffer(&self.prev_strokes, &self.shell_outputs, 0))
+    }
+
+    /// Add a stroke to the strokes and translate it. The translations in the window (from the last
+    /// stroke) are reused if they are still the same. Returns the commands, the window with the
+    /// new translations, and whether the commands moved the cursor back into the text
+    fn add_stroke(
+        &self,
+        prev_strokes: &mut Vec<Stroke>,
+        shell_outputs: &mut ShellOutputs,
+        window: TranslatedWindow,
+        stroke: Stroke,
+    ) -> (Vec<Command>, TranslatedWindow, bool) {
+        self.trim_strokes(prev_strokes, shell_outputs);
+
         // translate only latest strokes
-        let start = if self.prev_strokes.len() > MAX_TRANSLATION_STROKE_LEN {
-            self.prev_strokes.len() - MAX_TRANSLATION_STROKE_LEN
+        let start = if prev_strokes.len() > MAX_TRANSLATION_STROKE_LEN {
+            prev_strokes.len() - MAX_TRANSLATION_STROKE_LEN
         } else {
             0
         };
 
-        let old_translations = self.dict.translate(&self.prev_strokes[start..]);
+        let old_spans = window
+            .reuse(&prev_strokes[start..])
+            .unwrap_or_else(|| self.translate_buffer(prev_strokes, shell_outputs, start));
+        let old_translations = flatten_spans(&old_spans);
 
         // add a space if necessary
-        if self.retrospective_add_space.contains(&stroke) {
-            let mut index = self.prev_strokes.len();
+        let is_retro_add_space = self.retrospective_add_space.contains(&stroke);
+        if is_retro_add_space {
+            let mut index = prev_strokes.len();
             // find the first undoable stroke (from the back)
-            for s in self.prev_strokes.iter().rev() {
+            while index > 0 {
                 index -= 1;
-                let translated = self.dict.translate(&[s.clone()]);
-                if translated.into_iter().any(is_text) {
+                let translated =
+                    self.translate_buffer(&prev_strokes[..=index], shell_outputs, index);
+                if flatten_spans(&translated).into_iter().any(is_text) {
                     break;
                 }
             }
 
             // add a space
             if let Some(space) = self.add_space_insert.clone() {
-                self.prev_strokes.insert(index, space);
+                prev_strokes.insert(index, space);
+                shell_outputs.insert_stroke(index);
             }
         } else {
-            self.prev_strokes.push(stroke);
+            prev_strokes.push(stroke);
         }
 
-        let new_translations = self.dict.translate(&self.prev_strokes[start..]);
+        // usually the new stroke doesn't change the translations before it, so only it has to be
+        // translated. Otherwise (or if a space was inserted) translate all the strokes again
+        let new_strokes = &prev_strokes[start..];
+        let new_spans = if is_retro_add_space {
+            None
+        } else {
+            self.dict
+                .translate_appended(new_strokes, &old_spans)
+                .map(|spans| shell_outputs.insert_into(spans, prev_strokes, start))
+        }
+        .unwrap_or_else(|| self.translate_buffer(prev_strokes, shell_outputs, start));
+        let new_translations = flatten_spans(&new_spans);
+        let window = TranslatedWindow {
+            strokes: new_strokes.to_vec(),
+            spans: new_spans,
+        };
 
-        translation_diff(&old_translations, &new_translations, self.space_after)
+        // only the start of the stroke buffer is the first word
+        let format = if start == 0 || !self.format.capitalize_first_word {
+            Cow::Borrowed(&self.format)
+        } else {
+            Cow::Owned(FormatOptions {
+                capitalize_first_word: false,
+                ..self.format.clone()
+            })
+        };
+        let (commands, moved_cursor) =
+            translation_diff_with_cursor(&old_translations, &new_translations, &format);
+        (commands, window, moved_cursor)
+    }
+
+    /// Translate a stroke without changing the translator (dry run). Returns the text that the
+    /// stroke buffer would type with the stroke added, along with the commands that `translate`
+    /// would return. Applying the commands to the text typed before the stroke results in the
+    /// returned text.
+    ///
+    /// Like the text typed by the translator, the text starts with a space (unless spaces are
+    /// added after words). Translator commands are returned but not handled
+    pub fn preview(&self, stroke: Stroke) -> (String, Vec<Command>) {
+        let mut macros = self.macros.clone();
+        let mut prev_strokes = self.prev_strokes.clone();
+        let mut shell_outputs = self.shell_outputs.clone();
+
+        let (strokes, macro_cmds) = macros.push(stroke);
+        let mut commands = Vec::new();
+        for s in strokes {
+            let (cmds, _, _) = self.add_stroke(
+                &mut prev_strokes,
+                &mut shell_outputs,
+                TranslatedWindow::default(),
+                s,
+            );
+            commands.extend(cmds);
+        }
+        commands.extend(macro_cmds);
+        if commands.is_empty() {
+            // waiting for the rest of a macro
+            commands.push(Command::NoOp);
+        }
+
+        let text = render_text(
+            &flatten_spans(&self.translate_buffer(&prev_strokes, &shell_outputs, 0)),
+            &self.format,
+        );
+        (text, commands)
+    }
+
+    /// Translate a sequence of strokes, returning the commands of all the strokes in order. This
+    /// is the same as calling `translate` with each stroke (ex: to replay a stroke log)
+    pub fn translate_all(&mut self, strokes: Vec<Stroke>) -> Vec<Command> {
+        strokes
+            .into_iter()
+            .flat_map(|stroke| self.translate(stroke))
+            .collect()
+    }
+}
+
+impl Translator for StandardTranslator {
+    fn translate(&mut self, stroke: Stroke) -> Vec<Command> {
+        if self.passthrough.is_some() {
+            return self.passthrough_stroke(stroke);
+        }
+
+        let (strokes, macro_cmds) = self.macros.push(stroke);
+        let mut commands = Vec::new();
+        for s in strokes {
+            commands.extend(self.translate_stroke(s));
+        }
+        commands.extend(macro_cmds);
+
+        if commands.is_empty() {
+            // waiting for the rest of a macro
+            vec![Command::NoOp]
+        } else {
+            commands
+        }
     }
 
     fn undo(&mut self) -> Vec<Command> {
-        let old_translations = self.dict.translate(&self.prev_strokes);
+        // only the raw strokes can be undone in passthrough mode
+        if self.passthrough.is_some() {
+            return match self.passthrough.as_mut().and_then(Vec::pop) {
+                Some(stroke) => {
+                    vec![Command::Replace(
+                        self.raw_text(&stroke).chars().count(),
+                        String::new(),
+                    )]
+                }
+                None => vec![Command::NoOp],
+            };
+        }
+
+        // undoing in the middle of a macro only cancels the macro
+        if self.macros.cancel() {
+            return vec![Command::NoOp];
+        }
 
-        // keep on removing strokes as long as they are the same (when diffed)
+        let old_translations = self.translate_prev_strokes();
+
+        // keep on removing strokes (or translations) as long as they are the same (when diffed)
         while !self.prev_strokes.is_empty() {
-            self.prev_strokes.pop();
-            let new_translations = self.dict.translate(&self.prev_strokes);
-            let diff = translation_diff(&old_translations, &new_translations, self.space_after);
+            match self.undo_policy {
+                UndoPolicy::Stroke => {
+                    self.prev_strokes.pop();
+                }
+                UndoPolicy::Translation => self.remove_last_translation(),
+            }
+            let new_translations = self.translate_prev_strokes();
+            let diff = translation_diff(&old_translations, &new_translations, &self.format);
             if diff != vec![Command::NoOp] {
                 return diff;
             }
@@ -223,20 +1086,69 @@ impl Translator for StandardTranslator {
     /// Valid commands are:
     /// - "clear_prev_strokes": Clears the stroke buffer
     /// - "toggle_space_after": Toggles between space after and space before
+    /// - "toggle_space_mode": Also toggles between space after and space before, but moves the
+    ///   space after the last word to the right side and clears the stroke buffer so that the
+    ///   next word is spaced correctly
+    /// - "reload_dicts": Reads the dictionary files again without clearing the stroke buffer
+    /// - "toggle_title_case": Toggles capitalizing every word after it. This is applied when the
+    ///   text is parsed so there is nothing to do here
+    /// - "cap_prev_word:N": Capitalizes the previous N space separated words (N defaults to 1).
+    ///   This is also applied when the text is parsed
+    /// - "delete_prev_word": Deletes the previous space separated word from the text (without
+    ///   undoing its strokes). This is also applied when the text is parsed
+    /// - "split_prev_word": Puts a space between the last word and the text it is attached to
+    ///   (ex: a suffix), without changing the strokes like retrospective add space does. This is
+    ///   also applied when the text is parsed, so undo removes the space
+    /// - "toggle_number_words": Toggles spelling out number strokes as words (ex: `3` as `three`).
+    ///   This is also applied when the text is parsed
+    /// - "type_last_raw_stroke": Types the raw steno of the stroke before it (ex: `H-L`). This is
+    ///   applied when the strokes are translated, so it can be undone like any other text
+    /// - "repeat_last": Types the text of the last translation again (ex: a multi-stroke word).
+    ///   This is also applied when the strokes are translated, so it is undone in one step
+    /// - "toggle_passthrough": Toggles typing each stroke as raw steno instead of translating it.
+    ///   The stroke buffer is cleared when it is turned on, and undo only removes the raw strokes
+    ///   until it is turned off
+    /// - "start_passthrough" and "stop_passthrough": Turn passthrough on or off (ex: from Plover's
+    ///   `{PLOVER:SUSPEND}` and `{PLOVER:RESUME}`). Nothing happens if it is already on or off
     fn handle_command(&mut self, command: String) {
         match command.as_ref() {
-            "clear_prev_strokes" => {
-                // remove every stroke before the last, because that stroke triggered this command
-                // and the last stroke could have text_after text that needs to be preserved
-                let mut v = Vec::with_capacity(MAX_STROKE_BUFFER);
-                if let Some(last) = self.prev_strokes.pop() {
-                    v.push(last);
-                }
-                self.prev_strokes = v;
-            }
+            "clear_prev_strokes" => self.clear_prev_strokes(),
             "toggle_space_after" => {
-                self.space_after = !self.space_after;
+                self.format.space_after = !self.format.space_after;
+            }
+            TOGGLE_SPACE_MODE => {
+                // the text before was typed in the other mode, so it can't be diffed in this mode
+                self.format.space_after = !self.format.space_after;
+                self.clear_prev_strokes();
             }
+            "reload_dicts" => match self.reload_dicts() {
+                Ok(()) => println!("[INFO] Reloaded dictionaries"),
+                Err(e) => eprintln!("[ERR] Could not reload dictionaries: {}", e),
+            },
+            TOGGLE_TITLE_CASE => {}
+            TOGGLE_NUMBER_WORDS => {}
+            c if parse_cap_prev_word(c).is_some() => {}
+            DELETE_PREV_WORD => {}
+            SPLIT_PREV_WORD => {}
+            TYPE_LAST_RAW_STROKE => {}
+            REPEAT_LAST => {}
+            TOGGLE_PASSTHROUGH => {
+                self.passthrough = match self.passthrough {
+                    Some(_) => None,
+                    None => {
+                        // the raw strokes are typed after the text from the stroke buffer
+                        self.clear_prev_strokes();
+                        Some(vec![])
+                    }
+                };
+            }
+            START_PASSTHROUGH => {
+                if self.passthrough.is_none() {
+                    self.clear_prev_strokes();
+                    self.passthrough = Some(vec![]);
+                }
+            }
+            STOP_PASSTHROUGH => self.passthrough = None,
             _c => eprintln!("[WARN]: the standard translator cannot handle {:?}", _c),
         }
     }
@@ -293,4 +1205,45 @@ mod tests {
             false
         );
     }
+
+    #[test]
+    fn test_parse_cap_prev_word() {
+        assert_eq!(parse_cap_prev_word("cap_prev_word"), Some(1));
+        assert_eq!(parse_cap_prev_word("cap_prev_word:3"), Some(3));
+        assert_eq!(parse_cap_prev_word("cap_prev_word:"), None);
+        assert_eq!(parse_cap_prev_word("cap_prev_word:three"), None);
+        assert_eq!(parse_cap_prev_word("cap_prev_words"), None);
+        assert_eq!(parse_cap_prev_word("toggle_title_case"), None);
+    }
+
+    #[test]
+    fn test_window_reuse() {
+        fn span(len: usize, text: &str) -> Span {
+            (
+                len,
+                vec![Translation::Text(vec![Text::Lit(text.to_owned())])],
+            )
+        }
+        let window = || TranslatedWindow {
+            strokes: vec![Stroke::new("H-L"), Stroke::new("A"), Stroke::new("WORLD")],
+            spans: vec![span(2, "hello"), span(1, "world")],
+        };
+
+        assert_eq!(
+            window().reuse(&[Stroke::new("H-L"), Stroke::new("A"), Stroke::new("WORLD")]),
+            Some(vec![span(2, "hello"), span(1, "world")])
+        );
+        assert_eq!(
+            window().reuse(&[Stroke::new("WORLD")]),
+            Some(vec![span(1, "world")])
+        );
+        assert_eq!(window().reuse(&[]), Some(vec![]));
+        // starts in the middle of a translation
+        assert_eq!(
+            window().reuse(&[Stroke::new("A"), Stroke::new("WORLD")]),
+            None
+        );
+        // different strokes
+        assert_eq!(window().reuse(&[Stroke::new("H-L")]), None);
+    }
 }
@@ -0,0 +1,126 @@
+//! Recognizes sequences of strokes that dispatch commands as a unit (command macros)
+use plojo_core::{Command, Stroke};
+use std::mem;
+
+/// Matches strokes against the macro sequences as they are pressed. The strokes that could be the
+/// start of a macro are held back until the macro either fires or can no longer match, in which
+/// case they are translated normally
+#[derive(Debug, Clone, PartialEq, Default)]
+pub(crate) struct MacroMatcher {
+    macros: Vec<(Vec<Stroke>, Vec<Command>)>,
+    pending: Vec<Stroke>,
+}
+
+impl MacroMatcher {
+    pub(crate) fn new(macros: Vec<(Vec<Stroke>, Vec<Command>)>) -> Self {
+        Self {
+            macros,
+            pending: vec![],
+        }
+    }
+
+    /// Match the next stroke. Returns the strokes that are not part of a macro (which should be
+    /// translated normally) and the commands of the macro that fired (if any)
+    pub(crate) fn push(&mut self, stroke: Stroke) -> (Vec<Stroke>, Vec<Command>) {
+        if self.macros.is_empty() {
+            return (vec![stroke], vec![]);
+        }
+
+        self.pending.push(stroke);
+        if let Some(cmds) = self.find_macro() {
+            self.pending.clear();
+            return (vec![], cmds);
+        }
+        if self.is_prefix() {
+            return (vec![], vec![]);
+        }
+        if self.pending.len() == 1 {
+            return (mem::take(&mut self.pending), vec![]);
+        }
+
+        // the sequence was broken, but the last stroke could start another macro
+        let stroke = self.pending.pop().unwrap();
+        let mut unmatched = mem::take(&mut self.pending);
+        let (rest, cmds) = self.push(stroke);
+        unmatched.extend(rest);
+        (unmatched, cmds)
+    }
+
+    /// Drop the held back strokes. Returns whether there were any
+    pub(crate) fn cancel(&mut self) -> bool {
+        let had_pending = !self.pending.is_empty();
+        self.pending.clear();
+        had_pending
+    }
+
+    fn find_macro(&self) -> Option<Vec<Command>> {
+        self.macros
+            .iter()
+            .find(|(strokes, _)| *strokes == self.pending)
+            .map(|(_, cmds)| cmds.clone())
+    }
+
+    fn is_prefix(&self) -> bool {
+        self.macros
+            .iter()
+            .any(|(strokes, _)| strokes.starts_with(&self.pending))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matcher() -> MacroMatcher {
+        MacroMatcher::new(vec![
+            (
+                vec![Stroke::new("STPH-G"), Stroke::new("STPH-P")],
+                vec![Command::PrintHello],
+            ),
+            (vec![Stroke::new("STPH-T")], vec![Command::NoOp]),
+        ])
+    }
+
+    #[test]
+    fn fires_macro() {
+        let mut m = matcher();
+        assert_eq!(m.push(Stroke::new("STPH-G")), (vec![], vec![]));
+        assert_eq!(
+            m.push(Stroke::new("STPH-P")),
+            (vec![], vec![Command::PrintHello])
+        );
+        assert_eq!(
+            m.push(Stroke::new("H-L")),
+            (vec![Stroke::new("H-L")], vec![])
+        );
+    }
+
+    #[test]
+    fn broken_sequence() {
+        let mut m = matcher();
+        assert_eq!(m.push(Stroke::new("STPH-G")), (vec![], vec![]));
+        assert_eq!(
+            m.push(Stroke::new("H-L")),
+            (vec![Stroke::new("STPH-G"), Stroke::new("H-L")], vec![])
+        );
+
+        // the stroke that broke the sequence starts another one
+        assert_eq!(m.push(Stroke::new("STPH-G")), (vec![], vec![]));
+        assert_eq!(
+            m.push(Stroke::new("STPH-T")),
+            (vec![Stroke::new("STPH-G")], vec![Command::NoOp])
+        );
+    }
+
+    #[test]
+    fn cancel_pending() {
+        let mut m = matcher();
+        assert!(!m.cancel());
+        m.push(Stroke::new("STPH-G"));
+        assert!(m.cancel());
+        assert_eq!(
+            m.push(Stroke::new("STPH-P")),
+            (vec![Stroke::new("STPH-P")], vec![])
+        );
+    }
+}
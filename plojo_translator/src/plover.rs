@@ -0,0 +1,337 @@
+//! Converts between plover key combos (the `{#...}` keyboard shortcuts in plover dictionaries)
+//! and commands
+use plojo_core::{Command, Key, Modifier, SpecialKey};
+use regex::Regex;
+
+/// Why a key combo could not be converted
+#[derive(Debug, PartialEq)]
+pub enum KeyComboError {
+    // if the translation is not a single keyboard shortcut
+    InvalidFormat,
+    InvalidKeyboardShortcut,
+    UnbalancedParens,
+    UnknownModifier(String),
+    UnknownKey(String),
+}
+
+/// Convert a key and its modifiers into a plover key combo (ex: `shift_l(alt_l(tab))`)
+pub fn to_plover_key_combo(key: &Key, modifiers: &[Modifier]) -> Result<String, KeyComboError> {
+    let mut combo = to_plover_key(key)?;
+    for m in modifiers.iter().rev() {
+        combo = format!("{}({})", to_plover_modifier(*m)?, combo);
+    }
+    Ok(combo)
+}
+
+/// Converts a plojo modifier into a plover modifier
+fn to_plover_modifier(m: Modifier) -> Result<&'static str, KeyComboError> {
+    match m {
+        Modifier::Shift => Ok("shift_l"),
+        Modifier::Control => Ok("control_l"),
+        Modifier::Alt => Ok("alt_l"),
+        Modifier::Option => Ok("option"),
+        Modifier::Meta => Ok("super_l"),
+        Modifier::Fn => Err(KeyComboError::UnknownModifier(format!("{:?}", m))),
+    }
+}
+
+/// Converts a plojo key into a plover key
+fn to_plover_key(k: &Key) -> Result<String, KeyComboError> {
+    let name = match k {
+        Key::Layout(c) if c.is_ascii_lowercase() || c.is_ascii_digit() => return Ok(c.to_string()),
+        Key::Layout(c) => PLOVER_SYMBOLS
+            .iter()
+            .find(|(_, symbol)| symbol == c)
+            .map(|(name, _)| *name)
+            .ok_or_else(|| KeyComboError::UnknownKey(c.to_string()))?,
+        Key::Special(special_key) => match special_key {
+            SpecialKey::Backspace => "backspace",
+            SpecialKey::CapsLock => "caps_lock",
+            SpecialKey::Delete => "delete",
+            SpecialKey::End => "end",
+            SpecialKey::Escape => "escape",
+            SpecialKey::Home => "home",
+            SpecialKey::PageDown => "page_down",
+            SpecialKey::PageUp => "page_up",
+            SpecialKey::Return => "return",
+            SpecialKey::Space => "space",
+            SpecialKey::Tab => "tab",
+            SpecialKey::DownArrow => "down",
+            SpecialKey::LeftArrow => "left",
+            SpecialKey::RightArrow => "right",
+            SpecialKey::UpArrow => "up",
+            SpecialKey::F1 => "f1",
+            SpecialKey::F2 => "f2",
+            SpecialKey::F3 => "f3",
+            SpecialKey::F4 => "f4",
+            SpecialKey::F5 => "f5",
+            SpecialKey::F6 => "f6",
+            SpecialKey::F7 => "f7",
+            SpecialKey::F8 => "f8",
+            SpecialKey::F9 => "f9",
+            SpecialKey::F10 => "f10",
+            SpecialKey::F11 => "f11",
+            SpecialKey::F12 => "f12",
+        },
+    };
+    Ok(name.to_string())
+}
+
+/// Parses a single plover keyboard shortcut string into a plojo recognizable command
+///
+/// See plover documentation for details
+/// https://github.com/openstenoproject/plover/wiki/Dictionary-Format#keyboard-shortcuts
+///
+/// This only accepts a single key + modifiers. Multiple keys do not work (there should not be
+/// spaces)
+pub fn parse_key_combo(s: &str) -> Result<Command, KeyComboError> {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(r#"^((?:[a-z_]+\()*)([a-z0-9_]+)(\)*)$"#).unwrap();
+    }
+
+    let s = s.to_lowercase();
+
+    if let Some(c) = RE.captures(&s) {
+        let num_modifiers = c[3].len();
+        let mut modifiers_str: Vec<&str> = c[1].split('(').collect();
+        // remove last item created by trailing '(' from the regex
+        assert_eq!(modifiers_str.pop().unwrap(), "");
+
+        if modifiers_str.len() != num_modifiers {
+            return Err(KeyComboError::UnbalancedParens);
+        }
+
+        let mut modifiers = Vec::with_capacity(modifiers_str.len());
+        for m in modifiers_str {
+            modifiers.push(parse_plover_modifier(m)?);
+        }
+
+        let key = parse_plover_key(&c[2])?;
+
+        Ok(Command::Keys(key, modifiers))
+    } else {
+        Err(KeyComboError::InvalidKeyboardShortcut)
+    }
+}
+
+/// A translation made up of only a keyboard shortcut
+#[derive(Debug, PartialEq)]
+pub struct KeyboardShortcut {
+    pub cmd: Command,
+    /// The `{^}` or `{^}{-|}` after the shortcut, if any
+    pub text_after: Option<String>,
+    /// If there is a `{^}` before the shortcut
+    pub suppress_space_before: bool,
+}
+
+/// Parse a translation that is a basic keyboard shortcut.
+///
+/// This is the basic format: `{^}{#Shift_L(Alt_L(a))}{^}{-|}`
+/// Where the `{^}` in the beginning is optional and the ending `{^}` and `{-|}` are optional
+///
+/// The keyboard shortcut in the middle follows the pattern `{#..}`. There must be only one
+/// shortcut key (no spaces).
+pub fn parse_keyboard_shortcut(s: &str) -> Result<KeyboardShortcut, KeyComboError> {
+    lazy_static! {
+        static ref RE: Regex =
+            Regex::new(r#"^((?:\{\^\})?)\{#([^\} ]+)\}((?:\{\^\}(?:\{-\|\})?)?)$"#).unwrap();
+    }
+
+    let c = RE.captures(s).ok_or(KeyComboError::InvalidFormat)?;
+    let cmd = parse_key_combo(&c[2])?;
+    let text_after = if c[3].is_empty() {
+        None
+    } else {
+        Some(c[3].to_owned())
+    };
+    let suppress_space_before = !c[1].is_empty();
+
+    Ok(KeyboardShortcut {
+        cmd,
+        text_after,
+        suppress_space_before,
+    })
+}
+
+/// Parses a lowercased plover modifier into a plojo modifier (parsable into a command)
+fn parse_plover_modifier(m: &str) -> Result<Modifier, KeyComboError> {
+    match m {
+        "shift_l" | "shift_r" | "shift" => Ok(Modifier::Shift),
+        "control_l" | "control_r" | "control" => Ok(Modifier::Control),
+        "alt_l" | "alt_r" | "alt" => Ok(Modifier::Alt),
+        "option" => Ok(Modifier::Option),
+        "super_l" | "super_r" | "super" | "windows" | "command" => Ok(Modifier::Meta),
+        _m => Err(KeyComboError::UnknownModifier(_m.to_owned())),
+    }
+}
+
+// names of symbol keys in plover and the char they type (copied from plover/key_combo.py)
+const PLOVER_SYMBOLS: [(&str, char); 102] = [
+    ("aacute", 'á'),
+    ("acircumflex", 'â'),
+    ("acute", '´'),
+    ("adiaeresis", 'ä'),
+    ("ae", 'æ'),
+    ("agrave", 'à'),
+    ("ampersand", '&'),
+    ("apostrophe", '\''),
+    ("aring", 'å'),
+    ("asciicircum", '^'),
+    ("asciitilde", '~'),
+    ("asterisk", '*'),
+    ("at", '@'),
+    ("atilde", 'ã'),
+    ("backslash", '\\'),
+    ("bar", '|'),
+    ("braceleft", '{'),
+    ("braceright", '}'),
+    ("bracketleft", '['),
+    ("bracketright", ']'),
+    ("brokenbar", '¦'),
+    ("ccedilla", 'ç'),
+    ("cedilla", '¸'),
+    ("cent", '¢'),
+    ("clear", '\u{000b}'),
+    ("colon", ':'),
+    ("comma", ','),
+    ("copyright", '©'),
+    ("currency", '¤'),
+    ("degree", '°'),
+    ("diaeresis", '¨'),
+    ("division", '÷'),
+    ("dollar", '$'),
+    ("eacute", 'é'),
+    ("ecircumflex", 'ê'),
+    ("ediaeresis", 'ë'),
+    ("egrave", 'è'),
+    ("equal", '='),
+    ("eth", 'ð'),
+    ("exclam", '!'),
+    ("exclamdown", '¡'),
+    ("grave", '`'),
+    ("greater", '>'),
+    ("guillemotleft", '«'),
+    ("guillemotright", '»'),
+    ("hyphen", '\u{AD}'),
+    ("iacute", 'í'),
+    ("icircumflex", 'î'),
+    ("idiaeresis", 'ï'),
+    ("igrave", 'ì'),
+    ("less", '<'),
+    ("macron", '¯'),
+    ("masculine", 'º'),
+    ("minus", '-'),
+    ("mu", 'µ'),
+    ("multiply", '×'),
+    ("nobreakspace", '\u{00a0}'),
+    ("notsign", '¬'),
+    ("ntilde", 'ñ'),
+    ("numbersign", '#'),
+    ("oacute", 'ó'),
+    ("ocircumflex", 'ô'),
+    ("odiaeresis", 'ö'),
+    ("ograve", 'ò'),
+    ("onehalf", '½'),
+    ("onequarter", '¼'),
+    ("onesuperior", '¹'),
+    ("ooblique", 'Ø'),
+    ("ordfeminine", 'ª'),
+    ("oslash", 'ø'),
+    ("otilde", 'õ'),
+    ("paragraph", '¶'),
+    ("parenleft", '('),
+    ("parenright", ')'),
+    ("percent", '%'),
+    ("period", '.'),
+    ("periodcentered", '·'),
+    ("plus", '+'),
+    ("plusminus", '±'),
+    ("question", '?'),
+    ("questiondown", '¿'),
+    ("quotedbl", '"'),
+    ("quoteleft", '`'),
+    ("quoteright", '\''),
+    ("registered", '®'),
+    ("section", '§'),
+    ("semicolon", ';'),
+    ("slash", '/'),
+    ("ssharp", 'ß'),
+    ("sterling", '£'),
+    ("thorn", 'þ'),
+    ("threequarters", '¾'),
+    ("threesuperior", '³'),
+    ("twosuperior", '²'),
+    ("uacute", 'ú'),
+    ("ucircumflex", 'û'),
+    ("udiaeresis", 'ü'),
+    ("ugrave", 'ù'),
+    ("underscore", '_'),
+    ("yacute", 'ý'),
+    ("ydiaeresis", 'ÿ'),
+    ("yen", '¥'),
+];
+
+/// Parses a lowercased plover key into a plojo key (parsable into a command)
+fn parse_plover_key(k: &str) -> Result<Key, KeyComboError> {
+    match k {
+        "a" | "b" | "c" | "d" | "e" | "f" | "g" | "h" | "i" | "j" | "k" | "l" | "m" | "n" | "o"
+        | "p" | "q" | "r" | "s" | "t" | "u" | "v" | "w" | "x" | "y" | "z" | "0" | "1" | "2"
+        | "3" | "4" | "5" | "6" | "7" | "8" | "9" => Ok(Key::Layout(k.chars().next().unwrap())),
+        "backspace" => Ok(Key::Special(SpecialKey::Backspace)),
+        "caps_lock" => Ok(Key::Special(SpecialKey::CapsLock)),
+        "delete" => Ok(Key::Special(SpecialKey::Delete)),
+        "end" => Ok(Key::Special(SpecialKey::End)),
+        "escape" => Ok(Key::Special(SpecialKey::Escape)),
+        "home" => Ok(Key::Special(SpecialKey::Home)),
+        "page_down" => Ok(Key::Special(SpecialKey::PageDown)),
+        "page_up" => Ok(Key::Special(SpecialKey::PageUp)),
+        "return" => Ok(Key::Special(SpecialKey::Return)),
+        "space" => Ok(Key::Special(SpecialKey::Space)),
+        "tab" => Ok(Key::Special(SpecialKey::Tab)),
+        "down" => Ok(Key::Special(SpecialKey::DownArrow)),
+        "left" => Ok(Key::Special(SpecialKey::LeftArrow)),
+        "right" => Ok(Key::Special(SpecialKey::RightArrow)),
+        "up" => Ok(Key::Special(SpecialKey::UpArrow)),
+        "f1" => Ok(Key::Special(SpecialKey::F1)),
+        "f2" => Ok(Key::Special(SpecialKey::F2)),
+        "f3" => Ok(Key::Special(SpecialKey::F3)),
+        "f4" => Ok(Key::Special(SpecialKey::F4)),
+        "f5" => Ok(Key::Special(SpecialKey::F5)),
+        "f6" => Ok(Key::Special(SpecialKey::F6)),
+        "f7" => Ok(Key::Special(SpecialKey::F7)),
+        "f8" => Ok(Key::Special(SpecialKey::F8)),
+        "f9" => Ok(Key::Special(SpecialKey::F9)),
+        "f10" => Ok(Key::Special(SpecialKey::F10)),
+        "f11" => Ok(Key::Special(SpecialKey::F11)),
+        "f12" => Ok(Key::Special(SpecialKey::F12)),
+        _k => PLOVER_SYMBOLS
+            .iter()
+            .find(|(name, _)| *name == _k)
+            .map(|(_, c)| Key::Layout(*c))
+            .ok_or_else(|| KeyComboError::UnknownKey(_k.to_owned())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_key_combo() {
+        assert_eq!(
+            parse_key_combo("Control_L(Alt_L(Super_L(Left)))").unwrap(),
+            Command::Keys(
+                Key::Special(SpecialKey::LeftArrow),
+                vec![Modifier::Control, Modifier::Alt, Modifier::Meta]
+            )
+        );
+        assert_eq!(
+            parse_key_combo("option(a)").unwrap(),
+            Command::Keys(Key::Layout('a'), vec![Modifier::Option])
+        );
+        assert_eq!(
+            parse_key_combo("bAcKsPacE").unwrap(),
+            Command::Keys(Key::Special(SpecialKey::Backspace), vec![])
+        );
+    }
+}
@@ -0,0 +1,313 @@
+//! Runs the shell commands whose output is typed as text
+use crate::{Span, Text, Translation};
+use plojo_core::{Command as PlojoCommand, Stroke};
+use std::{
+    collections::BTreeMap,
+    io::Read,
+    process::{Command, Stdio},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+// how often to check if the command has exited
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+/// Runs a shell command and returns what it printed to stdout without the trailing new lines (like
+/// `$(...)` in a shell). The command is killed if it doesn't exit before the timeout.
+///
+/// Returns None if the command couldn't be run, exited with an error, or timed out. The reason (and
+/// what the command printed to stderr) is logged
+pub(super) fn run_for_output(cmd: &str, args: &[String], timeout: Duration) -> Option<String> {
+    let mut child = match Command::new(cmd)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(e) => {
+            eprintln!("[ERR] Could not run shell command {:?}: {}", cmd, e);
+            return None;
+        }
+    };
+
+    // the pipes are read while the command runs, otherwise it would block once it fills them
+    let stdout = read_pipe(child.stdout.take());
+    let stderr = read_pipe(child.stderr.take());
+
+    let start = Instant::now();
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if start.elapsed() < timeout => thread::sleep(POLL_INTERVAL),
+            Ok(None) => {
+                // the command may have already exited, so the error can be ignored
+                let _ = child.kill();
+                let _ = child.wait();
+                eprintln!(
+                    "[ERR] Shell command {:?} with args {:?} timed out after {:?}",
+                    cmd, args, timeout
+                );
+                return None;
+            }
+            Err(e) => {
+                eprintln!("[ERR] Could not wait for shell command {:?}: {}", cmd, e);
+                return None;
+            }
+        }
+    };
+
+    let stdout = stdout.join().unwrap_or_default();
+    if !status.success() {
+        let stderr = stderr.join().unwrap_or_default();
+        eprintln!(
+            "[ERR] Shell command {:?} with args {:?} exited with {}. stderr: {:?}",
+            cmd, args, status, stderr
+        );
+        return None;
+    }
+
+    Some(stdout.trim_end_matches(&['\n', '\r'][..]).to_string())
+}
+
+/// Read everything from the pipe of a command on another thread, until the command closes it
+fn read_pipe(pipe: Option<impl Read + Send + 'static>) -> JoinHandle<String> {
+    thread::spawn(move || {
+        let mut bytes = vec![];
+        if let Some(mut pipe) = pipe {
+            // whatever was read before an error is still used
+            let _ = pipe.read_to_end(&mut bytes);
+        }
+        String::from_utf8_lossy(&bytes).into_owned()
+    })
+}
+
+/// What the shell insert commands of the translations in the stroke buffer printed when they were
+/// stroked, by the index of the first stroke of each translation. The strokes of the translation
+/// are kept so that the output is only typed by the translation that printed it
+#[derive(Debug, PartialEq, Default, Clone)]
+pub(super) struct ShellOutputs(BTreeMap<usize, (Vec<Stroke>, Vec<String>)>);
+
+impl ShellOutputs {
+    /// Save the output of a translation that was just stroked. It is the last translation, so the
+    /// outputs after it are from strokes that were removed
+    pub(super) fn set(&mut self, start: usize, strokes: Vec<Stroke>, outputs: Vec<String>) {
+        self.0.split_off(&start);
+        self.0.insert(start, (strokes, outputs));
+    }
+
+    /// Move the outputs back when strokes are removed from the front of the stroke buffer
+    pub(super) fn remove_front(&mut self, count: usize) {
+        let kept = self.0.split_off(&count);
+        self.0 = kept
+            .into_iter()
+            .map(|(i, output)| (i - count, output))
+            .collect();
+    }
+
+    /// Move the outputs after a stroke that was inserted into the stroke buffer
+    pub(super) fn insert_stroke(&mut self, index: usize) {
+        let moved = self.0.split_off(&index);
+        self.0
+            .extend(moved.into_iter().map(|(i, output)| (i + 1, output)));
+    }
+
+    /// Type what the shell insert commands printed instead of running them, for the spans that
+    /// translate `strokes[start..]`
+    pub(super) fn insert_into(
+        &self,
+        spans: Vec<Span>,
+        strokes: &[Stroke],
+        start: usize,
+    ) -> Vec<Span> {
+        let mut index = start;
+        spans
+            .into_iter()
+            .map(|(len, translations)| {
+                let outputs = match self.0.get(&index) {
+                    Some((s, outputs)) if strokes.get(index..index + len) == Some(&s[..]) => {
+                        &outputs[..]
+                    }
+                    _ => &[],
+                };
+                index += len;
+                let mut outputs = outputs.iter();
+                let translations = translations
+                    .into_iter()
+                    .map(|t| insert_output(t, &mut outputs))
+                    .collect();
+                (len, translations)
+            })
+            .collect()
+    }
+}
+
+/// Whether the translation has a command whose output is typed
+pub(crate) fn has_shell_insert(translation: &Translation) -> bool {
+    match translation {
+        Translation::Command { cmds, .. } => cmds
+            .iter()
+            .any(|c| matches!(c, PlojoCommand::ShellInsert(..))),
+        Translation::Text(_) => false,
+    }
+}
+
+/// A shell insert command types what the command printed when it was stroked as text, so it is
+/// undone like any other text. The command itself does nothing, so it is not dispatched. If the
+/// command wasn't run (or failed), nothing is typed
+fn insert_output<'a>(
+    translation: Translation,
+    outputs: &mut impl Iterator<Item = &'a String>,
+) -> Translation {
+    match translation {
+        Translation::Command {
+            cmds,
+            text_after,
+            suppress_space_before,
+        } if cmds
+            .iter()
+            .any(|c| matches!(c, PlojoCommand::ShellInsert(..))) =>
+        {
+            let mut texts = vec![];
+            let cmds = cmds
+                .into_iter()
+                .map(|cmd| match cmd {
+                    PlojoCommand::ShellInsert(..) => {
+                        if let Some(output) = outputs.next() {
+                            if !output.is_empty() {
+                                texts.push(Text::Lit(output.clone()));
+                            }
+                        }
+                        PlojoCommand::NoOp
+                    }
+                    cmd => cmd,
+                })
+                .collect();
+            texts.extend(text_after.unwrap_or_default());
+            Translation::Command {
+                cmds,
+                text_after: Some(texts),
+                suppress_space_before,
+            }
+        }
+        translation => translation,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    #[test]
+    fn captures_stdout() {
+        assert_eq!(
+            run_for_output("echo", &["hello world".to_string()], TIMEOUT),
+            Some("hello world".to_string())
+        );
+        // only the new lines at the end are removed
+        assert_eq!(
+            run_for_output("printf", &["a\\nb\\n\\n".to_string()], TIMEOUT),
+            Some("a\nb".to_string())
+        );
+        assert_eq!(run_for_output("true", &[], TIMEOUT), Some(String::new()));
+    }
+
+    fn shell_insert(output: &str) -> Span {
+        let translation = Translation::Command {
+            cmds: vec![PlojoCommand::ShellInsert(output.to_string(), vec![])],
+            text_after: None,
+            suppress_space_before: false,
+        };
+        (1, vec![translation])
+    }
+
+    fn typed(output: &str) -> Span {
+        let translation = Translation::Command {
+            cmds: vec![PlojoCommand::NoOp],
+            text_after: Some(if output.is_empty() {
+                vec![]
+            } else {
+                vec![Text::Lit(output.to_string())]
+            }),
+            suppress_space_before: false,
+        };
+        (1, vec![translation])
+    }
+
+    #[test]
+    fn outputs_move_with_strokes() {
+        let strokes = vec![
+            Stroke::new("SKWR*"),
+            Stroke::new("H-L"),
+            Stroke::new("SKWR*"),
+        ];
+        let mut outputs = ShellOutputs::default();
+        outputs.set(0, vec![strokes[0].clone()], vec!["one".to_string()]);
+        outputs.set(2, vec![strokes[2].clone()], vec!["two".to_string()]);
+        let spans = vec![shell_insert("a"), shell_insert("b")];
+        assert_eq!(
+            outputs.insert_into(spans.clone(), &strokes, 1),
+            vec![typed(""), typed("two")]
+        );
+
+        // the strokes before the first output are removed
+        outputs.remove_front(1);
+        assert_eq!(
+            outputs.insert_into(spans.clone(), &strokes[1..], 0),
+            vec![typed(""), typed("two")]
+        );
+
+        // an output is only typed by the same strokes
+        let mut strokes = strokes[1..].to_vec();
+        strokes.insert(0, Stroke::new("S"));
+        assert_eq!(
+            outputs.insert_into(spans.clone(), &strokes, 1),
+            vec![typed(""), typed("")]
+        );
+        outputs.insert_stroke(0);
+        assert_eq!(
+            outputs.insert_into(spans, &strokes, 1),
+            vec![typed(""), typed("two")]
+        );
+    }
+
+    #[test]
+    fn large_output() {
+        let output = run_for_output(
+            "sh",
+            &[
+                "-c".to_string(),
+                "head -c 200000 /dev/zero | tr '\\0' a".to_string(),
+            ],
+            TIMEOUT,
+        );
+        assert_eq!(output, Some("a".repeat(200_000)));
+    }
+
+    #[test]
+    fn failed_command() {
+        assert_eq!(run_for_output("false", &[], TIMEOUT), None);
+        assert_eq!(run_for_output("not a real command", &[], TIMEOUT), None);
+        assert_eq!(
+            run_for_output(
+                "sh",
+                &["-c".to_string(), "echo output; exit 3".to_string()],
+                TIMEOUT
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn timed_out_command() {
+        let start = Instant::now();
+        assert_eq!(
+            run_for_output("sleep", &["5".to_string()], Duration::from_millis(100)),
+            None
+        );
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+}
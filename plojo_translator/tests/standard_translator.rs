@@ -1,5 +1,6 @@
-use plojo_core::{Command, Key, Modifier, SpecialKey, Stroke, Translator};
-use plojo_translator::StandardTranslator;
+use plojo_core::{apply_command, Command, Key, Modifier, SpecialKey, Stroke, Translator};
+use plojo_translator::{StandardTranslator, UndoPolicy};
+use std::{collections::HashMap, fs, path::Path};
 
 /// Blackbox assert macro for better line number tracing
 /// Expect that pressing stroke(s) causes a certain output
@@ -25,11 +26,26 @@ macro_rules! b_expect_keys {
     };
 }
 
+/// Blackbox assert macro for all the commands for better line number tracing
+/// Expect that pressing stroke(s) dispatches exactly these commands in order
+/// Similar to b_expect
+/// Only the commands from the stroke(s) just pressed are matched
+macro_rules! b_expect_commands {
+    ($blackbox:expr, $strokes:expr, $expected:expr) => {
+        $blackbox.lookup_and_dispatch($strokes);
+        assert_eq!($blackbox.last_commands, $expected);
+    };
+}
+
 /// Black box for testing the entire translator
 struct Blackbox {
     output: String,
     translator: StandardTranslator,
     output_keys: Vec<(Key, Vec<Modifier>)>,
+    // commands dispatched by the last call to lookup_and_dispatch
+    last_commands: Vec<Command>,
+    // stroke that corrects the last stroke instead of being translated
+    correction_stroke: Option<Stroke>,
 }
 
 impl Blackbox {
@@ -61,6 +77,26 @@ impl Blackbox {
         Self::new_internal(json_str, false, true)
     }
 
+    /// Creates a black box where `KRO*` corrects the last stroke
+    fn new_with_correction_stroke(raw_dict: &str, undo_policy: UndoPolicy) -> Self {
+        let mut blackbox = Self::new(raw_dict).map_translator(|t| t.with_undo_policy(undo_policy));
+        blackbox.correction_stroke = Some(Stroke::new("KRO*"));
+        blackbox
+    }
+
+    /// Creates a black box that can reload its dictionary from a file
+    fn new_with_dict_file(path: &Path) -> Self {
+        let json_str = fs::read_to_string(path).unwrap();
+        Self::new_internal(json_str, false, false)
+            .map_translator(|t| t.with_dict_paths(vec![path.to_path_buf()]))
+    }
+
+    /// Changes the options of the translator (ex: with its `with_*` builder methods)
+    fn map_translator(mut self, f: impl FnOnce(StandardTranslator) -> StandardTranslator) -> Self {
+        self.translator = f(self.translator);
+        self
+    }
+
     fn new_internal(json_str: String, is_retro_add_space: bool, is_space_after: bool) -> Self {
         let translator = if is_retro_add_space {
             StandardTranslator::new(
@@ -79,10 +115,13 @@ impl Blackbox {
             translator,
             output: String::new(),
             output_keys: vec![],
+            last_commands: vec![],
+            correction_stroke: None,
         }
     }
 
     fn lookup_and_dispatch(&mut self, strokes: &str) {
+        self.last_commands.clear();
         for s in strokes.split('/') {
             let stroke = Stroke::new(s);
             if !stroke.is_valid() {
@@ -91,21 +130,17 @@ impl Blackbox {
 
             let commands = if stroke.is_undo() {
                 self.translator.undo()
+            } else if self.correction_stroke.as_ref() == Some(&stroke) {
+                self.translator.correct_last_stroke()
             } else {
                 self.translator.translate(stroke)
             };
 
             for command in commands {
+                self.last_commands.push(command.clone());
                 match command {
-                    Command::Replace(backspace_num, add_text) => {
-                        if backspace_num > 0 {
-                            let output_len = self.output.chars().count();
-                            self.output.truncate(output_len - backspace_num)
-                        }
-
-                        if !add_text.is_empty() {
-                            self.output.push_str(&add_text);
-                        }
+                    Command::Replace(..) => {
+                        apply_command(&mut self.output, &command);
                     }
                     Command::PrintHello => {
                         panic!("Not expecting PrintHello to be outputted from the blackbox");
@@ -114,6 +149,35 @@ impl Blackbox {
                     Command::Keys(key, modifiers) => {
                         self.output_keys.push((key, modifiers));
                     }
+                    Command::RepeatKey {
+                        key,
+                        modifiers,
+                        count,
+                    } => {
+                        for _ in 0..count {
+                            self.output_keys.push((key.clone(), modifiers.clone()));
+                        }
+                    }
+                    Command::KeySequence(modifiers, keys) => {
+                        for key in keys {
+                            self.output_keys.push((key, modifiers.clone()));
+                        }
+                    }
+                    Command::KeyDown(..) | Command::KeyUp(..) => {
+                        // held keys are not pressed (or typed) until they are released
+                    }
+                    Command::MouseMove(..)
+                    | Command::MouseMoveTo(..)
+                    | Command::MouseClick(_)
+                    | Command::MouseScroll(..) => {
+                        // the mouse does not type anything
+                    }
+                    Command::Nav(nav) => {
+                        panic!("Cannot handle nav commands. Nav: {:?}", nav);
+                    }
+                    Command::ToggleSlowMode => {
+                        panic!("Cannot handle toggling slow mode");
+                    }
                     Command::Raw(code) => {
                         panic!("Cannot handle raw keycodes. Raw key code: {}", code);
                     }
@@ -123,6 +187,12 @@ impl Blackbox {
                             cmd, args
                         );
                     }
+                    Command::ShellInsert(cmd, args) => {
+                        panic!(
+                            "Shell insert commands should be run by the translator. Command: {:?} with args: {:?}",
+                            cmd, args
+                        );
+                    }
                     Command::TranslatorCommand(cmd) => {
                         self.translator.handle_command(cmd);
                     }
@@ -130,6 +200,42 @@ impl Blackbox {
             }
         }
     }
+
+    /// Adds a dictionary entry and applies the commands that fix the text already typed
+    fn add_entry(&mut self, strokes: &str, translation: &str) {
+        let strokes = strokes.split('/').map(Stroke::new).collect();
+        let added = self.translator.add_entry(strokes, translation).unwrap();
+        assert!(added.save_error.is_none());
+        for command in added.commands {
+            apply_command(&mut self.output, &command);
+        }
+    }
+
+    /// Removes a dictionary entry and applies the commands that fix the text already typed.
+    /// Returns whether there was an entry to remove
+    fn remove_entry(&mut self, strokes: &str) -> bool {
+        let strokes: Vec<_> = strokes.split('/').map(Stroke::new).collect();
+        match self.translator.remove_entry(&strokes) {
+            Some(commands) => {
+                for command in commands {
+                    apply_command(&mut self.output, &command);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Previews each stroke before translating it and checks that the preview has the same
+    /// commands and the same text as actually translating it
+    fn preview_and_dispatch(&mut self, strokes: &str) {
+        for s in strokes.split('/') {
+            let (text, commands) = self.translator.preview(Stroke::new(s));
+            self.lookup_and_dispatch(s);
+            assert_eq!(commands, self.last_commands, "commands for {}", s);
+            assert_eq!(text, self.output, "text for {}", s);
+        }
+    }
 }
 
 #[test]
@@ -159,6 +265,46 @@ fn basic_undo() {
     b_expect!(b, "*", "");
 }
 
+#[test]
+fn correct_last_stroke() {
+    let mut b = Blackbox::new_with_correction_stroke(
+        r#"
+            "H-L": "hello",
+            "WORLD": "world"
+        "#,
+        UndoPolicy::Stroke,
+    );
+    b_expect!(b, "H-L", " hello");
+    b_expect!(b, "WORLD", " hello world");
+    b_expect!(b, "TPHOT", " hello world TPHOT");
+    b_expect!(b, "KRO*", " hello world");
+    b_expect!(b, "KRO*", " hello");
+    b_expect!(b, "KRO*", "");
+    b_expect!(b, "KRO*", "");
+}
+
+#[test]
+fn correct_last_stroke_of_translation() {
+    let mut b = Blackbox::new_with_correction_stroke(
+        r#"
+            "H-L": "hello",
+            "H-L/WORLD": "hi",
+            "TEFT": {"cmds": [{ "Keys": [{"Special": "Tab"}, []] }]}
+        "#,
+        UndoPolicy::Translation,
+    );
+    // only the last stroke of a multi-stroke translation is removed
+    b_expect!(b, "H-L/WORLD", " hi");
+    b_expect!(b, "KRO*", " hello");
+    b_expect!(b, "WORLD", " hi");
+    b_expect!(b, "*", "");
+
+    // a stroke without text is corrected on its own
+    b_expect!(b, "H-L/TEFT", " hello");
+    b_expect_commands!(b, "KRO*", vec![Command::NoOp]);
+    b_expect!(b, "KRO*", "");
+}
+
 #[test]
 fn basic_correction() {
     let mut b = Blackbox::new(
@@ -214,6 +360,99 @@ fn punctuation_with_attached() {
     b_expect!(b, "TP-PL/KR-GS", ".\"");
 }
 
+#[test]
+fn carry_capitalization() {
+    let mut b = Blackbox::new(
+        r#"
+            "KPA": "{-|}",
+            "KPA*L": "{<}",
+            "TP-PL": "{.}",
+            "KW-GS": "{~|\"^}",
+            "KR-GS": "{^~|\"}",
+            "KAUZ": "{~|'^}cause",
+            "WORD": "word"
+        "#,
+    );
+    // the quote is skipped and the word after it is capitalized
+    b_expect!(b, "KPA/KW-GS/WORD", " \"Word");
+    b_expect!(b, "KR-GS", " \"Word\"");
+    b_expect!(b, "TP-PL/KW-GS", " \"Word\". \"");
+    b_expect!(b, "WORD", " \"Word\". \"Word");
+    b_expect!(b, "*", " \"Word\". \"");
+    b_expect!(b, "KAUZ", " \"Word\". \"'Cause");
+    b_expect!(b, "KPA*L/KW-GS/WORD", " \"Word\". \"'Cause \"WORD");
+    b_expect!(b, "WORD", " \"Word\". \"'Cause \"WORD word");
+
+    let mut b = Blackbox::new(
+        r#"
+            "KW-GS": "{~|\"^}",
+            "WORD": "word"
+        "#,
+    )
+    .map_translator(|t| t.with_capitalize_first_word(true));
+    b_expect!(b, "KW-GS/WORD", " \"Word");
+}
+
+#[test]
+fn carry_capitalization_attached_both_sides() {
+    let mut b = Blackbox::new(
+        r#"
+            "KPA": "{-|}",
+            "KPA*L": "{<}",
+            "TP-PL": "{.}",
+            "AE": "{^~|'^}",
+            "H-L": "hello",
+            "WORD": "word",
+            "-S": "{^s}"
+        "#,
+    );
+    // the apostrophe is attached to both words and the word after it is capitalized
+    b_expect!(b, "H-L/KPA/AE/WORD", " hello'Word");
+    b_expect!(b, "*", " hello'");
+    b_expect!(b, "H-L", " hello'Hello");
+    b_expect!(b, "TP-PL/AE/WORD", " hello'Hello.'Word");
+    b_expect!(b, "KPA*L/AE/WORD/H-L", " hello'Hello.'Word'WORD hello");
+    b_expect!(b, "*/*/*", " hello'Hello.'Word");
+    // a suffix after it is also capitalized
+    b_expect!(b, "KPA/AE/-S", " hello'Hello.'Word'S");
+}
+
+#[test]
+fn title_case_prev_word() {
+    let mut b = Blackbox::new(
+        r#"
+            "SKWRAOEPB": "jean",
+            "HR*UBG": "{^-luc}",
+            "TK*RBGS": "{*-|-}",
+            "TKPWHRAO*EB": "{^}_{^}",
+            "H-L": "hello"
+        "#,
+    );
+    b_expect!(b, "SKWRAOEPB/HR*UBG", " jean-luc");
+    b_expect!(b, "TK*RBGS", " Jean-Luc");
+    b_expect!(b, "H-L", " Jean-Luc hello");
+    b_expect!(b, "*/*", " jean-luc");
+    b_expect!(b, "H-L/TKPWHRAO*EB/H-L/TK*RBGS", " jean-luc Hello_Hello");
+    b_expect!(b, "*", " jean-luc hello_hello");
+}
+
+#[test]
+fn do_nothing_stroke() {
+    let mut b = Blackbox::new(
+        r#"
+            "H-L": "hello",
+            "TPH-G": "{#}",
+            "-D": "{^ed}"
+        "#,
+    );
+    b_expect_commands!(b, "TPH-G", vec![Command::NoOp]);
+    b_expect!(b, "H-L/TPH-G", " hello");
+    b_expect!(b, "TPH-G/-D", " helloed");
+    b_expect!(b, "*", " hello");
+    // undo skips over the strokes that did nothing
+    b_expect!(b, "*", "");
+}
+
 #[test]
 fn unknown_with_attached() {
     let mut b = Blackbox::new(
@@ -294,6 +533,27 @@ fn numbers_are_glued() {
     b_expect!(b, "123", " d12318 hello 123");
 }
 
+#[test]
+fn glued_across_commands() {
+    let dict = r#"
+            "A*": "{&a}",
+            "PW*": "{&b}",
+            "TKAO*ER": {"cmds": [{ "Keys": [{"Layout": "d"}, ["Meta"]] }]},
+            "TPH*": {"cmds": ["NoOp"]},
+            "KPA*": "{-|}"
+        "#;
+    let mut b = Blackbox::new(dict);
+    b_expect!(b, "A*/TKAO*ER/PW*", " ab");
+    b_expect!(b, "TPH*/TPH*/A*", " aba");
+    b_expect!(b, "KPA*/PW*", " abaB");
+    b_expect!(b, "*", " aba");
+    b_expect!(b, "*", " ab");
+
+    let mut b = Blackbox::new_with_space_after(dict);
+    b_expect!(b, "A*/TKAO*ER/PW*", "ab ");
+    b_expect!(b, "TPH*/A*", "aba ");
+}
+
 #[test]
 fn number_translation() {
     let mut b = Blackbox::new(
@@ -409,6 +669,43 @@ fn retrospective_add_space_glued() {
     b_expect!(b, "AFPS", " h i");
 }
 
+#[test]
+fn split_prev_word() {
+    let dict = r#"
+            "TRAOEU": "try",
+            "SREUL": "{^}ville",
+            "H*": "{&h}",
+            "*EU": "{&i}",
+            "-G": "{^ing}",
+            "H-L": "hello",
+            "WORLD": "world",
+            "H-L/WORLD": "Hello, world!",
+            "PHAEUBG": "make",
+            "SPWR*": { "cmds": [{ "TranslatorCommand": "split_prev_word" }] }
+        "#;
+    let mut b = Blackbox::new_with_retroactive_add_space(dict);
+    b_expect!(b, "TRAOEU/SREUL/SPWR*", " try ville");
+    // the strokes aren't changed, so undo removes the space first
+    b_expect!(b, "*", " tryville");
+    b_expect!(b, "*", " try");
+    b_expect!(b, "H*/*EU/SPWR*", " try h i");
+    b_expect!(b, "H-L/-G/SPWR*", " try h i hello ing");
+    // nothing to split if the word isn't attached
+    b_expect!(b, "SPWR*", " try h i hello ing");
+    b_expect!(b, "*/*/*", " try h i");
+    // the orthography changed the word, so the suffix can't be split off
+    b_expect!(b, "PHAEUBG/-G/SPWR*", " try h i making");
+    b_expect!(b, "*/*", " try h i");
+
+    // a multi-stroke entry is one word, so only retrospective add space breaks it up
+    b_expect!(b, "H-L/WORLD/SPWR*", " try h i Hello, world!");
+    b_expect!(b, "AFPS", " try h i hello world");
+
+    let mut b = Blackbox::new_with_space_after(dict);
+    b_expect!(b, "TRAOEU/SREUL/SPWR*", "try ville ");
+    b_expect!(b, "*", "tryville ");
+}
+
 #[test]
 fn basic_unicode() {
     let mut b = Blackbox::new(
@@ -437,6 +734,49 @@ fn suppress_space_lowercases_word() {
     b_expect!(b, "KPA/TK-LS/H-L", ".hellohello");
 }
 
+#[test]
+fn force_lowercase_next_word() {
+    let mut b = Blackbox::new(
+        r#"
+            "KPA": "{-|}",
+            "HRO*ER": "{>}",
+            "H-L": "hello",
+            "TPH-S": "NASA",
+            "TP-PL": "{.}"
+        "#,
+    );
+    b_expect!(b, "HRO*ER/TPH-S", " nASA");
+    // already lowercase
+    b_expect!(b, "HRO*ER/H-L", " nASA hello");
+    // the later of capitalize and lowercase wins
+    b_expect!(b, "KPA/HRO*ER/H-L", " nASA hello hello");
+    b_expect!(b, "HRO*ER/KPA/H-L", " nASA hello hello Hello");
+    // lowercase overrides the capitalization after a period
+    b_expect!(b, "TP-PL/HRO*ER/H-L", " nASA hello hello Hello. hello");
+    b_expect!(b, "*", " nASA hello hello Hello.");
+    b_expect!(b, "KPA/H-L", " nASA hello hello Hello. Hello");
+}
+
+#[test]
+fn uppercase_next_word() {
+    let mut b = Blackbox::new(
+        r#"
+            "KPA*PS": "{<}",
+            "TPAO/PWA*R": "foo bar",
+            "H-L": "hello",
+            "A*EUBG": "{^}abc",
+            "TK-LS": "{^^}"
+        "#,
+    );
+    // only the first word of the translation is uppercased
+    b_expect!(b, "KPA*PS/TPAO/PWA*R", " FOO bar");
+    b_expect!(b, "KPA*PS/H-L/H-L", " FOO bar HELLO hello");
+    // works with attached text and suppressed spaces
+    b_expect!(b, "KPA*PS/A*EUBG", " FOO bar HELLO helloABC");
+    b_expect!(b, "TK-LS/KPA*PS/H-L", " FOO bar HELLO helloABCHELLO");
+    b_expect!(b, "*", " FOO bar HELLO helloABC");
+}
+
 #[test]
 fn force_cap_should_clear_suppress_space() {
     let mut b = Blackbox::new(
@@ -489,6 +829,24 @@ fn suffix_folding_precedence() {
     b_expect!(b, "TPRAOEUS", " fries");
 }
 
+#[test]
+fn suffix_folding_no_fold() {
+    let mut b = Blackbox::new(
+        r#"
+            "-S": "{^s}",
+            "-D": "{^ed}",
+            "KAR": "car{:no_fold}",
+            "KA": "can",
+            "KAD": "cad{:no_fold}"
+        "#,
+    );
+    b_expect!(b, "KARS", " KARS");
+    b_expect!(b, "KAR", " KARS car");
+    // the stroke's own entry is still used
+    b_expect!(b, "KAD", " KARS car cad");
+    b_expect!(b, "KAS", " KARS car cad cans");
+}
+
 #[test]
 fn space_after_suppress_space() {
     let mut b = Blackbox::new_with_space_after(
@@ -505,6 +863,93 @@ fn space_after_suppress_space() {
     b_expect!(b, "*", "");
 }
 
+#[test]
+fn attach_without_orthography() {
+    let dict = r#"
+            "TRAOEU": "try",
+            "SREUL": "{^}ville",
+            "TEFT": {"cmds": [{ "Keys": [{"Special": "Tab"}, []] }]},
+            "TPHOEU": {"cmds": [{ "Keys": [{"Layout": "n"}, ["Meta"]] }], "text_after": "hi"},
+            "R-R": {
+                "cmds": [{ "Keys": [{"Special": "Return"}, []] }],
+                "text_after": "{^}{-|}",
+                "suppress_space_before": true
+            }
+        "#;
+    let mut b = Blackbox::new(dict);
+    b_expect!(b, "TRAOEU/SREUL", " tryville");
+    b_expect!(b, "TRAOEU/TEFT/SREUL", " tryville tryville");
+    b_expect!(b, "TPHOEU/SREUL", " tryville tryville hiville");
+    b_expect!(b, "R-R/SREUL", " tryville tryville hivilleville");
+    b_expect!(b, "*", " tryville tryville hiville");
+
+    let mut b = Blackbox::new_with_space_after(dict);
+    b_expect!(b, "TRAOEU/SREUL", "tryville ");
+    b_expect!(b, "TRAOEU/TEFT/SREUL", "tryville tryville ");
+    b_expect!(b, "TPHOEU/SREUL", "tryville tryville hiville ");
+    b_expect!(b, "R-R/SREUL", "tryville tryville hivilleville ");
+    b_expect!(b, "*", "tryville tryville hiville");
+}
+
+#[test]
+fn space_after_attach_after_suppress_space_before() {
+    let mut b = Blackbox::new_with_space_after(
+        r#"
+            "H-L": "hello",
+            "SREUL": "{^}ville",
+            "-S": "{^s}",
+            "TKPWO": {
+                "cmds": [{ "Keys": [{"Layout": "g"}, ["Meta"]] }],
+                "suppress_space_before": true
+            }
+        "#,
+    );
+    b_expect!(b, "H-L/TKPWO", "hello");
+    // attaching doesn't remove the space that the command already removed
+    b_expect!(b, "SREUL", "helloville ");
+    b_expect!(b, "*", "hello");
+    b_expect!(b, "-S", "hellos ");
+    b_expect!(b, "*", "hello");
+    b_expect!(b, "H-L", "hellohello ");
+    b_expect!(b, "*", "hello");
+    // undoing the command puts the space back
+    b_expect!(b, "*", "hello ");
+}
+
+#[test]
+fn non_breaking_space_separator() {
+    let dict = r#"
+            "H-L": "hello",
+            "WORLD": "world",
+            "TPHU/KWRORBG": "new york",
+            "-G": "{^ing}",
+            "KPA*": "{^}",
+            "TKFPS": "{*!}",
+            "TKWO*RD": { "cmds": [{ "TranslatorCommand": "delete_prev_word" }] },
+            "TKPWO": {
+                "cmds": [{ "Keys": [{"Layout": "g"}, ["Meta"]] }],
+                "suppress_space_before": true
+            }
+        "#;
+    let mut b = Blackbox::new(dict).map_translator(|t| t.with_separator("\u{a0}".to_string()));
+    b_expect!(b, "H-L/WORLD", "\u{a0}hello\u{a0}world");
+    // spaces inside a translation are kept
+    b_expect!(b, "TPHU/KWRORBG", "\u{a0}hello\u{a0}world\u{a0}new york");
+    b_expect!(b, "*/*/-G", "\u{a0}hello\u{a0}worlding");
+    b_expect!(b, "KPA*/H-L", "\u{a0}hello\u{a0}worldinghello");
+    b_expect!(b, "*/*", "\u{a0}hello\u{a0}world");
+    b_expect!(b, "TKFPS", "\u{a0}helloworld");
+    b_expect!(b, "TKWO*RD", "");
+
+    // the separator after the last word is removed before the command
+    let mut b = Blackbox::new_with_space_after(dict);
+    b.translator = b.translator.with_separator("\u{a0}".to_string());
+    b_expect!(b, "H-L/WORLD", "hello\u{a0}world\u{a0}");
+    b_expect!(b, "TKPWO", "hello\u{a0}world");
+    b_expect!(b, "H-L", "hello\u{a0}worldhello\u{a0}");
+    b_expect!(b, "*/*", "hello\u{a0}world\u{a0}");
+}
+
 #[test]
 fn space_after_suppress_space_before_command() {
     let mut b = Blackbox::new_with_space_after(
@@ -538,6 +983,76 @@ fn space_after_duplicate_deletes() {
     b_expect!(b, "TW-B", "hello");
 }
 
+#[test]
+fn command_text_after_keeps_space_before() {
+    let dict = r#"
+            "R-R": {
+                "cmds": [{ "Keys": [{"Special": "Return"}, []] }],
+                "text_after": "{^}{-|}"
+            },
+            "TW-B": {
+                "cmds": [{ "Keys": [{"Special": "Tab"}, []] }]
+            },
+            "H-L": "hello",
+            "WORLD": "world"
+        "#;
+
+    // the space before the command was already typed, so only the next word is attached
+    let mut b = Blackbox::new_with_space_after(dict);
+    b_expect!(b, "H-L/R-R", "hello ");
+    b_expect!(b, "WORLD", "hello World ");
+    b_expect!(b, "R-R/TW-B", "hello World ");
+    b_expect!(b, "H-L", "hello World Hello ");
+    b_expect!(b, "*", "hello World ");
+    // undo skips the commands instead of typing a space
+    b_expect!(b, "*", "hello ");
+    b_expect!(b, "*", "");
+
+    let mut b = Blackbox::new(dict);
+    b_expect!(b, "H-L/R-R", " hello");
+    b_expect!(b, "WORLD", " helloWorld");
+    b_expect!(b, "R-R/TW-B", " helloWorld");
+    b_expect!(b, "H-L", " helloWorldHello");
+    b_expect!(b, "*", " helloWorld");
+    b_expect!(b, "*", " hello");
+    b_expect!(b, "*", "");
+}
+
+#[test]
+fn command_text_after_in_both_space_modes() {
+    let dict = r#"
+            "R-R": {
+                "cmds": [{ "Keys": [{"Special": "Return"}, []] }],
+                "text_after": "{^}{-|}",
+                "suppress_space_before": true
+            },
+            "TW-B": {
+                "cmds": [{ "Keys": [{"Special": "Tab"}, []] }],
+                "suppress_space_before": true
+            },
+            "H-L": "hello",
+            "WORLD": "world"
+        "#;
+
+    let mut b = Blackbox::new_with_space_after(dict);
+    b_expect!(b, "H-L/R-R", "hello");
+    b_expect!(b, "WORLD", "helloWorld ");
+    b_expect!(b, "H-L", "helloWorld hello ");
+    b_expect!(b, "R-R", "helloWorld hello");
+    b_expect!(b, "R-R", "helloWorld hello");
+    b_expect!(b, "TW-B", "helloWorld hello");
+    b_expect!(b, "H-L", "helloWorld helloHello ");
+
+    let mut b = Blackbox::new(dict);
+    b_expect!(b, "H-L/R-R", " hello");
+    b_expect!(b, "WORLD", " helloWorld");
+    b_expect!(b, "H-L", " helloWorld hello");
+    b_expect!(b, "R-R", " helloWorld hello");
+    b_expect!(b, "R-R", " helloWorld hello");
+    b_expect!(b, "TW-B", " helloWorld hello");
+    b_expect!(b, "H-L", " helloWorld helloHello");
+}
+
 #[test]
 fn orthography_uppercase() {
     let mut b = Blackbox::new(
@@ -711,3 +1226,1310 @@ fn toggle_space_after() {
     b_expect!(b, "*", " hello");
     b_expect!(b, "*", "");
 }
+
+#[test]
+fn toggle_passthrough() {
+    let dict = r#"
+        "H-L": "hello",
+        "WORLD": "world",
+        "-G": "{^ing}",
+        "PA*S": { "cmds": [{ "TranslatorCommand": "toggle_passthrough" }] }
+    "#;
+    let mut b = Blackbox::new(dict);
+    b_expect!(b, "H-L", " hello");
+    assert!(!b.translator.is_passthrough());
+    b_expect!(b, "PA*S", " hello");
+    assert!(b.translator.is_passthrough());
+
+    // the strokes are typed raw, even if they are in the dictionary
+    b_expect!(b, "H-L/-G/WORLD", " hello H-L -G WORLD");
+    b_expect!(b, "*", " hello H-L -G");
+    b_expect!(b, "*/*", " hello");
+    // the text from before passthrough can't be undone
+    b_expect!(b, "*", " hello");
+    b_expect!(b, "WORLD", " hello WORLD");
+
+    // toggling it again goes back to translating
+    b_expect!(b, "PA*S", " hello WORLD");
+    assert!(!b.translator.is_passthrough());
+    b_expect!(b, "H-L/-G", " hello WORLD helloing");
+    b_expect!(b, "*", " hello WORLD hello");
+
+    let mut b = Blackbox::new_with_space_after(dict);
+    b_expect!(b, "H-L/PA*S/WORLD/-G", "hello WORLD -G ");
+    b_expect!(b, "*", "hello WORLD ");
+    b_expect!(b, "PA*S/WORLD", "hello WORLD world ");
+}
+
+#[test]
+fn plover_suspend_and_resume() {
+    let dict = r#"
+        "H-L": "hello",
+        "WORLD": "world",
+        "S-PD": "{PLOVER:SUSPEND}",
+        "RE-Z": "{PLOVER:RESUME}",
+        "TKUP": "{PLOVER:ADD_TRANSLATION}"
+    "#;
+    let mut b = Blackbox::new(dict);
+    b_expect!(b, "H-L/TKUP", " hello");
+    b_expect!(b, "S-PD", " hello");
+    assert!(b.translator.is_passthrough());
+    // suspending again does nothing
+    b_expect!(b, "H-L/S-PD", " hello H-L S-PD");
+    assert!(b.translator.is_passthrough());
+
+    b_expect!(b, "RE-Z", " hello H-L S-PD");
+    assert!(!b.translator.is_passthrough());
+    b_expect!(b, "WORLD", " hello H-L S-PD world");
+}
+
+#[test]
+fn toggle_space_mode() {
+    let dict = r#"
+        "H-L": "hello",
+        "WORLD": "world",
+        "TK-LS": "{^^}",
+        "SPA*EUS": { "cmds": [{ "TranslatorCommand": "toggle_space_mode" }] }
+    "#;
+    let mut b = Blackbox::new(dict);
+    b_expect!(b, "H-L", " hello");
+    b_expect!(b, "SPA*EUS", " hello ");
+    b_expect!(b, "WORLD", " hello world ");
+    b_expect!(b, "H-L", " hello world hello ");
+    b_expect!(b, "SPA*EUS", " hello world hello");
+    b_expect!(b, "WORLD", " hello world hello world");
+    b_expect!(b, "*", " hello world hello");
+    // the stroke buffer was cleared, so the text before the toggle can't be undone
+    b_expect!(b, "*", " hello world hello");
+
+    let mut b = Blackbox::new_with_space_after(dict);
+    b_expect!(b, "H-L", "hello ");
+    b_expect!(b, "SPA*EUS", "hello");
+    b_expect!(b, "WORLD", "hello world");
+    b_expect!(b, "SPA*EUS", "hello world ");
+    b_expect!(b, "H-L", "hello world hello ");
+
+    // an attached word stays attached
+    let mut b = Blackbox::new(dict);
+    b_expect!(b, "H-L/TK-LS/SPA*EUS", " hello");
+    b_expect!(b, "WORLD", " helloworld ");
+}
+
+#[test]
+fn toggle_suppress_space() {
+    let mut b = Blackbox::new(
+        r#"
+            "H-L": "hello",
+            "WORLD": "world",
+            "TPH-S": "{toggle_suppress_space}"
+        "#,
+    );
+    b_expect!(b, "H-L/TPH-S", " hello");
+    b_expect!(b, "WORLD", " helloworld");
+    b_expect!(b, "H-L", " helloworld hello");
+    b_expect!(b, "TPH-S/TPH-S/WORLD", " helloworld hello world");
+    b_expect!(b, "*", " helloworld hello");
+    b_expect!(b, "TPH-S/WORLD", " helloworld helloworld");
+}
+
+#[test]
+fn capitalize_first_word() {
+    let mut b = Blackbox::new(
+        r#"
+            "H-L": "hello",
+            "WORLD": "world",
+            "R-R": {
+                "cmds": [{ "TranslatorCommand": "clear_prev_strokes" }]
+            }
+        "#,
+    )
+    .map_translator(|t| t.with_capitalize_first_word(true));
+    b_expect!(b, "H-L", " Hello");
+    b_expect!(b, "WORLD", " Hello world");
+    b_expect!(b, "*/*", "");
+    b_expect!(b, "WORLD", " World");
+    b_expect!(b, "R-R/H-L", " World Hello");
+}
+
+#[test]
+fn capitalize_first_word_can_be_overridden() {
+    let dict = r#"
+            "H-L": "hello",
+            "HRO*ER": "{>}",
+            "KPA*L": "{<}"
+        "#;
+    let mut b = Blackbox::new(dict).map_translator(|t| t.with_capitalize_first_word(true));
+    // an explicit lowercase or uppercase wins over capitalizing the first word
+    b_expect!(b, "HRO*ER/H-L", " hello");
+    b_expect!(b, "*/*", "");
+    b_expect!(b, "KPA*L/H-L", " HELLO");
+    b_expect!(b, "*/*/H-L", " Hello");
+
+    // the first word is left alone without the option
+    let mut b = Blackbox::new(dict);
+    b_expect!(b, "H-L", " hello");
+}
+
+#[test]
+fn capitalize_after_literal_punctuation() {
+    let mut b = Blackbox::new(
+        r#"
+            "ET/SET/RA": "etc.",
+            "H-L": "hello",
+            "WORLD": "world",
+            "TP-PL": "{.}"
+        "#,
+    )
+    .map_translator(|t| t.with_capitalize_after_punctuation(true));
+    b_expect!(b, "H-L/ET/SET/RA", " hello etc.");
+    b_expect!(b, "H-L", " hello etc. Hello");
+    b_expect!(b, "TP-PL/WORLD", " hello etc. Hello. World");
+    b_expect!(b, "*/*/*", " hello etc.");
+    b_expect!(b, "WORLD", " hello etc. World");
+}
+
+#[test]
+fn literal_punctuation_does_not_capitalize_by_default() {
+    let mut b = Blackbox::new(
+        r#"
+            "ET/SET/RA": "etc.",
+            "H-L": "hello"
+        "#,
+    );
+    b_expect!(b, "H-L/ET/SET/RA/H-L", " hello etc. hello");
+}
+
+#[test]
+fn capitalize_previous_words() {
+    let mut b = Blackbox::new(
+        r#"
+            "-T": "the",
+            "TPHU/KWRORBG": "new york",
+            "TAOEUPLS": "times",
+            "WEL": "well",
+            "TPHOEPB": "{^-^}known",
+            "TPHAEUPL": "name",
+            "KA*PS": { "cmds": [{ "TranslatorCommand": "cap_prev_word:3" }] },
+            "KA*P": { "cmds": [{ "TranslatorCommand": "cap_prev_word" }] }
+        "#,
+    );
+    // the phrase spans multiple strokes
+    b_expect!(b, "-T/TPHU/KWRORBG/TAOEUPLS", " the new york times");
+    b_expect!(b, "KA*PS", " the New York Times");
+    b_expect!(b, "*", " the new york times");
+
+    // hyphenated words are capitalized once
+    b_expect!(
+        b,
+        "WEL/TPHOEPB/TPHAEUPL",
+        " the new york times well-known name"
+    );
+    b_expect!(b, "KA*PS", " the new york Times Well-known Name");
+    b_expect!(b, "KA*P/-T", " the new york Times Well-known Name the");
+    b_expect!(b, "KA*P", " the new york Times Well-known Name The");
+}
+
+#[test]
+fn delete_previous_word() {
+    let dict = r#"
+        "H-L": "hello",
+        "WORLD": "world",
+        "WEL": "well",
+        "TPHOEPB": "{^-^}known",
+        "KW-BG": "{,}",
+        "P-P": "{.}",
+        "T*": "{>}{&t}",
+        "*E": "{>}{&e}",
+        "S*": "{>}{&s}",
+        "TKWO*RD": { "cmds": [{ "TranslatorCommand": "delete_prev_word" }] }
+    "#;
+    let mut b = Blackbox::new(dict);
+    // nothing to delete
+    b_expect!(b, "TKWO*RD", "");
+    b_expect!(b, "H-L/WORLD", " hello world");
+    b_expect!(b, "TKWO*RD", " hello");
+    b_expect!(b, "H-L", " hello hello");
+    // the word is back after undoing the delete
+    b_expect!(b, "*/*", " hello world");
+
+    // a fingerspelled word is deleted without undoing its strokes
+    b_expect!(b, "T*/*E/S*/T*", " hello world test");
+    b_expect!(b, "TKWO*RD", " hello world");
+    b_expect!(b, "WORLD", " hello world world");
+
+    // hyphenated and punctuated words are deleted whole
+    b_expect!(b, "WEL/TPHOEPB/KW-BG", " hello world world well-known,");
+    b_expect!(b, "TKWO*RD", " hello world world");
+    b_expect!(b, "H-L/P-P", " hello world world hello.");
+    b_expect!(b, "TKWO*RD/TKWO*RD", " hello world");
+
+    let mut b = Blackbox::new_with_space_after(dict);
+    b_expect!(b, "H-L/WORLD", "hello world ");
+    b_expect!(b, "TKWO*RD", "hello ");
+    b_expect!(b, "TKWO*RD", "");
+    b_expect!(b, "WORLD", "world ");
+}
+
+#[test]
+fn toggle_title_case() {
+    let mut b = Blackbox::new(
+        r#"
+            "H-L": "hello",
+            "WORLD": "world",
+            "-T/WUPB": "the one",
+            "TAOEUT": { "cmds": [{ "TranslatorCommand": "toggle_title_case" }] },
+            "TKPWRAOER": "{>}",
+            "-S": "{^s}"
+        "#,
+    );
+    b_expect!(b, "H-L/TAOEUT", " hello");
+    b_expect!(b, "WORLD/-S", " hello Worlds");
+    b_expect!(b, "-T/WUPB", " hello Worlds The One");
+    b_expect!(b, "TKPWRAOER/H-L", " hello Worlds The One hello");
+    b_expect!(b, "TAOEUT/WORLD", " hello Worlds The One hello world");
+    b_expect!(b, "*", " hello Worlds The One hello");
+    b_expect!(b, "*", " hello Worlds The One");
+}
+
+#[test]
+fn toggle_number_words() {
+    let mut b = Blackbox::new(
+        r#"
+            "H-L": "hello",
+            "TPHUPL": { "cmds": [{ "TranslatorCommand": "toggle_number_words" }] }
+        "#,
+    );
+    b_expect!(b, "H-L/3-", " hello 3");
+    b_expect!(b, "TPHUPL/3-", " hello 3 three");
+    b_expect!(b, "3-", " hello 3 three three");
+    b_expect!(b, "TPHUPL/3-", " hello 3 three three 3");
+    b_expect!(b, "*", " hello 3 three three");
+}
+
+#[test]
+fn format_prev_number() {
+    let mut b = Blackbox::new(
+        r#"
+            "H-L": "hello",
+            "KPHAS": "{*(,)}",
+            "TKHRAR": "{*($c)}"
+        "#,
+    );
+    b_expect!(b, "1-/2-/3-/4-/5-/6-/7-", " 1234567");
+    b_expect!(b, "KPHAS", " 1,234,567");
+    b_expect!(b, "*", " 1234567");
+    b_expect!(b, "H-L/1-/2-/-9/-9/TKHRAR", " 1234567 hello $12.99");
+    // not a number
+    b_expect!(b, "H-L/KPHAS/TKHRAR", " 1234567 hello $12.99 hello");
+}
+
+#[test]
+fn smart_quotes() {
+    let mut b = Blackbox::new(
+        r#"
+            "H-L": "hello",
+            "KW-GS": "{:smart_quote}",
+            "KR-GS": "{^}{:smart_quote}",
+            "KWO*T": "{:smart_single_quote}"
+        "#,
+    );
+    // opening quote after a space
+    b_expect!(b, "H-L/KW-GS", " hello “");
+    b_expect!(b, "H-L", " hello “hello");
+    // closing quote after a letter
+    b_expect!(b, "KW-GS", " hello “hello “");
+    b_expect!(b, "*/KR-GS", " hello “hello”");
+    b_expect!(b, "H-L/KWO*T/H-L/KR-GS", " hello “hello” hello ‘hello”");
+    b_expect!(b, "*", " hello “hello” hello ‘hello");
+}
+
+#[test]
+fn type_last_raw_stroke() {
+    let mut b = Blackbox::new(
+        r#"
+            "H-L": "hello",
+            "RA*U": { "cmds": [{ "TranslatorCommand": "type_last_raw_stroke" }] }
+        "#,
+    );
+    // nothing to type without a stroke before it
+    b_expect!(b, "RA*U", "");
+    b_expect!(b, "H-L", " hello");
+    b_expect!(b, "RA*U", " hello H-L");
+    b_expect!(b, "*", " hello");
+    b_expect!(b, "TPHO/RA*U", " hello TPHO TPHO");
+}
+
+#[test]
+fn repeat_last() {
+    let mut b = Blackbox::new(
+        r#"
+            "H-L": "hello",
+            "TPHO/WUPB": "no one",
+            "-G": "{^ing}",
+            "TKAO*ER": { "cmds": [{ "Keys": [{ "Layout": "d" }, ["Meta"]] }] },
+            "R*PT": { "cmds": [{ "TranslatorCommand": "repeat_last" }] }
+        "#,
+    );
+    // nothing to repeat without a stroke before it
+    b_expect!(b, "R*PT", "");
+    b_expect!(b, "H-L", " hello");
+    b_expect!(b, "R*PT", " hello hello");
+    // a single undo removes the repeat
+    b_expect!(b, "*", " hello");
+
+    // multi-stroke translations are repeated, and repeats can be repeated
+    b_expect!(b, "TPHO/WUPB/R*PT", " hello no one no one");
+    b_expect!(b, "R*PT", " hello no one no one no one");
+    b_expect!(b, "*", " hello no one no one");
+    b_expect!(b, "*", " hello no one");
+
+    // a folded suffix is repeated along with the word it is attached to
+    b_expect!(b, "H-LG/R*PT", " hello no one helloing helloing");
+
+    // commands are skipped and not dispatched again
+    b_expect_keys!(
+        b,
+        "TKAO*ER/R*PT",
+        vec![(Key::Layout('d'), vec![Modifier::Meta])]
+    );
+    assert_eq!(b.output, " hello no one helloing helloing helloing");
+}
+
+#[test]
+fn insert_brackets_and_move_cursor() {
+    let dict = r#"
+        "H-L": "hello",
+        "WORLD": "world",
+        "PREPB": {
+            "cmds": [{ "Replace": [0, " ()"] }, { "Keys": [{ "Special": "LeftArrow" }, []] }],
+            "text_after": "{^}",
+            "suppress_space_before": true
+        }
+    "#;
+
+    let mut b = Blackbox::new(dict);
+    b_expect!(b, "H-L", " hello");
+    b_expect_commands!(
+        b,
+        "PREPB",
+        vec![
+            Command::Replace(0, " ()".to_string()),
+            Command::Keys(Key::Special(SpecialKey::LeftArrow), vec![]),
+        ]
+    );
+    b_expect_commands!(b, "WORLD", vec![Command::Replace(0, "world".to_string())]);
+
+    let mut b = Blackbox::new_with_space_after(dict);
+    b_expect!(b, "H-L", "hello ");
+    b_expect_commands!(
+        b,
+        "PREPB",
+        vec![
+            Command::Replace(1, " ()".to_string()),
+            Command::Keys(Key::Special(SpecialKey::LeftArrow), vec![]),
+        ]
+    );
+    b_expect_commands!(b, "WORLD", vec![Command::Replace(0, "world ".to_string())]);
+}
+
+#[test]
+fn inline_key_command() {
+    let dict = r#"
+        "H-L": "hello",
+        "TKPWRAOET": "Hi{#Return}there"
+    "#;
+    let enter = Command::Keys(Key::Special(SpecialKey::Return), vec![]);
+
+    let mut b = Blackbox::new(dict);
+    b_expect!(b, "H-L", " hello");
+    b_expect_commands!(
+        b,
+        "TKPWRAOET",
+        vec![
+            Command::Replace(0, " Hi".to_string()),
+            enter.clone(),
+            Command::Replace(0, "there".to_string()),
+        ]
+    );
+    assert_eq!(
+        b.output_keys,
+        vec![(Key::Special(SpecialKey::Return), vec![])]
+    );
+    b_expect_commands!(b, "H-L", vec![Command::Replace(0, " hello".to_string())]);
+    // the key press can't be undone, but the text around it can
+    b_expect_commands!(b, "*", vec![Command::Replace(6, "".to_string())]);
+    b_expect_commands!(b, "*", vec![Command::Replace(8, "".to_string())]);
+
+    let mut b = Blackbox::new_with_space_after(dict);
+    b_expect!(b, "H-L", "hello ");
+    b_expect_commands!(
+        b,
+        "TKPWRAOET",
+        vec![
+            Command::Replace(0, "Hi".to_string()),
+            enter,
+            Command::Replace(0, "there ".to_string()),
+        ]
+    );
+    b_expect_commands!(b, "H-L", vec![Command::Replace(0, "hello ".to_string())]);
+}
+
+#[test]
+fn paragraph_break() {
+    let dict = r#"
+        "H-L": "hello",
+        "OBG": "okay",
+        "PAR": {
+            "cmds": [{ "Keys": [{ "Special": "Return" }, []] }],
+            "text_after": "{^}{-|}",
+            "suppress_space_before": true
+        }
+    "#;
+    let paragraph = Command::Keys(Key::Special(SpecialKey::Return), vec![]);
+
+    let mut b = Blackbox::new(dict);
+    b_expect_commands!(b, "PAR", vec![paragraph.clone()]);
+    b_expect!(b, "H-L", "Hello");
+    b_expect_commands!(b, "PAR", vec![paragraph.clone()]);
+    b_expect_commands!(b, "OBG", vec![Command::Replace(0, "Okay".to_string())]);
+    b_expect_commands!(b, "H-L", vec![Command::Replace(0, " hello".to_string())]);
+    b_expect_commands!(b, "PAR/PAR", vec![paragraph.clone(), paragraph.clone()]);
+    b_expect_commands!(b, "OBG", vec![Command::Replace(0, "Okay".to_string())]);
+    b_expect_commands!(b, "*", vec![Command::Replace(4, "".to_string())]);
+    b_expect_commands!(b, "H-L", vec![Command::Replace(0, "Hello".to_string())]);
+
+    let mut b = Blackbox::new_with_space_after(dict);
+    b_expect_commands!(b, "PAR", vec![paragraph.clone()]);
+    b_expect!(b, "H-L", "Hello ");
+    b_expect_commands!(
+        b,
+        "PAR",
+        vec![Command::Replace(1, "".to_string()), paragraph.clone()]
+    );
+    b_expect_commands!(b, "OBG", vec![Command::Replace(0, "Okay ".to_string())]);
+    b_expect_commands!(
+        b,
+        "PAR",
+        vec![Command::Replace(1, "".to_string()), paragraph.clone()]
+    );
+    b_expect_commands!(b, "PAR", vec![paragraph.clone()]);
+    b_expect_commands!(b, "OBG", vec![Command::Replace(0, "Okay ".to_string())]);
+    b_expect_commands!(b, "*", vec![Command::Replace(5, "".to_string())]);
+    b_expect_commands!(b, "H-L", vec![Command::Replace(0, "Hello ".to_string())]);
+}
+
+#[test]
+fn reverse_lookup() {
+    let translator = StandardTranslator::new(
+        vec![
+            r#"{
+                "H-L": "hello",
+                "HEL/HRO": "hello",
+                "H*EL": "hello",
+                "WORLD": "world",
+                "-G": "{^ing}",
+                "TEFT": { "cmds": [{ "Keys": [{"Special": "Tab"}, []] }], "text_after": "hello" }
+            }"#
+            .to_string(),
+            r#"{ "H*EL": "help" }"#.to_string(),
+        ],
+        vec![],
+        vec![],
+        None,
+        false,
+    )
+    .unwrap();
+
+    let strokes = |s: &str| s.split('/').map(Stroke::new).collect::<Vec<_>>();
+    assert_eq!(
+        translator.reverse_lookup("hello"),
+        vec![strokes("H-L"), strokes("HEL/HRO")]
+    );
+    assert_eq!(translator.reverse_lookup("help"), vec![strokes("H*EL")]);
+    assert_eq!(translator.reverse_lookup("ing"), vec![strokes("-G")]);
+    assert!(translator.reverse_lookup("Hello").is_empty());
+}
+
+#[test]
+fn render_strokes() {
+    let dict = r#"
+            "H-L": "hello",
+            "H-L/WORLD": "hi",
+            "WORLD": "world",
+            "-Z": "{^s}",
+            "RAEUS": "race",
+            "TEFT": {"cmds": [{ "Keys": [{"Special": "Tab"}, []] }]}
+        "#;
+    let mut b = Blackbox::new(dict);
+    b_expect!(b, "H-L/H-L", " hello hello");
+
+    let strokes = |s: &str| s.split('/').map(Stroke::new).collect::<Vec<_>>();
+    assert_eq!(b.translator.render_strokes(&strokes("H-L")), " hello");
+    assert_eq!(b.translator.render_strokes(&strokes("H-L/WORLD")), " hi");
+    assert_eq!(
+        b.translator.render_strokes(&strokes("WORLD/H-L/WORLD")),
+        " world hi"
+    );
+    assert_eq!(b.translator.render_strokes(&strokes("RAEUSZ")), " races");
+    assert_eq!(b.translator.render_strokes(&strokes("TEFT")), "");
+    assert_eq!(b.translator.render_strokes(&[]), "");
+    // the stroke buffer is not changed
+    b_expect!(b, "WORLD", " hello hi");
+
+    let b = Blackbox::new_with_space_after(dict);
+    assert_eq!(
+        b.translator.render_strokes(&strokes("H-L/WORLD/H-L")),
+        "hi hello "
+    );
+}
+
+#[test]
+fn undo_policy_stroke() {
+    let mut b = Blackbox::new(
+        r#"
+            "H-L": "hello",
+            "KA": "can",
+            "KA/TPHOT": "cannot"
+        "#,
+    )
+    .map_translator(|t| t.with_undo_policy(UndoPolicy::Stroke));
+    b_expect!(b, "H-L/KA/TPHOT", " hello cannot");
+    b_expect!(b, "*", " hello can");
+    b_expect!(b, "*", " hello");
+    b_expect!(b, "KA/TPHOT/H-L", " hello cannot hello");
+    b_expect!(b, "*", " hello cannot");
+    b_expect!(b, "*", " hello can");
+}
+
+#[test]
+fn undo_policy_translation() {
+    let mut b = Blackbox::new(
+        r#"
+            "H-L": "hello",
+            "KA": "can",
+            "KA/TPHOT": "cannot",
+            "TEFT": {"cmds": [{ "Keys": [{"Special": "Tab"}, []] }]}
+        "#,
+    )
+    .map_translator(|t| t.with_undo_policy(UndoPolicy::Translation));
+    b_expect!(b, "H-L/KA/TPHOT", " hello cannot");
+    b_expect!(b, "*", " hello");
+    b_expect!(b, "KA/TPHOT/H-L", " hello cannot hello");
+    b_expect!(b, "*", " hello cannot");
+    b_expect!(b, "*", " hello");
+    b_expect!(b, "KA/TPHOT/TEFT", " hello cannot");
+    b_expect!(b, "*", " hello");
+    b_expect!(b, "*", "");
+    b_expect!(b, "*", "");
+}
+
+#[test]
+fn unit_spacing() {
+    let raw_dict = r#"
+            "KPH*": "{&km}",
+            "KP*": "{&k}",
+            "PH*": "{&m}",
+            "TKPWR*": "{^g}",
+            "TO": "to"
+        "#;
+    let mut b = Blackbox::new(raw_dict).map_translator(|t| t.with_units(vec!["km".to_string()]));
+    b_expect!(b, "5/KPH*", " 5 km");
+    b_expect!(b, "TO/1-/2-/KPH*", " 5 km to 12 km");
+    // explicitly attached words and other glued words are not spaced
+    b_expect!(b, "TO/3-/TKPWR*", " 5 km to 12 km to 3g");
+    b_expect!(b, "TO/KP*/PH*/KPH*", " 5 km to 12 km to 3g to kmkm");
+
+    // without configured units, numbers glue to glued words
+    let mut b = Blackbox::new(raw_dict);
+    b_expect!(b, "5/KPH*", " 5km");
+}
+
+#[test]
+fn custom_orthography_rules() {
+    let raw_dict = r#"
+            "TKEUS/PEL": "dispel",
+            "-G": "{^ing}",
+            "TPHRAEUT": "narrate"
+        "#;
+    // never double a final "l" after a vowel, even where both American and British spelling would
+    // (British spelling doubles it in even more words, ex: "travelling")
+    let rules = r#"[["^(.*[aeiou]l)$", "^(ing|ed)$", [{"BaseGroup": 1}, {"SuffixGroup": 1}]]]"#;
+    let mut b =
+        Blackbox::new(raw_dict).map_translator(|t| t.with_orthography_rules(rules).unwrap());
+    b_expect!(b, "TKEUS/PEL/-G", " dispeling");
+    // the default rules still apply
+    b_expect!(b, "TPHRAEUT/-G", " dispeling narrating");
+
+    let mut b = Blackbox::new(raw_dict);
+    b_expect!(b, "TKEUS/PEL/-G", " dispelling");
+}
+
+#[test]
+fn orthography_exceptions() {
+    let raw_dict = r#"
+            "PAPB/EUBG": "panic",
+            "-G": "{^ing}",
+            "TRAFL": "travel"
+        "#;
+    let mut b = Blackbox::new(raw_dict);
+    b_expect!(b, "PAPB/EUBG/-G", " panicking");
+
+    let mut b = Blackbox::new(raw_dict).map_translator(|t| {
+        t.with_orthography_exceptions(r#"{"travel+ing": "travelling"}"#)
+            .unwrap()
+    });
+    b_expect!(b, "TRAFL/-G", " travelling");
+    b_expect!(b, "PAPB/EUBG/-G", " travelling panicking");
+}
+
+#[test]
+fn invalid_orthography_rules() {
+    let translator = StandardTranslator::new(vec![], vec![], vec![], None, false).unwrap();
+    assert!(translator
+        .with_orthography_rules(r#"[["^(.*$", "^s$", [{"Lit": "es"}]]]"#)
+        .is_err());
+    let translator = StandardTranslator::new(vec![], vec![], vec![], None, false).unwrap();
+    assert!(translator
+        .with_orthography_exceptions(r#"{"travelling": "travelling"}"#)
+        .is_err());
+}
+
+#[test]
+fn hold_key() {
+    let mut b = Blackbox::new(
+        r#"
+            "H-L": "hello",
+            "HOLD": { "cmds": [{ "KeyDown": [{ "Layout": "w" }, ["Shift"]] }] },
+            "RAOES": { "cmds": [{ "KeyUp": [{ "Layout": "w" }, ["Shift"]] }] }
+        "#,
+    );
+    b_expect_commands!(
+        b,
+        "HOLD",
+        vec![Command::KeyDown(Key::Layout('w'), vec![Modifier::Shift])]
+    );
+    // the key stays held across strokes
+    b_expect!(b, "H-L", " hello");
+    b_expect_commands!(
+        b,
+        "RAOES",
+        vec![Command::KeyUp(Key::Layout('w'), vec![Modifier::Shift])]
+    );
+}
+
+#[test]
+fn app_switcher_key_sequence() {
+    let mut b = Blackbox::new(
+        r#"
+            "H-L": "hello",
+            "SWEUFP": {
+                "cmds": [{ "KeySequence": [["Meta"], [{ "Special": "Tab" }, { "Special": "Tab" }]] }]
+            }
+        "#,
+    );
+    b_expect!(b, "H-L", " hello");
+    b_expect_commands!(
+        b,
+        "SWEUFP",
+        vec![Command::KeySequence(
+            vec![Modifier::Meta],
+            vec![Key::Special(SpecialKey::Tab), Key::Special(SpecialKey::Tab)]
+        )]
+    );
+    assert_eq!(b.output, " hello");
+}
+
+#[test]
+fn command_macro() {
+    let save = vec![Command::Keys(Key::Layout('s'), vec![Modifier::Meta])];
+    let mut b = Blackbox::new(
+        r#"
+            "H-L": "hello",
+            "WORLD": "world",
+            "STPH-G": "sing"
+        "#,
+    )
+    .map_translator(|t| {
+        t.with_macros(vec![(
+            vec![Stroke::new("STPH-G"), Stroke::new("STPH-P")],
+            save.clone(),
+        )])
+    });
+    b_expect!(b, "H-L", " hello");
+    b_expect_commands!(b, "STPH-G", vec![Command::NoOp]);
+    b_expect_commands!(b, "STPH-P", save);
+    assert_eq!(b.output, " hello");
+    assert_eq!(
+        b.output_keys,
+        vec![(Key::Layout('s'), vec![Modifier::Meta])]
+    );
+
+    // the macro strokes are not in the undo history
+    b_expect!(b, "WORLD", " hello world");
+    b_expect!(b, "*", " hello");
+    b_expect!(b, "*", "");
+
+    // a broken sequence is translated normally
+    b_expect!(b, "STPH-G", "");
+    b_expect!(b, "WORLD", " sing world");
+
+    // undo in the middle of a sequence cancels it
+    b_expect!(b, "STPH-G/*/STPH-P", " sing world STPH-P");
+}
+
+#[test]
+fn reload_dicts() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("dict.json");
+    let reload = r#""RE-LD": { "cmds": [{ "TranslatorCommand": "reload_dicts" }] }"#;
+    fs::write(&path, format!(r#"{{ "H-L": "hello", {} }}"#, reload)).unwrap();
+
+    let mut b = Blackbox::new_with_dict_file(&path);
+    b_expect!(b, "H-L/WORLD", " hello WORLD");
+
+    fs::write(&path, format!(r#"{{ "WORLD": "world", {} }}"#, reload)).unwrap();
+    b_expect!(b, "RE-LD", " hello WORLD");
+    b_expect!(b, "WORLD", " hello WORLD world");
+    // the strokes from before the reload can still be undone
+    b_expect!(b, "*/*", " hello");
+
+    // keeps the old dictionary if the new one is invalid
+    fs::write(&path, "not json").unwrap();
+    b_expect!(b, "RE-LD/WORLD", " hello world");
+}
+
+#[test]
+fn add_entry() {
+    let mut b = Blackbox::new(
+        r#"
+            "H-L": "hello",
+            "WORLD": "world"
+        "#,
+    );
+    b_expect!(b, "H-L/PHRO", " hello PHRO");
+    // the unknown stroke that was just typed is translated with the new entry
+    b.add_entry("PHRO", "plojo");
+    assert_eq!(b.output, " hello plojo");
+    b_expect!(b, "PHRO", " hello plojo plojo");
+
+    // takes precedence over loaded entries and can be a multi-stroke entry
+    b.add_entry("WORLD", "{^ing}");
+    b.add_entry("H-L/PHRO", "hi");
+    assert_eq!(b.output, " hi plojo");
+    b_expect!(b, "WORLD", " hi plojoing");
+    b_expect!(b, "*/*", " hi");
+
+    // invalid entries are not added
+    assert!(b
+        .translator
+        .add_entry(vec![Stroke::new("TEFT")], "{")
+        .is_err());
+    assert!(b
+        .translator
+        .add_entry(vec![Stroke::new("TEPFT")], "test")
+        .is_err());
+    b_expect!(b, "TEFT", " hi TEFT");
+}
+
+#[test]
+fn remove_entry() {
+    let mut b = Blackbox::new(
+        r#"
+            "H-L": "hello",
+            "H-L/WORLD": "hi",
+            "WORLD": "world"
+        "#,
+    );
+    let strokes = |s: &str| s.split('/').map(Stroke::new).collect::<Vec<_>>();
+
+    // the loaded entry is used again after removing the added entry
+    b.add_entry("WORLD", "earth");
+    b_expect!(b, "WORLD", " earth");
+    assert!(b.remove_entry("WORLD"));
+    assert_eq!(b.output, " world");
+    b_expect!(b, "WORLD", " world world");
+    assert_eq!(
+        b.translator.reverse_lookup("earth"),
+        Vec::<Vec<Stroke>>::new()
+    );
+
+    // loaded entries are hidden, so the strokes fall back to shorter entries
+    assert!(b.remove_entry("H-L/WORLD"));
+    b_expect!(b, "H-L/WORLD", " world world hello world");
+    assert_eq!(b.translator.reverse_lookup("hi"), Vec::<Vec<Stroke>>::new());
+    assert!(b.remove_entry("H-L"));
+    assert_eq!(b.output, " world world H-L world");
+    b_expect!(b, "H-L", " world world H-L world H-L");
+
+    // there is nothing left to remove
+    assert!(!b.remove_entry("H-L"));
+    assert!(!b.remove_entry("TEFT"));
+
+    // a hidden entry can be added again
+    b.translator.add_entry(strokes("H-L"), "hey").unwrap();
+    assert_eq!(b.translator.reverse_lookup("hey"), vec![strokes("H-L")]);
+}
+
+#[test]
+fn undo_after_remove_entry() {
+    let mut b = Blackbox::new(r#""H-L": "hello""#);
+    b.add_entry("TEFT", "testing");
+    b_expect!(b, "H-L/TEFT", " hello testing");
+    // the text typed with the removed entry is replaced, so undo removes all of it
+    assert!(b.remove_entry("TEFT"));
+    assert_eq!(b.output, " hello TEFT");
+    b_expect!(b, "*", " hello");
+}
+
+#[test]
+fn add_entry_saved() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("dict.json");
+    let user_path = dir.path().join("user.json");
+    let reload = r#""RE-LD": { "cmds": [{ "TranslatorCommand": "reload_dicts" }] }"#;
+    fs::write(&path, format!(r#"{{ "H-L": "hello", {} }}"#, reload)).unwrap();
+
+    let mut b = Blackbox::new_with_dict_file(&path);
+    b.translator = b.translator.with_user_dict(user_path.clone());
+    b.add_entry("PHRO", "plojo");
+    b.add_entry("H-L/WORLD", "hi");
+    let saved: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&user_path).unwrap()).unwrap();
+    assert_eq!(
+        saved,
+        serde_json::json!({ "PHRO": "plojo", "H-L/WORLD": "hi" })
+    );
+
+    // added entries are kept when the dictionaries are reloaded
+    b_expect!(b, "RE-LD/PHRO/H-L/WORLD", " plojo hi");
+
+    // and so are removed entries, but they stay in the user dictionary
+    assert!(b.remove_entry("PHRO"));
+    assert!(b.remove_entry("H-L"));
+    assert_eq!(b.output, " PHRO hi");
+    b_expect!(b, "RE-LD/PHRO/H-L", " PHRO hi PHRO H-L");
+    assert_eq!(
+        fs::read_to_string(&user_path)
+            .unwrap()
+            .matches("plojo")
+            .count(),
+        1
+    );
+}
+
+#[test]
+fn add_entry_not_saved() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut b = Blackbox::new(r#""H-L": "hello""#);
+    // the folder of the user dictionary doesn't exist
+    b.translator = b
+        .translator
+        .with_user_dict(dir.path().join("missing").join("user.json"));
+    b_expect!(b, "H-L/PHRO", " hello PHRO");
+
+    // the entry is still added and the text already typed is still fixed
+    let added = b
+        .translator
+        .add_entry(vec![Stroke::new("PHRO")], "plojo")
+        .unwrap();
+    assert!(added.save_error.is_some());
+    for command in added.commands {
+        apply_command(&mut b.output, &command);
+    }
+    assert_eq!(b.output, " hello plojo");
+    b_expect!(b, "PHRO", " hello plojo plojo");
+}
+
+#[test]
+fn unknown_stroke_fallback() {
+    let mut b = Blackbox::new(
+        r#"
+            "H-L": "hello",
+            "TPH/KWRORBG": "new york"
+        "#,
+    )
+    .map_translator(|t| {
+        t.with_fallback(HashMap::from([
+            (Stroke::new("TPH"), "n".to_string()),
+            (Stroke::new("KWRORBG"), "yoke".to_string()),
+        ]))
+    });
+    b_expect!(b, "TPH", " n");
+    // the dictionary is still tried first
+    b_expect!(b, "KWRORBG", " new york");
+    b_expect!(b, "KWRORBG/H-L", " new york yoke hello");
+    // strokes without a fallback are still outputted raw
+    b_expect!(b, "WORLD", " new york yoke hello WORLD");
+    b_expect!(b, "*/*", " new york yoke");
+}
+
+#[test]
+fn select_placeholder_text() {
+    let mut b = Blackbox::new(
+        r#"
+            "H-L": "hello",
+            "TKAOER": "Dear",
+            "TPHAEUPL": "your name{:select_prev}{^,}",
+            "SEL": "placeholder{:select_prev:6}"
+        "#,
+    );
+    let shift_left = (Key::Special(SpecialKey::LeftArrow), vec![Modifier::Shift]);
+    let left = (Key::Special(SpecialKey::LeftArrow), vec![]);
+
+    b_expect!(b, "TKAOER/TPHAEUPL", " Dear your name,");
+    assert_eq!(
+        b.output_keys,
+        [vec![left.clone()], vec![shift_left.clone(); 9]].concat()
+    );
+    // the cursor was moved, so the text before it can't be undone
+    b_expect!(b, "*", " Dear your name,");
+
+    b.output_keys.clear();
+    b_expect!(b, "H-L/SEL", " Dear your name, hello placeholder");
+    assert_eq!(b.output_keys, vec![shift_left; 6]);
+}
+
+#[test]
+fn snippet_cursor_placement() {
+    let dict = r#"
+        "H-L": "hello",
+        "SEUG": "Best,\n{:cursor}\nJohn",
+        "PRAPBS": "{^}({:cursor}){^}"
+    "#;
+    let left = |count| Command::RepeatKey {
+        key: Key::Special(SpecialKey::LeftArrow),
+        modifiers: vec![],
+        count,
+    };
+
+    let mut b = Blackbox::new(dict);
+    b_expect_commands!(
+        b,
+        "SEUG",
+        vec![Command::Replace(0, " Best,\n\nJohn".to_string()), left(5)]
+    );
+    // the cursor was moved, so the text before it can't be undone, but the text typed at the
+    // cursor can
+    b_expect_commands!(b, "*", vec![Command::NoOp]);
+    b_expect_commands!(b, "H-L", vec![Command::Replace(0, " hello".to_string())]);
+    b_expect_commands!(b, "*", vec![Command::Replace(6, "".to_string())]);
+    b_expect_commands!(b, "H-L", vec![Command::Replace(0, " hello".to_string())]);
+    b_expect_commands!(
+        b,
+        "PRAPBS",
+        vec![Command::Replace(0, "()".to_string()), left(1)]
+    );
+
+    let mut b = Blackbox::new_with_space_after(dict);
+    b_expect!(b, "H-L", "hello ");
+    b_expect_commands!(
+        b,
+        "PRAPBS",
+        vec![Command::Replace(1, "()".to_string()), left(1)]
+    );
+    b_expect_commands!(b, "H-L", vec![Command::Replace(0, "hello ".to_string())]);
+}
+
+#[test]
+fn undo_repeat_key_command() {
+    let mut b = Blackbox::new(
+        r#"
+            "H-L": "hello",
+            "HREFT": {"cmds": [{ "RepeatKey": {"key": {"Special": "LeftArrow"}, "modifiers": [], "count": 5} }]}
+        "#,
+    );
+
+    b_expect!(b, "H-L/HREFT", " hello");
+    assert_eq!(
+        b.output_keys,
+        vec![(Key::Special(SpecialKey::LeftArrow), vec![]); 5]
+    );
+    // only a cursor marker keeps the text before it from being undone
+    b_expect!(b, "*", "");
+}
+
+#[test]
+fn long_buffer_corrections() {
+    let mut b = Blackbox::new(
+        r#"
+            "H-L": "hello",
+            "WORLD": "world",
+            "A": "a",
+            "H-L/A": "he..llo",
+            "TPHO/WUPB": "no one",
+            "-S": "{^s}"
+        "#,
+    );
+    // fill up the stroke buffer so that only the latest strokes are translated
+    let filler = " hello world".repeat(6);
+    b_expect!(
+        b,
+        "H-L/WORLD/H-L/WORLD/H-L/WORLD/H-L/WORLD/H-L/WORLD/H-L/WORLD",
+        filler
+    );
+    b_expect!(b, "H-L", filler.clone() + " hello");
+    b_expect!(b, "A", filler.clone() + " he..llo");
+    b_expect!(b, "TPHO", filler.clone() + " he..llo TPHO");
+    b_expect!(b, "WUPB", filler.clone() + " he..llo no one");
+    b_expect!(b, "H-LS", filler.clone() + " he..llo no one hellos");
+    b_expect!(b, "*", filler.clone() + " he..llo no one");
+    b_expect!(b, "A/A", filler + " he..llo no one a a");
+}
+
+#[test]
+fn preview_matches_translate() {
+    let mut b = Blackbox::new(
+        r#"
+            "H-L": "hello",
+            "H-L/WORLD": "hi",
+            "WORLD": "world",
+            "TP-PL": "{.}",
+            "KW-GS": "{^}{#Return}{^}{-|}",
+            "-S": "{^s}"
+        "#,
+    );
+    b.preview_and_dispatch("H-L/WORLD/H-L/-S/TP-PL/WORLD/KW-GS/H-L");
+    assert_eq!(b.output, " hi hellos. WorldHello");
+    assert_eq!(
+        b.output_keys,
+        vec![(Key::Special(SpecialKey::Return), vec![])]
+    );
+    b.lookup_and_dispatch("*");
+    b.preview_and_dispatch("WORLD/TKPWHRAO*");
+}
+
+#[test]
+fn translate_all_matches_translate() {
+    let dict = r#"
+            "H-L": "hello",
+            "H-L/WORLD": "hi",
+            "H-L/WORLD/H-L": "hi there",
+            "WORLD": "world",
+            "TP-PL": "{.}",
+            "-S": "{^s}"
+        "#;
+    let strokes: Vec<Stroke> = "H-L/WORLD/H-L/-S/TP-PL/WORLD/H-L/WORLD/WORLD"
+        .split('/')
+        .map(Stroke::new)
+        .collect();
+
+    let mut b = Blackbox::new(dict);
+    let commands: Vec<Command> = strokes
+        .iter()
+        .flat_map(|stroke| b.translator.translate(stroke.clone()))
+        .collect();
+
+    let mut b = Blackbox::new(dict);
+    assert_eq!(b.translator.translate_all(strokes), commands);
+    let mut output = String::new();
+    for command in &commands {
+        apply_command(&mut output, command);
+    }
+    assert_eq!(output, " hi theres. World hi world");
+}
+
+#[test]
+fn preview_does_not_translate() {
+    let mut b = Blackbox::new(
+        r#"
+            "H-L": "hello",
+            "H-L/WORLD": "hi",
+            "WORLD": "world"
+        "#,
+    );
+    b_expect!(b, "H-L", " hello");
+    let preview = b.translator.preview(Stroke::new("WORLD"));
+    assert_eq!(
+        preview,
+        (" hi".to_string(), vec![Command::replace_text(4, "i")])
+    );
+    // nothing changed, so the same stroke previews the same way again
+    assert_eq!(b.translator.preview(Stroke::new("WORLD")), preview);
+    assert_eq!(
+        b.translator.preview(Stroke::new("H-L")),
+        (
+            " hello hello".to_string(),
+            vec![Command::add_text(" hello")]
+        )
+    );
+    b_expect!(b, "H-L", " hello hello");
+}
+
+#[test]
+fn preview_options() {
+    let mut b = Blackbox::new_with_space_after(
+        r#"
+            "H-L": "hello",
+            "TP-PL": "{.}"
+        "#,
+    );
+    b.preview_and_dispatch("H-L/TP-PL/H-L");
+    assert_eq!(b.output, "hello. Hello ");
+
+    let mut b = Blackbox::new_with_retroactive_add_space(
+        r#"
+            "H-L": "hello",
+            "WORLD": "{^world}"
+        "#,
+    );
+    b.preview_and_dispatch("H-L/WORLD/AFPS/H-L");
+    assert_eq!(b.output, " hello world hello");
+
+    let b = Blackbox::new(
+        r#"
+            "H-L": "hello"
+        "#,
+    )
+    .map_translator(|t| {
+        t.with_macros(vec![(
+            vec![Stroke::new("H-L"), Stroke::new("H-L")],
+            vec![Command::PrintHello],
+        )])
+    });
+    assert_eq!(
+        b.translator.preview(Stroke::new("H-L")),
+        ("".to_string(), vec![Command::NoOp])
+    );
+}
+
+#[test]
+fn restore_dumped_strokes() {
+    let dict = r#"{
+        "H-L": "hello",
+        "H-L/WORLD": "hi",
+        "WORLD": "world"
+    }"#;
+    let mut b = Blackbox::new_internal(dict.to_string(), false, false);
+    b_expect!(b, "H-L/WORLD/H-L", " hi hello");
+
+    // the strokes are saved as JSON between sessions
+    let saved = serde_json::to_string(&b.translator.dump_strokes()).unwrap();
+    assert_eq!(saved, r#"["H-L","WORLD","H-L"]"#);
+    let strokes: Vec<Stroke> = serde_json::from_str(&saved).unwrap();
+
+    let mut restored = Blackbox::new_internal(dict.to_string(), false, false);
+    restored.translator =
+        StandardTranslator::new(vec![dict.to_string()], strokes, vec![], None, false).unwrap();
+    restored.output = b.output.clone();
+    assert_eq!(
+        restored.translator.dump_strokes(),
+        b.translator.dump_strokes()
+    );
+    // the restored translator can undo and correct what was typed before
+    b_expect!(restored, "*", " hi");
+    b_expect!(restored, "*", " hello");
+    b_expect!(restored, "WORLD", " hi");
+}
+
+#[test]
+fn undo_depth() {
+    let dict = r#"
+            "H-L": "hello",
+            "H-L/WORLD": "hi",
+            "WORLD": "world",
+            "TEFT": {"cmds": [{ "Keys": [{"Special": "Tab"}, []] }]}
+        "#;
+    let mut b = Blackbox::new(dict);
+    assert_eq!(b.translator.undo_depth(), 0);
+    b_expect!(b, "H-L/WORLD/TEFT/WORLD", " hi world");
+    // the command is skipped by undo
+    assert_eq!(b.translator.undo_depth(), 3);
+    b_expect!(b, "*", " hi");
+    assert_eq!(b.translator.undo_depth(), 2);
+    b_expect!(b, "*", " hello");
+    assert_eq!(b.translator.undo_depth(), 1);
+    b_expect!(b, "*", "");
+    assert_eq!(b.translator.undo_depth(), 0);
+
+    let mut b = Blackbox::new(dict).map_translator(|t| t.with_undo_policy(UndoPolicy::Translation));
+    b_expect!(b, "H-L/WORLD/WORLD", " hi world");
+    assert_eq!(b.translator.undo_depth(), 2);
+    b_expect!(b, "*", " hi");
+    assert_eq!(b.translator.undo_depth(), 1);
+}
+
+#[test]
+fn max_stroke_buffer() {
+    let mut b = Blackbox::new(
+        r#"
+            "H-L": "hello",
+            "H-L/WORLD": "hi",
+            "WORLD": "world"
+        "#,
+    );
+    b_expect!(b, "H-L/WORLD/H-L/H-L/WORLD", " hi hello hi");
+
+    // lowering the limit drops the oldest strokes
+    b.translator = b.translator.with_max_stroke_buffer(3);
+    assert_eq!(
+        b.translator.dump_strokes(),
+        vec![Stroke::new("H-L"), Stroke::new("H-L"), Stroke::new("WORLD")]
+    );
+    b_expect!(b, "WORLD", " hi hello hi world");
+    assert_eq!(b.translator.dump_strokes().len(), 4);
+    b_expect!(b, "H-L", " hi hello hi world hello");
+    assert_eq!(
+        b.translator.dump_strokes(),
+        vec![
+            Stroke::new("H-L"),
+            Stroke::new("WORLD"),
+            Stroke::new("WORLD"),
+            Stroke::new("H-L")
+        ]
+    );
+    assert_eq!(b.translator.undo_depth(), 4);
+
+    // the whole `H-L/WORLD` translation was dropped, so undo stops at the oldest translation
+    b_expect!(b, "*", " hi hello hi world");
+    b_expect!(b, "*", " hi hello hi");
+    b_expect!(b, "*", " hi hello hello");
+    b_expect!(b, "*", " hi hello");
+    b_expect!(b, "*", " hi hello");
+}
+
+#[test]
+fn shell_insert() {
+    let mut b = Blackbox::new(
+        r#"
+            "H-L": "hello",
+            "SKWR*": { "cmds": [{ "ShellInsert": ["echo", ["shell output"]] }] },
+            "TKAEUT": {
+                "cmds": [{ "ShellInsert": ["printf", ["2021"]] }],
+                "text_after": "{^-^}"
+            },
+            "TPAEUL": { "cmds": [{ "ShellInsert": ["false", []] }] }
+        "#,
+    );
+    b_expect!(b, "H-L/SKWR*", " hello shell output");
+    b_expect!(b, "*", " hello");
+    b_expect!(b, "TKAEUT/H-L", " hello 2021-hello");
+    b_expect!(b, "*/*", " hello");
+    // a command that fails doesn't type anything
+    b_expect!(b, "TPAEUL/H-L", " hello hello");
+}
+
+#[test]
+fn shell_insert_rerun() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("output.txt");
+    fs::write(&file, "one").unwrap();
+    let dict = format!(
+        r#""SKWR*": {{ "cmds": [{{ "ShellInsert": ["cat", [{:?}]] }}] }}, "H-L": "hello""#,
+        file.to_str().unwrap()
+    );
+    let mut b = Blackbox::new(&dict);
+    b_expect!(b, "SKWR*", " one");
+    // the command is run again every time it is stroked
+    fs::write(&file, "three").unwrap();
+    b_expect!(b, "H-L/SKWR*", " one hello three");
+    // the earlier translation still types what it printed
+    b_expect!(b, "*", " one hello");
+    b_expect!(b, "*", " one");
+    b_expect!(b, "*", "");
+
+    fs::write(&file, "one").unwrap();
+    b_expect!(b, "H-L/SKWR*", " hello one");
+    fs::write(&file, "three").unwrap();
+    b_expect!(b, "SKWR*/*", " hello one");
+    b_expect!(b, "*", " hello");
+}
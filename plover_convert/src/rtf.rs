@@ -0,0 +1,276 @@
+//! Reads dictionaries in the RTF/CRE format that many commercial steno dictionaries are shipped
+//! in. Only the common subset of control words is understood.
+use serde_json::{Map, Value};
+use std::iter::Peekable;
+use std::str::Chars;
+
+// punctuation that is attached to the word before it (like plover's `{.}`)
+const PUNCTUATION: [&str; 6] = [".", "!", "?", ",", ":", ";"];
+
+#[derive(Debug, PartialEq)]
+pub enum RtfError {
+    UnbalancedBraces,
+    EmptyStroke,
+    UnsupportedControl(String),
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    GroupStart,
+    GroupEnd,
+    // a control word (ex: `\cxs`) or a control symbol (ex: `\*`), without the backslash
+    Control(String),
+    Text(char),
+}
+
+/// Split RTF into tokens. The parameter of a control word and the space after it are dropped
+fn tokenize(rtf: &str) -> Vec<Token> {
+    let mut tokens = vec![];
+    let mut chars = rtf.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' => tokens.push(Token::GroupStart),
+            '}' => tokens.push(Token::GroupEnd),
+            '\\' => tokens.push(Token::Control(control_word(&mut chars))),
+            // line breaks in the file don't mean anything (`\par` is a new line)
+            '\r' | '\n' => {}
+            c => tokens.push(Token::Text(c)),
+        }
+    }
+    tokens
+}
+
+/// Read the control word after a backslash
+fn control_word(chars: &mut Peekable<Chars>) -> String {
+    let mut word = String::new();
+    while let Some(c) = chars.peek().filter(|c| c.is_ascii_alphabetic()) {
+        word.push(*c);
+        chars.next();
+    }
+    if word.is_empty() {
+        // a control symbol is a single character (ex: `\*` or an escaped `\{`)
+        return chars.next().map(String::from).unwrap_or_default();
+    }
+
+    // skip the numeric parameter
+    if chars.peek() == Some(&'-') {
+        chars.next();
+    }
+    while chars.peek().filter(|c| c.is_ascii_digit()).is_some() {
+        chars.next();
+    }
+    // a space after a control word only ends it
+    if chars.peek() == Some(&' ') {
+        chars.next();
+    }
+    word
+}
+
+/// Parse an RTF/CRE dictionary into a plover (JSON) dictionary. Entries with control words that
+/// can't be converted are skipped with a warning
+pub fn parse_rtf(rtf: &str) -> Result<Value, RtfError> {
+    let tokens = tokenize(rtf);
+    let mut dict = Map::new();
+
+    // each entry is a `{\*\cxs STROKE}` group followed by its translation
+    let mut i = 0;
+    while i < tokens.len() {
+        if !is_stroke_group(&tokens[i..]) {
+            i += 1;
+            continue;
+        }
+        let (stroke, end) = group_text(&tokens, i + 3)?;
+        if stroke.is_empty() {
+            return Err(RtfError::EmptyStroke);
+        }
+
+        // the translation goes until the next entry (or the end of the dictionary group)
+        let mut next = end + 1;
+        let mut depth = 0;
+        while next < tokens.len() && !(depth == 0 && is_stroke_group(&tokens[next..])) {
+            match tokens[next] {
+                Token::GroupStart => depth += 1,
+                Token::GroupEnd if depth == 0 => break,
+                Token::GroupEnd => depth -= 1,
+                _ => {}
+            }
+            next += 1;
+        }
+
+        match translation(&tokens[end + 1..next]) {
+            Ok(t) => {
+                dict.insert(stroke, Value::String(t));
+            }
+            Err(e) => {
+                eprintln!(
+                    r#"[WARN]: Could not convert "{}" because of {:?}"#,
+                    stroke, e
+                );
+            }
+        }
+        i = next;
+    }
+
+    Ok(Value::Object(dict))
+}
+
+/// Whether the tokens start with a `{\*\cxs` group
+fn is_stroke_group(tokens: &[Token]) -> bool {
+    matches!(
+        tokens,
+        [Token::GroupStart, Token::Control(star), Token::Control(cxs), ..]
+            if star == "*" && cxs == "cxs"
+    )
+}
+
+/// The text in a group up to the end of the group, along with the index of the end of the group
+fn group_text(tokens: &[Token], start: usize) -> Result<(String, usize), RtfError> {
+    let mut text = String::new();
+    for (i, token) in tokens.iter().enumerate().skip(start) {
+        match token {
+            Token::GroupEnd => return Ok((text, i)),
+            Token::Text(c) => text.push(*c),
+            Token::Control(c) if c == "\\" => text.push('\\'),
+            Token::Control(c) => return Err(RtfError::UnsupportedControl(c.clone())),
+            Token::GroupStart => return Err(RtfError::UnsupportedControl("{".to_string())),
+        }
+    }
+    Err(RtfError::UnbalancedBraces)
+}
+
+/// Convert the tokens of a translation into a plover translation
+fn translation(tokens: &[Token]) -> Result<String, RtfError> {
+    let mut translation = String::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        match &tokens[i] {
+            Token::Text(c) => translation.push(*c),
+            Token::Control(c) => translation.push_str(match c.as_ref() {
+                "\\" => "\\",
+                // literal braces are escaped in plover
+                "{" => "\\{",
+                "}" => "\\}",
+                // delete space
+                "cxds" => "{^}",
+                // capitalize or lowercase the next word
+                "cxfc" => "{-|}",
+                "cxfl" => "{>}",
+                "par" => "{^\n^}",
+                "~" => " ",
+                _ => return Err(RtfError::UnsupportedControl(c.clone())),
+            }),
+            Token::GroupStart => {
+                let group = match tokens.get(i + 1) {
+                    Some(Token::Control(c)) => c.as_ref(),
+                    _ => return Err(RtfError::UnsupportedControl("{".to_string())),
+                };
+                let (text, end) = match group {
+                    // ignorable groups can have anything in them
+                    "*" => skip_group(tokens, i)?,
+                    _ => group_text(tokens, i + 2).map_err(|e| match e {
+                        RtfError::UnsupportedControl(_) => {
+                            RtfError::UnsupportedControl(group.to_string())
+                        }
+                        e => e,
+                    })?,
+                };
+                match group {
+                    "*" => {}
+                    // fingerspelling
+                    "cxfing" => translation.push_str(&format!("{{&{}}}", text)),
+                    "cxp" => {
+                        let punctuation = text.trim();
+                        if PUNCTUATION.contains(&punctuation) {
+                            translation.push_str(&format!("{{{}}}", punctuation));
+                        } else {
+                            // other punctuation is attached on both sides
+                            translation.push_str(&format!("{{^{}^}}", punctuation));
+                        }
+                    }
+                    c => return Err(RtfError::UnsupportedControl(c.to_string())),
+                }
+                i = end;
+            }
+            Token::GroupEnd => return Err(RtfError::UnbalancedBraces),
+        }
+        i += 1;
+    }
+    Ok(translation.trim().to_string())
+}
+
+/// Find the end of the group that starts at `start`, skipping any groups in it
+fn skip_group(tokens: &[Token], start: usize) -> Result<(String, usize), RtfError> {
+    let mut depth = 0;
+    for (i, token) in tokens.iter().enumerate().skip(start) {
+        match token {
+            Token::GroupStart => depth += 1,
+            Token::GroupEnd => {
+                depth -= 1;
+                if depth == 0 {
+                    return Ok((String::new(), i));
+                }
+            }
+            _ => {}
+        }
+    }
+    Err(RtfError::UnbalancedBraces)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_rtf() {
+        let rtf = r#"{\rtf1\ansi{\*\cxrev100}\cxdict{\*\cxsystem Case CATalyst}{\stylesheet{\s0 Normal;}}
+{\*\cxs TEFT}test
+{\*\cxs H-L/WORLD}hello world
+{\*\cxs -G}\cxds ing
+{\*\cxs TP-PL}{\cxp. }\cxfc
+{\*\cxs KW-BG}{\cxp , }
+{\*\cxs H-PB}{\cxp -}
+{\*\cxs A*}{\cxfing a}
+{\*\cxs R-R}\par
+{\*\cxs SHRARB}\\{\*\cxsvatdictflags N}
+{\*\cxs PWRAEUS}\{braces\}
+}"#;
+        assert_eq!(
+            parse_rtf(rtf).unwrap(),
+            json!({
+                "TEFT": "test",
+                "H-L/WORLD": "hello world",
+                "-G": "{^}ing",
+                "TP-PL": "{.}{-|}",
+                "KW-BG": "{,}",
+                "H-PB": "{^-^}",
+                "A*": "{&a}",
+                "R-R": "{^\n^}",
+                "SHRARB": "\\",
+                "PWRAEUS": "\\{braces\\}",
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_rtf_skips_unsupported() {
+        let rtf = r#"{\rtf1\ansi
+{\*\cxs TEFT}test
+{\*\cxs STPH}\cxsomething
+{\*\cxs KWR}{\cxconf {\cxc one}{\cxc two}}
+}"#;
+        assert_eq!(parse_rtf(rtf).unwrap(), json!({ "TEFT": "test" }));
+    }
+
+    #[test]
+    fn test_parse_rtf_fails() {
+        assert_eq!(
+            parse_rtf(r#"{\rtf1 {\*\cxs TEFT test"#).unwrap_err(),
+            RtfError::UnbalancedBraces
+        );
+        assert_eq!(
+            parse_rtf(r#"{\rtf1 {\*\cxs }test}"#).unwrap_err(),
+            RtfError::EmptyStroke
+        );
+    }
+}
@@ -6,6 +6,7 @@ use std::collections::HashMap;
 pub struct FrequencyAnalyzer {
     grams_1: HashMap<Stroke, u32>,
     grams_2: HashMap<[Stroke; 2], u32>,
+    grams_3: HashMap<[Stroke; 3], u32>,
 }
 
 impl FrequencyAnalyzer {
@@ -13,6 +14,7 @@ impl FrequencyAnalyzer {
         Self {
             grams_1: HashMap::new(),
             grams_2: HashMap::new(),
+            grams_3: HashMap::new(),
         }
     }
 
@@ -47,6 +49,21 @@ impl FrequencyAnalyzer {
         freqs
     }
 
+    /// Get a list of tri-grams (three strokes in a row)
+    pub fn grams_3(&self, threshold: u32) -> Vec<(&[Stroke; 3], u32)> {
+        let mut freqs = Vec::new();
+        for (strokes, &count) in &self.grams_3 {
+            if count >= threshold {
+                freqs.push((strokes, count));
+            }
+        }
+
+        // reverse sort
+        freqs.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+
+        freqs
+    }
+
     fn process_grams_1(&mut self, entries: &[&LogEntry]) {
         for entry in entries {
             let stroke = entry.stroke.clone();
@@ -86,6 +103,28 @@ impl FrequencyAnalyzer {
             prev = Some(entry.stroke.clone());
         }
     }
+
+    fn process_grams_3(&mut self, entries: &[&LogEntry]) {
+        // each stroke of the tri-gram must occur this frequently
+        const THRESHOLD: u32 = 2;
+
+        // every three strokes in a row (which overlap, so repeated strokes are counted each time)
+        for window in entries.windows(3) {
+            let strokes = [
+                window[0].stroke.clone(),
+                window[1].stroke.clone(),
+                window[2].stroke.clone(),
+            ];
+
+            // only insert if all of them occur frequently enough on their own
+            if strokes
+                .iter()
+                .all(|s| self.grams_1.get(s).unwrap_or(&0) >= &THRESHOLD)
+            {
+                *self.grams_3.entry(strokes).or_insert(0) += 1;
+            }
+        }
+    }
 }
 
 impl Processor for FrequencyAnalyzer {
@@ -100,6 +139,7 @@ impl Processor for FrequencyAnalyzer {
             .collect();
         self.process_grams_1(&cleaned);
         self.process_grams_2(&cleaned);
+        self.process_grams_3(&cleaned);
     }
 }
 
@@ -181,4 +221,63 @@ mod tests {
         let freq = f.grams_2(2);
         assert_eq!(freq, vec![(&["K-R".to_string(), "-T".to_string()], 2)])
     }
+
+    #[test]
+    fn test_3_gram_statistics() {
+        let mut f = FrequencyAnalyzer::new();
+        f.process(&log_entries());
+
+        let freq = f.grams_3(1);
+        assert_eq!(
+            freq,
+            vec![(&["-T".to_string(), "K-R".to_string(), "-T".to_string()], 1)]
+        );
+        assert_eq!(f.grams_3(2), vec![]);
+    }
+
+    #[test]
+    fn test_3_gram_repeated_strokes() {
+        let mut f = FrequencyAnalyzer::new();
+        f.process(&[
+            entry(1607820695881, "-T", 0, " the"),
+            entry(1607820696136, "-T", 0, " the"),
+            entry(1607820696286, "-T", 0, " the"),
+            entry(1607820696540, "-T", 0, " the"),
+            entry(1607820697320, "TO", 0, " to"),
+            entry(1607820697605, "-T", 0, " the"),
+            entry(1607820697808, "TO", 0, " to"),
+        ]);
+
+        // the overlapping tri-grams are each counted
+        let freq = f.grams_3(2);
+        assert_eq!(
+            freq,
+            vec![(&["-T".to_string(), "-T".to_string(), "-T".to_string()], 2)]
+        );
+        let mut freq = f.grams_3(1);
+        freq.sort();
+        assert_eq!(
+            freq,
+            vec![
+                (&["-T".to_string(), "-T".to_string(), "-T".to_string()], 2),
+                (&["-T".to_string(), "-T".to_string(), "TO".to_string()], 1),
+                (&["-T".to_string(), "TO".to_string(), "-T".to_string()], 1),
+                (&["TO".to_string(), "-T".to_string(), "TO".to_string()], 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_3_gram_too_few_entries() {
+        let mut f = FrequencyAnalyzer::new();
+        f.process(&[
+            entry(1607820695881, "-T", 0, " the"),
+            entry(1607820696136, "-T", 0, " the"),
+        ]);
+        assert_eq!(f.grams_3(1), vec![]);
+
+        let mut f = FrequencyAnalyzer::new();
+        f.process(&[]);
+        assert_eq!(f.grams_3(1), vec![]);
+    }
 }
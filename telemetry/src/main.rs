@@ -1,6 +1,7 @@
 #[macro_use]
 extern crate lazy_static;
 use itertools::Itertools;
+use std::cmp;
 use std::fs::File;
 use std::io::{BufRead, BufReader, LineWriter, Write};
 
@@ -8,15 +9,24 @@ mod frequency;
 mod parsed;
 mod processor;
 mod raw;
+mod wpm;
 
 use frequency::FrequencyAnalyzer;
 use parsed::LogEntry;
 use processor::Processor;
+use wpm::WpmAnalyzer;
 
 const CHUNK_SIZE: usize = 1000;
+// length of typing time (in milliseconds) that each typing speed is measured over
+const WPM_WINDOW: i64 = 60_000;
+// pauses between strokes longer than this (in milliseconds) aren't counted as typing time
+const WPM_IDLE_THRESHOLD: i64 = 5_000;
+// width of each bar of the typing speed histogram in WPM
+const WPM_BUCKET_SIZE: u32 = 10;
 
 fn main() {
     analyze_frequency("logs/parsed.txt");
+    analyze_wpm("logs/parsed.txt");
 
     // to prevent unused code warnings
     if false {
@@ -60,14 +70,19 @@ fn read_raw_and_parse(raw_file: &str, out_file: &str) {
     println!("Done!");
 }
 
-fn analyze_frequency(file: &str) {
+/// Reads the log entries from a parsed log file
+fn read_parsed(file: &str) -> Vec<LogEntry> {
     let contents = std::fs::read_to_string(file).expect("Could not read from file");
+    contents
+        .lines()
+        .map(|l| serde_json::from_str(l).expect("Invalid serialized data"))
+        .collect()
+}
+
+fn analyze_frequency(file: &str) {
     let mut freq = FrequencyAnalyzer::new();
 
-    let parsed: Vec<LogEntry> = contents
-        .lines()
-        .map(|l| serde_json::from_str(&l).expect("Invalid serialized data"))
-        .collect();
+    let parsed = read_parsed(file);
     freq.process(&parsed);
 
     let grams = freq.grams_1(1);
@@ -81,4 +96,26 @@ fn analyze_frequency(file: &str) {
     println!("bi-grams");
     println!("{:?}", &grams_2[..20]);
     println!("");
+    let grams_3 = freq.grams_3(2);
+    println!("tri-grams");
+    println!("{:?}", &grams_3[..cmp::min(20, grams_3.len())]);
+    println!();
+}
+
+fn analyze_wpm(file: &str) {
+    let mut wpm = WpmAnalyzer::new(WPM_WINDOW).with_idle_threshold(WPM_IDLE_THRESHOLD);
+    wpm.process(&read_parsed(file));
+
+    match wpm.average_wpm() {
+        Some(average) => println!("Average speed: {:.1} WPM", average),
+        None => println!("Average speed: nothing typed"),
+    }
+    println!(
+        "Speed over each minute of typing ({} minutes)",
+        wpm.window_wpms().len()
+    );
+    for (speed, count) in wpm.histogram(WPM_BUCKET_SIZE) {
+        println!("{:>4} WPM: {}", speed, "#".repeat(count));
+    }
+    println!();
 }
@@ -3,11 +3,12 @@ use chrono::{DateTime, Utc};
 use regex::Regex;
 use std::{error::Error, fmt};
 
-/// Parse a raw line from a log file into a common data format
+/// Parse a raw line from a log file into a common data format. The stroke is either logged as
+/// `Stroke("H-L")` (older logs) or just `H-L`
 pub fn parse_raw(raw: &str) -> Result<LogEntry, Box<dyn Error>> {
     lazy_static! {
         static ref RE: Regex =
-            Regex::new(r#"^([^ ]+) Stroke\("([^"]+)"\) => (.+)$"#).unwrap();
+            Regex::new(r#"^([^ ]+) (?:Stroke\("([^"]+)"\)|([^ "]+)) => (.+)$"#).unwrap();
             // Regex::new(r#"^([^ ]+) Stroke\("([^"]+)"\) => \[Replace\((\d+), "(.*)"\)\]$"#).unwrap();
         static ref TEXT_RE: Regex =
             Regex::new(r#"^\[Replace\((\d+), "(.*)"\)\]$"#).unwrap();
@@ -22,10 +23,11 @@ pub fn parse_raw(raw: &str) -> Result<LogEntry, Box<dyn Error>> {
     let time = time.timestamp_millis();
     let stroke = groups
         .get(2)
+        .or_else(|| groups.get(3))
         .map(|m| m.as_str())
         .ok_or(ParseError::NoStroke)?;
     let payload = groups
-        .get(3)
+        .get(4)
         .map(|m| m.as_str())
         .ok_or(ParseError::NoPayload)?;
 
@@ -145,6 +147,35 @@ mod tests {
         );
     }
 
+    #[test]
+    fn parse_line_stroke_display() {
+        assert_eq!(
+            parse_raw(r#"2020-11-29T16:20:50.529-08:00 H-L => [Replace(0, " hello")]"#).unwrap(),
+            LogEntry {
+                time: "2020-11-29T16:20:50.529-08:00"
+                    .parse::<DateTime<Utc>>()
+                    .unwrap()
+                    .timestamp_millis(),
+                stroke: "H-L".to_string(),
+                content: Content::Replace {
+                    text: " hello".to_string(),
+                    backspace_num: 0,
+                },
+            }
+        );
+        assert_eq!(
+            parse_raw(r#"2020-11-29T16:20:50.529-08:00 * => [NoOp]"#).unwrap(),
+            LogEntry {
+                time: "2020-11-29T16:20:50.529-08:00"
+                    .parse::<DateTime<Utc>>()
+                    .unwrap()
+                    .timestamp_millis(),
+                stroke: "*".to_string(),
+                content: Content::NoOp,
+            }
+        );
+    }
+
     #[test]
     fn parse_lines_commands() {
         assert_eq!(
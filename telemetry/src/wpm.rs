@@ -0,0 +1,241 @@
+use crate::parsed::{Content, LogEntry};
+use crate::processor::Processor;
+
+/// A word is counted as this many chars (including spaces), which is the standard for typing speed
+const CHARS_PER_WORD: f64 = 5.0;
+const MILLIS_PER_MINUTE: f64 = 60_000.0;
+/// Pauses between strokes longer than this (in milliseconds) are not counted as typing
+const DEFAULT_IDLE_THRESHOLD: i64 = 5_000;
+
+/// Measures typing speed in words per minute. The time between strokes is only counted while
+/// typing (pauses longer than the idle threshold are skipped), and the chars typed are the chars
+/// added minus the chars deleted, so corrections slow down the speed.
+///
+/// The typing time is split into windows of the same length to see how the speed varies
+pub struct WpmAnalyzer {
+    // length of each window of typing time in milliseconds
+    window: i64,
+    idle_threshold: i64,
+    // typing time and chars over all the entries
+    total_time: i64,
+    total_chars: i64,
+    // typing time and chars in the window that is not finished yet
+    window_time: i64,
+    window_chars: i64,
+    // speed of each finished window
+    speeds: Vec<f64>,
+    prev_time: Option<i64>,
+}
+
+impl WpmAnalyzer {
+    /// Measure the speed over windows of this many milliseconds of typing
+    pub fn new(window: i64) -> Self {
+        Self {
+            window,
+            idle_threshold: DEFAULT_IDLE_THRESHOLD,
+            total_time: 0,
+            total_chars: 0,
+            window_time: 0,
+            window_chars: 0,
+            speeds: Vec::new(),
+            prev_time: None,
+        }
+    }
+
+    /// Skip pauses longer than this many milliseconds instead of 5 seconds
+    pub fn with_idle_threshold(mut self, idle_threshold: i64) -> Self {
+        self.idle_threshold = idle_threshold;
+        self
+    }
+
+    /// The speed over all of the typing time, or None if nothing was typed
+    pub fn average_wpm(&self) -> Option<f64> {
+        if self.total_time > 0 {
+            Some(wpm(self.total_chars, self.total_time))
+        } else {
+            None
+        }
+    }
+
+    /// The speed of each window in order. The last window is left out if it isn't full yet
+    pub fn window_wpms(&self) -> &[f64] {
+        &self.speeds
+    }
+
+    /// Count the windows by speed, in buckets of `bucket_size` WPM. Returns the lowest speed of
+    /// each bucket and how many windows are in it, from 0 WPM up to the fastest bucket. Windows
+    /// slower than 0 WPM (more deleted than typed) are in the first bucket
+    pub fn histogram(&self, bucket_size: u32) -> Vec<(u32, usize)> {
+        let buckets: Vec<usize> = self
+            .speeds
+            .iter()
+            .map(|&speed| (speed.max(0.0) / bucket_size as f64) as usize)
+            .collect();
+        let num_buckets = match buckets.iter().max() {
+            Some(max) => max + 1,
+            None => return vec![],
+        };
+
+        let mut counts = vec![0; num_buckets];
+        for bucket in buckets {
+            counts[bucket] += 1;
+        }
+        counts
+            .into_iter()
+            .enumerate()
+            .map(|(i, count)| (i as u32 * bucket_size, count))
+            .collect()
+    }
+
+    fn process_entry(&mut self, entry: &LogEntry) {
+        let prev_time = self.prev_time.replace(entry.time);
+        let elapsed = match prev_time {
+            Some(prev_time) => entry.time - prev_time,
+            // the time it took to type the first stroke is unknown
+            None => return,
+        };
+        if elapsed > self.idle_threshold {
+            // the stroke after a pause starts typing again
+            return;
+        }
+
+        let chars = net_chars(&entry.content);
+        self.total_time += elapsed;
+        self.total_chars += chars;
+        self.window_time += elapsed;
+        self.window_chars += chars;
+        if self.window_time >= self.window {
+            self.speeds.push(wpm(self.window_chars, self.window_time));
+            self.window_time = 0;
+            self.window_chars = 0;
+        }
+    }
+}
+
+impl Processor for WpmAnalyzer {
+    /// Process a series of entries
+    fn process(&mut self, entries: &[LogEntry]) {
+        for entry in entries {
+            self.process_entry(entry);
+        }
+    }
+}
+
+/// The number of chars added minus the number of chars deleted
+fn net_chars(content: &Content) -> i64 {
+    match content {
+        Content::Replace {
+            backspace_num,
+            text,
+        } => text.chars().count() as i64 - *backspace_num as i64,
+        Content::Command | Content::NoOp => 0,
+    }
+}
+
+fn wpm(chars: i64, millis: i64) -> f64 {
+    (chars as f64 / CHARS_PER_WORD) / (millis as f64 / MILLIS_PER_MINUTE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(time: i64, backspace_num: u32, text: &str) -> LogEntry {
+        LogEntry {
+            time,
+            stroke: "TEFT".to_string(),
+            content: Content::Replace {
+                backspace_num,
+                text: text.to_string(),
+            },
+        }
+    }
+
+    /// A 5 char word every `interval` milliseconds, starting at `start`
+    fn words(start: i64, interval: i64, count: i64) -> Vec<LogEntry> {
+        (0..count)
+            .map(|i| entry(start + i * interval, 0, " test"))
+            .collect()
+    }
+
+    #[test]
+    fn steady_speed() {
+        let mut w = WpmAnalyzer::new(10_000);
+        // a word every second is 60 WPM
+        w.process(&words(0, 1_000, 31));
+        assert_eq!(w.average_wpm(), Some(60.0));
+        assert_eq!(w.window_wpms(), &[60.0, 60.0, 60.0]);
+    }
+
+    #[test]
+    fn windows_with_different_speeds() {
+        let mut w = WpmAnalyzer::new(10_000);
+        let mut entries = words(0, 1_000, 11);
+        // a word every half a second is 120 WPM
+        entries.extend(words(10_500, 500, 20));
+        w.process(&entries);
+        assert_eq!(w.window_wpms(), &[60.0, 120.0]);
+        // 30 words in 20 seconds
+        assert_eq!(w.average_wpm(), Some(90.0));
+    }
+
+    #[test]
+    fn ignore_idle_gaps() {
+        let mut w = WpmAnalyzer::new(10_000);
+        let mut entries = words(0, 1_000, 6);
+        // a long pause (longer than 5 seconds) isn't counted
+        entries.extend(words(60_000, 1_000, 6));
+        w.process(&entries);
+        assert_eq!(w.average_wpm(), Some(60.0));
+        assert_eq!(w.window_wpms(), &[60.0]);
+
+        // unless the idle threshold is longer than the pause: 11 words in 65 seconds
+        let mut w = WpmAnalyzer::new(10_000).with_idle_threshold(60_000);
+        w.process(&entries);
+        assert_eq!(w.average_wpm(), Some(11.0 / (65.0 / 60.0)));
+    }
+
+    #[test]
+    fn corrections_and_commands() {
+        let mut w = WpmAnalyzer::new(60_000);
+        w.process(&[
+            entry(0, 0, " hello"),
+            entry(1_000, 0, " world"),
+            // deleting a word takes away its chars
+            entry(2_000, 6, ""),
+            entry(3_000, 0, " there"),
+            LogEntry {
+                time: 4_000,
+                stroke: "SKWR-RBGS".to_string(),
+                content: Content::Command,
+            },
+        ]);
+        // 6 chars in 4 seconds
+        assert_eq!(w.average_wpm(), Some(18.0));
+        assert!(w.window_wpms().is_empty());
+    }
+
+    #[test]
+    fn no_typing() {
+        let mut w = WpmAnalyzer::new(10_000);
+        assert_eq!(w.average_wpm(), None);
+        w.process(&words(0, 1_000, 1));
+        assert_eq!(w.average_wpm(), None);
+        assert_eq!(w.histogram(10), vec![]);
+    }
+
+    #[test]
+    fn speed_histogram() {
+        let mut w = WpmAnalyzer::new(5_000);
+        let mut entries = words(0, 1_000, 11);
+        entries.extend(words(10_500, 500, 20));
+        entries.extend(words(20_250, 250, 20));
+        w.process(&entries);
+        assert_eq!(w.window_wpms(), &[60.0, 60.0, 120.0, 120.0, 240.0]);
+        assert_eq!(
+            w.histogram(50),
+            vec![(0, 0), (50, 2), (100, 2), (150, 0), (200, 1)]
+        );
+        assert_eq!(w.histogram(100), vec![(0, 2), (100, 2), (200, 1)]);
+    }
+}